@@ -0,0 +1,1174 @@
+use std;
+use std::ffi;
+use std::fmt;
+use std::fs;
+use std::path;
+
+use clap;
+use time;
+
+use data;
+use sources::WithSources;
+use types;
+
+/// Generates the command line interface.
+pub fn app<'a>() -> clap::App<'a, 'a> {
+    clap::App::new(crate_name!())
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about(crate_description!())
+        .arg(
+            clap::Arg::with_name("MOUNT_POINT")
+                .help("The target mount point.")
+                .required_unless_one(&["PROBE", "VERIFY_READS"]),
+        )
+        .arg(
+            clap::Arg::with_name("FUSE_OPTION")
+                .help("Options passed to FUSE")
+                .multiple(true)
+                .value_delimiter(",")
+                .short("o")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("VERBOSE")
+                .help(concat!(
+                    "Increases the log verbosity; pass once for debug ",
+                    "messages and twice for trace messages.",
+                ))
+                .short("v")
+                .long("verbose")
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::with_name("SELF_CHECK")
+                .help(concat!(
+                    "Mounts the file system, verifies that all write ",
+                    "operations are rejected and unmounts it again.",
+                ))
+                .long("self-check")
+                .alias("read-only-verify"),
+        )
+        .arg(
+            clap::Arg::with_name("VERIFY_READS")
+                .help(concat!(
+                    "Scans the source, reads a sample of the files in the ",
+                    "file system and verifies that their content matches ",
+                    "that of their source files, and exits. Files are read ",
+                    "by calling the file system handlers directly, not ",
+                    "through a kernel mount. Fails if no files are verified. ",
+                    "No mount point is required.",
+                ))
+                .long("verify-reads"),
+        )
+        .arg(
+            clap::Arg::with_name("PRINT_CONFIG")
+                .help(concat!(
+                    "Prints the resolved configuration, including that of ",
+                    "the source, and exits.",
+                ))
+                .long("print-config"),
+        )
+        .arg(
+            clap::Arg::with_name("LIST_TAGS")
+                .help(concat!(
+                    "Scans the source, prints every tag, including all ",
+                    "parent tags, one per line and exits.",
+                ))
+                .long("list-tags"),
+        )
+        .arg(
+            clap::Arg::with_name("EMIT_MANIFEST")
+                .help(concat!(
+                    "Scans the source, prints every file in the file system ",
+                    "with the size and modification time of its source file, ",
+                    "sorted by path, and exits. Listings of an unchanged ",
+                    "library are identical, so they can be compared to find ",
+                    "changes.",
+                ))
+                .long("emit-manifest"),
+        )
+        .arg(
+            clap::Arg::with_name("PROBE")
+                .help(concat!(
+                    "Reads the metadata of a single file in the source, ",
+                    "prints it and exits. No mount point is required.",
+                ))
+                .long("probe")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("CONFIG")
+                .help(concat!(
+                    "A file selecting which items to include. The file is ",
+                    "read again when SIGHUP is received.",
+                ))
+                .long("config")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("FILTER")
+                .help(concat!(
+                    "A query that included items must match, such as ",
+                    "\"tag:Family and year:2020\". Terms are tag:, year:, ",
+                    "month:, type: and rating followed by a comparison, ",
+                    "combined with and, or and not.",
+                ))
+                .long("filter")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("ALBUMS")
+                .help(concat!(
+                    "A file pinning items to albums, with one line on the ",
+                    "form \"/path/to/photo.jpg = Favorites/2020\" per item ",
+                    "and album. Pinned items are linked from Albums.",
+                ))
+                .long("albums")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("DATE_LAYOUT")
+                .help(concat!(
+                    "The layout of the date directories; the year, month and ",
+                    "day templates separated by \"/\". Templates may use %Y, ",
+                    "%m, %B and %d.",
+                ))
+                .long("date-layout")
+                .takes_value(true)
+                .default_value("%Y/%m/%d"),
+        )
+        .arg(
+            clap::Arg::with_name("MERGE_ADJACENT_DAYS")
+                .help(concat!(
+                    "The hour at which a day starts. Items generated after ",
+                    "midnight but before this hour are filed under the ",
+                    "previous day, so that a night of shooting stays in one ",
+                    "directory; 0 to use calendar days.",
+                ))
+                .long("merge-adjacent-days")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("TAG_SEPARATOR")
+                .help(concat!(
+                    "The character separating the levels of hierarchical ",
+                    "tags, both when reading keywords and in albums and ",
+                    "aliases.",
+                ))
+                .long("tag-separator")
+                .takes_value(true)
+                .default_value("/"),
+        )
+        .arg(
+            clap::Arg::with_name("FOLDERS_ROOT")
+                .help(concat!(
+                    "Mirrors the source directory structure under this ",
+                    "directory, for example \"Folders\".",
+                ))
+                .long("folders-root")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("MODIFIED_ROOT")
+                .help(concat!(
+                    "Adds items dated by their file modification time, ",
+                    "rather than by their metadata, under this directory, ",
+                    "for example \"NoExif\".",
+                ))
+                .long("modified-root")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("LINK_STYLE")
+                .help(concat!(
+                    "The style of links in the tagged tree; absolute links ",
+                    "include the mount point.",
+                ))
+                .long("link-style")
+                .takes_value(true)
+                .possible_values(&["relative", "absolute"])
+                .default_value("relative"),
+        )
+        .arg(
+            clap::Arg::with_name("VIEWS")
+                .help(concat!(
+                    "The views presented under the root, separated by \",\"; ",
+                    "timestamps, tags, people, type, camera, lens, size and ",
+                    "weeks. The timestamps view is always included.",
+                ))
+                .long("views")
+                .takes_value(true)
+                .multiple(true)
+                .value_delimiter(",")
+                .possible_values(&[
+                    "timestamps",
+                    "tags",
+                    "people",
+                    "type",
+                    "camera",
+                    "lens",
+                    "size",
+                    "weeks",
+                ])
+                .default_value("timestamps,tags"),
+        )
+        .arg(
+            clap::Arg::with_name("LENSES_ROOT")
+                .help("The root of the lens view.")
+                .long("lenses-root")
+                .takes_value(true)
+                .default_value(data::cache::LENSES_ROOT),
+        )
+        .arg(
+            clap::Arg::with_name("SIZES_ROOT")
+                .help("The root of the size view.")
+                .long("sizes-root")
+                .takes_value(true)
+                .default_value(data::cache::SIZES_ROOT),
+        )
+        .arg(
+            clap::Arg::with_name("WEEKS_ROOT")
+                .help("The root of the weeks view.")
+                .long("weeks-root")
+                .takes_value(true)
+                .default_value(data::cache::WEEKS_ROOT),
+        )
+        .arg(
+            clap::Arg::with_name("TYPE_GROUPS")
+                .help(concat!(
+                    "A file grouping media types in the type view, with one ",
+                    "line on the form \"image/x-canon-cr2 = RAW\" or ",
+                    "\"image/* = Photos\" per media type. Unmapped types are ",
+                    "grouped under Other.",
+                ))
+                .long("type-groups")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("IDENTITY")
+                .help(concat!(
+                    "How duplicate items are detected; by source path, by ",
+                    "content hash, or by timestamp and size. Only the first ",
+                    "of a set of duplicates is presented. Hashing reads every ",
+                    "file on each scan. By default, duplicates are kept.",
+                ))
+                .long("identity")
+                .takes_value(true)
+                .possible_values(&["path", "hash", "timestamp-size"]),
+        )
+        .arg(
+            clap::Arg::with_name("CHECKSUM_DEDUP_DB")
+                .help(concat!(
+                    "A file in which the content hashes used to detect ",
+                    "duplicates are stored, so that only new or changed ",
+                    "files are read again. Requires --identity hash.",
+                ))
+                .long("checksum-dedup-db")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("MERGE_TAG_CASE")
+                .help(concat!(
+                    "Merges tags differing only in case, such as beach and ",
+                    "Beach, into a directory named as the first one seen.",
+                ))
+                .long("merge-tag-case"),
+        )
+        .arg(
+            clap::Arg::with_name("ORDER_BY")
+                .help(concat!(
+                    "The order of items generated during the same second, ",
+                    "which decides their names; by source path, or by the ",
+                    "sub-second EXIF timestamp and the number in the file ",
+                    "name, to keep bursts in the order they were taken.",
+                ))
+                .long("order-by")
+                .takes_value(true)
+                .possible_values(&["path", "exif-serial"])
+                .default_value("path"),
+        )
+        .arg(
+            clap::Arg::with_name("TAG_DATE_NESTING")
+                .help(concat!(
+                    "Nests the links in every tag directory in date ",
+                    "directories, for example Tagged/Family/2020/01/02.",
+                ))
+                .long("tag-date-nesting"),
+        )
+        .arg(
+            clap::Arg::with_name("TAG_YEAR_SPLIT")
+                .help(concat!(
+                    "Groups the links of tags with more than this number of ",
+                    "items spanning several years in year directories, for ",
+                    "example Tagged/Family/2019.",
+                ))
+                .long("tag-year-split")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("TAG_LEAF_ONLY")
+                .help(concat!(
+                    "Links items only from their most specific tags, so that ",
+                    "an item tagged with both Places/France and ",
+                    "Places/France/Paris appears only under the latter.",
+                ))
+                .long("tag-leaf-only"),
+        )
+        .arg(
+            clap::Arg::with_name("NORMALIZE_EXT")
+                .help(concat!(
+                    "Treats source files whose extensions differ only in ",
+                    "case, such as IMG_0001.JPG and IMG_0001.jpg, as the ",
+                    "same item.",
+                ))
+                .long("normalize-ext"),
+        )
+        .arg(
+            clap::Arg::with_name("DIR_ATTR_TTL")
+                .help(concat!(
+                    "The number of seconds for which the attributes of ",
+                    "directories may be cached. By default they are cached ",
+                    "indefinitely.",
+                ))
+                .long("dir-attr-ttl")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("FILE_ATTR_TTL")
+                .help(concat!(
+                    "The number of seconds for which the attributes of files ",
+                    "and links may be cached. By default they are cached ",
+                    "indefinitely.",
+                ))
+                .long("file-attr-ttl")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("MAX_OPEN_FILES")
+                .help(concat!(
+                    "The maximum number of items open at the same time. ",
+                    "Opening more fails with EMFILE. By default the number ",
+                    "is limited only by the process.",
+                ))
+                .long("max-open-files")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("CHECKSUMS")
+                .help(concat!(
+                    "Exposes the SHA-256 checksum of the source file of items ",
+                    "as the extended attribute user.medifs.sha256. Checksums ",
+                    "are calculated when first read.",
+                ))
+                .long("checksums"),
+        )
+        .arg(
+            clap::Arg::with_name("DRY_RUN_WRITES")
+                .help(concat!(
+                    "Accepts tagging by linking, unlinking and moving links ",
+                    "in tag directories, but only logs the change that would ",
+                    "be written and updates the file system until the next ",
+                    "refresh. Source files are never modified.",
+                ))
+                .long("dry-run-writes"),
+        )
+        .arg(
+            clap::Arg::with_name("DIR_SIZE_COUNTS")
+                .help(concat!(
+                    "Reports the number of entries in each directory as its ",
+                    "size instead of 0.",
+                ))
+                .long("dir-size-counts"),
+        )
+        .arg(
+            clap::Arg::with_name("HTML_INDEX")
+                .help(concat!(
+                    "Adds a generated index.html to every year directory, ",
+                    "listing and linking to the items of the year grouped by ",
+                    "month.",
+                ))
+                .long("html-index"),
+        )
+        .arg(
+            clap::Arg::with_name("DISPLAY_TREE")
+                .help(concat!(
+                    "Mirrors all images under Display, where they are served ",
+                    "with their EXIF orientation applied. JPEG images are ",
+                    "transformed losslessly using jpegtran, which must be ",
+                    "installed, and other images are served unchanged.",
+                ))
+                .long("display-tree"),
+        )
+        .arg(
+            clap::Arg::with_name("DAY_COVERS")
+                .help(concat!(
+                    "Adds a cover link to every date directory containing ",
+                    "images, pointing at the image with the highest rating, ",
+                    "or the earliest one among those with the same rating.",
+                ))
+                .long("day-covers"),
+        )
+        .arg(
+            clap::Arg::with_name("QUARANTINE")
+                .help(concat!(
+                    "Links empty and unreadable files, and files whose date ",
+                    "cannot be read, from directories named after the ",
+                    "problem under Problems.",
+                ))
+                .long("quarantine"),
+        )
+        .arg(
+            clap::Arg::with_name("COLLAPSE_SINGLE")
+                .help(concat!(
+                    "Collapses date directories containing only a single ",
+                    "directory into one, so that 2020/01 becomes 2020-01 if ",
+                    "there are no other months in 2020.",
+                ))
+                .long("collapse-single"),
+        )
+        .arg(
+            clap::Arg::with_name("EXPOSE_SIDECARS")
+                .help(concat!(
+                    "Exposes .xmp and .aae sidecar files next to their ",
+                    "items, named as the item but with the extension of the ",
+                    "sidecar.",
+                ))
+                .long("expose-sidecars"),
+        )
+        .arg(
+            clap::Arg::with_name("PAGE_SIZE")
+                .help(concat!(
+                    "The maximum number of items in a date directory before ",
+                    "it is split into page directories; 0 for no limit.",
+                ))
+                .long("page-size")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("REMOVAL_GRACE")
+                .help(concat!(
+                    "The number of seconds for which items whose source ",
+                    "files have disappeared are retained, so that files ",
+                    "renamed briefly do not disappear and reappear; 0 to ",
+                    "remove them immediately.",
+                ))
+                .long("removal-grace")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::with_name("MAX_PATH_DEPTH")
+                .help(concat!(
+                    "The maximum number of components of a path in the file ",
+                    "system. Deeper paths are never found, and items that ",
+                    "would be placed below them are skipped.",
+                ))
+                .long("max-path-depth")
+                .takes_value(true)
+                .default_value("256"),
+        )
+        .with_sources()
+}
+
+/// The resolved global configuration.
+///
+/// This collects all settings not specific to a source.
+pub struct Config {
+    /// The mount point. This is only unset when probing a file.
+    pub mount_point: Option<types::MountPoint>,
+
+    /// The options passed to FUSE.
+    pub fuse_options: Vec<String>,
+
+    /// The log verbosity.
+    pub verbosity: u64,
+
+    /// Whether to run the self-check instead of mounting.
+    pub self_check: bool,
+
+    /// Whether to verify reads of a sample of files instead of mounting.
+    pub verify_reads: bool,
+
+    /// Whether to print the configuration instead of mounting.
+    pub print_config: bool,
+
+    /// Whether to print all tags instead of mounting.
+    pub list_tags: bool,
+
+    /// Whether to print a listing of all files instead of mounting.
+    pub emit_manifest: bool,
+
+    /// A file whose metadata to print instead of mounting.
+    pub probe: Option<path::PathBuf>,
+
+    /// The path of the filter configuration file.
+    pub config: Option<ffi::OsString>,
+
+    /// The filter query, both as passed and as parsed.
+    pub filter: Option<(String, data::Query)>,
+
+    /// The path of the album mapping file, and the mapping read from it.
+    pub albums: Option<(ffi::OsString, data::Albums)>,
+
+    /// The layout of the date directories.
+    pub date_layout: data::DateLayout,
+
+    /// The hour at which a day starts when filing items, or `0` to use
+    /// calendar days.
+    pub day_start: u32,
+
+    /// The separator between the levels of hierarchical tags.
+    pub tag_separator: char,
+
+    /// The root of the tree mirroring the source directory structure.
+    pub folders_root: Option<ffi::OsString>,
+
+    /// The root of the items dated by their modification time, if they are
+    /// kept apart.
+    pub modified_root: Option<ffi::OsString>,
+
+    /// The style of links in the tagged tree.
+    pub link_style: data::cache::LinkStyle,
+
+    /// The views presented under the root.
+    pub views: Vec<data::cache::View>,
+
+    /// The root of the lens view.
+    pub lenses_root: ffi::OsString,
+
+    /// The root of the size view.
+    pub sizes_root: ffi::OsString,
+
+    /// The root of the weeks view.
+    pub weeks_root: ffi::OsString,
+
+    /// The path of the media type group file, and the groups read from it.
+    pub type_groups: Option<(ffi::OsString, data::TypeGroups)>,
+
+    /// The strategy used to detect duplicate items, if any.
+    pub identity: Option<data::Identity>,
+
+    /// The path of the content hash database, and the hashes read from it.
+    pub checksum_dedup_db: Option<(ffi::OsString, data::HashDatabase)>,
+
+    /// Whether tags differing only in case are merged.
+    pub merge_tag_case: bool,
+
+    /// Whether only the most specific tags of an item produce links.
+    pub tag_leaf_only: bool,
+
+    /// Whether links in tag directories are nested in date directories.
+    pub tag_date_nesting: bool,
+
+    /// The number of items a tag spanning several years must exceed for its
+    /// links to be grouped by year, if enabled.
+    pub tag_year_split: Option<usize>,
+
+    /// Whether source file extensions differing only in case are considered
+    /// the same.
+    pub normalize_ext: bool,
+
+    /// The order of items generated during the same second.
+    pub order_by: data::cache::OrderBy,
+
+    /// Whether year directories contain a generated index.
+    pub html_index: bool,
+
+    /// Whether images are mirrored by display versions.
+    pub display_tree: bool,
+
+    /// Whether date directories contain a link to a representative image.
+    pub day_covers: bool,
+
+    /// Whether problematic source files are linked from the quarantine.
+    pub quarantine: bool,
+
+    /// Whether sidecar files are exposed next to their items.
+    pub expose_sidecars: bool,
+
+    /// Whether chains of single date directories are collapsed.
+    pub collapse_single: bool,
+
+    /// The maximum number of items in a date directory.
+    pub page_size: usize,
+
+    /// The time for which items whose source files have disappeared are
+    /// retained.
+    pub removal_grace: std::time::Duration,
+
+    /// The maximum number of components of a path in the file system.
+    pub max_depth: usize,
+
+    /// The time for which directory attributes may be cached.
+    pub directory_ttl: Option<time::Timespec>,
+
+    /// The time for which file attributes may be cached.
+    pub file_ttl: Option<time::Timespec>,
+
+    /// The maximum number of items open at the same time.
+    pub max_open_files: Option<usize>,
+
+    /// Whether checksums of source files are available.
+    pub checksums: bool,
+
+    /// Whether tagging operations are logged and applied to the cache only.
+    pub dry_run_writes: bool,
+
+    /// Whether directories report the number of their children as size.
+    pub dir_size_counts: bool,
+}
+
+impl Config {
+    /// Resolves the configuration from command line arguments.
+    ///
+    /// # Arguments
+    /// *  `matches` - The command line arguments.
+    pub fn from_matches<'a>(
+        matches: &clap::ArgMatches<'a>,
+    ) -> Result<Self, clap::Error> {
+        let invalid = |description: String| {
+            clap::Error::with_description(
+                &description,
+                clap::ErrorKind::InvalidValue,
+            )
+        };
+        let ttl = |name| {
+            matches
+                .value_of(name)
+                .map(|value| {
+                    value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|&seconds| seconds >= 0)
+                        .map(|seconds| time::Timespec::new(seconds, 0))
+                        .ok_or_else(|| {
+                            invalid(format!("invalid time to live: {}", value))
+                        })
+                })
+                .map_or(Ok(None), |ttl| ttl.map(Some))
+        };
+
+        let mount_point = matches
+            .value_of("MOUNT_POINT")
+            .map(str::parse::<types::MountPoint>)
+            .map_or(Ok(None), |mount_point| mount_point.map(Some))?;
+        let link_style = match (
+            matches.value_of("LINK_STYLE"),
+            mount_point.as_ref(),
+        ) {
+            (Some("absolute"), Some(mount_point)) => {
+                data::cache::LinkStyle::Absolute(
+                    mount_point
+                        .as_ref()
+                        .canonicalize()
+                        .unwrap_or_else(|_| mount_point.as_ref().into()),
+                )
+            }
+            _ => data::cache::LinkStyle::Relative,
+        };
+        let probe = match matches.value_of_os("PROBE") {
+            Some(path) => Some(path::Path::new(path).canonicalize().map_err(
+                |e| {
+                    invalid(format!(
+                        "invalid probe file {}: {}",
+                        path.to_string_lossy(),
+                        e
+                    ))
+                },
+            )?),
+            None => None,
+        };
+        let filter = match matches.value_of("FILTER") {
+            Some(filter) => Some((
+                filter.to_string(),
+                filter
+                    .parse::<data::Query>()
+                    .map_err(|e| invalid(format!("invalid filter: {}", e)))?,
+            )),
+            None => None,
+        };
+
+        let tag_separator = {
+            let value = matches.value_of("TAG_SEPARATOR").unwrap();
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if !c.is_whitespace() => c,
+                _ => {
+                    return Err(invalid(format!(
+                        "invalid tag separator: {}",
+                        value,
+                    )))
+                }
+            }
+        };
+
+        let albums = match matches.value_of_os("ALBUMS") {
+            Some(path) => Some((
+                path.into(),
+                fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| data::Albums::parse(&s, tag_separator))
+                    .map_err(|e| {
+                        invalid(format!(
+                            "invalid albums {}: {}",
+                            path.to_string_lossy(),
+                            e
+                        ))
+                    })?,
+            )),
+            None => None,
+        };
+
+        let type_groups = match matches.value_of_os("TYPE_GROUPS") {
+            Some(path) => Some((
+                path.into(),
+                fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| s.parse::<data::TypeGroups>())
+                    .map_err(|e| {
+                        invalid(format!(
+                            "invalid type groups {}: {}",
+                            path.to_string_lossy(),
+                            e
+                        ))
+                    })?,
+            )),
+            None => None,
+        };
+
+        let hashes = match matches.value_of_os("CHECKSUM_DEDUP_DB") {
+            Some(_) if matches.value_of("IDENTITY") != Some("hash") => {
+                return Err(invalid(
+                    "--checksum-dedup-db requires --identity hash".into(),
+                ))
+            }
+            Some(path) => Some((
+                path.into(),
+                data::HashDatabase::open(path::Path::new(path)).map_err(|e| {
+                    invalid(format!(
+                        "invalid checksum database {}: {}",
+                        path.to_string_lossy(),
+                        e
+                    ))
+                })?,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            mount_point,
+            fuse_options: matches
+                .values_of("FUSE_OPTION")
+                .map(|values| values.map(String::from).collect())
+                .unwrap_or_default(),
+            verbosity: matches.occurrences_of("VERBOSE"),
+            self_check: matches.is_present("SELF_CHECK"),
+            verify_reads: matches.is_present("VERIFY_READS"),
+            print_config: matches.is_present("PRINT_CONFIG"),
+            list_tags: matches.is_present("LIST_TAGS"),
+            emit_manifest: matches.is_present("EMIT_MANIFEST"),
+            probe,
+            config: matches.value_of_os("CONFIG").map(ffi::OsString::from),
+            filter,
+            albums,
+            date_layout: matches
+                .value_of("DATE_LAYOUT")
+                .unwrap()
+                .parse::<data::DateLayout>()
+                .map_err(|_| invalid("invalid date layout".into()))?,
+            day_start: matches
+                .value_of("MERGE_ADJACENT_DAYS")
+                .unwrap()
+                .parse::<u32>()
+                .ok()
+                .filter(|&hour| hour < 24)
+                .ok_or_else(|| invalid("invalid day start hour".into()))?,
+            tag_separator,
+            folders_root: matches
+                .value_of_os("FOLDERS_ROOT")
+                .map(ffi::OsString::from),
+            modified_root: matches
+                .value_of_os("MODIFIED_ROOT")
+                .map(ffi::OsString::from),
+            link_style,
+            views: matches
+                .values_of("VIEWS")
+                .map(|values| {
+                    values
+                        .map(str::parse::<data::cache::View>)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .unwrap_or_else(|| Ok(Vec::new()))
+                .map_err(invalid)?,
+            lenses_root: matches.value_of_os("LENSES_ROOT").unwrap().into(),
+            sizes_root: matches.value_of_os("SIZES_ROOT").unwrap().into(),
+            weeks_root: matches.value_of_os("WEEKS_ROOT").unwrap().into(),
+            type_groups,
+            identity: matches
+                .value_of("IDENTITY")
+                .map(str::parse::<data::Identity>)
+                .map_or(Ok(None), |identity| identity.map(Some))
+                .map_err(invalid)?,
+            checksum_dedup_db: hashes,
+            merge_tag_case: matches.is_present("MERGE_TAG_CASE"),
+            tag_leaf_only: matches.is_present("TAG_LEAF_ONLY"),
+            tag_date_nesting: matches.is_present("TAG_DATE_NESTING"),
+            tag_year_split: matches
+                .value_of("TAG_YEAR_SPLIT")
+                .map(|value| {
+                    value.parse::<usize>().map_err(|_| {
+                        invalid("invalid tag year split threshold".into())
+                    })
+                })
+                .map_or(Ok(None), |threshold| threshold.map(Some))?,
+            normalize_ext: matches.is_present("NORMALIZE_EXT"),
+            order_by: matches
+                .value_of("ORDER_BY")
+                .unwrap()
+                .parse()
+                .map_err(invalid)?,
+            html_index: matches.is_present("HTML_INDEX"),
+            display_tree: matches.is_present("DISPLAY_TREE"),
+            day_covers: matches.is_present("DAY_COVERS"),
+            quarantine: matches.is_present("QUARANTINE"),
+            expose_sidecars: matches.is_present("EXPOSE_SIDECARS"),
+            collapse_single: matches.is_present("COLLAPSE_SINGLE"),
+            page_size: matches
+                .value_of("PAGE_SIZE")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid page size".into()))?,
+            removal_grace: matches
+                .value_of("REMOVAL_GRACE")
+                .unwrap()
+                .parse::<u64>()
+                .map(std::time::Duration::from_secs)
+                .map_err(|_| invalid("invalid removal grace period".into()))?,
+            max_depth: matches
+                .value_of("MAX_PATH_DEPTH")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid maximum path depth".into()))?,
+            directory_ttl: ttl("DIR_ATTR_TTL")?,
+            file_ttl: ttl("FILE_ATTR_TTL")?,
+            max_open_files: matches
+                .value_of("MAX_OPEN_FILES")
+                .map(|value| {
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| invalid("invalid open file limit".into()))
+                })
+                .map_or(Ok(None), |limit| limit.map(Some))?,
+            checksums: matches.is_present("CHECKSUMS"),
+            dry_run_writes: matches.is_present("DRY_RUN_WRITES"),
+            dir_size_counts: matches.is_present("DIR_SIZE_COUNTS"),
+        })
+    }
+
+    /// Constructs the cache described by this configuration.
+    pub fn cache(&self) -> data::cache::Cache {
+        let cache = data::cache::Cache::new("All".into(), "Tagged".into())
+            .with_date_layout(self.date_layout.clone())
+            .with_day_start(self.day_start)
+            .with_page_size(self.page_size)
+            .with_removal_grace(self.removal_grace)
+            .with_max_depth(self.max_depth)
+            .with_link_style(self.link_style.clone())
+            .with_tag_separator(self.tag_separator)
+            .with_merge_tag_case(self.merge_tag_case)
+            .with_tag_leaf_only(self.tag_leaf_only)
+            .with_tag_date_nesting(self.tag_date_nesting)
+            .with_normalize_ext(self.normalize_ext)
+            .with_expose_sidecars(self.expose_sidecars)
+            .with_collapse_single(self.collapse_single)
+            .with_quarantine(self.quarantine)
+            .with_display_tree(self.display_tree)
+            .with_day_covers(self.day_covers)
+            .with_order_by(self.order_by)
+            .with_views(self.views.iter().cloned())
+            .with_lenses_root(self.lenses_root.clone())
+            .with_sizes_root(self.sizes_root.clone())
+            .with_weeks_root(self.weeks_root.clone());
+        let cache = match self.identity {
+            Some(identity) => cache.with_identity(identity),
+            None => cache,
+        };
+        let cache = match self.tag_year_split {
+            Some(threshold) => cache.with_tag_year_split(threshold),
+            None => cache,
+        };
+        let cache = if self.html_index {
+            cache.with_html_index()
+        } else {
+            cache
+        };
+        let cache = match self.albums {
+            Some((_, ref albums)) => cache.with_albums(albums.clone()),
+            None => cache,
+        };
+        let cache = match self.type_groups {
+            Some((_, ref groups)) => cache.with_type_groups(groups.clone()),
+            None => cache,
+        };
+        let cache = match self.checksum_dedup_db {
+            Some((_, ref hashes)) => cache.with_hash_database(hashes.clone()),
+            None => cache,
+        };
+        let cache = match self.modified_root {
+            Some(ref root) => cache.with_modified_root(root.clone()),
+            None => cache,
+        };
+        match self.folders_root {
+            Some(ref root) => cache.with_folders_root(root.clone()),
+            None => cache,
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    /// Formats this configuration as one `key = value` line per setting.
+    ///
+    /// Keys are named after the corresponding command line options. Unset
+    /// optional settings are omitted.
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let ttl = |ttl: &Option<time::Timespec>| match *ttl {
+            Some(ttl) => ttl.sec.to_string(),
+            None => "indefinite".into(),
+        };
+
+        if let Some(ref mount_point) = self.mount_point {
+            writeln!(
+                formatter,
+                "mount-point = {}",
+                mount_point.as_ref().display()
+            )?;
+        }
+        for option in self.fuse_options.iter() {
+            writeln!(formatter, "option = {}", option)?;
+        }
+        writeln!(formatter, "verbose = {}", self.verbosity)?;
+        if let Some(ref config) = self.config {
+            writeln!(formatter, "config = {}", config.to_string_lossy())?;
+        }
+        if let Some((ref filter, _)) = self.filter {
+            writeln!(formatter, "filter = {}", filter)?;
+        }
+        if let Some((ref path, _)) = self.albums {
+            writeln!(formatter, "albums = {}", path.to_string_lossy())?;
+        }
+        writeln!(formatter, "date-layout = {}", self.date_layout)?;
+        writeln!(formatter, "merge-adjacent-days = {}", self.day_start)?;
+        writeln!(formatter, "tag-separator = {}", self.tag_separator)?;
+        if let Some(ref root) = self.folders_root {
+            writeln!(formatter, "folders-root = {}", root.to_string_lossy())?;
+        }
+        if let Some(ref root) = self.modified_root {
+            writeln!(
+                formatter,
+                "modified-root = {}",
+                root.to_string_lossy()
+            )?;
+        }
+        match self.link_style {
+            data::cache::LinkStyle::Relative => {
+                writeln!(formatter, "link-style = relative")?
+            }
+            data::cache::LinkStyle::Absolute(ref root) => writeln!(
+                formatter,
+                "link-style = absolute ({})",
+                root.display()
+            )?,
+        }
+        writeln!(
+            formatter,
+            "views = {}",
+            self.views
+                .iter()
+                .map(|view| view.name())
+                .collect::<Vec<_>>()
+                .join(",")
+        )?;
+        writeln!(
+            formatter,
+            "lenses-root = {}",
+            self.lenses_root.to_string_lossy()
+        )?;
+        writeln!(
+            formatter,
+            "sizes-root = {}",
+            self.sizes_root.to_string_lossy()
+        )?;
+        writeln!(
+            formatter,
+            "weeks-root = {}",
+            self.weeks_root.to_string_lossy()
+        )?;
+        if let Some((ref path, _)) = self.type_groups {
+            writeln!(formatter, "type-groups = {}", path.to_string_lossy())?;
+        }
+        if let Some(identity) = self.identity {
+            writeln!(formatter, "identity = {}", identity)?;
+        }
+        if let Some((ref path, _)) = self.checksum_dedup_db {
+            writeln!(
+                formatter,
+                "checksum-dedup-db = {}",
+                path.to_string_lossy()
+            )?;
+        }
+        writeln!(formatter, "merge-tag-case = {}", self.merge_tag_case)?;
+        writeln!(formatter, "tag-leaf-only = {}", self.tag_leaf_only)?;
+        writeln!(formatter, "tag-date-nesting = {}", self.tag_date_nesting)?;
+        if let Some(threshold) = self.tag_year_split {
+            writeln!(formatter, "tag-year-split = {}", threshold)?;
+        }
+        writeln!(formatter, "normalize-ext = {}", self.normalize_ext)?;
+        writeln!(formatter, "order-by = {}", self.order_by)?;
+        writeln!(formatter, "html-index = {}", self.html_index)?;
+        writeln!(formatter, "display-tree = {}", self.display_tree)?;
+        writeln!(formatter, "day-covers = {}", self.day_covers)?;
+        writeln!(formatter, "quarantine = {}", self.quarantine)?;
+        writeln!(formatter, "expose-sidecars = {}", self.expose_sidecars)?;
+        writeln!(formatter, "collapse-single = {}", self.collapse_single)?;
+        writeln!(formatter, "page-size = {}", self.page_size)?;
+        writeln!(
+            formatter,
+            "removal-grace = {}",
+            self.removal_grace.as_secs()
+        )?;
+        writeln!(formatter, "max-path-depth = {}", self.max_depth)?;
+        writeln!(formatter, "dir-attr-ttl = {}", ttl(&self.directory_ttl))?;
+        writeln!(formatter, "file-attr-ttl = {}", ttl(&self.file_ttl))?;
+        if let Some(max_open_files) = self.max_open_files {
+            writeln!(formatter, "max-open-files = {}", max_open_files)?;
+        }
+        writeln!(formatter, "checksums = {}", self.checksums)?;
+        writeln!(formatter, "dry-run-writes = {}", self.dry_run_writes)?;
+        writeln!(formatter, "dir-size-counts = {}", self.dir_size_counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir;
+
+    use super::*;
+
+    /// Tests that non-default options appear in the printed configuration.
+    #[test]
+    fn print_non_default() {
+        let mount_point = tempdir::TempDir::new("medifs-mount").unwrap();
+        let config = Config::from_matches(&app().get_matches_from(vec![
+            "medifs",
+            "--page-size",
+            "50",
+            "--date-layout",
+            "%Y/%B/%d",
+            "--filter",
+            "tag:Family and year:2020",
+            "--dir-attr-ttl",
+            "5",
+            "--views",
+            "timestamps,camera",
+            "--sizes-root",
+            "BySize",
+            mount_point.path().to_str().unwrap(),
+            "directory",
+            "/source",
+        ])).unwrap()
+            .to_string();
+        let lines = config.lines().collect::<Vec<_>>();
+
+        assert!(lines.contains(&"page-size = 50"));
+        assert!(lines.contains(&"date-layout = %Y/%B/%d"));
+        assert!(lines.contains(&"filter = tag:Family and year:2020"));
+        assert!(lines.contains(&"dir-attr-ttl = 5"));
+        assert!(lines.contains(&"file-attr-ttl = indefinite"));
+        assert!(lines.contains(&"link-style = relative"));
+        assert!(lines.contains(&"views = timestamps,camera"));
+        assert!(lines.contains(&"sizes-root = BySize"));
+        assert!(lines.contains(&"weeks-root = Weeks"));
+    }
+
+    /// Tests that invalid values are rejected.
+    #[test]
+    fn invalid() {
+        let mount_point = tempdir::TempDir::new("medifs-mount").unwrap();
+        let config = |option, value| {
+            Config::from_matches(&app().get_matches_from(vec![
+                "medifs",
+                option,
+                value,
+                mount_point.path().to_str().unwrap(),
+                "directory",
+                "/source",
+            ]))
+        };
+
+        assert!(config("--page-size", "many").is_err());
+        assert!(config("--date-layout", "%Y").is_err());
+        assert!(config("--filter", "tag:").is_err());
+        assert!(config("--file-attr-ttl", "soon").is_err());
+        assert!(config("--tag-separator", "::").is_err());
+        assert!(config("--checksum-dedup-db", "/hashes").is_err());
+    }
+
+    /// Tests that a probed file needs no mount point and is canonicalized.
+    #[test]
+    fn probe_without_mount_point() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let file = root.path().join("test.jpg");
+        fs::write(&file, b"data").unwrap();
+        let probe = root.path().join("sub").join("..").join("test.jpg");
+        fs::create_dir(root.path().join("sub")).unwrap();
+        let matches = |probe: &path::Path| {
+            app().get_matches_from_safe(vec![
+                "medifs",
+                "--probe",
+                probe.to_str().unwrap(),
+                "directory",
+                root.path().to_str().unwrap(),
+            ])
+        };
+
+        let config = Config::from_matches(&matches(&probe).unwrap()).unwrap();
+        assert!(config.mount_point.is_none());
+        assert_eq!(Some(file.canonicalize().unwrap()), config.probe);
+        assert!(
+            Config::from_matches(
+                &matches(&root.path().join("missing.jpg")).unwrap()
+            ).is_err()
+        );
+        assert!(
+            app()
+                .get_matches_from_safe(vec![
+                    "medifs",
+                    "directory",
+                    root.path().to_str().unwrap(),
+                ])
+                .is_err()
+        );
+    }
+
+    /// Tests that read verification needs no mount point.
+    #[test]
+    fn verify_reads_without_mount_point() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let matches = app()
+            .get_matches_from_safe(vec![
+                "medifs",
+                "--verify-reads",
+                "directory",
+                root.path().to_str().unwrap(),
+            ])
+            .unwrap();
+
+        let config = Config::from_matches(&matches).unwrap();
+        assert!(config.mount_point.is_none());
+        assert!(config.verify_reads);
+    }
+}
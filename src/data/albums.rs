@@ -0,0 +1,140 @@
+use std::collections;
+use std::path;
+use std::str;
+
+use super::{Item, Tag};
+
+/// A mapping of source paths to the albums containing them.
+///
+/// A mapping is read from a configuration with one `path = album` setting per
+/// line. Empty lines and lines starting with `#` are ignored. An item is
+/// added to several albums by repeating its path, and albums are nested by
+/// separating their levels with the tag separator, as for tags.
+///
+/// Absolute paths are matched against the source path of items, and relative
+/// paths against the path relative to the source root. Since the album name
+/// follows the last `=`, paths may contain `=` but album names may not.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Albums {
+    /// The albums containing each path.
+    albums: collections::HashMap<path::PathBuf, Vec<Tag>>,
+}
+
+impl Albums {
+    /// Lists the albums containing an item.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    pub fn of(&self, item: &Item) -> Vec<&Tag> {
+        self.albums
+            .get(&item.path)
+            .into_iter()
+            .chain(item.rel_path.as_ref().and_then(|p| self.albums.get(p)))
+            .flat_map(|albums| albums.iter())
+            .collect()
+    }
+
+    /// Parses an album mapping.
+    ///
+    /// The error describes the first invalid line.
+    ///
+    /// # Arguments
+    /// *  `s` - The album mapping.
+    /// *  `separator` - The separator between the levels of albums.
+    pub fn parse(s: &str, separator: char) -> Result<Self, String> {
+        s.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|&(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .fold(Ok(Albums::default()), |acc, (number, line)| {
+                acc.and_then(|mut albums| {
+                    let mut parts = line.rsplitn(2, '=').map(str::trim);
+                    match (parts.next(), parts.next()) {
+                        (Some(album), Some(path)) if !path.is_empty() => {
+                            let album =
+                                Tag::parse(album, separator).map_err(|_| {
+                                    format!(
+                                        "invalid album on line {}: {}",
+                                        number, album
+                                    )
+                                })?;
+                            albums
+                                .albums
+                                .entry(path.into())
+                                .or_insert_with(Vec::new)
+                                .push(album);
+                        }
+                        _ => {
+                            return Err(format!(
+                                "invalid setting on line {}: {}",
+                                number, line
+                            ))
+                        }
+                    }
+                    Ok(albums)
+                })
+            })
+    }
+}
+
+impl str::FromStr for Albums {
+    type Err = String;
+
+    /// Parses an album mapping using the default tag separator.
+    ///
+    /// See [`Albums::parse`].
+    ///
+    /// [`Albums::parse`]: struct.Albums.html#method.parse
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, Tag::DEFAULT_SEPARATOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data::tests::*;
+    use super::*;
+
+    /// Tests that invalid mappings are rejected.
+    #[test]
+    fn from_str_invalid() {
+        assert!("/photo.jpg".parse::<Albums>().is_err());
+        assert!("= Favorites".parse::<Albums>().is_err());
+        assert!("/photo.jpg = Favorites/".parse::<Albums>().is_err());
+        assert!("/photo.jpg = ".parse::<Albums>().is_err());
+        assert!(Albums::parse("/photo.jpg = Favorites>", '>').is_err());
+    }
+
+    /// Tests that items are found by source path and relative path.
+    #[test]
+    fn of() {
+        let albums = concat!(
+            "# Favourites\n",
+            "/photos/a=b.jpg = Favorites/2020\n",
+            "\n",
+            "/photos/a=b.jpg = Family\n",
+            "c.jpg = Favorites\n",
+        ).parse::<Albums>()
+            .unwrap();
+        let mut relative = item("/photos/c.jpg", 2000, 1, 1);
+        relative.rel_path = Some("c.jpg".into());
+
+        assert_eq!(
+            vec![&Tag::new("Favorites/2020"), &Tag::new("Family")],
+            albums.of(&item("/photos/a=b.jpg", 2000, 1, 1)),
+        );
+        assert_eq!(vec![&Tag::new("Favorites")], albums.of(&relative));
+        assert!(albums.of(&item("/photos/d.jpg", 2000, 1, 1)).is_empty());
+    }
+
+    /// Tests that album levels are separated by the given separator.
+    #[test]
+    fn parse_separator() {
+        let albums =
+            Albums::parse("/photo.jpg = Favorites>AC/DC", '>').unwrap();
+        assert_eq!(
+            vec![&Tag::with_separator("Favorites>AC/DC", '>')],
+            albums.of(&item("/photo.jpg", 2000, 1, 1)),
+        );
+    }
+}
@@ -1,10 +1,63 @@
+use std::cmp;
 use std::collections;
 use std::ffi;
+use std::fmt;
+use std::io;
+use std::mem;
 use std::path;
+use std::str;
+use std::sync;
+use std::time as stdtime;
 
 use time;
 
 use data;
+use util;
+
+/// The directory, relative to the root of the time stamped items, of undated
+/// items.
+const UNDATED_DIRECTORY: &[&str] = &["0000", "undated"];
+
+/// The root of the items grouped by the people appearing in them.
+const PEOPLE_ROOT: &str = "People";
+
+/// The root of the items grouped by media type.
+const TYPES_ROOT: &str = "Types";
+
+/// The root of the items grouped by camera.
+const CAMERAS_ROOT: &str = "Cameras";
+
+/// The default root of the items grouped by lens.
+pub const LENSES_ROOT: &str = "Lenses";
+
+/// The directory, relative to the root of the items grouped by lens, of
+/// items without lens information.
+const UNKNOWN_LENS: &str = "Unknown";
+
+/// The default root of the items grouped by the size of their source files.
+pub const SIZES_ROOT: &str = "Sizes";
+
+/// The default root of the items grouped by ISO 8601 week.
+pub const WEEKS_ROOT: &str = "Weeks";
+
+/// The root of the items pinned to albums.
+const ALBUMS_ROOT: &str = "Albums";
+
+/// The root of the links to problematic source files.
+const QUARANTINE_ROOT: &str = "Problems";
+
+/// The root of the display versions of images.
+pub const DISPLAY_ROOT: &str = "Display";
+
+/// The name of the generated index in year directories.
+const INDEX_NAME: &str = "index.html";
+
+/// The name, without extension, of the link to the cover of a date
+/// directory.
+const COVER_NAME: &str = "cover";
+
+/// The default maximum number of components of a path in the cache.
+const DEFAULT_MAX_DEPTH: usize = 256;
 
 /// A directory tree.
 pub type Tree = collections::HashMap<ffi::OsString, Entry>;
@@ -15,26 +68,43 @@ pub type AddItemResult = Result<path::PathBuf, data::Item>;
 /// A cache entry.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Entry {
-    /// A directory entry. The value is the child entries.
-    Directory(Tree),
+    /// A directory entry. The values are the latest timestamp of any entry
+    /// below it and the child entries.
+    ///
+    /// The timestamp is maintained as entries are added, so that it need not
+    /// be calculated from the child entries.
+    Directory(time::Timespec, Tree),
 
     /// An item entry. The value is the item.
     Item(data::Item),
 
     /// A link to another item.
     Link(time::Timespec, ffi::OsString),
+
+    /// A sidecar file exposed next to its item. The values are the
+    /// timestamp of the item and the path of the sidecar file.
+    ///
+    /// Sidecars are not items; they are not listed, indexed or retained.
+    Sidecar(time::Timespec, path::PathBuf),
+
+    /// A generated index of the items in the parent directory. The value is
+    /// the latest timestamp of any item listed.
+    ///
+    /// The content is not stored, but generated when read.
+    Index(time::Timespec),
 }
 
 impl Entry {
     /// Returns the latest timestamp selected from this and child entries.
     pub fn timestamp(&self) -> time::Timespec {
         match self {
-            &Entry::Directory(ref tree) => tree.values()
-                .max_by(|a, b| a.timestamp().cmp(&b.timestamp()))
-                .map(|entry| entry.timestamp())
-                .unwrap_or(time::Timespec::new(0, 0)),
-            &Entry::Item(ref item) => item.timestamp.as_ref().to_timespec(),
-            &Entry::Link(timestamp, _) => timestamp,
+            &Entry::Directory(timestamp, _)
+            | &Entry::Link(timestamp, _)
+            | &Entry::Sidecar(timestamp, _)
+            | &Entry::Index(timestamp) => {
+                timestamp
+            }
+            Entry::Item(item) => item.timestamp.to_timespec(),
         }
     }
 
@@ -59,10 +129,239 @@ impl Entry {
     /// This has an effect only on directory entries.
     pub fn clear(&mut self) {
         match self {
-            &mut Entry::Directory(ref mut tree) => tree.clear(),
+            &mut Entry::Directory(ref mut timestamp, ref mut tree) => {
+                *timestamp = time::Timespec::new(0, 0);
+                tree.clear();
+            }
             _ => (),
         }
     }
+
+    /// Removes all empty directories below this entry.
+    ///
+    /// Directories containing only empty directories are also removed. This
+    /// has an effect only on directory entries.
+    pub fn prune(&mut self) {
+        if let &mut Entry::Directory(_, ref mut tree) = self {
+            tree.values_mut().for_each(Entry::prune);
+            tree.retain(|_, entry| match entry {
+                &mut Entry::Directory(_, ref tree) => !tree.is_empty(),
+                _ => true,
+            });
+        }
+    }
+}
+
+/// The style of link targets.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkStyle {
+    /// Links target items relative to the link directory.
+    Relative,
+
+    /// Links target items by absolute path. The value is the path at which
+    /// the cache root is accessible, typically the mount point.
+    Absolute(path::PathBuf),
+}
+
+/// A view of the items, presented under its own root.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum View {
+    /// Items in date directories. This view holds the items themselves, and
+    /// is thus always present.
+    Timestamps,
+
+    /// Links to items in directories named after their tags.
+    Tags,
+
+    /// Links to items in directories named after the people appearing in
+    /// them.
+    People,
+
+    /// Links to items in directories named after their media type and
+    /// subtype.
+    Type,
+
+    /// Links to items in directories named after the camera with which they
+    /// were generated.
+    Camera,
+
+    /// Links to items in directories named after the lens with which they
+    /// were generated.
+    Lens,
+
+    /// Links to items in directories named after the size bucket of their
+    /// source files.
+    Size,
+
+    /// Links to items in directories named after the ISO 8601 week-numbering
+    /// year and week during which they were generated.
+    Weeks,
+}
+
+impl View {
+    /// All views, in the order they are listed.
+    pub const ALL: &'static [View] = &[
+        View::Timestamps,
+        View::Tags,
+        View::People,
+        View::Type,
+        View::Camera,
+        View::Lens,
+        View::Size,
+        View::Weeks,
+    ];
+
+    /// The name of this view, as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            View::Timestamps => "timestamps",
+            View::Tags => "tags",
+            View::People => "people",
+            View::Type => "type",
+            View::Camera => "camera",
+            View::Lens => "lens",
+            View::Size => "size",
+            View::Weeks => "weeks",
+        }
+    }
+}
+
+impl fmt::Display for View {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+impl str::FromStr for View {
+    type Err = String;
+
+    /// Parses the name of a view.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        View::ALL
+            .iter()
+            .find(|view| view.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown view: {}", s))
+    }
+}
+
+/// The order of items generated during the same second.
+///
+/// This decides which item of a burst gets which index in its name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OrderBy {
+    /// Items are ordered by source path.
+    Path,
+
+    /// Items are ordered by the sub-second part of their timestamp, then by
+    /// the numbers in their file names, such as the counter assigned by
+    /// cameras to consecutive shots, and finally by source path.
+    ExifSerial,
+}
+
+impl OrderBy {
+    /// All orders.
+    pub const ALL: &'static [OrderBy] = &[OrderBy::Path, OrderBy::ExifSerial];
+
+    /// The name of this order, as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            OrderBy::Path => "path",
+            OrderBy::ExifSerial => "exif-serial",
+        }
+    }
+
+    /// Compares two items.
+    ///
+    /// Items are always ordered by timestamp first.
+    ///
+    /// # Arguments
+    /// *  `a` - The first item.
+    /// *  `b` - The second item.
+    /// *  `normalize_ext` - Whether file names are compared with their
+    ///    extensions in lower case.
+    pub fn compare(
+        &self,
+        a: &data::Item,
+        b: &data::Item,
+        normalize_ext: bool,
+    ) -> cmp::Ordering {
+        let ordering = a.timestamp.cmp(&b.timestamp);
+        match *self {
+            OrderBy::Path => ordering,
+            OrderBy::ExifSerial => ordering
+                .then_with(|| a.subsecond.cmp(&b.subsecond))
+                .then_with(|| {
+                    if normalize_ext {
+                        serial_cmp(
+                            &data::normalize_extension(&a.path),
+                            &data::normalize_extension(&b.path),
+                        )
+                    } else {
+                        serial_cmp(&a.path, &b.path)
+                    }
+                }),
+        }.then_with(|| a.path.cmp(&b.path))
+    }
+}
+
+impl Default for OrderBy {
+    /// The default order; [`OrderBy::Path`].
+    ///
+    /// [`OrderBy::Path`]: enum.OrderBy.html#variant.Path
+    fn default() -> Self {
+        OrderBy::Path
+    }
+}
+
+impl fmt::Display for OrderBy {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+impl str::FromStr for OrderBy {
+    type Err = String;
+
+    /// Parses the name of an order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OrderBy::ALL
+            .iter()
+            .find(|order_by| order_by.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown order: {}", s))
+    }
+}
+
+/// A reason for linking a source file from the quarantine.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Problem {
+    /// The time of generation of the file is unknown, and its item is
+    /// dropped.
+    NoDate,
+
+    /// The file cannot be read.
+    Unreadable,
+
+    /// The file is empty.
+    Empty,
+}
+
+impl Problem {
+    /// The name of the quarantine directory of this problem.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Problem::NoDate => "no-date",
+            Problem::Unreadable => "unreadable",
+            Problem::Empty => "empty",
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name())
+    }
 }
 
 /// A wrapper for the simple data cache.
@@ -75,6 +374,129 @@ pub struct Cache {
 
     /// The root of the tagged items.
     tagged_root: ffi::OsString,
+
+    /// The root of the time stamped items dated by their modification time,
+    /// if they are kept apart.
+    modified_root: Option<ffi::OsString>,
+
+    /// The number of links referencing each item, keyed by source path.
+    references: collections::HashMap<path::PathBuf, usize>,
+
+    /// The layout of the time stamped items.
+    layout: data::DateLayout,
+
+    /// The hour at which a day starts when filing items, or `0` to use
+    /// calendar days.
+    day_start: u32,
+
+    /// The maximum number of items in a date directory before it is split
+    /// into pages, or `0` for no limit.
+    page_size: usize,
+
+    /// The maximum number of components of a path in the cache.
+    max_depth: usize,
+
+    /// The root of the items in their original directory structure, if
+    /// enabled.
+    folders_root: Option<ffi::OsString>,
+
+    /// The style of link targets.
+    link_style: LinkStyle,
+
+    /// The separator between the levels of hierarchical tags.
+    tag_separator: char,
+
+    /// Whether tags differing only in case are merged.
+    merge_tag_case: bool,
+
+    /// The displayed form of tag directories, keyed by lower case tag
+    /// prefix. This is used only when tags differing in case are merged.
+    tag_names: collections::HashMap<String, String>,
+
+    /// Whether only the most specific tags of an item produce links.
+    tag_leaf_only: bool,
+
+    /// Whether links in tag directories are nested in date directories.
+    tag_date_nesting: bool,
+
+    /// The number of items a tag spanning several years must exceed for its
+    /// links to be grouped by year, if enabled.
+    tag_year_split: Option<usize>,
+
+    /// The directories of the tags whose links are grouped by year.
+    split_tags: collections::HashSet<path::PathBuf>,
+
+    /// The order of items generated during the same second.
+    order_by: OrderBy,
+
+    /// The enabled views.
+    views: collections::HashSet<View>,
+
+    /// The root of the items grouped by lens.
+    lenses_root: ffi::OsString,
+
+    /// The root of the items grouped by the size of their source files.
+    sizes_root: ffi::OsString,
+
+    /// The root of the items grouped by ISO 8601 week.
+    weeks_root: ffi::OsString,
+
+    /// The albums to which items are pinned.
+    albums: data::Albums,
+
+    /// The calculation of the identities used to detect duplicate items.
+    identifier: data::Identifier,
+
+    /// The paths of the added items, keyed by their identity.
+    identities: collections::HashMap<data::IdentityKey, path::PathBuf>,
+
+    /// Whether sidecar files are exposed next to their items.
+    expose_sidecars: bool,
+
+    /// Whether chains of single date directories are collapsed.
+    collapse_single: bool,
+
+    /// Whether year directories contain a generated index.
+    html_index: bool,
+
+    /// Whether problematic source files are linked from the quarantine.
+    quarantine: bool,
+
+    /// Whether images are mirrored by display versions with their
+    /// orientation applied.
+    display_tree: bool,
+
+    /// Whether date directories contain a link to a representative image.
+    day_covers: bool,
+
+    /// The name of the cover link and the cover image, keyed by the path of
+    /// the date directory.
+    covers: collections::HashMap<path::PathBuf, (ffi::OsString, data::Item)>,
+
+    /// The groups of media types in the type view. If this is not set, the
+    /// view is grouped by type and subtype.
+    type_groups: Option<data::TypeGroups>,
+
+    /// The time for which items missing when all items are replaced are
+    /// retained.
+    removal_grace: stdtime::Duration,
+
+    /// The time when each retained item first went missing, keyed by source
+    /// path.
+    vanished: collections::HashMap<path::PathBuf, stdtime::Instant>,
+
+    /// The path of each item and the index in its name, keyed by source
+    /// path. Items keep these names when all items are replaced.
+    names: collections::HashMap<path::PathBuf, (path::PathBuf, usize)>,
+
+    /// The source path of each item kept from before all items are replaced,
+    /// keyed by its previous path. This is used only while replacing all
+    /// items, to keep new items from taking these names.
+    reserved: collections::HashMap<path::PathBuf, path::PathBuf>,
+
+    /// The number of times items have been added or replaced. Content
+    /// generated from the items is valid only as long as this is unchanged.
+    generation: u64,
 }
 
 impl Cache {
@@ -83,349 +505,3726 @@ impl Cache {
         timestamp_root: ffi::OsString,
         tagged_root: ffi::OsString,
     ) -> Self {
-        let root = Entry::Directory(Tree::new());
+        let root = Entry::Directory(time::Timespec::new(0, 0), Tree::new());
         Self {
             root,
             timestamp_root,
             tagged_root,
+            modified_root: None,
+            references: collections::HashMap::new(),
+            layout: data::DateLayout::default(),
+            day_start: 0,
+            page_size: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            folders_root: None,
+            link_style: LinkStyle::Relative,
+            tag_separator: data::Tag::DEFAULT_SEPARATOR,
+            merge_tag_case: false,
+            tag_names: collections::HashMap::new(),
+            tag_leaf_only: false,
+            tag_date_nesting: false,
+            tag_year_split: None,
+            split_tags: collections::HashSet::new(),
+            order_by: OrderBy::default(),
+            views: [View::Timestamps, View::Tags].iter().cloned().collect(),
+            lenses_root: LENSES_ROOT.into(),
+            sizes_root: SIZES_ROOT.into(),
+            weeks_root: WEEKS_ROOT.into(),
+            albums: data::Albums::default(),
+            identities: collections::HashMap::new(),
+            identifier: data::Identifier::default(),
+            expose_sidecars: false,
+            collapse_single: false,
+            html_index: false,
+            quarantine: false,
+            display_tree: false,
+            day_covers: false,
+            covers: collections::HashMap::new(),
+            type_groups: None,
+            removal_grace: stdtime::Duration::from_secs(0),
+            vanished: collections::HashMap::new(),
+            names: collections::HashMap::new(),
+            reserved: collections::HashMap::new(),
+            generation: 0,
         }
     }
 
-    /// Finds an entry by path.
+    /// Sets the layout of the time stamped items.
     ///
     /// # Arguments
-    /// *  `path` - The path of the entry. This must be an absolute path.
-    pub fn lookup<P: AsRef<path::Path>>(&self, path: &P) -> Option<&Entry> {
-        path.as_ref().components().fold(
-            Some(&self.root),
-            |acc, part| match part {
-                // The root will be the first component, so we return the
-                // initial value unchanged, and for the current directory we do
-                // the same
-                path::Component::RootDir | path::Component::CurDir => acc,
-
-                // Go deeper for normal files
-                path::Component::Normal(path) => {
-                    acc.and_then(|entry| match entry {
-                        &Entry::Directory(ref tree) => {
-                            tree.get(path.into()).clone()
-                        }
-                        _ => None,
-                    })
-                }
-
-                // We support only absolute paths without special directories
-                _ => None,
-            },
-        )
+    /// *  `layout` - The date directory layout.
+    pub fn with_date_layout(mut self, layout: data::DateLayout) -> Self {
+        self.layout = layout;
+        self
     }
 
-    /// Adds an item to the file system.
-    ///
-    /// On success, the path of the new item is returned.
+    /// Sets the hour at which a day starts when filing items.
     ///
-    /// This method will fail if an item named after the generated parent
-    /// directory for `item` exists and is not a directory.
+    /// Items generated after midnight but before this hour are filed in the
+    /// date directories of the previous day, so that a night of shooting
+    /// stays in one directory. Their names still use their actual time.
     ///
     /// # Arguments
-    /// *  `item` - The item to add.
-    pub fn add(&mut self, item: data::Item) -> AddItemResult {
-        let directory: path::PathBuf = {
-            let base: &path::Path = self.timestamp_root.as_ref();
-            [
-                base,
-                format!("{}", item.timestamp.year()).as_ref(),
-                format!("{:02}", item.timestamp.month()).as_ref(),
-                format!("{:02}", item.timestamp.day()).as_ref(),
-            ].iter()
-                .collect()
-        };
-
-        let tags = item.tags.clone();
-        self.add_item(directory, item.clone()).and_then(|path| {
-            for tag in tags {
-                let directory = path::PathBuf::from(&self.tagged_root)
-                    .join(path::PathBuf::from(tag));
-                self.add_link(&directory, &path, &item);
-            }
-
-            Ok(path)
-        })
+    /// *  `day_start` - The hour, or `0` to use calendar days.
+    pub fn with_day_start(mut self, day_start: u32) -> Self {
+        self.day_start = day_start;
+        self
     }
 
-    /// Adds a sequence of items to the file system.
+    /// Sets the maximum number of items in a date directory.
     ///
-    /// This method will fail if an item named after the generated parent
-    /// directory for any item exists and is not a directory.
+    /// When all items are replaced, date directories containing more items
+    /// than this are split into the subdirectories `page-001`, `page-002` and
+    /// so on, each containing at most `page_size` items in chronological
+    /// order.
     ///
     /// # Arguments
-    /// *  `items` - The items to add.
-    pub fn add_iter<T: Iterator<Item = data::Item>>(
-        &mut self,
-        items: T,
-    ) -> Result<(), Option<data::Item>> {
-        items.fold(Ok(()), |acc, item| {
-            acc.and_then(|_| {
-                self.add(item).map(|_| ()).map_err(|item| Some(item))
-            })
-        })
+    /// *  `page_size` - The maximum number of items, or `0` for no limit.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
     }
 
-    /// Replaces all items in the file system.
+    /// Sets the maximum number of components of a path in the cache.
     ///
-    /// This method will fail with `Err(None)` if the lock cannot be taken, or
-    /// with `Err(item)` for an item that cannot be added.
+    /// Looking up a deeper path fails without traversing the tree, and items
+    /// and links that would be added below it are rejected.
     ///
     /// # Arguments
-    /// *  `items` - The items to add.
-    pub fn replace_all<T: Iterator<Item = data::Item>>(
-        &mut self,
-        items: T,
-    ) -> Result<(), Option<data::Item>> {
-        self.root.clear();
-        items.fold(Ok(()), |acc, item| {
-            acc.and_then(|_| {
-                self.add(item).map(|_| ()).map_err(|item| Some(item))
-            })
-        })
+    /// *  `max_depth` - The maximum number of components.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
     }
 
-    /// Adds a single item to the file system.
-    ///
-    /// On success, the path of the new item is returned.
+    /// Keeps items dated by their modification time apart from the other
+    /// time stamped items.
     ///
-    /// This method will fail if an item named after the generated parent
-    /// directory for `item` exists and is not a directory.
+    /// When set, items whose timestamp is not read from their metadata are
+    /// added below `modified_root` instead of the root of the time stamped
+    /// items, using the same date layout. Undated items are not affected.
     ///
     /// # Arguments
-    /// *  `item` - The item to add.
-    fn add_item<P: AsRef<path::Path>>(
-        &mut self,
-        directory: P,
-        item: data::Item,
-    ) -> AddItemResult {
-        if let Some(&mut Entry::Directory(ref mut tree)) =
-            self.assert_exists(&directory)
-        {
-            Ok(Self::add_with_index(&directory, tree, Entry::Item(item)))
-        } else {
-            Err(item)
-        }
+    /// *  `modified_root` - The root of the items, for example `NoExif`.
+    pub fn with_modified_root(mut self, modified_root: ffi::OsString) -> Self {
+        self.modified_root = Some(modified_root);
+        self
     }
 
-    /// Adds a link to an item to the file system.
+    /// Enables the tree mirroring the source directory structure.
+    ///
+    /// Items with a relative path are added below `folders_root` using that
+    /// path, and thus keep their original file names.
     ///
     /// # Arguments
-    /// *  `tag` - The item tag.
-    /// *  `path` - The target path.
-    fn add_link<P: AsRef<path::Path>>(
-        &mut self,
-        directory: P,
-        path: P,
-        item: &data::Item,
-    ) {
-        let directory: &path::Path = directory.as_ref();
-        let path: &path::Path = path.as_ref();
+    /// *  `folders_root` - The root of the tree.
+    pub fn with_folders_root(mut self, folders_root: ffi::OsString) -> Self {
+        self.folders_root = Some(folders_root);
+        self
+    }
 
-        // TODO: Rename item and try again?
-        if let Some(&mut Entry::Directory(ref mut tree)) =
-            self.assert_exists(&directory)
-        {
-            let mut relative = {
-                let mut relative = path::PathBuf::new();
-                for _ in directory.components() {
-                    relative.push("..");
-                }
-                relative.push(path);
-                relative
-            };
-            Self::add_with_index(
-                &directory,
-                tree,
-                Entry::Link(
-                    item.timestamp.as_ref().to_timespec(),
-                    relative.into_os_string(),
-                ),
-            );
-        }
+    /// Sets the style of link targets.
+    ///
+    /// # Arguments
+    /// *  `link_style` - The link style.
+    pub fn with_link_style(mut self, link_style: LinkStyle) -> Self {
+        self.link_style = link_style;
+        self
     }
 
-    /// Adds an item under a tree by incrementing an index until a unique name
-    /// is found.
+    /// Sets the separator between the levels of hierarchical tags.
     ///
-    /// If the first attempt succeeds, no index is added.
+    /// Tags are split on this separator into nested tag directories.
     ///
     /// # Arguments
-    /// *  `directory` - The path of the directory tree.
-    /// *  `tree` - The directory tree to which to add the item.
-    /// *  `entry` - The entry to add. This must not be a directory entry.
+    /// *  `tag_separator` - The separator.
+    pub fn with_tag_separator(mut self, tag_separator: char) -> Self {
+        self.tag_separator = tag_separator;
+        self
+    }
+
+    /// Sets whether tags differing only in case are merged.
     ///
-    /// # Panics
-    /// This method will panic if passed as directory entry.
-    fn add_with_index<P: AsRef<path::Path>>(
-        directory: &P,
-        tree: &mut Tree,
-        entry: Entry,
-    ) -> path::PathBuf {
-        let directory: &path::Path = directory.as_ref();
-        let mut index = 0;
+    /// When enabled, links for tags such as `beach` and `Beach` are added to
+    /// the same directory, named using the casing first seen.
+    ///
+    /// # Arguments
+    /// *  `merge_tag_case` - Whether to merge tags.
+    pub fn with_merge_tag_case(mut self, merge_tag_case: bool) -> Self {
+        self.merge_tag_case = merge_tag_case;
+        self
+    }
 
-        // Construct a suitable name
-        let name = loop {
-            let name = entry.name(index).as_os_str().to_os_string();
-            if !tree.contains_key(&name) {
-                break name;
-            } else {
-                index += 1;
-            }
-        };
+    /// Sets whether only the most specific tags of an item produce links.
+    ///
+    /// When enabled, an item tagged with both `Places/France` and
+    /// `Places/France/Paris` is linked only from the directory of the latter.
+    ///
+    /// # Arguments
+    /// *  `tag_leaf_only` - Whether to skip parent tags.
+    pub fn with_tag_leaf_only(mut self, tag_leaf_only: bool) -> Self {
+        self.tag_leaf_only = tag_leaf_only;
+        self
+    }
 
-        tree.insert(name.clone(), entry);
-        directory.join(name)
+    /// Sets whether links in tag directories are nested in date directories.
+    ///
+    /// When enabled, the link to an item tagged with `Family` is added to
+    /// `Family/2020/01/02` below the root of the tagged items, using the
+    /// date layout, rather than directly to `Family`.
+    ///
+    /// # Arguments
+    /// *  `tag_date_nesting` - Whether to nest links.
+    pub fn with_tag_date_nesting(mut self, tag_date_nesting: bool) -> Self {
+        self.tag_date_nesting = tag_date_nesting;
+        self
     }
 
-    /// Asserts that a path exists.
+    /// Sets the threshold for grouping links in tag directories by year.
     ///
-    /// All missing parents will be created. If a non-directory entry is
-    /// encountered along the way, except for the final component, `None` is
-    /// returned.
+    /// When set, the links of a tag with more than `threshold` items spanning
+    /// several years are added to year directories, such as `Family/2019`
+    /// below the root of the tagged items. Links of other tags, and of
+    /// undated items, remain directly in the tag directory. The tags to split
+    /// are selected when all items are replaced.
     ///
-    /// If the final part has to be created, it will be created as a directory.
+    /// This has no effect if links are nested in date directories.
     ///
     /// # Arguments
-    /// *  `path` - The full path.
-    fn assert_exists<P: AsRef<path::Path>>(
-        &mut self,
-        path: &P,
-    ) -> Option<&mut Entry> {
-        path.as_ref()
-            .components()
-            .fold(Some(&mut self.root), |acc, part| match part {
+    /// *  `threshold` - The number of items a tag must exceed.
+    pub fn with_tag_year_split(mut self, threshold: usize) -> Self {
+        self.tag_year_split = Some(threshold);
+        self
+    }
+
+    /// Sets the order of items generated during the same second.
+    ///
+    /// The order is applied when all items are replaced.
+    ///
+    /// # Arguments
+    /// *  `order_by` - The order.
+    pub fn with_order_by(mut self, order_by: OrderBy) -> Self {
+        self.order_by = order_by;
+        self
+    }
+
+    /// Sets the enabled views.
+    ///
+    /// By default, the timestamps and tags views are enabled. The timestamps
+    /// view is always enabled, since the other views link to its items.
+    ///
+    /// # Arguments
+    /// *  `views` - The views to enable.
+    pub fn with_views<T: IntoIterator<Item = View>>(
+        mut self,
+        views: T,
+    ) -> Self {
+        self.views = views.into_iter().collect();
+        self.views.insert(View::Timestamps);
+        self
+    }
+
+    /// Sets the root of the items grouped by lens.
+    ///
+    /// By default, this is [`LENSES_ROOT`].
+    ///
+    /// # Arguments
+    /// *  `lenses_root` - The root of the items, for example `ByLens`.
+    ///
+    /// [`LENSES_ROOT`]: constant.LENSES_ROOT.html
+    pub fn with_lenses_root(mut self, lenses_root: ffi::OsString) -> Self {
+        self.lenses_root = lenses_root;
+        self
+    }
+
+    /// Sets the root of the items grouped by the size of their source files.
+    ///
+    /// By default, this is [`SIZES_ROOT`].
+    ///
+    /// # Arguments
+    /// *  `sizes_root` - The root of the items, for example `BySize`.
+    ///
+    /// [`SIZES_ROOT`]: constant.SIZES_ROOT.html
+    pub fn with_sizes_root(mut self, sizes_root: ffi::OsString) -> Self {
+        self.sizes_root = sizes_root;
+        self
+    }
+
+    /// Sets the root of the items grouped by ISO 8601 week.
+    ///
+    /// By default, this is [`WEEKS_ROOT`].
+    ///
+    /// # Arguments
+    /// *  `weeks_root` - The root of the items, for example `ByWeek`.
+    ///
+    /// [`WEEKS_ROOT`]: constant.WEEKS_ROOT.html
+    pub fn with_weeks_root(mut self, weeks_root: ffi::OsString) -> Self {
+        self.weeks_root = weeks_root;
+        self
+    }
+
+    /// Sets the albums to which items are pinned.
+    ///
+    /// Links to items in an album are added below `Albums`, regardless of
+    /// the enabled views.
+    ///
+    /// # Arguments
+    /// *  `albums` - The album mapping.
+    pub fn with_albums(mut self, albums: data::Albums) -> Self {
+        self.albums = albums;
+        self
+    }
+
+    /// Sets the strategy used to detect duplicate items.
+    ///
+    /// An item that is a duplicate of an item already added is not added
+    /// again; instead the path of the existing item is returned. This must be
+    /// set before any items are added.
+    ///
+    /// The identities of items are calculated by their sources using the
+    /// [`identifier`](#method.identifier), since that may read the source
+    /// files; items without a calculated identity are compared by their
+    /// source paths.
+    ///
+    /// # Arguments
+    /// *  `identity` - The identity strategy.
+    pub fn with_identity(mut self, identity: data::Identity) -> Self {
+        self.identifier.identity = Some(identity);
+        self
+    }
+
+    /// Sets the database of checksums used to detect duplicates by content.
+    ///
+    /// Checksums of unchanged files are read from the database instead of
+    /// being calculated again, and the database is saved every time all
+    /// items are replaced. This has an effect only for the
+    /// [`Hash`](../enum.Identity.html#variant.Hash) identity strategy.
+    ///
+    /// # Arguments
+    /// *  `hashes` - The checksum database.
+    pub fn with_hash_database(mut self, hashes: data::HashDatabase) -> Self {
+        self.identifier.hashes =
+            Some(sync::Arc::new(sync::Mutex::new(hashes)));
+        self
+    }
+
+    /// Sets whether source file extensions differing only in case are
+    /// considered the same.
+    ///
+    /// When enabled, `IMG_0001.JPG` and `IMG_0001.jpg` in the same directory
+    /// are the same item, and only the first one is added. If no identity
+    /// strategy is set, items are compared by their source paths. This must
+    /// be set before any items are added.
+    ///
+    /// # Arguments
+    /// *  `normalize_ext` - Whether to normalise extensions.
+    pub fn with_normalize_ext(mut self, normalize_ext: bool) -> Self {
+        self.identifier.normalize_ext = normalize_ext;
+        self
+    }
+
+    /// Sets whether sidecar files are exposed next to their items.
+    ///
+    /// When enabled, an `.xmp` or `.aae` file sharing the file stem of the
+    /// source file of an item is added to the date directory as an item
+    /// named as the item, but with the extension of the sidecar.
+    ///
+    /// # Arguments
+    /// *  `expose_sidecars` - Whether to expose sidecar files.
+    pub fn with_expose_sidecars(mut self, expose_sidecars: bool) -> Self {
+        self.expose_sidecars = expose_sidecars;
+        self
+    }
+
+    /// Sets whether chains of single date directories are collapsed.
+    ///
+    /// When enabled, a directory below the root of the time stamped items
+    /// containing nothing but a single directory is merged with it, and named
+    /// after both joined by `"-"`, so that `2020/01` becomes `2020-01` if
+    /// there are no other months in 2020. Links are updated to target the
+    /// new paths.
+    ///
+    /// The directories are collapsed when all items are replaced.
+    ///
+    /// # Arguments
+    /// *  `collapse_single` - Whether to collapse directories.
+    pub fn with_collapse_single(mut self, collapse_single: bool) -> Self {
+        self.collapse_single = collapse_single;
+        self
+    }
+
+    /// Adds a generated index, `index.html`, to every year directory of the
+    /// time stamped items.
+    ///
+    /// The index lists and links to all items in the year; its content is
+    /// generated when read.
+    pub fn with_html_index(mut self) -> Self {
+        self.html_index = true;
+        self
+    }
+
+    /// Groups the type view by friendly groups rather than by media type.
+    ///
+    /// Links are added to a directory named after the group of the media
+    /// type of an item, rather than to nested directories named after the
+    /// type and subtype.
+    ///
+    /// # Arguments
+    /// *  `type_groups` - The groups of media types.
+    pub fn with_type_groups(mut self, type_groups: data::TypeGroups) -> Self {
+        self.type_groups = Some(type_groups);
+        self
+    }
+
+    /// Sets the time for which missing items are retained.
+    ///
+    /// When all items are replaced, items that were present before but are
+    /// missing from the replacement are kept until they have been missing for
+    /// this long. This prevents a source file that is briefly renamed, for
+    /// example while being saved, from disappearing and reappearing. Items
+    /// whose source file was listed by the scan, or was renamed to a path in
+    /// the replacement, are not retained; see
+    /// [`replace_listed`](#method.replace_listed).
+    ///
+    /// # Arguments
+    /// *  `removal_grace` - The grace period, or zero to remove missing items
+    ///    immediately.
+    pub fn with_removal_grace(
+        mut self,
+        removal_grace: stdtime::Duration,
+    ) -> Self {
+        self.removal_grace = removal_grace;
+        self
+    }
+
+    /// Sets whether problematic source files are linked from the quarantine.
+    ///
+    /// When enabled, the files passed to
+    /// [`add_problem`](#method.add_problem) are linked from a directory
+    /// named after their problem under [`QUARANTINE_ROOT`].
+    ///
+    /// # Arguments
+    /// *  `quarantine` - Whether to link problematic files.
+    ///
+    /// [`QUARANTINE_ROOT`]: constant.QUARANTINE_ROOT.html
+    pub fn with_quarantine(mut self, quarantine: bool) -> Self {
+        self.quarantine = quarantine;
+        self
+    }
+
+    /// Sets whether images are mirrored by display versions.
+    ///
+    /// When enabled, every image is also added below [`DISPLAY_ROOT`] using
+    /// its path relative to the root of the time stamped items. The file
+    /// system serves these entries with the orientation of the image
+    /// applied, so that they appear correctly in viewers ignoring it.
+    ///
+    /// # Arguments
+    /// *  `display_tree` - Whether to add display versions.
+    ///
+    /// [`DISPLAY_ROOT`]: constant.DISPLAY_ROOT.html
+    pub fn with_display_tree(mut self, display_tree: bool) -> Self {
+        self.display_tree = display_tree;
+        self
+    }
+
+    /// Sets whether date directories contain a link to a representative
+    /// image.
+    ///
+    /// When enabled, every date directory containing images also contains a
+    /// link named `cover` with the extension of the linked image. The cover
+    /// is the image with the highest rating, or the earliest one among those
+    /// with the same rating.
+    ///
+    /// # Arguments
+    /// *  `day_covers` - Whether to add cover links.
+    pub fn with_day_covers(mut self, day_covers: bool) -> Self {
+        self.day_covers = day_covers;
+        self
+    }
+
+    /// Returns whether a path is a display version of an image.
+    ///
+    /// # Arguments
+    /// *  `path` - The path to check.
+    pub fn is_display(&self, path: &path::Path) -> bool {
+        self.display_tree
+            && path.components()
+                .filter_map(|part| match part {
+                    path::Component::Normal(name) => Some(name),
+                    _ => None,
+                })
+                .next() == Some(ffi::OsStr::new(DISPLAY_ROOT))
+    }
+
+    /// Returns the number of links and skipped duplicates referencing an
+    /// item.
+    ///
+    /// # Arguments
+    /// *  `item` - The item for which to count references.
+    pub fn references(&self, item: &data::Item) -> usize {
+        self.references.get(&item.path).cloned().unwrap_or(0)
+    }
+
+    /// Finds an entry by path.
+    ///
+    /// The path is normalized first, so that `.` components are dropped and
+    /// `..` components remove the preceding component.
+    ///
+    /// If the path is deeper than the maximum depth, `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the entry. This must be an absolute path.
+    pub fn lookup<P: AsRef<path::Path>>(&self, path: &P) -> Option<&Entry> {
+        let path = normalize(path.as_ref());
+        if self.depth(&path) > self.max_depth {
+            return None;
+        }
+        path.components().fold(
+            Some(&self.root),
+            |acc, part| match part {
                 // The root will be the first component, so we return the
-                // initial value unchanged, and for the current directory we do
-                // the same
-                path::Component::RootDir | path::Component::CurDir => acc,
+                // initial value unchanged; special directories have been
+                // normalized away
+                path::Component::RootDir => acc,
+
+                // Go deeper for normal files
+                path::Component::Normal(path) => {
+                    acc.and_then(|entry| match entry {
+                        Entry::Directory(_, tree) => {
+                            tree.get(path)
+                        }
+                        _ => None,
+                    })
+                }
+
+                // We do not support path prefixes
+                _ => None,
+            },
+        )
+    }
+
+    /// The separator between the levels of hierarchical tags.
+    pub fn tag_separator(&self) -> char {
+        self.tag_separator
+    }
+
+    /// The number of times items have been added or replaced.
+    ///
+    /// Content generated from the items, such as indices, may be kept until
+    /// this changes.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the calculation of the identities used to detect duplicate
+    /// items.
+    ///
+    /// Sources use this to calculate the identities of items before they are
+    /// added, without holding the cache lock.
+    pub fn identifier(&self) -> data::Identifier {
+        self.identifier.clone()
+    }
+
+    /// Lists all items.
+    ///
+    /// The items are those in the trees of time stamped items, in no
+    /// particular order.
+    pub fn items(&self) -> Vec<&data::Item> {
+        let root = path::Path::new("/");
+        let mut result = Vec::new();
+        let mut stack = Some(&self.timestamp_root)
+            .into_iter()
+            .chain(self.modified_root.as_ref())
+            .filter_map(|name| self.lookup(&root.join(name)))
+            .collect::<Vec<_>>();
+        while let Some(entry) = stack.pop() {
+            match entry {
+                Entry::Directory(_, tree) => stack.extend(tree.values()),
+                Entry::Item(item) => result.push(item),
+                &Entry::Link(_, _)
+                | &Entry::Sidecar(_, _)
+                | &Entry::Index(_) => (),
+            }
+        }
+        result
+    }
+
+    /// Finds the item referenced by an entry.
+    ///
+    /// For item entries, the item itself is returned, and for links the item
+    /// targeted by the link. For directories, sidecars and dangling links,
+    /// `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the entry. This must be an absolute path.
+    pub fn resolve<P: AsRef<path::Path>>(
+        &self,
+        path: &P,
+    ) -> Option<&data::Item> {
+        let path: &path::Path = path.as_ref();
+        let target = match self.lookup(&path)? {
+            Entry::Item(item) => return Some(item),
+            Entry::Link(_, target) => path::Path::new(target),
+            &Entry::Directory(_, _)
+            | &Entry::Sidecar(_, _)
+            | &Entry::Index(_) => return None,
+        };
+        match self.lookup(&self.target(&path, target)?)? {
+            Entry::Item(item) => Some(item),
+            _ => None,
+        }
+    }
+
+    /// Finds the path targeted by a link.
+    ///
+    /// The target is interpreted according to the link style. Neither the
+    /// link nor the target need exist.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the link. This must be an absolute path.
+    /// *  `target` - The target of the link.
+    pub fn target<P: AsRef<path::Path>>(
+        &self,
+        path: &P,
+        target: &path::Path,
+    ) -> Option<path::PathBuf> {
+        let target = match self.link_style {
+            LinkStyle::Relative => path.as_ref().parent()?.join(target),
+            LinkStyle::Absolute(ref root) => {
+                path::Path::new("/").join(target.strip_prefix(root).ok()?)
+            }
+        };
+        Some(normalize(&target))
+    }
+
+    /// Tags an item and links it from the directory of the tag.
+    ///
+    /// Only the cache is updated, so the tag is lost when the items are next
+    /// replaced. On success, the path of the new link and the tag are
+    /// returned.
+    ///
+    /// This method fails if `directory` is not a tag directory, if `path` is
+    /// not the path of an item or if the item already has the tag.
+    ///
+    /// # Arguments
+    /// *  `directory` - The tag directory. This must be an absolute path.
+    /// *  `path` - The path of the item. This must be an absolute path.
+    pub fn add_tag<P: AsRef<path::Path>, Q: AsRef<path::Path>>(
+        &mut self,
+        directory: &P,
+        path: &Q,
+    ) -> Option<(path::PathBuf, String)> {
+        let tag = self.tag_of(directory)?;
+        let path = path.as_ref().to_path_buf();
+        let mut item = match self.lookup(&path)? {
+            Entry::Item(item) => item.clone(),
+            _ => return None,
+        };
+        if !item.tags.insert(tag.clone()) {
+            return None;
+        }
+        let directory = self.tag_directory(tag.clone())?;
+
+        self.update_items(&item.path, |i| {
+            i.tags.insert(tag.clone());
+        });
+        self.add_link(&directory, &path, &item)
+            .map(|link| (path::Path::new("/").join(link), tag))
+    }
+
+    /// Removes a link from a tag directory, and the tag from the linked item.
+    ///
+    /// Only the cache is updated, so the tag is restored when the items are
+    /// next replaced. On success, the untagged item and the tag are returned.
+    ///
+    /// This method fails if `path` is not a link in a tag directory.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the link. This must be an absolute path.
+    pub fn remove_tag<P: AsRef<path::Path>>(
+        &mut self,
+        path: &P,
+    ) -> Option<(data::Item, String)> {
+        let path = path.as_ref();
+        let (directory, name) = (path.parent()?, path.file_name()?);
+        let mut item = match self.lookup(&path)? {
+            &Entry::Link(_, _) => self.resolve(&path)?.clone(),
+            _ => return None,
+        };
+        let tag = if self.tag_date_nesting {
+            let depth = self.date_directories(path::PathBuf::new(), &item)
+                .components()
+                .count();
+            self.tag_of(&directory.ancestors().nth(depth)?)?
+        } else {
+            match directory.parent() {
+                Some(parent)
+                    if parent
+                        .strip_prefix("/")
+                        .is_ok_and(|p| self.split_tags.contains(p))
+                        && !item.timestamp.is_undated()
+                        && directory.file_name()
+                            == Some(
+                                year_name(&self.filed_timestamp(&item))
+                                    .as_ref(),
+                            ) =>
+                {
+                    self.tag_of(&parent)?
+                }
+                _ => self.tag_of(&directory)?,
+            }
+        };
+        let merge_tag_case = self.merge_tag_case;
+        let tag = item.tags
+            .iter()
+            .find(|t| {
+                **t == tag
+                    || merge_tag_case && t.to_lowercase() == tag.to_lowercase()
+            })
+            .cloned()?;
+
+        if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            self.assert_exists(&directory)
+        {
+            tree.remove(name);
+        }
+        if let Some(references) = self.references.get_mut(&item.path) {
+            *references -= 1;
+        }
+        self.update_items(&item.path, |i| {
+            i.tags.remove(&tag);
+        });
+        item.tags.remove(&tag);
+
+        // The removed link may have been the latest one
+        self.root.prune();
+        let names = directory
+            .components()
+            .filter_map(|part| match part {
+                path::Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        Self::retime(&mut self.root, &names);
+
+        Some((item, tag))
+    }
+
+    /// Finds the tag of a tag directory.
+    ///
+    /// If the tags view is not enabled, or `directory` is not below the tag
+    /// root, `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `directory` - The path of the directory. This must be an absolute
+    ///    path.
+    fn tag_of<P: AsRef<path::Path>>(&self, directory: &P) -> Option<String> {
+        if !self.views.contains(&View::Tags) {
+            return None;
+        }
+        let parts = directory
+            .as_ref()
+            .strip_prefix("/")
+            .ok()?
+            .strip_prefix(&self.tagged_root)
+            .ok()?
+            .components()
+            .map(|part| match part {
+                path::Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(&self.tag_separator.to_string()))
+        }
+    }
+
+    /// Applies a change to every copy of an item.
+    ///
+    /// Items are present both in the tree of time stamped items and, if
+    /// enabled, the tree mirroring the source directory structure.
+    ///
+    /// # Arguments
+    /// *  `source` - The source path of the item.
+    /// *  `update` - The change to apply.
+    fn update_items<F: FnMut(&mut data::Item)>(
+        &mut self,
+        source: &path::Path,
+        mut update: F,
+    ) {
+        let mut stack = vec![&mut self.root];
+        while let Some(entry) = stack.pop() {
+            match *entry {
+                Entry::Directory(_, ref mut tree) => {
+                    stack.extend(tree.values_mut())
+                }
+                Entry::Item(ref mut item) if item.path == source => {
+                    update(item)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Adds an item to the file system.
+    ///
+    /// On success, the path of the new item is returned.
+    ///
+    /// This method will fail if an item named after the generated parent
+    /// directory for `item` exists and is not a directory.
+    ///
+    /// # Arguments
+    /// *  `item` - The item to add.
+    pub fn add(&mut self, item: data::Item) -> AddItemResult {
+        let directory = self.directory(&item);
+        self.add_to(directory, item)
+    }
+
+    /// Adds an item to a specific date directory.
+    ///
+    /// Links are added to the directories of the item in all other enabled
+    /// views. If the item is a duplicate of an item already added, nothing
+    /// is added, the duplicate is counted as a reference to the existing
+    /// item, and the path of the existing item is returned.
+    ///
+    /// # Arguments
+    /// *  `directory` - The directory to which to add the item.
+    /// *  `item` - The item to add.
+    fn add_to(
+        &mut self,
+        directory: path::PathBuf,
+        item: data::Item,
+    ) -> AddItemResult {
+        self.generation += 1;
+        let key = self.identity_key(&item);
+        if let Some(path) = key.as_ref().and_then(|k| self.identities.get(k)) {
+            trace!("Skipping duplicate {}", item.path.display());
+
+            // The duplicate references the existing item
+            let path = path.clone();
+            let source = match self.lookup(&path) {
+                Some(Entry::Item(existing)) => existing.path.clone(),
+                _ => return Ok(path),
+            };
+            *self.references.entry(source).or_insert(0) += 1;
+            return Ok(path);
+        }
+
+        self.add_item(directory, item.clone()).inspect(|path| {
+            if let Some(key) = key {
+                self.identities.insert(key, path.clone());
+            }
+            if self.expose_sidecars {
+                self.add_sidecars(path, &item);
+            }
+            for directory in self.view_directories(&item) {
+                self.add_link(&directory, path, &item);
+            }
+            if self.html_index && !item.timestamp.is_undated() {
+                self.add_index(&item);
+            }
+            if self.display_tree && item.media_type.type_() == "image" {
+                self.add_display(path, item.clone());
+            }
+            if self.day_covers
+                && !item.timestamp.is_undated()
+                && item.media_type.type_() == "image"
+            {
+                self.add_cover(path, &item);
+            }
+            self.add_folder(item);
+        })
+    }
+
+    /// Finds the identity of an item, if duplicates are detected.
+    ///
+    /// The identity calculated by the source is used if present; otherwise
+    /// the source path is used, so that no source file is read while the
+    /// cache is locked.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    fn identity_key(&self, item: &data::Item) -> Option<data::IdentityKey> {
+        if self.identifier.is_enabled() {
+            item.identity
+                .clone()
+                .or_else(|| self.identifier.path_key(item))
+        } else {
+            None
+        }
+    }
+
+    /// Generates the directories for links to an item in all enabled views
+    /// except the timestamps view, and in its albums.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    fn view_directories(&mut self, item: &data::Item) -> Vec<path::PathBuf> {
+        let mut result = Vec::new();
+        if self.views.contains(&View::Tags) {
+            for tag in item.tags.iter() {
+                if self.tag_leaf_only && !self.is_leaf(tag, item) {
+                    continue;
+                }
+                if let Some(directory) = self.tag_directory(tag.clone()) {
+                    result.push(if self.tag_date_nesting {
+                        self.date_directories(directory, item)
+                    } else if self.split_tags.contains(&directory)
+                        && !item.timestamp.is_undated()
+                    {
+                        directory.join(year_name(&self.filed_timestamp(item)))
+                    } else {
+                        directory
+                    });
+                }
+            }
+        }
+        if self.views.contains(&View::People) {
+            result.extend(
+                item.people
+                    .iter()
+                    .filter_map(|person| directory_name(person))
+                    .map(|person| path::Path::new(PEOPLE_ROOT).join(person)),
+            );
+        }
+        if self.views.contains(&View::Type) {
+            let root = path::Path::new(TYPES_ROOT);
+            result.push(match self.type_groups {
+                Some(ref groups) => root.join(groups.group(&item.media_type)),
+                None => root
+                    .join(item.media_type.type_().as_str())
+                    .join(item.media_type.subtype().as_str()),
+            });
+        }
+        if self.views.contains(&View::Camera) {
+            result.extend(
+                item.camera
+                    .iter()
+                    .filter_map(|camera| directory_name(camera))
+                    .map(|camera| path::Path::new(CAMERAS_ROOT).join(camera)),
+            );
+        }
+        if self.views.contains(&View::Lens) {
+            let lens = match item.lens {
+                Some(ref lens) => directory_name(lens),
+                None => Some(UNKNOWN_LENS.into()),
+            };
+            result.extend(
+                lens.map(|lens| path::Path::new(&self.lenses_root).join(lens)),
+            );
+        }
+        if self.views.contains(&View::Size) {
+            result.extend(
+                item.size_bucket().map(|bucket| {
+                    path::Path::new(&self.sizes_root).join(bucket)
+                }),
+            );
+        }
+        if self.views.contains(&View::Weeks) && !item.timestamp.is_undated() {
+            let (year, week) = self.filed_timestamp(item).iso_week();
+            result.push(
+                path::Path::new(&self.weeks_root)
+                    .join(format!("{:04}", year))
+                    .join(format!("W{:02}", week)),
+            );
+        }
+        result.extend(self.albums.of(item).into_iter().filter_map(|album| {
+            let parts = album
+                .parts
+                .iter()
+                .filter_map(|part| directory_name(part))
+                .collect::<Vec<_>>();
+            if parts.is_empty() {
+                None
+            } else {
+                Some(parts.iter().fold(
+                    path::PathBuf::from(ALBUMS_ROOT),
+                    |acc, part| acc.join(part),
+                ))
+            }
+        }));
+        result
+    }
+
+    /// Returns whether no other tag of an item is nested under a tag.
+    ///
+    /// If tags differing in case are merged, case is ignored.
+    ///
+    /// # Arguments
+    /// *  `tag` - The tag.
+    /// *  `item` - The item with the tag.
+    fn is_leaf(&self, tag: &str, item: &data::Item) -> bool {
+        let normalize = |tag: &str| {
+            if self.merge_tag_case {
+                data::Tag::with_separator(
+                    &tag.to_lowercase(),
+                    self.tag_separator,
+                )
+            } else {
+                data::Tag::with_separator(tag, self.tag_separator)
+            }
+        };
+        let tag = normalize(tag);
+        !item.tags.iter().any(|other| tag.is_parent_of(&normalize(other)))
+    }
+
+    /// Generates the directory for links to items with a tag.
+    ///
+    /// If tags differing in case are merged, every part of the tag is
+    /// replaced by the form first seen for its lower case prefix.
+    ///
+    /// Parts that are not valid directory names are dropped. If no part
+    /// remains, `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `tag` - The tag.
+    fn tag_directory(&mut self, tag: String) -> Option<path::PathBuf> {
+        let base = path::PathBuf::from(&self.tagged_root);
+        let parts = data::Tag::with_separator(&tag, self.tag_separator)
+            .parts
+            .iter()
+            .filter_map(|part| directory_name(part))
+            .collect::<Vec<_>>();
+        if parts.is_empty() {
+            return None;
+        }
+        if !self.merge_tag_case {
+            return Some(
+                parts.into_iter().fold(base, |acc, part| acc.join(part)),
+            );
+        }
+
+        let mut key = String::new();
+        Some(parts.into_iter().fold(base, |acc, part| {
+            if !key.is_empty() {
+                key.push('/');
+            }
+            key.push_str(&part.to_lowercase());
+            acc.join(self.tag_names.entry(key.clone()).or_insert(part))
+        }))
+    }
+
+    /// Adds the generated index to the year directory of an item, or updates
+    /// its timestamp if it already exists.
+    ///
+    /// # Arguments
+    /// *  `item` - The item added to the year directory.
+    fn add_index(&mut self, item: &data::Item) {
+        let directory = path::Path::new(self.date_root(item))
+            .join(self.layout.label(0, &self.filed_timestamp(item)));
+        let timestamp = item.timestamp.to_timespec();
+        if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            self.assert_exists(&directory)
+        {
+            let entry = tree.entry(INDEX_NAME.into())
+                .or_insert(Entry::Index(timestamp));
+            if let Entry::Index(ref mut current) = *entry {
+                if *current < timestamp {
+                    *current = timestamp;
+                }
+            }
+        }
+    }
+
+    /// Adds or replaces the cover link of the date directory of an image, if
+    /// it is a better cover than the current one.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    /// *  `item` - The image.
+    fn add_cover(&mut self, path: &path::Path, item: &data::Item) {
+        let directory = self.directory(item);
+        let previous = match self.covers.get(&directory) {
+            Some((_, cover))
+                if (cover.rating, &item.timestamp)
+                    >= (item.rating, &cover.timestamp) =>
+            {
+                return
+            }
+            Some((name, _)) => Some(name.clone()),
+            None => None,
+        };
+
+        let mut name = ffi::OsString::from(COVER_NAME);
+        if let Some(extension) = path.extension() {
+            name.push(".");
+            name.push(extension);
+        }
+        let target = self.link_target(&directory, path);
+        let timestamp = item.timestamp.to_timespec();
+        if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            self.assert_exists(&directory)
+        {
+            if let Some(previous) = previous {
+                tree.remove(&previous);
+            }
+            tree.insert(
+                name.clone(),
+                Entry::Link(timestamp, target.into_os_string()),
+            );
+        } else {
+            return;
+        }
+        self.covers.insert(directory, (name, item.clone()));
+    }
+
+    /// Adds the display version of an image.
+    ///
+    /// The display version is added below [`DISPLAY_ROOT`] using the path of
+    /// the item relative to the root of the time stamped items.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the item.
+    /// *  `item` - The item.
+    ///
+    /// [`DISPLAY_ROOT`]: constant.DISPLAY_ROOT.html
+    fn add_display(&mut self, path: &path::Path, item: data::Item) {
+        let path = path.components()
+            .filter_map(|part| match part {
+                path::Component::Normal(name) => Some(name),
+                _ => None,
+            })
+            .skip(1)
+            .fold(path::PathBuf::from(DISPLAY_ROOT), |acc, part| {
+                acc.join(part)
+            });
+        if let (Some(directory), Some(name)) = (path.parent(), path.file_name())
+        {
+            let timestamp = item.timestamp.to_timespec();
+            if let Some(&mut Entry::Directory(_, ref mut tree)) =
+                self.assert_exists(&directory)
+            {
+                tree.insert(name.to_os_string(), Entry::Item(item));
+            } else {
+                return;
+            }
+            self.touch(&directory, timestamp);
+        }
+    }
+
+    /// Adds an item to the tree mirroring the source directory structure.
+    ///
+    /// If the tree is not enabled or the item has no relative path, nothing
+    /// is added. An existing entry with the same path is replaced.
+    ///
+    /// # Arguments
+    /// *  `item` - The item to add.
+    fn add_folder(&mut self, item: data::Item) {
+        let path = match (&self.folders_root, &item.rel_path) {
+            (Some(root), Some(rel_path)) => {
+                path::PathBuf::from(root).join(rel_path)
+            }
+            _ => return,
+        };
+        if let (Some(directory), Some(name)) = (path.parent(), path.file_name())
+        {
+            let timestamp = item.timestamp.to_timespec();
+            let replaced = if let Some(&mut Entry::Directory(_, ref mut tree)) =
+                self.assert_exists(&directory)
+            {
+                tree.insert(name.to_os_string(), Entry::Item(item)).is_some()
+            } else {
+                return;
+            };
+
+            // A replaced entry may have been the latest one
+            if replaced {
+                let names = directory
+                    .components()
+                    .filter_map(|part| match part {
+                        path::Component::Normal(name) => Some(name),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                Self::retime(&mut self.root, &names);
+            } else {
+                self.touch(&directory, timestamp);
+            }
+        }
+    }
+
+    /// Adds a sequence of items to the file system.
+    ///
+    /// Items that cannot be added, because an item named after a generated
+    /// parent directory exists and is not a directory, are skipped and
+    /// returned as `Err(items)` once all other items have been added.
+    ///
+    /// # Arguments
+    /// *  `items` - The items to add.
+    pub fn add_iter<T: Iterator<Item = data::Item>>(
+        &mut self,
+        items: T,
+    ) -> Result<(), Vec<data::Item>> {
+        let failed = items.fold(Vec::new(), |mut acc, item| {
+            if let Err(item) = self.add(item) {
+                acc.push(item);
+            }
+            acc
+        });
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed)
+        }
+    }
+
+    /// Replaces all items in the file system.
+    ///
+    /// Items are added in chronological order, with items sharing a timestamp
+    /// ordered by source path. An item already present keeps its name, and
+    /// thus its inode, as long as it is filed in the same directory; new
+    /// items sharing its timestamp are given other names. Items that cannot
+    /// be added are skipped and returned as `Err(items)`; see
+    /// [`add_iter`](#method.add_iter).
+    ///
+    /// If a page size is set, date directories with too many items are split
+    /// into pages; see [`with_page_size`](#method.with_page_size).
+    ///
+    /// Once all items have been added, empty directories are pruned.
+    ///
+    /// Items with the same source path as an earlier item, such as those found
+    /// through overlapping roots, are dropped before adding.
+    ///
+    /// If a removal grace period is set, items missing from `items` are
+    /// retained until the period has passed; see
+    /// [`with_removal_grace`](#method.with_removal_grace). Since no listing
+    /// of source files is known, every missing item that was not renamed is
+    /// considered gone.
+    ///
+    /// # Arguments
+    /// *  `items` - The items to add.
+    pub fn replace_all<T: Iterator<Item = data::Item>>(
+        &mut self,
+        items: T,
+    ) -> Result<(), Vec<data::Item>> {
+        self.replace_listed(items, &collections::HashSet::new())
+    }
+
+    /// Replaces all items in the file system with those found by a scan.
+    ///
+    /// This is like [`replace_all`](#method.replace_all), except that items
+    /// missing from `items` whose source file was listed by the scan are not
+    /// retained during the removal grace period, since their source file
+    /// still exists and was excluded on purpose.
+    ///
+    /// # Arguments
+    /// *  `items` - The items to add.
+    /// *  `listed` - The paths of all files listed by the scan.
+    pub fn replace_listed<T: Iterator<Item = data::Item>>(
+        &mut self,
+        items: T,
+        listed: &collections::HashSet<path::PathBuf>,
+    ) -> Result<(), Vec<data::Item>> {
+        self.replace_at(items, listed, stdtime::Instant::now())
+    }
+
+    /// Replaces all items in the file system at a specific time.
+    ///
+    /// # Arguments
+    /// *  `items` - The items to add.
+    /// *  `listed` - The paths of all files listed by the scan.
+    /// *  `now` - The current time, used to measure removal grace periods.
+    fn replace_at<T: Iterator<Item = data::Item>>(
+        &mut self,
+        items: T,
+        listed: &collections::HashSet<path::PathBuf>,
+        now: stdtime::Instant,
+    ) -> Result<(), Vec<data::Item>> {
+        self.generation += 1;
+        let mut seen = collections::HashSet::new();
+        let mut items = items
+            .filter(|item| {
+                let first = seen.insert(item.path.clone());
+                if !first {
+                    trace!("Dropping duplicate {}", item.path.display());
+                }
+                first
+            })
+            .collect::<Vec<_>>();
+        let retained = self.retained(&items, listed, now);
+        items.extend(retained);
+
+        // Reserve the names of the items already present
+        {
+            let present = items
+                .iter()
+                .map(|item| &item.path)
+                .collect::<collections::HashSet<_>>();
+            self.names.retain(|source, _| present.contains(source));
+        }
+        self.reserved = self.names
+            .iter()
+            .map(|(source, (path, _))| (path.clone(), source.clone()))
+            .collect();
+
+        self.root.clear();
+        self.references.clear();
+        self.tag_names.clear();
+        self.identities.clear();
+        self.covers.clear();
+
+        let (order_by, normalize_ext) =
+            (self.order_by, self.identifier.normalize_ext);
+        items.sort_by(|a, b| order_by.compare(a, b, normalize_ext));
+        self.split_tags = self.year_split_tags(&items);
+        let directories = self.pages(&items);
+        let failed = items.into_iter().zip(directories).fold(
+            Vec::new(),
+            |mut acc, (item, directory)| {
+                if let Err(item) = self.add_to(directory, item) {
+                    acc.push(item);
+                }
+                acc
+            },
+        );
+        self.reserved.clear();
+
+        self.root.prune();
+        if self.collapse_single {
+            self.collapse();
+        }
+        if let (Some(data::Identity::Hash), Some(hashes)) =
+            (self.identifier.identity, self.identifier.hashes.as_ref())
+        {
+            if let Ok(mut hashes) = hashes.lock() {
+                hashes.forget_unused();
+                if let Err(e) = hashes.save() {
+                    warn!("Failed to save checksums: {}", e);
+                }
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed)
+        }
+    }
+
+    /// Writes the state of this cache persisted between runs.
+    ///
+    /// The checksum database is saved when all items are replaced; this
+    /// writes any checksums that have not been saved since.
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.identifier.hashes {
+            Some(ref hashes) => match hashes.lock() {
+                Ok(mut hashes) => hashes.save(),
+                Err(_) => Err(io::Error::other(
+                    "checksum database poisoned",
+                )),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Lists the current items missing from a replacement that are still
+    /// within the removal grace period.
+    ///
+    /// Only items whose source file is actually gone are retained. A source
+    /// file that was listed by the scan still exists and was excluded on
+    /// purpose, and one that was renamed is present in the replacement under
+    /// its new path, which is recognised by the inode recorded by its source.
+    /// Items that have reappeared or whose grace period has passed are
+    /// forgotten.
+    ///
+    /// No source files are accessed, since this is called with the cache
+    /// locked.
+    ///
+    /// # Arguments
+    /// *  `items` - The replacement items.
+    /// *  `listed` - The paths of all files listed by the scan.
+    /// *  `now` - The current time.
+    fn retained(
+        &mut self,
+        items: &[data::Item],
+        listed: &collections::HashSet<path::PathBuf>,
+        now: stdtime::Instant,
+    ) -> Vec<data::Item> {
+        if self.removal_grace == stdtime::Duration::from_secs(0) {
+            self.vanished.clear();
+            return Vec::new();
+        }
+
+        let present = items
+            .iter()
+            .map(|item| &item.path)
+            .collect::<collections::HashSet<_>>();
+        let renamed = items
+            .iter()
+            .filter_map(|item| item.inode)
+            .collect::<collections::HashSet<_>>();
+        let mut vanished = collections::HashMap::new();
+        let mut result = Vec::new();
+        for item in self.items() {
+            if present.contains(&item.path) || listed.contains(&item.path) {
+                continue;
+            }
+            if item.inode.is_some_and(|inode| renamed.contains(&inode)) {
+                continue;
+            }
+            let since = self.vanished.get(&item.path).cloned().unwrap_or(now);
+            if now.duration_since(since) < self.removal_grace {
+                trace!("Retaining missing {}", item.path.display());
+                vanished.insert(item.path.clone(), since);
+                result.push(item.clone());
+            }
+        }
+        self.vanished = vanished;
+        result
+    }
+
+    /// Links a problematic source file from the quarantine.
+    ///
+    /// The link is added to the directory of the problem and targets the
+    /// source file itself, so `path` should be absolute. Since the file is
+    /// not an item, it is not referenced by any other entry. The links are
+    /// removed when all items are replaced.
+    ///
+    /// If the quarantine is disabled, nothing is added and `None` is
+    /// returned, otherwise the path of the new link.
+    ///
+    /// # Arguments
+    /// *  `problem` - The problem of the file.
+    /// *  `path` - The path of the source file.
+    pub fn add_problem(
+        &mut self,
+        problem: Problem,
+        path: &path::Path,
+    ) -> Option<path::PathBuf> {
+        if !self.quarantine {
+            return None;
+        }
+        let directory = path::Path::new(QUARANTINE_ROOT).join(problem.name());
+        let timestamp = data::timestamp(&path)
+            .map(data::system_time_to_timespec)
+            .unwrap_or_else(|| time::Timespec::new(0, 0));
+        let result = if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            self.assert_exists(&directory)
+        {
+            Self::add_with_index(
+                &directory,
+                tree,
+                Entry::Link(timestamp, path.as_os_str().to_os_string()),
+            )
+        } else {
+            return None;
+        };
+        self.touch(&directory, timestamp);
+        Some(result)
+    }
+
+    /// Collapses chains of single directories below the root of the time
+    /// stamped items, and updates all links and known item paths.
+    fn collapse(&mut self) {
+        let root = path::Path::new("/").join(&self.timestamp_root);
+        let mut renames = Vec::new();
+        if self.lookup(&root).is_some() {
+            if let Some(&mut Entry::Directory(_, ref mut tree)) =
+                self.assert_exists(&root)
+            {
+                collapse(tree, &root, &root, &mut renames);
+            }
+        }
+        if renames.is_empty() {
+            return;
+        }
+
+        let mut links = Vec::new();
+        collect_links(&self.root, path::Path::new("/"), &mut links);
+        for (path, target) in links {
+            let target = match self.target(&path, path::Path::new(&target)) {
+                Some(target) => target,
+                None => continue,
+            };
+            if let Some(target) = renamed(&target, &renames) {
+                let target = match path.parent() {
+                    Some(directory) => self.link_target(directory, &target),
+                    None => continue,
+                };
+                if let Some(&mut Entry::Link(_, ref mut current)) =
+                    self.assert_exists(&path)
+                {
+                    *current = target.into_os_string();
+                }
+            }
+        }
+
+        let root = path::Path::new("/");
+        for path in self.identities.values_mut() {
+            if let Some(renamed) = renamed(&root.join(&path), &renames) {
+                *path = renamed.strip_prefix(root).unwrap().to_path_buf();
+            }
+        }
+    }
+
+    /// Selects the root of the time stamped items below which to add an
+    /// item.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    fn date_root(&self, item: &data::Item) -> &ffi::OsStr {
+        match self.modified_root {
+            Some(ref root)
+                if item.date_source == data::DateSource::Modified
+                    && !item.timestamp.is_undated() =>
+            {
+                root
+            }
+            _ => &self.timestamp_root,
+        }
+    }
+
+    /// Generates the date directory for an item.
+    ///
+    /// Undated items are added to [`UNDATED_DIRECTORY`] regardless of the
+    /// layout.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    ///
+    /// [`UNDATED_DIRECTORY`]: constant.UNDATED_DIRECTORY.html
+    fn directory(&self, item: &data::Item) -> path::PathBuf {
+        let base: &path::Path = self.date_root(item).as_ref();
+        self.date_directories(base.to_path_buf(), item)
+    }
+
+    /// Appends the date directories of an item to a path.
+    ///
+    /// Undated items use [`UNDATED_DIRECTORY`] regardless of the layout.
+    ///
+    /// # Arguments
+    /// *  `base` - The path to which to append the directories.
+    /// *  `item` - The item.
+    ///
+    /// [`UNDATED_DIRECTORY`]: constant.UNDATED_DIRECTORY.html
+    fn date_directories(
+        &self,
+        base: path::PathBuf,
+        item: &data::Item,
+    ) -> path::PathBuf {
+        if item.timestamp.is_undated() {
+            UNDATED_DIRECTORY
+                .iter()
+                .fold(base, |acc, name| acc.join(name))
+        } else {
+            self.layout
+                .directories(&self.filed_timestamp(item))
+                .iter()
+                .fold(base, |acc, name| acc.join(name))
+        }
+    }
+
+    /// Calculates the timestamp used to select the date directories of an
+    /// item, taking the start of the day into account.
+    ///
+    /// The offset of the timestamp from UTC is kept.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    fn filed_timestamp(&self, item: &data::Item) -> data::Timestamp {
+        if self.day_start == 0 || item.timestamp.is_undated() {
+            return item.timestamp.clone();
+        }
+        let timestamp = item.timestamp.as_ref();
+        let utcoff = timestamp.tm_utcoff;
+        let filed = time::at_utc(
+            timestamp.to_timespec()
+                + time::Duration::seconds(
+                    i64::from(utcoff) - i64::from(self.day_start) * 3600,
+                ),
+        );
+        time::Tm {
+            tm_utcoff: utcoff,
+            ..filed
+        }.into()
+    }
+
+    /// Selects the directories of the tags whose links are grouped by year.
+    ///
+    /// # Arguments
+    /// *  `items` - The items, in the order they will be added.
+    fn year_split_tags(
+        &mut self,
+        items: &[data::Item],
+    ) -> collections::HashSet<path::PathBuf> {
+        let threshold = match self.tag_year_split {
+            Some(threshold)
+                if !self.tag_date_nesting
+                    && self.views.contains(&View::Tags) =>
+            {
+                threshold
+            }
+            _ => return collections::HashSet::new(),
+        };
+
+        let mut counts = collections::HashMap::new();
+        let mut years = collections::HashMap::new();
+        for item in items.iter() {
+            for tag in item.tags.iter() {
+                if self.tag_leaf_only && !self.is_leaf(tag, item) {
+                    continue;
+                }
+                let directory = match self.tag_directory(tag.clone()) {
+                    Some(directory) => directory,
+                    None => continue,
+                };
+                if !item.timestamp.is_undated() {
+                    years
+                        .entry(directory.clone())
+                        .or_insert_with(collections::HashSet::new)
+                        .insert(self.filed_timestamp(item).year());
+                }
+                *counts.entry(directory).or_insert(0) += 1;
+            }
+        }
+
+        counts
+            .into_iter()
+            .filter(|&(ref directory, count)| {
+                count > threshold
+                    && years.get(directory).map_or(0, |y| y.len()) > 1
+            })
+            .map(|(directory, _)| directory)
+            .collect()
+    }
+
+    /// Generates the date directories for a sequence of items, taking the
+    /// page size into account.
+    ///
+    /// # Arguments
+    /// *  `items` - The items, in the order they will be added.
+    fn pages(&self, items: &[data::Item]) -> Vec<path::PathBuf> {
+        let directories = items
+            .iter()
+            .map(|item| self.directory(item))
+            .collect::<Vec<_>>();
+        if self.page_size == 0 {
+            return directories;
+        }
+
+        let mut counts = collections::HashMap::new();
+        for directory in directories.iter() {
+            *counts.entry(directory.clone()).or_insert(0) += 1;
+        }
+
+        let mut indices = collections::HashMap::new();
+        directories
+            .into_iter()
+            .map(|directory| {
+                if counts[&directory] > self.page_size {
+                    let index =
+                        indices.entry(directory.clone()).or_insert(0usize);
+                    let page = *index / self.page_size + 1;
+                    *index += 1;
+                    directory.join(format!("page-{:03}", page))
+                } else {
+                    directory
+                }
+            })
+            .collect()
+    }
+
+    /// Adds a single item to the file system.
+    ///
+    /// On success, the path of the new item is returned.
+    ///
+    /// This method will fail if an item named after the generated parent
+    /// directory for `item` exists and is not a directory, or if the item
+    /// would be deeper than the maximum depth.
+    ///
+    /// # Arguments
+    /// *  `item` - The item to add.
+    fn add_item<P: AsRef<path::Path>>(
+        &mut self,
+        directory: P,
+        item: data::Item,
+    ) -> AddItemResult {
+        if self.depth(directory.as_ref()) >= self.max_depth {
+            warn!("Path too deep: {}", item.path.display());
+            return Err(item);
+        }
+        let timestamp = item.timestamp.to_timespec();
+        let source = item.path.clone();
+        let previous = self.names.get(&source).cloned();
+        let reserved = mem::take(&mut self.reserved);
+        let result = if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            self.assert_exists(&directory)
+        {
+            Ok(Self::add_with_reserved_index(
+                &directory,
+                tree,
+                item,
+                previous.as_ref(),
+                &reserved,
+            ))
+        } else {
+            Err(item)
+        };
+        self.reserved = reserved;
+        result.map(|(path, index)| {
+            self.touch(&directory, timestamp);
+            self.names.insert(source, (path.clone(), index));
+            path
+        })
+    }
+
+    /// Adds the sidecar files of an item next to it.
+    ///
+    /// The sidecars are those found by the source of the item, and they are
+    /// exposed with their extension in lower case. A sidecar is not added if
+    /// its name is already taken.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the item.
+    /// *  `item` - The item.
+    fn add_sidecars(&mut self, path: &path::Path, item: &data::Item) {
+        let directory = match path.parent() {
+            Some(directory) => directory,
+            None => return,
+        };
+        for source in &item.sidecars {
+            let name = match data::normalize_extension(source).extension() {
+                Some(extension) => path.with_extension(extension),
+                None => continue,
+            };
+            let name = match name.file_name() {
+                Some(name) => name.to_os_string(),
+                None => continue,
+            };
+            if let Some(&mut Entry::Directory(_, ref mut tree)) =
+                self.assert_exists(&directory)
+            {
+                tree.entry(name).or_insert(Entry::Sidecar(
+                    item.timestamp.to_timespec(),
+                    source.clone(),
+                ));
+            }
+        }
+    }
+
+    /// Adds a link to an item to the file system.
+    ///
+    /// On success, the path of the new link is returned.
+    ///
+    /// # Arguments
+    /// *  `tag` - The item tag.
+    /// *  `path` - The target path.
+    fn add_link<P: AsRef<path::Path>>(
+        &mut self,
+        directory: P,
+        path: P,
+        item: &data::Item,
+    ) -> Option<path::PathBuf> {
+        let directory: &path::Path = directory.as_ref();
+        let path: &path::Path = path.as_ref();
+        if self.depth(directory) >= self.max_depth {
+            return None;
+        }
+        let target = self.link_target(directory, path);
+
+        // TODO: Rename item and try again?
+        let timestamp = item.timestamp.to_timespec();
+        let result = if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            self.assert_exists(&directory)
+        {
+            Self::add_with_index(
+                &directory,
+                tree,
+                Entry::Link(timestamp, target.into_os_string()),
+            )
+        } else {
+            return None;
+        };
+        self.touch(&directory, timestamp);
+
+        *self.references.entry(item.path.clone()).or_insert(0) += 1;
+        Some(result)
+    }
+
+    /// Generates the target of a link according to the link style.
+    ///
+    /// # Arguments
+    /// *  `directory` - The directory containing the link.
+    /// *  `path` - The path of the item to link.
+    fn link_target(
+        &self,
+        directory: &path::Path,
+        path: &path::Path,
+    ) -> path::PathBuf {
+        match self.link_style {
+            LinkStyle::Relative => {
+                let mut relative = path::PathBuf::new();
+                for component in directory.components() {
+                    if let path::Component::Normal(_) = component {
+                        relative.push("..");
+                    }
+                }
+                relative.push(path.strip_prefix("/").unwrap_or(path));
+                relative
+            }
+            LinkStyle::Absolute(ref root) => {
+                root.join(path.strip_prefix("/").unwrap_or(path))
+            }
+        }
+    }
+
+    /// Adds an item under a tree by incrementing an index until a unique name
+    /// is found.
+    ///
+    /// If the first attempt succeeds, no index is added.
+    ///
+    /// # Arguments
+    /// *  `directory` - The path of the directory tree.
+    /// *  `tree` - The directory tree to which to add the item.
+    /// *  `entry` - The entry to add. This must not be a directory entry.
+    ///
+    /// # Panics
+    /// This method will panic if passed as directory entry.
+    fn add_with_index<P: AsRef<path::Path>>(
+        directory: &P,
+        tree: &mut Tree,
+        entry: Entry,
+    ) -> path::PathBuf {
+        let directory: &path::Path = directory.as_ref();
+        let mut index = 0;
+
+        // Construct a suitable name
+        let name = loop {
+            let name = entry.name(index).as_os_str().to_os_string();
+            if !tree.contains_key(&name) {
+                break name;
+            } else {
+                index += 1;
+            }
+        };
+
+        tree.insert(name.clone(), entry);
+        directory.join(name)
+    }
+
+    /// Adds an item under a tree like
+    /// [`add_with_index`](#method.add_with_index), but keeps the name the
+    /// item had before all items were replaced, and skips names reserved for
+    /// other items.
+    ///
+    /// The previous name is kept only if it is still a valid name for the
+    /// item in this directory.
+    ///
+    /// # Arguments
+    /// *  `directory` - The path of the directory tree.
+    /// *  `tree` - The directory tree to which to add the item.
+    /// *  `item` - The item to add.
+    /// *  `previous` - The previous path of the item and the index in its
+    ///    name, if known.
+    /// *  `reserved` - The source paths of the items whose names are
+    ///    reserved, keyed by their previous paths.
+    fn add_with_reserved_index<P: AsRef<path::Path>>(
+        directory: &P,
+        tree: &mut Tree,
+        item: data::Item,
+        previous: Option<&(path::PathBuf, usize)>,
+        reserved: &collections::HashMap<path::PathBuf, path::PathBuf>,
+    ) -> (path::PathBuf, usize) {
+        let directory: &path::Path = directory.as_ref();
+        let entry = Entry::Item(item);
+        let kept = previous.and_then(|&(ref path, index)| {
+            let name = entry.name(index).as_os_str().to_os_string();
+            if *path == directory.join(&name) && !tree.contains_key(&name) {
+                Some((name, index))
+            } else {
+                None
+            }
+        });
+
+        // Construct a suitable name unless the previous one is kept
+        let (name, index) = match kept {
+            Some(kept) => kept,
+            None => {
+                let mut index = 0;
+                loop {
+                    let name = entry.name(index).as_os_str().to_os_string();
+                    if !tree.contains_key(&name)
+                        && !reserved.contains_key(&directory.join(&name))
+                    {
+                        break (name, index);
+                    } else {
+                        index += 1;
+                    }
+                }
+            }
+        };
+
+        tree.insert(name.clone(), entry);
+        (directory.join(name), index)
+    }
+
+    /// Updates the timestamps of all directories along a path after an entry
+    /// has been added to the last one.
+    ///
+    /// The timestamp of a directory is only ever increased.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the directory to which the entry was added.
+    /// *  `timestamp` - The timestamp of the added entry.
+    fn touch<P: AsRef<path::Path>>(
+        &mut self,
+        path: &P,
+        timestamp: time::Timespec,
+    ) {
+        let update = |entry: &mut Entry| {
+            if let Entry::Directory(ref mut current, _) = *entry {
+                if *current < timestamp {
+                    *current = timestamp;
+                }
+            }
+        };
+        let last = path.as_ref().components().fold(
+            Some(&mut self.root),
+            |acc, part| match part {
+                path::Component::Normal(name) => acc.and_then(|entry| {
+                    update(entry);
+                    match *entry {
+                        Entry::Directory(_, ref mut tree) => tree.get_mut(name),
+                        _ => None,
+                    }
+                }),
+                _ => acc,
+            },
+        );
+        if let Some(entry) = last {
+            update(entry);
+        }
+    }
+
+    /// Recalculates the timestamps of all directories along a path from their
+    /// child entries.
+    ///
+    /// This is required when an entry is replaced, since the timestamp of a
+    /// directory may then decrease.
+    ///
+    /// # Arguments
+    /// *  `entry` - The entry at which to start.
+    /// *  `names` - The names of the directories along the path.
+    fn retime(entry: &mut Entry, names: &[&ffi::OsStr]) {
+        if let Entry::Directory(ref mut timestamp, ref mut tree) = *entry {
+            if let Some((name, rest)) = names.split_first() {
+                if let Some(child) = tree.get_mut(*name) {
+                    Self::retime(child, rest);
+                }
+            }
+            *timestamp = tree.values()
+                .map(Entry::timestamp)
+                .max()
+                .unwrap_or(time::Timespec::new(0, 0));
+        }
+    }
+
+    /// Calculates the depth of a path.
+    ///
+    /// Counting stops as soon as the maximum depth is exceeded, so the result
+    /// is at most one more than the maximum.
+    ///
+    /// # Arguments
+    /// *  `path` - The path.
+    fn depth(&self, path: &path::Path) -> usize {
+        path.components()
+            .filter(|part| matches!(*part, path::Component::Normal(_)))
+            .take(self.max_depth + 1)
+            .count()
+    }
+
+    /// Asserts that a path exists.
+    ///
+    /// All missing parents will be created. If a non-directory entry is
+    /// encountered along the way, except for the final component, `None` is
+    /// returned.
+    ///
+    /// If the final part has to be created, it will be created as a directory.
+    ///
+    /// The path is normalized first, like in [`lookup`](#method.lookup),
+    /// except that `..` components never leave the tree of the first
+    /// component, so that entries for one tree are never created in another.
+    ///
+    /// If the path is deeper than the maximum depth, nothing is created and
+    /// `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `path` - The full path.
+    fn assert_exists<P: AsRef<path::Path>>(
+        &mut self,
+        path: &P,
+    ) -> Option<&mut Entry> {
+        let path = normalize_in_tree(path.as_ref());
+        if self.depth(&path) > self.max_depth {
+            warn!("Path too deep: {}", path.display());
+            return None;
+        }
+        path.components()
+            .fold(Some(&mut self.root), |acc, part| match part {
+                // The root will be the first component, so we return the
+                // initial value unchanged
+                path::Component::RootDir => acc,
+
+                // Go deeper for normal files
+                path::Component::Normal(path) => {
+                    acc.and_then(|entry| match *entry {
+                        Entry::Directory(_, ref mut tree) => Some(
+                            tree.entry(path.to_os_string()).or_insert_with(
+                                || {
+                                    Entry::Directory(
+                                        time::Timespec::new(0, 0),
+                                        Tree::new(),
+                                    )
+                                },
+                            ),
+                        ),
+                        _ => None,
+                    })
+                }
+
+                // We do not support path prefixes; special directories have
+                // been normalized away
+                _ => None,
+            })
+    }
+}
+
+/// Normalizes the special directories of a path.
+///
+/// Current directory components are dropped, and parent directory components
+/// remove the preceding component. Parent directories of the root are the
+/// root itself.
+///
+/// # Arguments
+/// *  `path` - The path to normalize.
+fn normalize(path: &path::Path) -> path::PathBuf {
+    path.components()
+        .fold(path::PathBuf::new(), |mut acc, component| {
+            match component {
+                path::Component::CurDir => {}
+                path::Component::ParentDir => {
+                    acc.pop();
+                }
+                component => acc.push(component.as_os_str()),
+            }
+            acc
+        })
+}
+
+/// Normalizes the special directories of a path without leaving the tree of
+/// its first component.
+///
+/// This is like [`normalize`], except that parent directory components never
+/// remove the first normal component, which is the root of a tree such as
+/// the time stamped items.
+///
+/// # Arguments
+/// *  `path` - The path to normalize.
+///
+/// [`normalize`]: fn.normalize.html
+fn normalize_in_tree(path: &path::Path) -> path::PathBuf {
+    path.components()
+        .fold(path::PathBuf::new(), |mut acc, component| {
+            match component {
+                path::Component::CurDir => {}
+                path::Component::ParentDir => {
+                    let depth = acc
+                        .components()
+                        .filter(|part| {
+                            matches!(*part, path::Component::Normal(_))
+                        })
+                        .count();
+                    if depth > 1 {
+                        acc.pop();
+                    }
+                }
+                component => acc.push(component.as_os_str()),
+            }
+            acc
+        })
+}
+
+/// Converts a name read from item metadata to a directory name.
+///
+/// Any `"/"` is replaced with `"∕"`, so that the name is a single directory
+/// rather than nested ones. Names that are empty or special directories
+/// cannot be used, so `None` is returned for them.
+///
+/// # Arguments
+/// *  `name` - The name.
+pub fn directory_name(name: &str) -> Option<String> {
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.replace('/', "\u{2215}")),
+    }
+}
+
+/// Compares the file names of two paths by the numbers in them.
+///
+/// If either path has no file name, they are considered equal.
+///
+/// # Arguments
+/// *  `a` - The first path.
+/// *  `b` - The second path.
+fn serial_cmp(a: &path::Path, b: &path::Path) -> cmp::Ordering {
+    match (a.file_name(), b.file_name()) {
+        (Some(a), Some(b)) => util::natural_cmp(a, b),
+        _ => cmp::Ordering::Equal,
+    }
+}
+
+/// Generates the name of the year directory of an item in a tag directory
+/// whose links are grouped by year.
+///
+/// # Arguments
+/// *  `timestamp` - The timestamp used to file the item.
+fn year_name(timestamp: &data::Timestamp) -> String {
+    format!("{:04}", timestamp.year())
+}
+
+/// Collapses chains of single directories in a tree.
+///
+/// Every directory in `tree` containing only a single directory is merged
+/// with it, repeatedly, and the merged directory is named after all of them
+/// joined by `"-"`. The old and new paths of every merged directory are
+/// appended to `renames`.
+///
+/// # Arguments
+/// *  `tree` - The tree whose children to collapse.
+/// *  `old` - The original path of the tree.
+/// *  `new` - The path of the tree after collapsing its parents.
+/// *  `renames` - The old and new paths of collapsed directories.
+fn collapse(
+    tree: &mut Tree,
+    old: &path::Path,
+    new: &path::Path,
+    renames: &mut Vec<(path::PathBuf, path::PathBuf)>,
+) {
+    let names = tree.keys().cloned().collect::<Vec<_>>();
+    for name in names {
+        let mut entry = match tree.remove(&name) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let mut label = name.clone();
+        let mut old_path = old.join(&name);
+        loop {
+            let child = match entry {
+                Entry::Directory(_, ref mut children)
+                    if children.len() == 1 =>
+                {
+                    let single = children
+                        .iter()
+                        .filter(|&(_, child)| {
+                            matches!(*child, Entry::Directory(_, _))
+                        })
+                        .map(|(name, _)| name.clone())
+                        .next();
+                    single.and_then(|single| {
+                        children.remove(&single).map(|child| (single, child))
+                    })
+                }
+                _ => None,
+            };
+            match child {
+                Some((child_name, child)) => {
+                    label.push("-");
+                    label.push(&child_name);
+                    old_path.push(&child_name);
+                    entry = child;
+                }
+                None => break,
+            }
+        }
+
+        let new_path = new.join(&label);
+        if label != name {
+            renames.push((old_path.clone(), new_path.clone()));
+        }
+        if let Entry::Directory(_, ref mut children) = entry {
+            collapse(children, &old_path, &new_path, renames);
+        }
+        tree.insert(label, entry);
+    }
+}
+
+/// Collects the paths and targets of all links below an entry.
+///
+/// # Arguments
+/// *  `entry` - The entry.
+/// *  `path` - The path of the entry.
+/// *  `result` - The collected links.
+fn collect_links(
+    entry: &Entry,
+    path: &path::Path,
+    result: &mut Vec<(path::PathBuf, ffi::OsString)>,
+) {
+    match entry {
+        Entry::Directory(_, tree) => {
+            for (name, child) in tree {
+                collect_links(child, &path.join(name), result);
+            }
+        }
+        Entry::Link(_, target) => {
+            result.push((path.to_path_buf(), target.clone()))
+        }
+        &Entry::Item(_) | &Entry::Sidecar(_, _) | &Entry::Index(_) => (),
+    }
+}
+
+/// Finds the new path of an entry after directories have been collapsed.
+///
+/// If the entry was not moved, `None` is returned.
+///
+/// # Arguments
+/// *  `path` - The original path of the entry.
+/// *  `renames` - The old and new paths of collapsed directories.
+fn renamed(
+    path: &path::Path,
+    renames: &[(path::PathBuf, path::PathBuf)],
+) -> Option<path::PathBuf> {
+    renames
+        .iter()
+        .filter(|&(old, _)| path.starts_with(old))
+        .max_by_key(|&(old, _)| old.components().count())
+        .map(|(old, new)| new.join(path.strip_prefix(old).unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::vec;
+
+    use tempdir;
+
+    use data::tests::*;
+    use super::*;
+
+    /// Tests that all items are listed once.
+    #[test]
+    fn test_items() {
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_folders_root("Folders".into());
+        let mut items = (1..4)
+            .map(|i| {
+                let mut result = item(&format!("test{}.jpg", i), 2000, 1, i);
+                result.tags.insert("tag".into());
+                result.rel_path = Some(format!("test{}.jpg", i).into());
+                result
+            })
+            .collect::<Vec<_>>();
+        cache.replace_all(items.clone().into_iter()).unwrap();
+
+        let mut listed = cache.items().into_iter().cloned().collect::<Vec<_>>();
+        listed.sort_by(|a, b| a.path.cmp(&b.path));
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(items, listed);
+    }
+
+    /// Tests that looking up an empty path yields the root.
+    #[test]
+    fn test_lookup_root() {
+        let valid: path::PathBuf = ["/"].iter().collect();
+        let cache = Cache::new("/base".into(), "tagged".into());
+
+        assert!(cache.lookup(&valid).is_some());
+    }
+
+    /// Tests that paths with special directories are normalized.
+    #[test]
+    fn test_lookup_normalized() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+        let item = item("test.jpg", 2000, 1, 1);
+        cache.add(item.clone()).unwrap();
+
+        assert_eq!(
+            cache.lookup(&"/base/2000/01"),
+            cache.lookup(&"/base/2000/../2000/./01"),
+        );
+        assert_eq!(
+            Some(&Entry::Item(item)),
+            cache.lookup(&"/../base/2000/02/../01/01/2000-01-01 00:00.jpeg"),
+        );
+        assert_eq!(
+            cache.lookup(&"/base/2000"),
+            cache.lookup(&"/base/2000/01/.."),
+        );
+    }
+
+    /// Tests that paths with special directories are normalized when created,
+    /// without leaving the tree of the path.
+    #[test]
+    fn test_assert_exists_normalized() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        assert!(cache.assert_exists(&"/base/2000/../2001/./01").is_some());
+        assert!(cache.lookup(&"/base/2001/01").is_some());
+        assert!(cache.lookup(&"/base/2000").is_none());
+
+        assert!(cache.assert_exists(&"/tagged/../../base/a").is_some());
+        assert!(cache.lookup(&"/tagged/base/a").is_some());
+        assert!(cache.lookup(&"/base/a").is_none());
+    }
+
+    /// Tests that tags with special directories do not create links outside
+    /// of the tag tree.
+    #[test]
+    fn test_tag_special_directories() {
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        let mut item = item("test.jpg", 2000, 1, 1);
+        item.tags.insert("a/../../base".into());
+        item.tags.insert("..".into());
+        cache.add(item.clone()).unwrap();
+
+        let names = |path| match cache.lookup(&path) {
+            Some(Entry::Directory(_, tree)) => {
+                let mut names = tree.keys().cloned().collect::<Vec<_>>();
+                names.sort();
+                names
+            }
+            _ => Vec::new(),
+        };
+        assert_eq!(vec![ffi::OsString::from("2000")], names("/base"));
+        assert!(
+            names("/")
+                .iter()
+                .all(|name| name == "base" || name == "tagged")
+        );
+        assert_eq!(
+            vec![ffi::OsString::from("2000-01-01 00:00.jpeg")],
+            names("/tagged/a/base"),
+        );
+        assert_eq!(vec![ffi::OsString::from("a")], names("/tagged"));
+        assert_eq!(1, cache.items().len());
+    }
+
+    /// Tests that adding an item immediately under the root works.
+    #[test]
+    fn test_add_item_simple() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let item = item("test.jpg", 2000, 1, 1);
+        let expected_path =
+            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        assert_eq!(
+            expected_path,
+            cache
+                .add_item(expected_path.parent().unwrap(), item.clone())
+                .unwrap(),
+        );
+        assert_eq!(Some(&Entry::Item(item)), cache.lookup(&expected_path),);
+    }
+
+    /// Tests that adding an item over a directory works.
+    #[test]
+    fn test_add_item_twice() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let item1 = item("test1.jpg", 2000, 1, 1);
+        let expected_path1 =
+            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        assert_eq!(
+            expected_path1,
+            cache
+                .add_item(expected_path1.parent().unwrap(), item1)
+                .unwrap(),
+        );
+
+        let item2 = item("test2.jpg", 2000, 1, 1);
+        let expected_path2 =
+            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00 (1).jpeg");
+        assert_eq!(
+            expected_path2,
+            cache
+                .add_item(expected_path2.parent().unwrap(), item2.clone())
+                .unwrap(),
+        );
+        assert_eq!(Some(&Entry::Item(item2)), cache.lookup(&expected_path2),);
+    }
+
+    /// Tests that timestamps are correct.
+    #[test]
+    fn test_timestamp() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let item1 = item("test1.jpg", 2000, 1, 1);
+        let expected_path1 =
+            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        assert_eq!(
+            expected_path1,
+            cache
+                .add_item(expected_path1.parent().unwrap(), item1.clone())
+                .unwrap(),
+        );
+        assert_eq!(
+            Some(item1.timestamp.to_timespec()),
+            cache.lookup(&"/").map(|e| e.timestamp()),
+        );
+
+        let item2 = item("test2.jpg", 2000, 1, 2);
+        cache
+            .add_item(expected_path1.parent().unwrap(), item2.clone())
+            .unwrap();
+        assert_eq!(
+            Some(item2.timestamp.to_timespec()),
+            cache.lookup(&"/").map(|e| e.timestamp()),
+        );
+    }
+
+    /// Tests that an item that cannot be added does not prevent other items
+    /// from being added.
+    #[test]
+    fn test_add_iter_conflict() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let blocker = item("blocker.jpg", 1999, 1, 1);
+        if let Some(&mut Entry::Directory(_, ref mut tree)) =
+            cache.assert_exists(&"/base/2000")
+        {
+            tree.insert("01".into(), Entry::Item(blocker));
+        }
+
+        let conflicting = item("test1.jpg", 2000, 1, 1);
+        let other = item("test2.jpg", 2000, 2, 1);
+        assert_eq!(
+            Err(vec![conflicting.clone()]),
+            cache.add_iter(vec![conflicting, other.clone()].into_iter()),
+        );
+        assert_eq!(
+            Some(&Entry::Item(other)),
+            cache.lookup(&"/base/2000/02/01/2000-02-01 00:00.jpeg"),
+        );
+    }
+
+    /// Tests that links to an item are counted.
+    #[test]
+    fn test_references() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let mut tagged = item("test.jpg", 2000, 1, 1);
+        tagged.tags.insert("tag1".into());
+        tagged.tags.insert("tag2".into());
+        let other = item("other.jpg", 2000, 1, 1);
+        cache.add(tagged.clone()).unwrap();
+        cache.add(other.clone()).unwrap();
+
+        assert_eq!(2, cache.references(&tagged));
+        assert_eq!(0, cache.references(&other));
+    }
+
+    /// Tests that tags added to and removed from items are reflected in the
+    /// tag directories.
+    #[test]
+    fn test_add_remove_tag() {
+        let mut cache = Cache::new("All".into(), "Tagged".into());
+        let item = path::Path::new("/")
+            .join(cache.add(item("test.jpg", 2000, 1, 1)).unwrap());
+        let link = path::Path::new("/Tagged/a/b/2000-01-01 00:00.jpeg");
+
+        assert_eq!(None, cache.add_tag(&"/All", &item));
+        assert_eq!(None, cache.add_tag(&"/Tagged", &item));
+        assert_eq!(
+            Some((link.to_path_buf(), "a/b".into())),
+            cache.add_tag(&"/Tagged/a/b", &item),
+        );
+        assert_eq!(None, cache.add_tag(&"/Tagged/a/b", &item));
+        assert!(cache.resolve(&link).unwrap().tags.contains("a/b"));
+        assert!(cache.resolve(&item).unwrap().tags.contains("a/b"));
+        assert_eq!(1, cache.references(cache.resolve(&item).unwrap()));
+
+        assert_eq!(None, cache.remove_tag(&item));
+        let (removed, tag) = cache.remove_tag(&link).unwrap();
+        assert_eq!("a/b", tag);
+        assert!(removed.tags.is_empty());
+        assert!(cache.resolve(&item).unwrap().tags.is_empty());
+        assert_eq!(0, cache.references(&removed));
+        assert_eq!(None, cache.lookup(&"/Tagged/a"));
+    }
+
+    /// Tests that pruning removes empty directories only.
+    #[test]
+    fn test_prune() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let item = item("test.jpg", 2000, 1, 1);
+        let expected_path =
+            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        cache
+            .add_item(expected_path.parent().unwrap(), item.clone())
+            .unwrap();
+        cache.assert_exists(&"/base/2000/02/01");
+        cache.assert_exists(&"/base/2001/01/01");
+        cache.root.prune();
+
+        assert_eq!(Some(&Entry::Item(item)), cache.lookup(&expected_path));
+        assert_eq!(None, cache.lookup(&"/base/2000/02"));
+        assert_eq!(None, cache.lookup(&"/base/2001"));
+    }
+
+    /// Tests that items are added according to the date layout.
+    #[test]
+    fn test_date_layout() {
+        let mut cache = Cache::new("/base".into(), "tagged".into())
+            .with_date_layout("%Y/%B/%Y-%m-%d".parse().unwrap());
+
+        let item = item("test.jpg", 2000, 1, 2);
+        let expected_path = path::PathBuf::from(
+            "/base/2000/January/2000-01-02/2000-01-02 00:00.jpeg",
+        );
+        assert_eq!(expected_path, cache.add(item.clone()).unwrap());
+        assert_eq!(Some(&Entry::Item(item)), cache.lookup(&expected_path));
+    }
+
+    /// Tests that crowded date directories are split into pages.
+    #[test]
+    fn test_page_size() {
+        let mut cache =
+            Cache::new("/base".into(), "tagged".into()).with_page_size(2);
+
+        let mut items = (0..5)
+            .map(|i| {
+                data::Item::new(
+                    format!("test{}.jpg", i),
+                    (2000, 1, 1, 12, 5 - i, 0),
+                    collections::HashSet::new(),
+                )
+            })
+            .collect::<Vec<_>>();
+        items[0].tags.insert("tag".into());
+        let other = item("other.jpg", 2000, 1, 2);
+        cache
+            .replace_all(items.iter().cloned().chain(vec![other.clone()]))
+            .unwrap();
+
+        let names = |path: &str| match cache.lookup(&path) {
+            Some(Entry::Directory(_, tree)) => {
+                let mut names = tree.keys().cloned().collect::<Vec<_>>();
+                names.sort();
+                names
+            }
+            _ => vec![],
+        };
+        assert_eq!(
+            vec!["page-001", "page-002", "page-003"],
+            names("/base/2000/01/01"),
+        );
+        assert_eq!(
+            vec!["2000-01-01 12:01.jpeg", "2000-01-01 12:02.jpeg"],
+            names("/base/2000/01/01/page-001"),
+        );
+        assert_eq!(
+            vec!["2000-01-01 12:03.jpeg", "2000-01-01 12:04.jpeg"],
+            names("/base/2000/01/01/page-002"),
+        );
+        assert_eq!(
+            Some(&Entry::Item(items[0].clone())),
+            cache.lookup(&"/base/2000/01/01/page-003/2000-01-01 12:05.jpeg"),
+        );
+        assert_eq!(
+            Some(&Entry::Item(other)),
+            cache.lookup(&"/base/2000/01/02/2000-01-02 00:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&Entry::Link(
+                items[0].timestamp.to_timespec(),
+                "../../base/2000/01/01/page-003/2000-01-01 12:05.jpeg".into(),
+            )),
+            cache.lookup(&"/tagged/tag/2000-01-01 12:05.jpeg"),
+        );
+    }
+
+    /// Tests that items are added to the tree mirroring the source
+    /// directories.
+    #[test]
+    fn test_folders() {
+        let mut cache = Cache::new("/base".into(), "tagged".into())
+            .with_folders_root("/folders".into());
+
+        let mut nested = item("a/b/test.jpg", 2000, 1, 1);
+        nested.rel_path = Some("a/b/test.jpg".into());
+        let mut top = item("other.jpg", 2000, 1, 1);
+        top.rel_path = Some("other.jpg".into());
+        let unknown = item("unknown.jpg", 2000, 1, 1);
+        cache
+            .add_iter(vec![nested.clone(), top.clone(), unknown].into_iter())
+            .unwrap();
+
+        assert_eq!(
+            Some(&Entry::Item(nested)),
+            cache.lookup(&"/folders/a/b/test.jpg"),
+        );
+        assert_eq!(
+            Some(&Entry::Item(top)),
+            cache.lookup(&"/folders/other.jpg"),
+        );
+        match cache.lookup(&"/folders") {
+            Some(Entry::Directory(_, tree)) => assert_eq!(2, tree.len()),
+            e => panic!("unexpected entry {:?}", e),
+        }
+    }
+
+    /// Tests that images are mirrored below the display root only when
+    /// enabled.
+    #[test]
+    fn test_display_tree() {
+        let image = item("test.jpg", 2000, 1, 1);
+        let video = item("clip.mp4", 2000, 1, 1);
+        let display = |enabled: bool| {
+            let mut cache = Cache::new("/base".into(), "tagged".into())
+                .with_display_tree(enabled);
+            cache
+                .add_iter(vec![image.clone(), video.clone()].into_iter())
+                .unwrap();
+            cache
+        };
+
+        let cache = display(true);
+        let path = path::Path::new("/Display/2000/01/01/2000-01-01 00:00.jpeg");
+        assert_eq!(Some(&Entry::Item(image.clone())), cache.lookup(&path));
+        assert!(cache.is_display(path));
+        assert!(!cache.is_display(path::Path::new("/base")));
+        match cache.lookup(&"/Display/2000/01/01") {
+            Some(Entry::Directory(_, tree)) => assert_eq!(1, tree.len()),
+            e => panic!("unexpected entry {:?}", e),
+        }
+
+        let cache = display(false);
+        assert_eq!(None, cache.lookup(&path));
+        assert!(!cache.is_display(path));
+    }
+
+    /// Tests that the cover of a date directory is the image with the highest
+    /// rating, and the earliest one among those with the same rating.
+    #[test]
+    fn test_day_covers() {
+        let rated = |name, hour, rating| data::Item {
+            timestamp: (2000, 1, 1, hour, 0, 0).into(),
+            rating: Some(rating),
+            ..item(name, 2000, 1, 1)
+        };
+        let low = rated("low.jpg", 10, 3);
+        let late = rated("late.jpg", 12, 5);
+        let early = rated("early.jpg", 11, 5);
+        let video = rated("clip.mp4", 9, 9);
+        let single = item("single.jpg", 2000, 1, 2);
+
+        let mut cache =
+            Cache::new("All".into(), "Tagged".into()).with_day_covers(true);
+        cache
+            .add_iter(
+                vec![low, late, early.clone(), video, single.clone()]
+                    .into_iter(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            Some(&early),
+            cache.resolve(&"/All/2000/01/01/cover.jpeg"),
+        );
+        assert_eq!(
+            Some(&single),
+            cache.resolve(&"/All/2000/01/02/cover.jpeg"),
+        );
+        match cache.lookup(&"/All/2000/01/01") {
+            Some(Entry::Directory(_, tree)) => assert_eq!(5, tree.len()),
+            e => panic!("unexpected entry {:?}", e),
+        }
+    }
+
+    /// Tests that links of both styles resolve to the item.
+    #[test]
+    fn test_link_style() {
+        let mut tagged = item("test.jpg", 2000, 1, 1);
+        tagged.tags.insert("a/b".into());
+        let link = path::Path::new("/tagged/a/b/2000-01-01 00:00.jpeg");
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        let path = cache.add(tagged.clone()).unwrap();
+        let target = match cache.lookup(&link) {
+            Some(Entry::Link(_, target)) => path::PathBuf::from(target),
+            e => panic!("unexpected entry {:?}", e),
+        };
+        assert!(target.is_relative());
+        let resolved = link.parent().unwrap().join(target).components().fold(
+            path::PathBuf::new(),
+            |mut acc, c| {
+                match c {
+                    path::Component::ParentDir => {
+                        acc.pop();
+                    }
+                    c => acc.push(c.as_os_str()),
+                }
+                acc
+            },
+        );
+        assert_eq!(path, resolved);
+        assert_eq!(
+            Some(&Entry::Item(tagged.clone())),
+            cache.lookup(&resolved),
+        );
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_link_style(LinkStyle::Absolute("/mnt/media".into()));
+        let path = cache.add(tagged.clone()).unwrap();
+        let target = match cache.lookup(&link) {
+            Some(Entry::Link(_, target)) => path::PathBuf::from(target),
+            e => panic!("unexpected entry {:?}", e),
+        };
+        assert_eq!(
+            path::Path::new("/mnt/media/base/2000/01/01/2000-01-01 00:00.jpeg"),
+            target,
+        );
+        let resolved = path::Path::new("/")
+            .join(target.strip_prefix("/mnt/media").unwrap());
+        assert_eq!(path, resolved);
+        assert_eq!(Some(&Entry::Item(tagged)), cache.lookup(&resolved));
+    }
+
+    /// Tests that clearing removes everything.
+    #[test]
+    fn test_clear() {
+        let mut cache = Cache::new("/base".into(), "tagged".into());
+
+        let item = item("test.jpg", 2000, 1, 1);
+        let expected_path =
+            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        assert_eq!(
+            expected_path,
+            cache
+                .add_item(expected_path.parent().unwrap(), item)
+                .unwrap(),
+        );
+        cache.root.clear();
+        assert_eq!(None, cache.lookup(&expected_path),);
+    }
+
+    /// Tests that only the first of several items with the same source path
+    /// is added.
+    #[test]
+    fn test_replace_all_duplicate_path() {
+        let item1 = item("test.jpg", 2000, 1, 1);
+        let mut item2 = item1.clone();
+        item2.timestamp = item("test.jpg", 2001, 1, 1).timestamp;
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        cache
+            .replace_all(vec![item1.clone(), item2].into_iter())
+            .unwrap();
+        assert_eq!(1, cache.items().len());
+        assert_eq!(
+            Some(&Entry::Item(item1)),
+            cache.lookup(&"/base/2000/01/01/2000-01-01 00:00.jpeg"),
+        );
+        assert_eq!(None, cache.lookup(&"/base/2001"));
+    }
+
+    /// Tests that items missing from a replacement are retained during the
+    /// removal grace period only.
+    #[test]
+    fn test_removal_grace() {
+        let item1 = item("test1.jpg", 2000, 1, 1);
+        let item2 = item("test2.jpg", 2000, 1, 1);
+        let second =
+            path::Path::new("/base/2000/01/01/2000-01-01 00:00 (1).jpeg");
+
+        // A quick rename keeps the item and its name
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_removal_grace(stdtime::Duration::from_secs(3600));
+        for items in [
+            vec![item1.clone(), item2.clone()],
+            vec![item1.clone()],
+            vec![item2.clone(), item1.clone()],
+        ] {
+            cache.replace_all(items.into_iter()).unwrap();
+            assert_eq!(2, cache.items().len());
+            assert_eq!(
+                Some(&Entry::Item(item2.clone())),
+                cache.lookup(&second),
+            );
+        }
+
+        // Items are removed once the grace period has passed
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_removal_grace(stdtime::Duration::from_secs(10));
+        let listed = collections::HashSet::new();
+        let start = stdtime::Instant::now();
+        for &(items, seconds, count) in &[
+            (&[&item1, &item2][..], 0, 2),
+            (&[&item1][..], 1, 2),
+            (&[&item1][..], 10, 1),
+        ] {
+            let items = items.iter().map(|&item| item.clone());
+            let now = start + stdtime::Duration::from_secs(seconds);
+            cache.replace_at(items, &listed, now).unwrap();
+            assert_eq!(count, cache.items().len());
+        }
+
+        // Without a grace period, items are removed immediately
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        cache
+            .replace_all(vec![item1.clone(), item2.clone()].into_iter())
+            .unwrap();
+        cache.replace_all(vec![item1.clone()].into_iter()).unwrap();
+        assert_eq!(vec![&item1], cache.items());
+    }
+
+    /// Tests that only items whose source file is gone are retained, and
+    /// that renamed source files are not presented twice.
+    #[test]
+    fn test_removal_grace_existing() {
+        let item1 = data::Item {
+            inode: Some((1, 1)),
+            ..item("/photos/a.jpg", 2000, 1, 1)
+        };
+        let item2 = data::Item {
+            inode: Some((1, 2)),
+            ..item("/photos/b.jpg", 2000, 1, 2)
+        };
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_removal_grace(stdtime::Duration::from_secs(3600));
+        cache
+            .replace_all(vec![item1.clone(), item2.clone()].into_iter())
+            .unwrap();
+
+        // A listed source file missing from the replacement was excluded
+        let listed = vec![item1.path.clone(), item2.path]
+            .into_iter()
+            .collect();
+        cache
+            .replace_listed(vec![item1.clone()].into_iter(), &listed)
+            .unwrap();
+        assert_eq!(vec![&item1], cache.items());
+
+        // A renamed source file is presented only under its new path
+        let renamed = data::Item {
+            path: "/photos/c.jpg".into(),
+            ..item1
+        };
+        for _ in 0..2 {
+            cache.replace_all(vec![renamed.clone()].into_iter()).unwrap();
+            assert_eq!(vec![&renamed], cache.items());
+        }
+    }
+
+    /// Tests that tags are split into nested directories on the tag
+    /// separator.
+    #[test]
+    fn test_tag_separator() {
+        let mut item = item("test.jpg", 2000, 1, 1);
+        item.tags.insert("Places>Europe".into());
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_tag_separator('>');
+        cache.replace_all(vec![item.clone()].into_iter()).unwrap();
+        assert_eq!('>', cache.tag_separator());
+        assert_eq!(
+            Some(&item),
+            cache.resolve(&"/tagged/Places/Europe/2000-01-01 00:00.jpeg"),
+        );
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        cache.replace_all(vec![item.clone()].into_iter()).unwrap();
+        assert_eq!(
+            Some(&item),
+            cache.resolve(&"/tagged/Places>Europe/2000-01-01 00:00.jpeg"),
+        );
+    }
+
+    /// Tests that tag and album parts cannot escape or nest directories.
+    #[test]
+    fn test_tag_separator_parts() {
+        let mut item = item("/photos/test.jpg", 2000, 1, 1);
+        item.tags.insert("Places>/All/2000".into());
+        item.tags.insert("Music>AC/DC".into());
+        item.tags.insert("Other>..>.".into());
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_tag_separator('>')
+            .with_albums(
+                data::Albums::parse("/photos/test.jpg = Bands>AC/DC", '>')
+                    .unwrap(),
+            );
+        cache.replace_all(vec![item.clone()].into_iter()).unwrap();
+        assert_eq!(
+            Some(&item),
+            cache.resolve(
+                &"/tagged/Places/\u{2215}All\u{2215}2000/2000-01-01 00:00.jpeg",
+            ),
+        );
+        assert_eq!(
+            Some(&item),
+            cache.resolve(&"/tagged/Music/AC\u{2215}DC/2000-01-01 00:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&item),
+            cache.resolve(&"/tagged/Other/2000-01-01 00:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&item),
+            cache.resolve(&"/Albums/Bands/AC\u{2215}DC/2000-01-01 00:00.jpeg"),
+        );
+        assert_eq!(None, cache.lookup(&"/All/2000/2000-01-01 00:00.jpeg"));
+    }
+
+    /// Tests that tags differing in case are merged when enabled.
+    #[test]
+    fn test_merge_tag_case() {
+        let tagged = |name, day, tag: &str| {
+            let mut result = item(name, 2000, 1, day);
+            result.tags.insert(tag.into());
+            result
+        };
+        let items = vec![
+            tagged("test1.jpg", 1, "Beach/Sand"),
+            tagged("test2.jpg", 2, "beach/sand"),
+            tagged("test3.jpg", 3, "BEACH"),
+        ];
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_merge_tag_case(true);
+        cache.replace_all(items.clone().into_iter()).unwrap();
+        match cache.lookup(&"/tagged") {
+            Some(Entry::Directory(_, tree)) => {
+                assert_eq!(vec!["Beach"], tree.keys().collect::<Vec<_>>())
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+        match cache.lookup(&"/tagged/Beach/Sand") {
+            Some(Entry::Directory(_, tree)) => assert_eq!(2, tree.len()),
+            e => panic!("unexpected entry {:?}", e),
+        }
+        assert!(cache.lookup(&"/tagged/Beach/2000-01-03 00:00.jpeg").is_some());
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        cache.replace_all(items.into_iter()).unwrap();
+        assert!(cache.lookup(&"/tagged/Beach/Sand").is_some());
+        assert!(cache.lookup(&"/tagged/beach/sand").is_some());
+        assert!(cache.lookup(&"/tagged/BEACH").is_some());
+    }
+
+    /// Tests that only the most specific tags produce links when enabled.
+    #[test]
+    fn test_tag_leaf_only() {
+        let mut tagged = item("test.jpg", 2000, 1, 1);
+        tagged.tags.insert("Places/France".into());
+        tagged.tags.insert("Places/France/Paris".into());
+        tagged.tags.insert("Travel".into());
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into())
+            .with_tag_leaf_only(true);
+        cache.replace_all(vec![tagged.clone()].into_iter()).unwrap();
+        assert_eq!(2, cache.references(&tagged));
+        assert!(
+            cache
+                .lookup(&"/tagged/Places/France/Paris/2000-01-01 00:00.jpeg")
+                .is_some()
+        );
+        assert!(
+            cache
+                .lookup(&"/tagged/Places/France/2000-01-01 00:00.jpeg")
+                .is_none()
+        );
+        assert!(
+            cache
+                .lookup(&"/tagged/Travel/2000-01-01 00:00.jpeg")
+                .is_some()
+        );
 
-                // Go deeper for normal files
-                path::Component::Normal(path) => {
-                    acc.and_then(|entry| match *entry {
-                        Entry::Directory(ref mut tree) => Some(
-                            tree.entry(path.to_os_string()).or_insert_with(
-                                || Entry::Directory(Tree::new()),
-                            ),
-                        ),
-                        _ => None,
-                    })
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        cache.replace_all(vec![tagged.clone()].into_iter()).unwrap();
+        assert_eq!(3, cache.references(&tagged));
+    }
+
+    /// Tests that exactly the enabled views are present under the root.
+    #[test]
+    fn test_views() {
+        let mut full = item("test.jpg", 2000, 1, 1);
+        full.tags.insert("tag".into());
+        full.people.insert("Alice".into());
+        full.camera = Some("Canon EOS 5D".into());
+        full.lens = Some("EF50mm f/1.8 STM".into());
+        full.size = Some(2 * 1024 * 1024);
+        let views = |views: &[View]| {
+            let mut cache = Cache::new("All".into(), "Tagged".into())
+                .with_views(views.iter().cloned());
+            cache.replace_all(vec![full.clone()].into_iter()).unwrap();
+            match cache.lookup(&"/") {
+                Some(Entry::Directory(_, tree)) => {
+                    let mut names = tree.keys()
+                        .map(|name| name.to_str().unwrap().to_string())
+                        .collect::<Vec<_>>();
+                    names.sort();
+                    (cache.references(&full), names)
                 }
+                e => panic!("unexpected entry {:?}", e),
+            }
+        };
 
-                // We support only absolute paths without special directories
-                _ => None,
-            })
-    }
-}
+        assert_eq!((0, vec!["All".to_string()]), views(&[]));
+        assert_eq!(
+            (
+                2,
+                vec![
+                    "All".to_string(),
+                    "Cameras".to_string(),
+                    "Tagged".to_string(),
+                ],
+            ),
+            views(&[View::Timestamps, View::Tags, View::Camera]),
+        );
+        assert_eq!(
+            (
+                2,
+                vec![
+                    "All".to_string(),
+                    "People".to_string(),
+                    "Types".to_string(),
+                ],
+            ),
+            views(&[View::People, View::Type]),
+        );
 
-#[cfg(test)]
-mod tests {
-    use data::tests::*;
-    use super::*;
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_views(View::ALL.iter().cloned());
+        cache.replace_all(vec![full.clone()].into_iter()).unwrap();
+        for path in &[
+            "/Tagged/tag/2000-01-01 00:00.jpeg",
+            "/People/Alice/2000-01-01 00:00.jpeg",
+            "/Types/image/jpeg/2000-01-01 00:00.jpeg",
+            "/Cameras/Canon EOS 5D/2000-01-01 00:00.jpeg",
+            "/Lenses/EF50mm f\u{2215}1.8 STM/2000-01-01 00:00.jpeg",
+            "/Sizes/1-5MB/2000-01-01 00:00.jpeg",
+            "/Weeks/1999/W52/2000-01-01 00:00.jpeg",
+        ] {
+            assert_eq!(Some(&full), cache.resolve(path), "{}", path);
+        }
+    }
 
-    /// Tests that looking up an empty path yields the root.
+    /// Tests that names from metadata are used as single directories.
     #[test]
-    fn test_lookup_root() {
-        let valid: path::PathBuf = ["/"].iter().collect();
-        let cache = Cache::new("/base".into(), "tagged".into());
+    fn test_view_directory_names() {
+        let mut item = item("test.jpg", 2000, 1, 1);
+        item.people.insert("..".into());
+        item.people.insert("AC/DC".into());
+        item.camera = Some("../../All/Model 1/2".into());
+        item.lens = Some("..".into());
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_views(View::ALL.iter().cloned());
+        cache.replace_all(vec![item.clone()].into_iter()).unwrap();
 
-        assert!(cache.lookup(&valid).is_some());
+        for path in &[
+            "/People/AC\u{2215}DC/2000-01-01 00:00.jpeg",
+            "/Cameras/..\u{2215}..\u{2215}All\u{2215}Model 1\u{2215}2/\
+             2000-01-01 00:00.jpeg",
+        ] {
+            assert_eq!(Some(&item), cache.resolve(path), "{}", path);
+        }
+        assert_eq!(None, cache.lookup(&"/Lenses"));
+        for path in &["/People", "/Cameras"] {
+            match cache.lookup(path) {
+                Some(Entry::Directory(_, tree)) => {
+                    assert_eq!(1, tree.len())
+                }
+                e => panic!("unexpected entry {:?}", e),
+            }
+        }
+        assert_eq!(4, cache.references(&item));
     }
 
-    /// Tests that adding an item immediately under the root works.
+    /// Tests that items are linked from the ISO 8601 week during which they
+    /// were generated, and that undated items are not.
     #[test]
-    fn test_add_item_simple() {
-        let mut cache = Cache::new("/base".into(), "tagged".into());
+    fn test_weeks() {
+        let item1 = item("test1.jpg", 2008, 12, 29);
+        let item2 = item("test2.jpg", 2009, 1, 5);
+        let undated = data::Item {
+            timestamp: data::Timestamp::undated(),
+            ..item("test3.jpg", 2000, 1, 1)
+        };
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_views(vec![View::Weeks]);
+        cache
+            .replace_all(
+                vec![item1.clone(), item2.clone(), undated.clone()]
+                    .into_iter(),
+            )
+            .unwrap();
+
+        match cache.lookup(&"/Weeks/2009") {
+            Some(Entry::Directory(_, tree)) => assert_eq!(2, tree.len()),
+            e => panic!("unexpected entry {:?}", e),
+        }
+        assert_eq!(
+            Some(&item1),
+            cache.resolve(&"/Weeks/2009/W01/2008-12-29 00:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&item2),
+            cache.resolve(&"/Weeks/2009/W02/2009-01-05 00:00.jpeg"),
+        );
+        assert_eq!(None, cache.lookup(&"/Weeks/2008"));
+        assert_eq!(0, cache.references(&undated));
+    }
 
+    /// Tests that items without lens information are grouped as unknown.
+    #[test]
+    fn test_lens_unknown() {
         let item = item("test.jpg", 2000, 1, 1);
-        let expected_path =
-            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_views(vec![View::Lens]);
+        cache.replace_all(vec![item.clone()].into_iter()).unwrap();
+
         assert_eq!(
-            expected_path,
-            cache
-                .add_item(expected_path.parent().unwrap(), item.clone())
-                .unwrap(),
+            Some(&item),
+            cache.resolve(&"/Lenses/Unknown/2000-01-01 00:00.jpeg"),
         );
-        assert_eq!(Some(&Entry::Item(item)), cache.lookup(&expected_path),);
     }
 
-    /// Tests that adding an item over a directory works.
+    /// Tests that the roots of the lens, size and week views can be changed.
     #[test]
-    fn test_add_item_twice() {
-        let mut cache = Cache::new("/base".into(), "tagged".into());
+    fn test_view_roots() {
+        let mut item = item("test.jpg", 2000, 1, 1);
+        item.lens = Some("EF50mm".into());
+        item.size = Some(1024);
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_views(View::ALL.iter().cloned())
+            .with_lenses_root("ByLens".into())
+            .with_sizes_root("BySize".into())
+            .with_weeks_root("ByWeek".into());
+        cache.replace_all(vec![item.clone()].into_iter()).unwrap();
 
-        let item1 = item("test1.jpg", 2000, 1, 1);
-        let expected_path1 =
-            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        for path in &[
+            "/ByLens/EF50mm/2000-01-01 00:00.jpeg",
+            "/BySize/<1MB/2000-01-01 00:00.jpeg",
+            "/ByWeek/1999/W52/2000-01-01 00:00.jpeg",
+        ] {
+            assert_eq!(Some(&item), cache.resolve(path), "{}", path);
+        }
+        for path in &["/Lenses", "/Sizes", "/Weeks"] {
+            assert_eq!(None, cache.lookup(path));
+        }
+    }
+
+    /// Tests that items are linked from their albums.
+    #[test]
+    fn test_albums() {
+        let pinned = item("/photos/test1.jpg", 2000, 1, 1);
+        let other = item("/photos/test2.jpg", 2000, 1, 2);
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_albums("/photos/test1.jpg = Favorites/2020".parse().unwrap());
+        cache
+            .replace_all(vec![pinned.clone(), other].into_iter())
+            .unwrap();
+
+        match cache.lookup(&"/Albums/Favorites/2020") {
+            Some(Entry::Directory(_, tree)) => assert_eq!(1, tree.len()),
+            e => panic!("unexpected entry {:?}", e),
+        }
         assert_eq!(
-            expected_path1,
-            cache
-                .add_item(expected_path1.parent().unwrap(), item1.clone())
-                .unwrap(),
+            Some(&pinned),
+            cache.resolve(&"/Albums/Favorites/2020/2000-01-01 00:00.jpeg"),
         );
+        assert_eq!(1, cache.references(&pinned));
+    }
 
-        let item2 = item("test2.jpg", 2000, 1, 1);
-        let expected_path2 =
-            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00 (1).jpeg");
+    /// Tests that duplicate items are added only once.
+    #[test]
+    fn test_identity() {
+        let dir = tempdir::TempDir::new("medifs-cache").unwrap();
+        let original =
+            item_with_data(dir.path().join("a.jpg"), b"abc", 2000, 1, 1);
+        let copy = item_with_data(dir.path().join("b.jpg"), b"abc", 2000, 1, 2);
+        let items = vec![original.clone(), copy.clone()];
+
+        let mut cache = Cache::new("All".into(), "Tagged".into());
+        cache.replace_all(identified(&cache, &items)).unwrap();
+        assert_eq!(2, cache.items().len());
+
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_identity(data::Identity::Hash);
+        cache.replace_all(identified(&cache, &items)).unwrap();
+        assert_eq!(vec![&original], cache.items());
         assert_eq!(
-            expected_path2,
+            path::PathBuf::from("All/2000/01/01/2000-01-01 00:00.jpeg"),
             cache
-                .add_item(expected_path2.parent().unwrap(), item2.clone())
+                .add(
+                    identified(&cache, std::slice::from_ref(&copy))
+                        .next()
+                        .unwrap()
+                )
                 .unwrap(),
         );
-        assert_eq!(Some(&Entry::Item(item2)), cache.lookup(&expected_path2),);
+        assert_eq!(1, cache.items().len());
+
+        // Items without a calculated identity are compared by path only
+        cache.add(copy).unwrap();
+        assert_eq!(2, cache.items().len());
     }
 
-    /// Tests that timestamps are correct.
+    /// Tests that persisted checksums are reused when detecting duplicates.
     #[test]
-    fn test_timestamp() {
-        let mut cache = Cache::new("/base".into(), "tagged".into());
+    fn test_hash_database() {
+        let dir = tempdir::TempDir::new("medifs-cache").unwrap();
+        let database = dir.path().join("hashes");
+        let original =
+            item_with_data(dir.path().join("a.jpg"), b"abc", 2000, 1, 1);
+        let copy = item_with_data(dir.path().join("b.jpg"), b"abc", 2000, 1, 2);
+        let items = vec![original.clone(), copy];
+        let run = || {
+            let mut cache = Cache::new("All".into(), "Tagged".into())
+                .with_identity(data::Identity::Hash)
+                .with_hash_database(
+                    data::HashDatabase::open(&database).unwrap(),
+                );
+            cache.replace_all(identified(&cache, &items)).unwrap();
+            cache.items().into_iter().cloned().collect::<Vec<_>>()
+        };
 
-        let item1 = item("test1.jpg", 2000, 1, 1);
-        let expected_path1 =
-            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        assert_eq!(vec![original.clone()], run());
+        let content = fs::read_to_string(&database).unwrap();
+        assert_eq!(2, content.lines().count());
+
+        // The second run uses the stored checksums without reading the files
+        let digest = content.split(' ').next().unwrap().to_string();
+        fs::write(&database, content.replace(&digest, "cached")).unwrap();
+        assert_eq!(vec![original], run());
+        assert!(
+            fs::read_to_string(&database)
+                .unwrap()
+                .lines()
+                .all(|line| line.starts_with("cached "))
+        );
+    }
+
+    /// Calculates the identities of items as a source does before adding
+    /// them.
+    ///
+    /// # Arguments
+    /// *  `cache` - The cache to which the items are added.
+    /// *  `items` - The items.
+    fn identified(
+        cache: &Cache,
+        items: &[data::Item],
+    ) -> vec::IntoIter<data::Item> {
+        let identifier = cache.identifier();
+        items
+            .iter()
+            .map(|item| data::Item {
+                identity: identifier.key(item),
+                ..item.clone()
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Tests that source extensions differing only in case are the same item
+    /// when normalised.
+    #[test]
+    fn test_normalize_ext() {
+        let items = vec![
+            item("/a/IMG_0001.JPG", 2000, 1, 1),
+            item("/a/IMG_0001.jpg", 2000, 1, 1),
+            item("/b/IMG_0001.jpg", 2000, 1, 1),
+        ];
+
+        let mut cache = Cache::new("All".into(), "Tagged".into());
+        cache.replace_all(items.clone().into_iter()).unwrap();
+        assert_eq!(3, cache.items().len());
+
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_normalize_ext(true);
+        cache.replace_all(items.clone().into_iter()).unwrap();
+        let mut added = cache.items();
+        added.sort_by_key(|item| item.path.clone());
+        assert_eq!(vec![&items[0], &items[2]], added);
         assert_eq!(
-            expected_path1,
+            path::PathBuf::from("All/2000/01/01/2000-01-01 00:00.jpeg"),
+            cache.add(item("/a/IMG_0001.Jpg", 2000, 1, 1)).unwrap(),
+        );
+        assert_eq!(2, cache.items().len());
+    }
+
+    /// Tests that sidecar files are exposed next to their items.
+    #[test]
+    fn test_expose_sidecars() {
+        let dir = tempdir::TempDir::new("medifs-cache").unwrap();
+        let mut photo =
+            item_with_data(dir.path().join("a.jpg"), b"abc", 2000, 1, 1);
+        let sidecar = dir.path().join("a.XMP");
+        fs::write(&sidecar, b"<x:xmpmeta/>").unwrap();
+        photo.sidecars.push(sidecar.clone());
+
+        let mut cache = Cache::new("All".into(), "Tagged".into());
+        cache.add(photo.clone()).unwrap();
+        assert_eq!(
+            None,
+            cache.lookup(&"/All/2000/01/01/2000-01-01 00:00.xmp"),
+        );
+
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_expose_sidecars(true);
+        cache.add(photo.clone()).unwrap();
+        assert_eq!(
+            Some(&Entry::Item(photo.clone())),
+            cache.lookup(&"/All/2000/01/01/2000-01-01 00:00.jpeg"),
+        );
+        match cache.lookup(&"/All/2000/01/01/2000-01-01 00:00.xmp") {
+            Some(Entry::Sidecar(_, path)) => assert_eq!(sidecar, *path),
+            entry => panic!("unexpected entry: {:?}", entry),
+        }
+        assert_eq!(vec![&photo], cache.items());
+    }
+
+    /// Tests that items and links deeper than the maximum depth are rejected
+    /// without creating their directories.
+    #[test]
+    fn test_max_depth() {
+        let mut tagged = item("test.jpg", 2000, 1, 1);
+        tagged.tags.insert("a/b/c".into());
+        tagged.tags.insert("a/b/c/d".into());
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_max_depth(5);
+        let path =
+            path::Path::new("/").join(cache.add(tagged.clone()).unwrap());
+        assert!(cache.lookup(&path).is_some());
+        assert!(
             cache
-                .add_item(expected_path1.parent().unwrap(), item1.clone())
-                .unwrap(),
+                .lookup(&"/Tagged/a/b/c/2000-01-01 00:00.jpeg")
+                .is_some()
+        );
+        assert_eq!(None, cache.lookup(&"/Tagged/a/b/c/d"));
+        assert_eq!(1, cache.references(&tagged));
+
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_max_depth(4);
+        assert!(cache.add(item("test.jpg", 2000, 1, 1)).is_err());
+        assert_eq!(None, cache.lookup(&"/All"));
+    }
+
+    /// Tests that chains of single date directories are collapsed, and that
+    /// links follow.
+    #[test]
+    fn test_collapse_single() {
+        let mut tagged = item("test2.jpg", 2020, 1, 2);
+        tagged.tags.insert("a".into());
+        let items = vec![item("test1.jpg", 2020, 1, 1), tagged.clone()];
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_collapse_single(true);
+        cache.replace_all(items.into_iter()).unwrap();
+
+        assert_eq!(None, cache.lookup(&"/All/2020"));
+        for path in &[
+            "/All/2020-01/01/2020-01-01 00:00.jpeg",
+            "/All/2020-01/02/2020-01-02 00:00.jpeg",
+        ] {
+            assert!(cache.lookup(path).is_some());
+        }
+        assert_eq!(
+            Some(&Entry::Link(
+                tagged.timestamp.to_timespec(),
+                "../../All/2020-01/02/2020-01-02 00:00.jpeg".into(),
+            )),
+            cache.lookup(&"/Tagged/a/2020-01-02 00:00.jpeg"),
         );
         assert_eq!(
-            Some(item1.timestamp.as_ref().to_timespec()),
-            cache.lookup(&"/").map(|e| e.timestamp()),
+            Some(&tagged),
+            cache.resolve(&"/Tagged/a/2020-01-02 00:00.jpeg"),
         );
+    }
 
-        let item2 = item("test2.jpg", 2000, 1, 2);
+    /// Tests that items dated by their modification time are kept apart when
+    /// enabled.
+    #[test]
+    fn test_modified_root() {
+        let exif = item("exif.jpg", 2020, 1, 1);
+        let mut modified = item("modified.jpg", 2020, 1, 1);
+        modified.date_source = data::DateSource::Modified;
+        modified.tags.insert("a".into());
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_modified_root("NoExif".into());
         cache
-            .add_item(expected_path1.parent().unwrap(), item2.clone())
+            .replace_all(vec![exif.clone(), modified.clone()].into_iter())
             .unwrap();
+
         assert_eq!(
-            Some(item2.timestamp.as_ref().to_timespec()),
-            cache.lookup(&"/").map(|e| e.timestamp()),
+            Some(&Entry::Item(exif)),
+            cache.lookup(&"/All/2020/01/01/2020-01-01 00:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&Entry::Item(modified.clone())),
+            cache.lookup(&"/NoExif/2020/01/01/2020-01-01 00:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&modified),
+            cache.resolve(&"/Tagged/a/2020-01-01 00:00.jpeg"),
         );
+        assert_eq!(2, cache.items().len());
     }
 
-    /// Tests that clearing removes everything.
+    /// Tests that the type view is grouped by friendly groups when enabled.
     #[test]
-    fn test_clear() {
-        let mut cache = Cache::new("/base".into(), "tagged".into());
+    fn test_type_groups() {
+        let groups = concat!(
+            "image/jpeg = Photos\n",
+            "image/x-canon-cr2 = RAW\n",
+            "image/x-nikon-nef = RAW\n",
+        ).parse::<data::TypeGroups>()
+            .unwrap();
+        let items = vec![
+            item("a.jpg", 2000, 1, 1),
+            item("b.cr2", 2000, 1, 2),
+            item("c.nef", 2000, 1, 3),
+            item("d.png", 2000, 1, 4),
+        ];
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_views(vec![View::Timestamps, View::Type])
+            .with_type_groups(groups);
+        cache.replace_all(items.clone().into_iter()).unwrap();
 
-        let item = item("test.jpg", 2000, 1, 1);
-        let expected_path =
-            path::PathBuf::from("/base/2000/01/01/2000-01-01 00:00.jpeg");
+        for (path, item) in [
+            "/Types/Photos/2000-01-01 00:00.jpeg",
+            "/Types/RAW/2000-01-02 00:00.cr2",
+            "/Types/RAW/2000-01-03 00:00.nef",
+            "/Types/Other/2000-01-04 00:00.png",
+        ].iter()
+            .zip(items.iter())
+        {
+            assert_eq!(Some(item), cache.resolve(path), "{}", path);
+        }
+        assert_eq!(None, cache.lookup(&"/Types/image"));
+    }
+
+    /// Tests that problematic files are linked from the quarantine only when
+    /// enabled, and that the links are removed when items are replaced.
+    #[test]
+    fn test_quarantine() {
+        let source = path::Path::new("/source/a/test.jpg");
+        let other = path::Path::new("/source/b/test.jpg");
+        let mut cache = Cache::new("All".into(), "Tagged".into());
+        assert_eq!(None, cache.add_problem(Problem::NoDate, source));
+        assert_eq!(None, cache.lookup(&"/Problems"));
+
+        let mut cache = cache.with_quarantine(true);
         assert_eq!(
-            expected_path,
+            Some(path::PathBuf::from("Problems/no-date/test.jpg")),
+            cache.add_problem(Problem::NoDate, source),
+        );
+        assert_eq!(
+            Some(path::PathBuf::from("Problems/no-date/test (1).jpg")),
+            cache.add_problem(Problem::NoDate, other),
+        );
+        cache.add_problem(Problem::Unreadable, other);
+        assert_eq!(
+            Some(&Entry::Link(
+                time::Timespec::new(0, 0),
+                other.as_os_str().to_os_string(),
+            )),
+            cache.lookup(&"/Problems/unreadable/test.jpg"),
+        );
+        assert_eq!(None, cache.resolve(&"/Problems/unreadable/test.jpg"));
+
+        cache
+            .replace_all(vec![item("test.jpg", 2020, 1, 1)].into_iter())
+            .unwrap();
+        assert_eq!(None, cache.lookup(&"/Problems"));
+    }
+
+    /// Tests that links in tag directories are nested in date directories
+    /// when enabled, and that they can still be removed.
+    #[test]
+    fn test_tag_date_nesting() {
+        let mut tagged = item("test.jpg", 2020, 1, 2);
+        tagged.tags.insert("Family".into());
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_tag_date_nesting(true);
+        cache.replace_all(vec![tagged.clone()].into_iter()).unwrap();
+
+        let link = "/Tagged/Family/2020/01/02/2020-01-02 00:00.jpeg";
+        assert_eq!(
+            Some(&Entry::Link(
+                tagged.timestamp.to_timespec(),
+                "../../../../../All/2020/01/02/2020-01-02 00:00.jpeg".into(),
+            )),
+            cache.lookup(&link),
+        );
+        assert_eq!(Some(&tagged), cache.resolve(&link));
+
+        let (_, tag) = cache.remove_tag(&link).unwrap();
+        assert_eq!("Family", tag);
+        assert_eq!(None, cache.lookup(&"/Tagged/Family"));
+    }
+
+    /// Tests that items generated before the start of the day are filed under
+    /// the previous day, but keep their actual time in their names.
+    #[test]
+    fn test_day_start() {
+        let late = data::Item {
+            timestamp: (2020, 1, 2, 2, 0, 0).into(),
+            ..item("late.jpg", 2020, 1, 2)
+        };
+        let morning = data::Item {
+            timestamp: (2020, 1, 2, 5, 0, 0).into(),
+            ..item("morning.jpg", 2020, 1, 2)
+        };
+        let mut cache =
+            Cache::new("All".into(), "Tagged".into()).with_day_start(4);
+        cache
+            .add_iter(vec![late.clone(), morning.clone()].into_iter())
+            .unwrap();
+
+        assert_eq!(
+            Some(&Entry::Item(late)),
+            cache.lookup(&"/All/2020/01/01/2020-01-02 02:00.jpeg"),
+        );
+        assert_eq!(
+            Some(&Entry::Item(morning)),
+            cache.lookup(&"/All/2020/01/02/2020-01-02 05:00.jpeg"),
+        );
+    }
+
+    /// Tests that links of a tag with enough items spanning several years are
+    /// grouped by year, that other tags remain flat, and that grouped links
+    /// can still be removed.
+    #[test]
+    fn test_tag_year_split() {
+        let tagged = |name, year, month, day, tag: &str| {
+            let mut result = item(name, year, month, day);
+            result.tags.insert(tag.into());
+            result
+        };
+        let first = tagged("a.jpg", 2019, 1, 2, "Family");
+        let items = vec![
+            first.clone(),
+            tagged("b.jpg", 2019, 3, 4, "Family"),
+            tagged("c.jpg", 2020, 5, 6, "Family"),
+            tagged("d.jpg", 2021, 1, 1, "Trips"),
+            tagged("e.jpg", 2021, 2, 2, "Trips"),
+        ];
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_tag_year_split(2);
+        cache.replace_all(items.into_iter()).unwrap();
+
+        let link = "/Tagged/Family/2019/2019-01-02 00:00.jpeg";
+        assert_eq!(
+            Some(&Entry::Link(
+                first.timestamp.to_timespec(),
+                "../../../All/2019/01/02/2019-01-02 00:00.jpeg".into(),
+            )),
+            cache.lookup(&link),
+        );
+        assert!(
             cache
-                .add_item(expected_path.parent().unwrap(), item.clone())
-                .unwrap(),
+                .lookup(&"/Tagged/Family/2020/2020-05-06 00:00.jpeg")
+                .is_some()
+        );
+        assert!(
+            cache
+                .lookup(&"/Tagged/Trips/2021-01-01 00:00.jpeg")
+                .is_some()
+        );
+        assert_eq!(None, cache.lookup(&"/Tagged/Trips/2021"));
+
+        let (_, tag) = cache.remove_tag(&link).unwrap();
+        assert_eq!("Family", tag);
+        assert_eq!(None, cache.lookup(&link));
+    }
+
+    /// Tests that views are parsed by name.
+    #[test]
+    fn view_from_str() {
+        for view in View::ALL {
+            assert_eq!(Ok(*view), view.to_string().parse::<View>());
+        }
+        assert!("camera,tags".parse::<View>().is_err());
+    }
+
+    /// Calculates the timestamp of an entry from its child entries.
+    ///
+    /// # Arguments
+    /// *  `entry` - The entry.
+    fn recursive_timestamp(entry: &Entry) -> time::Timespec {
+        match entry {
+            Entry::Directory(_, tree) => tree.values()
+                .map(recursive_timestamp)
+                .max()
+                .unwrap_or(time::Timespec::new(0, 0)),
+            entry => entry.timestamp(),
+        }
+    }
+
+    /// Asserts that the timestamps of an entry and all entries below it match
+    /// the recursively calculated timestamps.
+    ///
+    /// # Arguments
+    /// *  `entry` - The entry to check.
+    fn assert_timestamps(entry: &Entry) {
+        assert_eq!(recursive_timestamp(entry), entry.timestamp());
+        if let Entry::Directory(_, tree) = entry {
+            tree.values().for_each(assert_timestamps);
+        }
+    }
+
+    /// Tests that the cached directory timestamps match the recursively
+    /// calculated ones.
+    #[test]
+    fn test_directory_timestamp() {
+        let mut cache = Cache::new("All".into(), "Tagged".into())
+            .with_folders_root("Folders".into())
+            .with_page_size(1);
+        let items = (1..10)
+            .map(|i| {
+                let mut result = item(&format!("test{}.jpg", i), 2000, i, i);
+                result.tags.insert(format!("tag{}", i % 3));
+                result.rel_path = Some(format!("dir{}/test.jpg", i % 2).into());
+                result
+            })
+            .collect::<Vec<_>>();
+
+        cache.add_iter(items.iter().rev().cloned()).unwrap();
+        assert_timestamps(&cache.root);
+        assert_eq!(
+            Some(items[8].timestamp.to_timespec()),
+            cache.lookup(&"/").map(Entry::timestamp),
+        );
+
+        cache.replace_all(items.clone().into_iter().take(4)).unwrap();
+        assert_timestamps(&cache.root);
+        assert_eq!(
+            Some(items[3].timestamp.to_timespec()),
+            cache.lookup(&"/").map(Entry::timestamp),
         );
+
         cache.root.clear();
-        assert_eq!(None, cache.lookup(&expected_path),);
+        assert_timestamps(&cache.root);
+        assert_eq!(
+            Some(time::Timespec::new(0, 0)),
+            cache.lookup(&"/").map(Entry::timestamp),
+        );
+    }
+
+    /// Tests that items generated during the same second are ordered by their
+    /// sub-second timestamps when enabled.
+    #[test]
+    fn test_order_by_exif_serial() {
+        let burst = |name, subsecond| data::Item {
+            subsecond,
+            ..item(name, 2000, 1, 1)
+        };
+        let items = vec![
+            burst("IMG_0010.jpg", Some(500_000_000)),
+            burst("IMG_0008.jpg", Some(300_000_000)),
+            burst("IMG_0009.jpg", Some(100_000_000)),
+            burst("IMG_9.jpg", None),
+            burst("IMG_10.jpg", None),
+        ];
+        let names = |order_by| {
+            let mut cache = Cache::new("/base".into(), "/tagged".into())
+                .with_order_by(order_by);
+            cache.replace_all(items.clone().into_iter()).unwrap();
+            (0..items.len())
+                .map(|i| {
+                    let name = if i == 0 {
+                        "2000-01-01 00:00.jpeg".to_string()
+                    } else {
+                        format!("2000-01-01 00:00 ({}).jpeg", i)
+                    };
+                    let path = path::Path::new("/base/2000/01/01").join(name);
+                    match cache.lookup(&path) {
+                        Some(Entry::Item(item)) => item.path.clone(),
+                        e => panic!("unexpected entry {:?}", e),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let paths = |names: &[&str]| {
+            names.iter().map(path::PathBuf::from).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            paths(&[
+                "IMG_9.jpg",
+                "IMG_10.jpg",
+                "IMG_0009.jpg",
+                "IMG_0008.jpg",
+                "IMG_0010.jpg",
+            ]),
+            names(OrderBy::ExifSerial),
+        );
+        assert_eq!(
+            paths(&[
+                "IMG_0008.jpg",
+                "IMG_0009.jpg",
+                "IMG_0010.jpg",
+                "IMG_10.jpg",
+                "IMG_9.jpg",
+            ]),
+            names(OrderBy::Path),
+        );
+    }
+
+    /// Tests that file names are ordered by serial with their extensions in
+    /// lower case when extensions are normalised.
+    #[test]
+    fn test_order_by_exif_serial_normalize_ext() {
+        let items = vec![
+            item("/b/IMG_0001.JPG", 2000, 1, 1),
+            item("/a/IMG_0001.jpeg", 2000, 1, 1),
+        ];
+        let first = |normalize_ext| {
+            let mut cache = Cache::new("/base".into(), "/tagged".into())
+                .with_order_by(OrderBy::ExifSerial)
+                .with_normalize_ext(normalize_ext);
+            cache.replace_all(items.clone().into_iter()).unwrap();
+            match cache.lookup(&"/base/2000/01/01/2000-01-01 00:00.jpeg") {
+                Some(Entry::Item(item)) => item.path.clone(),
+                e => panic!("unexpected entry {:?}", e),
+            }
+        };
+
+        assert_eq!(items[0].path, first(false));
+        assert_eq!(items[1].path, first(true));
+    }
+
+    /// Tests that undated items are added to the undated directory.
+    #[test]
+    fn test_undated() {
+        let undated = data::Item {
+            timestamp: data::Timestamp::undated(),
+            ..item("test.jpg", 2000, 1, 1)
+        };
+
+        let mut cache = Cache::new("/base".into(), "/tagged".into());
+        assert_eq!(
+            path::PathBuf::from("/base/0000/undated/undated.jpeg"),
+            cache.add(undated.clone()).unwrap(),
+        );
+        assert_eq!(
+            path::PathBuf::from("/base/0000/undated/undated (1).jpeg"),
+            cache.add(undated).unwrap(),
+        );
+    }
+
+    /// Tests that links of both styles are resolved to the item.
+    #[test]
+    fn test_resolve() {
+        let mut tagged = item("test.jpg", 2000, 1, 1);
+        tagged.tags.insert("a/b".into());
+        let link = path::Path::new("/tagged/a/b/2000-01-01 00:00.jpeg");
+
+        for link_style in [
+            LinkStyle::Relative,
+            LinkStyle::Absolute("/mnt/media".into()),
+        ] {
+            let mut cache = Cache::new("/base".into(), "/tagged".into())
+                .with_link_style(link_style);
+            let path = cache.add(tagged.clone()).unwrap();
+            assert_eq!(Some(&tagged), cache.resolve(&path));
+            assert_eq!(Some(&tagged), cache.resolve(&link));
+            assert_eq!(None, cache.resolve(&"/tagged/a"));
+            assert_eq!(None, cache.resolve(&"/tagged/c"));
+        }
     }
 }
@@ -0,0 +1,139 @@
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+
+/// The signature of PNG files.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The extensions of the formats whose headers are read.
+const EXTENSIONS: &[&str] = &["gif", "jpeg", "jpg", "png"];
+
+/// The maximum number of bytes read when looking for the dimensions.
+const HEADER_LIMIT: u64 = 256 * 1024;
+
+/// Reads the width and height in pixels of an image from its header.
+///
+/// PNG, GIF and JPEG images are supported. Files with other extensions are
+/// not opened, and only the beginning of the file is read, so if the
+/// dimensions are not found there, or the format is unknown, `None` is
+/// returned.
+///
+/// # Arguments
+/// *  `path` - The path of the image.
+pub fn dimensions(path: &path::Path) -> Option<(u32, u32)> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if !EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+
+    let mut header = Vec::new();
+    fs::File::open(path)
+        .and_then(|f| f.take(HEADER_LIMIT).read_to_end(&mut header))
+        .ok()?;
+    parse(&header)
+}
+
+/// Parses the width and height in pixels from an image header.
+///
+/// # Arguments
+/// *  `header` - The beginning of the image file.
+fn parse(header: &[u8]) -> Option<(u32, u32)> {
+    if header.starts_with(PNG_SIGNATURE) {
+        // The IHDR chunk always comes first
+        if header.get(12..16)? == b"IHDR" {
+            Some((be32(header.get(16..20)?), be32(header.get(20..24)?)))
+        } else {
+            None
+        }
+    } else if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        let size = header.get(6..10)?;
+        Some((
+            u32::from(size[0]) | u32::from(size[1]) << 8,
+            u32::from(size[2]) | u32::from(size[3]) << 8,
+        ))
+    } else if header.starts_with(b"\xFF\xD8") {
+        jpeg(&mut io::Cursor::new(&header[2..])).ok()?
+    } else {
+        None
+    }
+}
+
+/// Finds the dimensions of a JPEG image in the frame header.
+///
+/// # Arguments
+/// *  `source` - The data following the start of image marker.
+fn jpeg<R: Read>(source: &mut R) -> io::Result<Option<(u32, u32)>> {
+    let mut buffer = [0u8; 2];
+    loop {
+        source.read_exact(&mut buffer[..1])?;
+        if buffer[0] != 0xFF {
+            return Ok(None);
+        }
+
+        // Markers may be preceded by any number of fill bytes
+        let marker = loop {
+            source.read_exact(&mut buffer[..1])?;
+            if buffer[0] != 0xFF {
+                break buffer[0];
+            }
+        };
+
+        // Markers without a segment
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            continue;
+        }
+        source.read_exact(&mut buffer[..2])?;
+        let length = u16::from(buffer[0]) << 8 | u16::from(buffer[1]);
+        if length < 2 {
+            return Ok(None);
+        }
+
+        // Any start of frame marker except DHT, JPG and DAC
+        if (0xC0..=0xCF).contains(&marker)
+            && marker != 0xC4
+            && marker != 0xC8
+            && marker != 0xCC
+        {
+            let mut frame = [0u8; 5];
+            source.read_exact(&mut frame)?;
+            let height = u32::from(frame[1]) << 8 | u32::from(frame[2]);
+            let width = u32::from(frame[3]) << 8 | u32::from(frame[4]);
+            return Ok(Some((width, height)));
+        }
+        io::copy(
+            &mut source.by_ref().take(u64::from(length) - 2),
+            &mut io::sink(),
+        )?;
+    }
+}
+
+/// Reads a big endian 32 bit value.
+///
+/// # Arguments
+/// *  `bytes` - The four bytes of the value.
+fn be32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, &b| acc << 8 | u32::from(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the dimensions are read from known headers.
+    #[test]
+    fn parse_headers() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(b"\0\0\0\x0DIHDR\0\0\x01\x40\0\0\0\xF0");
+        assert_eq!(Some((320, 240)), parse(&png));
+
+        assert_eq!(Some((320, 240)), parse(b"GIF89a\x40\x01\xF0\x00"));
+
+        let jpeg = b"\xFF\xD8\xFF\xE0\x00\x04\x00\x00\
+                     \xFF\xC0\x00\x11\x08\x00\xF0\x01\x40";
+        assert_eq!(Some((320, 240)), parse(jpeg));
+
+        assert_eq!(None, parse(b"\xFF\xD8\xFF\xE0\x00\x04"));
+        assert_eq!(None, parse(b"hello world"));
+    }
+}
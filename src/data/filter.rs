@@ -0,0 +1,155 @@
+use std::collections;
+use std::str;
+use std::sync;
+
+use time;
+
+use super::{Item, Query, Timestamp};
+
+/// The format used for dates in filter configurations.
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A filter selecting which items to include.
+///
+/// A filter is read from a configuration with one `key = value` setting per
+/// line. Empty lines and lines starting with `#` are ignored. The supported
+/// keys are:
+///
+/// *  `after` - Only items generated on or after this date, on the form
+///    `YYYY-MM-DD`, are included.
+/// *  `before` - Only items generated before this date are included.
+/// *  `tag` - Only items with at least one of the tags are included. This key
+///    may be repeated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    /// The earliest timestamp of included items.
+    pub after: Option<Timestamp>,
+
+    /// The timestamp before which items are included.
+    pub before: Option<Timestamp>,
+
+    /// The tags of included items. If this is empty, tags are not considered.
+    pub tags: collections::HashSet<String>,
+
+    /// A query that included items must match.
+    pub query: Option<Query>,
+}
+
+impl Filter {
+    /// Returns whether an item passes this filter.
+    ///
+    /// # Arguments
+    /// *  `item` - The item to check.
+    pub fn matches(&self, item: &Item) -> bool {
+        self.after.as_ref().map(|t| &item.timestamp >= t).unwrap_or(true)
+            && self.before.as_ref().map(|t| &item.timestamp < t).unwrap_or(true)
+            && (self.tags.is_empty()
+                || self.tags.iter().any(|t| item.tags.contains(t)))
+            && self.query.as_ref().map(|q| q.matches(item)).unwrap_or(true)
+    }
+
+    /// Parses a date value.
+    ///
+    /// # Arguments
+    /// *  `value` - The value to parse.
+    fn date(value: &str) -> Result<Timestamp, String> {
+        time::strptime(value, DATE_FORMAT)
+            .map(Timestamp::from)
+            .map_err(|e| format!("invalid date {}: {}", value, e))
+    }
+}
+
+impl str::FromStr for Filter {
+    type Err = String;
+
+    /// Parses a filter configuration.
+    ///
+    /// The error describes the first invalid line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|&(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .fold(Ok(Filter::default()), |acc, (number, line)| {
+                acc.and_then(|mut filter| {
+                    let mut parts = line.splitn(2, '=').map(str::trim);
+                    match (parts.next(), parts.next()) {
+                        (Some("after"), Some(value)) => {
+                            filter.after = Some(Self::date(value)?)
+                        }
+                        (Some("before"), Some(value)) => {
+                            filter.before = Some(Self::date(value)?)
+                        }
+                        (Some("tag"), Some(value)) => {
+                            filter.tags.insert(value.into());
+                        }
+                        _ => {
+                            return Err(format!(
+                                "invalid setting on line {}: {}",
+                                number, line
+                            ))
+                        }
+                    }
+                    Ok(filter)
+                })
+            })
+    }
+}
+
+/// A filter shared between the source and whatever reconfigures it.
+pub type SharedFilter = sync::Arc<sync::RwLock<Filter>>;
+
+/// Constructs a sharable filter from an owned filter.
+///
+/// # Arguments
+/// *  `filter` - The filter.
+pub fn shared_filter(filter: Filter) -> SharedFilter {
+    sync::Arc::new(sync::RwLock::new(filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use data::tests::*;
+    use super::*;
+
+    /// Tests that an empty configuration yields the default filter.
+    #[test]
+    fn from_str_empty() {
+        assert_eq!(Ok(Filter::default()), "".parse::<Filter>());
+        assert_eq!(
+            Ok(Filter::default()),
+            "# comment\n\n  \n".parse::<Filter>(),
+        );
+    }
+
+    /// Tests that invalid configurations are rejected.
+    #[test]
+    fn from_str_invalid() {
+        assert!("unknown = 1".parse::<Filter>().is_err());
+        assert!("after".parse::<Filter>().is_err());
+        assert!("after = yesterday".parse::<Filter>().is_err());
+    }
+
+    /// Tests that all settings are applied.
+    #[test]
+    fn matches() {
+        let filter = concat!(
+            "after = 2000-01-02\n",
+            "before = 2000-01-04\n",
+            "tag = a\n",
+            "tag = b\n",
+        ).parse::<Filter>()
+            .unwrap();
+        let tagged = |day, tag: &str| {
+            let mut result = item("test.jpg", 2000, 1, day);
+            result.tags.insert(tag.into());
+            result
+        };
+
+        assert!(!filter.matches(&tagged(1, "a")));
+        assert!(filter.matches(&tagged(2, "a")));
+        assert!(filter.matches(&tagged(3, "b")));
+        assert!(!filter.matches(&tagged(3, "c")));
+        assert!(!filter.matches(&tagged(4, "a")));
+    }
+}
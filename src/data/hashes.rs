@@ -0,0 +1,256 @@
+use std;
+use std::collections;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+
+use util;
+
+/// The state of a file at the time it was hashed; its modification time as
+/// seconds and nanoseconds since the epoch, and its size.
+type Stamp = (u64, u32, u64);
+
+/// A database of SHA-256 checksums of source files, persisted between runs.
+///
+/// Checksums are reused as long as the modification time and size of the
+/// file are unchanged. The database is stored as a text file with one line
+/// on the form `checksum seconds.nanoseconds size path` per file. Paths that
+/// are not valid UTF-8 or that contain line breaks are not stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HashDatabase {
+    /// The path of the database file.
+    path: path::PathBuf,
+
+    /// The known checksums, along with the state of each file when it was
+    /// hashed.
+    digests: collections::HashMap<path::PathBuf, (Stamp, String)>,
+
+    /// The files whose checksums have been requested since unused checksums
+    /// were last forgotten.
+    used: collections::HashSet<path::PathBuf>,
+
+    /// Whether checksums have been added or forgotten since the database was
+    /// opened or last saved.
+    changed: bool,
+}
+
+impl HashDatabase {
+    /// Opens a database file.
+    ///
+    /// If the file does not exist, the database is empty and the file is
+    /// created when the database is saved. Since the checksums can always be
+    /// calculated again, a file that cannot be parsed is discarded with a
+    /// warning, and the database is empty.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the database file.
+    pub fn open(path: &path::Path) -> io::Result<Self> {
+        let digests = match fs::read_to_string(path) {
+            Ok(content) => content
+                .lines()
+                .enumerate()
+                .filter(|&(_, line)| !line.is_empty())
+                .map(|(i, line)| {
+                    entry(line).ok_or_else(|| {
+                        format!("invalid entry on line {}", i + 1)
+                    })
+                })
+                .collect::<Result<_, _>>(),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(collections::HashMap::new())
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                Err(e.to_string())
+            }
+            Err(e) => return Err(e),
+        }.unwrap_or_else(|e| {
+            warn!(
+                "Discarding checksum database {}: {}",
+                path.display(),
+                e
+            );
+            collections::HashMap::new()
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            digests,
+            used: collections::HashSet::new(),
+            changed: false,
+        })
+    }
+
+    /// Retrieves the checksum of a file as a lower case hexadecimal string.
+    ///
+    /// If a checksum for the current state of the file is known, it is
+    /// returned, otherwise the file is read and the result stored.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the file.
+    pub fn get(&mut self, path: &path::Path) -> io::Result<String> {
+        let stamp = stamp(path)?;
+        self.used.insert(path.to_path_buf());
+        if let Some((s, digest)) = self.digests.get(path) {
+            if s == &stamp {
+                return Ok(digest.clone());
+            }
+        }
+
+        let digest = util::sha256(path)?;
+        self.digests.insert(path.to_path_buf(), (stamp, digest.clone()));
+        self.changed = true;
+        Ok(digest)
+    }
+
+    /// Forgets the checksums of all files not requested since this was last
+    /// called.
+    ///
+    /// This is called once the checksums of all present files have been
+    /// requested, so that files no longer present are forgotten.
+    pub fn forget_unused(&mut self) {
+        let used = std::mem::take(&mut self.used);
+        let count = self.digests.len();
+        self.digests.retain(|path, _| used.contains(path));
+        self.changed |= self.digests.len() != count;
+    }
+
+    /// Writes this database to its file, if it has changed.
+    ///
+    /// The file is replaced atomically.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+
+        let mut lines = self.digests
+            .iter()
+            .filter_map(|(path, &((sec, nsec, size), ref digest))| {
+                path.to_str().filter(|p| !p.contains('\n')).map(|p| {
+                    format!("{} {}.{:09} {} {}\n", digest, sec, nsec, size, p)
+                })
+            })
+            .collect::<Vec<_>>();
+        lines.sort();
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::File::create(&temporary)
+            .and_then(|mut file| file.write_all(lines.concat().as_bytes()))
+            .and_then(|_| fs::rename(&temporary, &self.path))?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Reads the current state of a file.
+///
+/// # Arguments
+/// *  `path` - The path of the file.
+fn stamp(path: &path::Path) -> io::Result<Stamp> {
+    let metadata = path.metadata()?;
+    let modified = metadata
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((modified.as_secs(), modified.subsec_nanos(), metadata.len()))
+}
+
+/// Parses a line of a database file.
+///
+/// # Arguments
+/// *  `line` - The line.
+fn entry(line: &str) -> Option<(path::PathBuf, (Stamp, String))> {
+    let mut parts = line.splitn(4, ' ');
+    let digest = parts.next()?;
+    let mut modified = parts.next()?.splitn(2, '.');
+    let sec = modified.next()?.parse().ok()?;
+    let nsec = modified.next()?.parse().ok()?;
+    let size = parts.next()?.parse().ok()?;
+    let path = parts.next().filter(|path| !path.is_empty())?;
+    Some((path.into(), ((sec, nsec, size), digest.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir;
+
+    use super::*;
+
+    /// The SHA-256 checksum of `"hello world\n"`.
+    const HELLO_WORLD: &str = concat!(
+        "a948904f2f0f479b8f8197694b30184b",
+        "0d2ed1c1cd2a1ec0fb85d299a192a447",
+    );
+
+    /// Tests that stored checksums are reused while files are unchanged, and
+    /// that only requested checksums are kept.
+    #[test]
+    fn reused() {
+        let dir = tempdir::TempDir::new("medifs-hashes").unwrap();
+        let database = dir.path().join("hashes");
+        let path = dir.path().join("test.jpg");
+        let other = dir.path().join("other.jpg");
+        fs::write(&path, b"hello world\n").unwrap();
+        fs::write(&other, b"other").unwrap();
+
+        let mut hashes = HashDatabase::open(&database).unwrap();
+        assert_eq!(HELLO_WORLD, hashes.get(&path).unwrap());
+        hashes.get(&other).unwrap();
+        hashes.save().unwrap();
+
+        // A stored checksum is used without reading the file
+        let content = fs::read_to_string(&database)
+            .unwrap()
+            .replace(HELLO_WORLD, "cached");
+        fs::write(&database, content).unwrap();
+        let mut hashes = HashDatabase::open(&database).unwrap();
+        assert_eq!("cached", hashes.get(&path).unwrap());
+        hashes.forget_unused();
+        hashes.save().unwrap();
+
+        let content = fs::read_to_string(&database).unwrap();
+        assert_eq!(1, content.lines().count());
+        assert!(content.starts_with("cached "));
+    }
+
+    /// Tests that a changed file is hashed again.
+    #[test]
+    fn changed() {
+        let dir = tempdir::TempDir::new("medifs-hashes").unwrap();
+        let database = dir.path().join("hashes");
+        let path = dir.path().join("test.jpg");
+        fs::write(&path, b"hello").unwrap();
+
+        let mut hashes = HashDatabase::open(&database).unwrap();
+        let before = hashes.get(&path).unwrap();
+        hashes.save().unwrap();
+        fs::write(&path, b"hello world\n").unwrap();
+
+        let mut hashes = HashDatabase::open(&database).unwrap();
+        assert_ne!(before, hashes.get(&path).unwrap());
+        assert_eq!(HELLO_WORLD, hashes.get(&path).unwrap());
+    }
+
+    /// Tests that invalid database files are discarded.
+    #[test]
+    fn invalid() {
+        let dir = tempdir::TempDir::new("medifs-hashes").unwrap();
+        let database = dir.path().join("hashes");
+        let path = dir.path().join("test.jpg");
+        fs::write(&path, b"hello world\n").unwrap();
+        fs::write(&database, "cached 1.0 5\n").unwrap();
+
+        let mut hashes = HashDatabase::open(&database).unwrap();
+        assert_eq!(HELLO_WORLD, hashes.get(&path).unwrap());
+        hashes.save().unwrap();
+        assert!(
+            fs::read_to_string(&database)
+                .unwrap()
+                .starts_with(HELLO_WORLD)
+        );
+
+        fs::write(&database, b"\xff\n").unwrap();
+        assert!(HashDatabase::open(&database).is_ok());
+    }
+}
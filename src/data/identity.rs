@@ -0,0 +1,285 @@
+use std::fmt;
+use std::path;
+use std::str;
+use std::sync;
+
+use util;
+
+use super::{normalize_extension, HashDatabase, Item};
+
+/// The strategy deciding whether two items are duplicates.
+///
+/// Unlike [`Item::eq`](struct.Item.html), which always compares source paths,
+/// this is used when adding items, so that copies of the same file found in
+/// several places are presented only once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Identity {
+    /// Items are duplicates if they have the same source path.
+    Path,
+
+    /// Items are duplicates if their source files have the same content.
+    Hash,
+
+    /// Items are duplicates if they have the same timestamp and their source
+    /// files have the same size.
+    TimestampSize,
+}
+
+/// The identity of a single item under an identity strategy.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum IdentityKey {
+    /// The source path.
+    Path(path::PathBuf),
+
+    /// The SHA-256 checksum of the source file.
+    Hash(String),
+
+    /// The timestamp, as seconds and nanoseconds, and the file size.
+    TimestampSize(i64, i32, u64),
+}
+
+/// Calculates the identities of items as configured for a cache.
+///
+/// Calculating an identity may read the source file, so this is done by
+/// sources before items are passed to the cache, without holding its lock.
+/// Clones share the checksum database.
+#[derive(Clone, Debug, Default)]
+pub struct Identifier {
+    /// The strategy used to detect duplicate items. If this is not set,
+    /// duplicates are detected only if `normalize_ext` is set.
+    pub identity: Option<Identity>,
+
+    /// Whether source file extensions differing only in case are considered
+    /// the same.
+    pub normalize_ext: bool,
+
+    /// The database of checksums used by the
+    /// [`Hash`](enum.Identity.html#variant.Hash) strategy, if they are
+    /// persisted.
+    pub hashes: Option<sync::Arc<sync::Mutex<HashDatabase>>>,
+}
+
+impl Identifier {
+    /// Returns whether duplicates are detected.
+    pub fn is_enabled(&self) -> bool {
+        self.identity.is_some() || self.normalize_ext
+    }
+
+    /// Calculates the identity of an item, if duplicates are detected.
+    ///
+    /// This may read the source file.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    pub fn key(&self, item: &Item) -> Option<IdentityKey> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let identity = self.identity.unwrap_or(Identity::Path);
+        let key = identity.key_using(item, |path| match self.hashes {
+            Some(ref hashes) => hashes
+                .lock()
+                .ok()
+                .and_then(|mut hashes| hashes.get(path).ok()),
+            None => util::sha256(path).ok(),
+        });
+        Some(self.normalized(key))
+    }
+
+    /// Uses the source path of an item as its identity, if duplicates are
+    /// detected.
+    ///
+    /// This never reads the source file, and is used for items whose
+    /// identity was not calculated.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    pub fn path_key(&self, item: &Item) -> Option<IdentityKey> {
+        if self.is_enabled() {
+            Some(self.normalized(IdentityKey::Path(item.path.clone())))
+        } else {
+            None
+        }
+    }
+
+    /// Applies the normalisation of extensions to an identity, if enabled.
+    ///
+    /// # Arguments
+    /// *  `key` - The identity.
+    fn normalized(&self, key: IdentityKey) -> IdentityKey {
+        if self.normalize_ext {
+            key.with_normalized_extension()
+        } else {
+            key
+        }
+    }
+}
+
+impl Identity {
+    /// All identity strategies, in the order they are listed.
+    pub const ALL: &'static [Identity] =
+        &[Identity::Path, Identity::Hash, Identity::TimestampSize];
+
+    /// The name of this strategy, as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Identity::Path => "path",
+            Identity::Hash => "hash",
+            Identity::TimestampSize => "timestamp-size",
+        }
+    }
+
+    /// Calculates the identity of an item.
+    ///
+    /// If the source file cannot be read, the source path is used as
+    /// identity, so that the item is never considered a duplicate of an item
+    /// with another path.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    pub fn key(&self, item: &Item) -> IdentityKey {
+        self.key_using(item, |path| util::sha256(path).ok())
+    }
+
+    /// Calculates the identity of an item using a specific checksum
+    /// function.
+    ///
+    /// This allows checksums to be cached; see [`key`](#method.key).
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    /// *  `sha256` - A function calculating the SHA-256 checksum of a file.
+    pub fn key_using<F>(&self, item: &Item, mut sha256: F) -> IdentityKey
+    where
+        F: FnMut(&path::Path) -> Option<String>,
+    {
+        match *self {
+            Identity::Path => None,
+            Identity::Hash => sha256(&item.path).map(IdentityKey::Hash),
+            Identity::TimestampSize => item.path.metadata().ok().map(|m| {
+                let timestamp = item.timestamp.to_timespec();
+                IdentityKey::TimestampSize(
+                    timestamp.sec,
+                    timestamp.nsec,
+                    m.len(),
+                )
+            }),
+        }
+        .unwrap_or_else(|| IdentityKey::Path(item.path.clone()))
+    }
+}
+
+impl IdentityKey {
+    /// Converts the extension of a source path identity to lower case, so
+    /// that `a.JPG` and `a.jpg` are considered the same item.
+    ///
+    /// Other identities are returned unchanged.
+    pub fn with_normalized_extension(self) -> Self {
+        match self {
+            IdentityKey::Path(path) => {
+                IdentityKey::Path(normalize_extension(&path))
+            }
+            key => key,
+        }
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.name())
+    }
+}
+
+impl str::FromStr for Identity {
+    type Err = String;
+
+    /// Parses the name of an identity strategy.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Identity::ALL
+            .iter()
+            .find(|identity| identity.name() == s)
+            .cloned()
+            .ok_or_else(|| format!("unknown identity: {}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir;
+
+    use data::tests::*;
+    use super::*;
+
+    /// Tests that identities are parsed from their names.
+    #[test]
+    fn from_str() {
+        for identity in Identity::ALL {
+            assert_eq!(Ok(*identity), identity.to_string().parse::<Identity>());
+        }
+        assert!("size".parse::<Identity>().is_err());
+    }
+
+    /// Tests that each strategy merges and distinguishes the expected items.
+    #[test]
+    fn key() {
+        let dir = tempdir::TempDir::new("medifs-identity").unwrap();
+        let original =
+            item_with_data(dir.path().join("a.jpg"), b"abc", 2000, 1, 1);
+        let copy = item_with_data(dir.path().join("b.jpg"), b"abc", 2000, 1, 1);
+        let other =
+            item_with_data(dir.path().join("c.jpg"), b"xyz", 2000, 1, 1);
+        let later =
+            item_with_data(dir.path().join("d.jpg"), b"abc", 2000, 1, 2);
+        let missing = item("/missing.jpg", 2000, 1, 1);
+        let same = |identity: Identity, a: &Item, b: &Item| {
+            identity.key(a) == identity.key(b)
+        };
+
+        assert!(same(Identity::Path, &original, &original.clone()));
+        assert!(!same(Identity::Path, &original, &copy));
+
+        assert!(same(Identity::Hash, &original, &copy));
+        assert!(same(Identity::Hash, &original, &later));
+        assert!(!same(Identity::Hash, &original, &other));
+
+        assert!(same(Identity::TimestampSize, &original, &copy));
+        assert!(same(Identity::TimestampSize, &original, &other));
+        assert!(!same(Identity::TimestampSize, &original, &later));
+
+        for identity in Identity::ALL {
+            assert_eq!(
+                IdentityKey::Path("/missing.jpg".into()),
+                identity.key(&missing),
+            );
+        }
+    }
+
+    /// Tests that identities are calculated only if duplicates are detected,
+    /// and that path identities never read the source file.
+    #[test]
+    fn identifier() {
+        let dir = tempdir::TempDir::new("medifs-identity").unwrap();
+        let item =
+            item_with_data(dir.path().join("a.JPG"), b"abc", 2000, 1, 1);
+        let path = IdentityKey::Path(dir.path().join("a.JPG"));
+        let normalized = IdentityKey::Path(dir.path().join("a.jpg"));
+
+        let disabled = Identifier::default();
+        assert_eq!(None, disabled.key(&item));
+        assert_eq!(None, disabled.path_key(&item));
+
+        let normalize_ext = Identifier {
+            normalize_ext: true,
+            ..Identifier::default()
+        };
+        assert_eq!(Some(normalized.clone()), normalize_ext.key(&item));
+        assert_eq!(Some(normalized), normalize_ext.path_key(&item));
+
+        let hash = Identifier {
+            identity: Some(Identity::Hash),
+            ..Identifier::default()
+        };
+        assert_eq!(Some(Identity::Hash.key(&item)), hash.key(&item));
+        assert_eq!(Some(path), hash.path_key(&item));
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow;
 use std::cmp;
 use std::collections;
 use std::fmt;
@@ -6,7 +7,88 @@ use std::sync;
 
 use mime_guess;
 
-use super::{FileBase, FileExtension, Timestamp};
+use super::{FileBase, FileExtension, Identity, IdentityKey, Timestamp};
+
+/// The number of bytes in a megabyte.
+const MEGABYTE: u64 = 1024 * 1024;
+
+/// The size buckets as the exclusive upper limit in megabytes and the name.
+///
+/// Items larger than all limits belong to [`LARGEST_BUCKET`].
+///
+/// [`LARGEST_BUCKET`]: constant.LARGEST_BUCKET.html
+const BUCKETS: &[(u64, &str)] = &[(1, "<1MB"), (5, "1-5MB"), (20, "5-20MB")];
+
+/// The name of the bucket of items larger than all buckets.
+const LARGEST_BUCKET: &str = ">20MB";
+
+lazy_static! {
+    /// The media types already guessed, keyed by lower case file extension.
+    static ref MEDIA_TYPES: sync::RwLock<
+        collections::HashMap<String, mime_guess::Mime>,
+    > = sync::RwLock::new(collections::HashMap::new());
+}
+
+/// Guesses the media type of a file based on its extension.
+///
+/// The guess is made only once for every distinct lower case extension.
+///
+/// # Arguments
+/// *  `path` - The path of the file.
+fn media_type(path: &path::Path) -> mime_guess::Mime {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    if let Some(extension) = extension {
+        if let Some(media_type) = MEDIA_TYPES
+            .read()
+            .ok()
+            .and_then(|media_types| media_types.get(&extension).cloned())
+        {
+            return media_type;
+        }
+
+        let media_type =
+            mime_guess::from_ext(&extension).first_or_octet_stream();
+        if let Ok(mut media_types) = MEDIA_TYPES.write() {
+            media_types.insert(extension, media_type.clone());
+        }
+        media_type
+    } else {
+        mime_guess::from_path(path).first_or_octet_stream()
+    }
+}
+
+/// The source of the timestamp of an item.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DateSource {
+    /// The timestamp was read from the item metadata, a sidecar date file or
+    /// the file name.
+    Metadata,
+
+    /// The timestamp is the modification time of the source file, or the
+    /// time at which it was read, and may not be the time of generation.
+    Modified,
+}
+
+impl fmt::Display for DateSource {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            DateSource::Metadata => write!(formatter, "metadata"),
+            DateSource::Modified => write!(formatter, "modified"),
+        }
+    }
+}
+
+/// A kind of descriptive text of an item.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Caption {
+    /// A short title.
+    Title,
+
+    /// A longer description of the content.
+    Description,
+}
 
 /// A media item.
 ///
@@ -24,6 +106,68 @@ pub struct Item {
 
     /// The media type, guessed from the file extension.
     pub media_type: mime_guess::Mime,
+
+    /// The names of people appearing in this item.
+    pub people: collections::HashSet<String>,
+
+    /// The path of the source item relative to the source root, if known.
+    pub rel_path: Option<path::PathBuf>,
+
+    /// The rating of this item, if known.
+    pub rating: Option<u32>,
+
+    /// The camera with which this item was generated, if known.
+    pub camera: Option<String>,
+
+    /// The lens with which this item was generated, if known.
+    pub lens: Option<String>,
+
+    /// The name of the colour space of this item, if known.
+    pub colorspace: Option<String>,
+
+    /// The exposure settings with which this item was generated, such as
+    /// `f/2.8 1/250 ISO400 50mm`, if known.
+    pub settings: Option<String>,
+
+    /// The altitude in metres at which this item was generated, negative
+    /// below sea level, if known.
+    pub altitude: Option<String>,
+
+    /// The direction in degrees in which this item was generated, if known.
+    pub direction: Option<String>,
+
+    /// The width and height of this item in pixels, if known.
+    pub dimensions: Option<(u32, u32)>,
+
+    /// The sub-second part of the time of generation in nanoseconds, if
+    /// known.
+    pub subsecond: Option<u32>,
+
+    /// The source of the timestamp.
+    pub date_source: DateSource,
+
+    /// The descriptive texts of this item, if known.
+    pub captions: collections::HashMap<Caption, String>,
+
+    /// The number of pages or frames of a multi-page item.
+    ///
+    /// This is `None` for items with a single page.
+    pub pages: Option<u32>,
+
+    /// The size of the source file in bytes, if known.
+    pub size: Option<u64>,
+
+    /// The identity under which duplicates of this item are detected, if
+    /// calculated by its source; see [`Identifier`].
+    ///
+    /// [`Identifier`]: struct.Identifier.html
+    pub identity: Option<IdentityKey>,
+
+    /// The sidecar files of this item, as found by its source.
+    pub sidecars: Vec<path::PathBuf>,
+
+    /// The device and inode of the source file, if recorded by its source.
+    pub inode: Option<(u64, u64)>,
 }
 
 impl Item {
@@ -42,14 +186,45 @@ impl Item {
     ) -> Self {
         let path: path::PathBuf = path.into();
         let timestamp: Timestamp = timestamp.into();
-        let media_type = mime_guess::guess_mime_type(&path);
+        let media_type = media_type(&path);
         Item {
             path,
             timestamp,
             tags,
             media_type,
+            people: collections::HashSet::new(),
+            rel_path: None,
+            rating: None,
+            camera: None,
+            lens: None,
+            colorspace: None,
+            settings: None,
+            altitude: None,
+            direction: None,
+            dimensions: None,
+            subsecond: None,
+            date_source: DateSource::Metadata,
+            captions: collections::HashMap::new(),
+            pages: None,
+            size: None,
+            identity: None,
+            sidecars: Vec::new(),
+            inode: None,
         }
     }
+
+    /// Returns the name of the size bucket of the source file.
+    ///
+    /// If the size is unknown, `None` is returned.
+    pub fn size_bucket(&self) -> Option<&'static str> {
+        self.size.map(|size| {
+            BUCKETS
+                .iter()
+                .find(|&&(limit, _)| size < limit * MEGABYTE)
+                .map(|&(_, name)| name)
+                .unwrap_or(LARGEST_BUCKET)
+        })
+    }
 }
 
 impl FileBase for Item {
@@ -116,16 +291,83 @@ pub fn shared_monitor<T: ItemMonitor + 'static>(monitor: T) -> SharedMonitor {
     sync::Arc::new(sync::RwLock::new(Box::new(monitor)))
 }
 
+/// The lightweight key of an item.
+///
+/// A key holds what is needed to place an item in the directory tree. Keys
+/// are kept in memory for all items in a collection, also for items whose
+/// full metadata has been evicted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ItemKey {
+    /// The path of the source item.
+    pub path: path::PathBuf,
+
+    /// The timestamp of generation.
+    pub timestamp: Timestamp,
+
+    /// Tags applied to the item.
+    pub tags: collections::HashSet<String>,
+}
+
+impl<'a> From<&'a Item> for ItemKey {
+    fn from(source: &'a Item) -> Self {
+        Self {
+            path: source.path.clone(),
+            timestamp: source.timestamp.clone(),
+            tags: source.tags.clone(),
+        }
+    }
+}
+
+impl FileBase for ItemKey {
+    type T = Timestamp;
+
+    fn file_base(&self) -> Self::T {
+        self.timestamp.clone()
+    }
+}
+
+impl FileExtension for ItemKey {
+    type T = &'static str;
+
+    fn file_extension(&self) -> Self::T {
+        media_type(&self.path).file_extension()
+    }
+}
+
+/// A function re-reading the full item for a key.
+///
+/// If the item cannot be read, `None` is returned.
+pub type ItemLoader = Box<dyn Fn(&ItemKey) -> Option<Item> + Send + Sync>;
+
 /// A collection of items.
 ///
 /// Apart from holding a collection of items, this type allows registering a
 /// monitor that is notified when the collection changes.
+///
+/// By default all items are kept in memory. A collection may be bounded, in
+/// which case only the most recently added items are kept in memory, and only
+/// the keys of the remaining items. Evicted items are re-read using a loader
+/// whenever they are requested.
 pub struct ItemCollection {
-    /// The items.
-    pub items: Vec<Item>,
+    /// The keys of all items along with the resident items.
+    entries: Vec<(ItemKey, Option<Item>)>,
+
+    /// The number of resident items. These are always the last entries.
+    resident: usize,
+
+    /// The maximum number of resident items and the loader used to re-read
+    /// evicted items.
+    bound: Option<(usize, ItemLoader)>,
 
     /// The registered monitor.
     monitor: SharedMonitor,
+
+    /// The strategy used to detect duplicate items. If this is not set,
+    /// duplicates are not detected.
+    identity: Option<Identity>,
+
+    /// The source paths of all items, keyed by their identity.
+    identities: collections::HashMap<IdentityKey, path::PathBuf>,
 }
 
 impl ItemCollection {
@@ -135,11 +377,94 @@ impl ItemCollection {
     /// *  `monitor` - The item monitor.
     pub fn new(monitor: SharedMonitor) -> Self {
         Self {
-            items: vec![],
+            entries: vec![],
+            resident: 0,
+            bound: None,
             monitor,
+            identity: None,
+            identities: collections::HashMap::new(),
         }
     }
 
+    /// Sets the strategy used to detect duplicate items.
+    ///
+    /// Items already in this collection are not removed, even if they are
+    /// duplicates under the new strategy.
+    ///
+    /// # Arguments
+    /// *  `identity` - The identity strategy.
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identities = self.iter()
+            .map(|item| (identity.key(&item), item.path.clone()))
+            .collect();
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Bounds the number of items kept in memory.
+    ///
+    /// Items exceeding the bound are evicted immediately.
+    ///
+    /// # Arguments
+    /// *  `limit` - The maximum number of resident items.
+    /// *  `loader` - A function used to re-read evicted items.
+    pub fn with_bound(mut self, limit: usize, loader: ItemLoader) -> Self {
+        self.bound = Some((limit, loader));
+        self.evict();
+        self
+    }
+
+    /// The number of items in this collection.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether this collection is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The number of items whose full metadata is kept in memory.
+    pub fn resident(&self) -> usize {
+        self.resident
+    }
+
+    /// Iterates over the keys of all items.
+    ///
+    /// This never causes items to be re-read.
+    pub fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = &'a ItemKey> + 'a> {
+        Box::new(self.entries.iter().map(|(key, _)| key))
+    }
+
+    /// Iterates over all items.
+    ///
+    /// Evicted items are re-read, but not made resident.
+    pub fn iter<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = borrow::Cow<'a, Item>> + 'a> {
+        Box::new(
+            self.entries
+                .iter()
+                .map(move |(key, item)| self.resolve(key, item)),
+        )
+    }
+
+    /// Finds an item by source path.
+    ///
+    /// If the item has been evicted, it is re-read, but not made resident.
+    ///
+    /// # Arguments
+    /// *  `path` - The source path of the item.
+    pub fn get<'a, P>(&'a self, path: &P) -> Option<borrow::Cow<'a, Item>>
+    where
+        P: cmp::PartialEq<path::PathBuf>,
+    {
+        self.entries
+            .iter()
+            .find(|&(key, _)| path == &key.path)
+            .map(|(key, item)| self.resolve(key, item))
+    }
+
     /// Adds an item to this item collection.
     ///
     /// Calling this method will cause [`ItemMonitor::item_added`] to be called
@@ -147,13 +472,29 @@ impl ItemCollection {
     ///
     /// When the callback is called, the item has not yet been added.
     ///
+    /// If the item is a duplicate of an item already in this collection, as
+    /// decided by the identity strategy, it is not added and the monitor is
+    /// not called.
+    ///
+    /// If this collection is bounded, the least recently added resident item
+    /// may be evicted.
+    ///
     /// # Arguments
     /// *  `item` - The item to add.
     ///
     /// [`ItemMonitor::item_added`]: trait.ItemMonitor.html#method.item_added
     pub fn add(&mut self, item: Item) {
+        if let Some(key) = self.identity.map(|identity| identity.key(&item)) {
+            if self.identities.contains_key(&key) {
+                return;
+            }
+            self.identities.insert(key, item.path.clone());
+        }
+
         self.monitor.item_added(&item);
-        self.items.push(item);
+        self.entries.push((ItemKey::from(&item), Some(item)));
+        self.resident += 1;
+        self.evict();
     }
 
     /// Removes an item from this item collection.
@@ -171,19 +512,78 @@ impl ItemCollection {
     where
         P: cmp::PartialEq<path::PathBuf>,
     {
-        if let Some((index, _)) = self.items
+        if let Some(index) = self.entries
             .iter()
-            .enumerate()
-            .filter(|&(_, item)| path == &item.path)
-            .next()
+            .position(|(key, _)| path == &key.path)
         {
-            let item = self.items.remove(index);
+            let (key, item) = self.entries.remove(index);
+            let item = match item {
+                Some(item) => {
+                    self.resident -= 1;
+                    item
+                }
+                None => self.load(&key),
+            };
+            self.identities.retain(|_, path| path != &item.path);
             self.monitor.item_removed(&item);
             Some(item)
         } else {
             None
         }
     }
+
+    /// Returns an item, re-reading it if it has been evicted.
+    ///
+    /// # Arguments
+    /// *  `key` - The key of the item.
+    /// *  `item` - The resident item, if any.
+    fn resolve<'a>(
+        &self,
+        key: &ItemKey,
+        item: &'a Option<Item>,
+    ) -> borrow::Cow<'a, Item> {
+        item.as_ref()
+            .map(borrow::Cow::Borrowed)
+            .unwrap_or_else(|| borrow::Cow::Owned(self.load(key)))
+    }
+
+    /// Re-reads an evicted item.
+    ///
+    /// If the item cannot be re-read, an item with only the information of
+    /// the key is created.
+    ///
+    /// # Arguments
+    /// *  `key` - The key of the item.
+    fn load(&self, key: &ItemKey) -> Item {
+        self.bound
+            .as_ref()
+            .and_then(|(_, loader)| loader(key))
+            .unwrap_or_else(|| {
+                Item::new(
+                    key.path.clone(),
+                    key.timestamp.clone(),
+                    key.tags.clone(),
+                )
+            })
+    }
+
+    /// Evicts the least recently added resident items until the bound is
+    /// satisfied.
+    fn evict(&mut self) {
+        if let Some((limit, _)) = self.bound {
+            while self.resident > limit {
+                let index = self.entries.len() - self.resident;
+                self.entries[index].1 = None;
+                self.resident -= 1;
+            }
+        }
+    }
+}
+
+impl Extend<Item> for ItemCollection {
+    fn extend<T: IntoIterator<Item = Item>>(&mut self, items: T) {
+        items.into_iter().for_each(|item| self.add(item));
+    }
 }
 
 /// A sharable item collection.
@@ -199,13 +599,31 @@ pub fn shared_collection(monitor: SharedMonitor) -> SharedCollection {
 
 #[cfg(test)]
 mod tests {
+    use std::fs;
+    use std::mem;
     use std::path;
 
     use mime;
+    use tempdir;
 
     use data::tests::*;
     use super::*;
 
+    /// Tests that items are put in the size bucket whose limit they are
+    /// below.
+    #[test]
+    fn size_bucket() {
+        let of = |size| Item {
+            size,
+            ..Item::new("test.jpg", (2000, 1, 1), collections::HashSet::new())
+        }.size_bucket();
+        assert_eq!(None, of(None));
+        assert_eq!(Some("<1MB"), of(Some(MEGABYTE - 1)));
+        assert_eq!(Some("1-5MB"), of(Some(MEGABYTE)));
+        assert_eq!(Some("5-20MB"), of(Some(5 * MEGABYTE)));
+        assert_eq!(Some(">20MB"), of(Some(20 * MEGABYTE)));
+    }
+
     /// Tests creation of item of unknown type.
     #[test]
     fn new_unknown() {
@@ -230,6 +648,27 @@ mod tests {
         assert_eq!(mime::IMAGE_JPEG, item.media_type);
     }
 
+    /// Tests that memoized media types match the guessed ones.
+    #[test]
+    #[allow(deprecated)]
+    fn media_type_memoized() {
+        for name in &[
+            "a.jpg",
+            "b.JPG",
+            "c.jpeg",
+            "d.png",
+            "e.tar.gz",
+            "f.unknown-extension",
+            "no extension",
+            "g.jpg",
+        ] {
+            let path = path::Path::new(name);
+            assert_eq!(mime_guess::guess_mime_type(path), media_type(path));
+        }
+        assert!(MEDIA_TYPES.read().unwrap().contains_key("jpg"));
+        assert!(!MEDIA_TYPES.read().unwrap().contains_key("JPG"));
+    }
+
     /// Tests stringification.
     #[test]
     fn to_string() {
@@ -264,7 +703,10 @@ mod tests {
         ];
 
         items.iter().for_each(|i| collection.add(i.clone()));
-        assert_eq!(items, collection.items);
+        assert_eq!(
+            items,
+            collection.iter().map(|i| i.into_owned()).collect::<Vec<_>>(),
+        );
         assert_eq!(items, *added.read().unwrap());
     }
 
@@ -294,8 +736,124 @@ mod tests {
             Some(items[0].clone()),
             collection.remove_by_path(&items[0].path),
         );
-        assert_eq!(vec![items[1].clone()], collection.items);
+        assert_eq!(
+            vec![items[1].clone()],
+            collection.iter().map(|i| i.into_owned()).collect::<Vec<_>>(),
+        );
         assert_eq!(items, *added.read().unwrap());
         assert_eq!(vec![items[0].clone()], *removed.read().unwrap());
     }
+
+    /// Tests that duplicates are skipped according to the identity strategy.
+    #[test]
+    fn identity() {
+        let dir = tempdir::TempDir::new("medifs").unwrap();
+        let added = Monitor::list();
+        let original =
+            item_with_data(dir.path().join("a.jpg"), b"abc", 2000, 1, 1);
+        let copy = item_with_data(dir.path().join("b.jpg"), b"abc", 2000, 1, 1);
+
+        let mut collection = ItemCollection::new(shared_monitor(
+            Monitor::new(Monitor::list(), Monitor::list()),
+        ));
+        collection.extend(vec![original.clone(), copy.clone()]);
+        assert_eq!(2, collection.len());
+
+        let mut collection = ItemCollection::new(shared_monitor(
+            Monitor::new(added.clone(), Monitor::list()),
+        )).with_identity(Identity::TimestampSize);
+        collection.extend(vec![original.clone(), copy.clone()]);
+        assert_eq!(1, collection.len());
+        assert_eq!(vec![original.clone()], *added.read().unwrap());
+
+        collection.remove_by_path(&original.path);
+        collection.add(copy.clone());
+        assert_eq!(
+            Some(copy.clone()),
+            collection.get(&copy.path).map(|i| i.into_owned()),
+        );
+    }
+
+    /// Tests that evicted items are re-read with correct metadata.
+    #[test]
+    fn bounded() {
+        let dir = tempdir::TempDir::new("medifs").unwrap();
+        let sized = |item: Item| Item {
+            size: fs::metadata(&item.path).map(|m| m.len()).ok(),
+            ..item
+        };
+        let mut collection = ItemCollection::new(shared_monitor(
+            Monitor::new(Monitor::list(), Monitor::list()),
+        )).with_bound(
+            1,
+            Box::new(move |key| {
+                fs::metadata(&key.path).ok().map(|metadata| Item {
+                    size: Some(metadata.len()),
+                    ..Item::new(
+                        key.path.clone(),
+                        key.timestamp.clone(),
+                        key.tags.clone(),
+                    )
+                })
+            }),
+        );
+        let first = sized(item_with_data(
+            dir.path().join("first.jpg"),
+            b"123",
+            2000,
+            1,
+            1,
+        ));
+        let second = sized(item_with_data(
+            dir.path().join("second.jpg"),
+            b"12345",
+            2000,
+            1,
+            2,
+        ));
+
+        collection.add(first.clone());
+        collection.add(second.clone());
+        assert_eq!(2, collection.len());
+        assert_eq!(1, collection.resident());
+        assert_eq!(
+            vec![ItemKey::from(&first), ItemKey::from(&second)],
+            collection.keys().cloned().collect::<Vec<_>>(),
+        );
+
+        let reloaded = collection.get(&first.path).unwrap();
+        match reloaded {
+            borrow::Cow::Owned(_) => {}
+            borrow::Cow::Borrowed(_) => panic!("first item still resident"),
+        }
+        assert_eq!(Some(3), reloaded.size);
+        assert_eq!(first.timestamp, reloaded.timestamp);
+        assert_eq!(Some(5), collection.get(&second.path).unwrap().size);
+
+        assert_eq!(Some(first.clone()), collection.remove_by_path(&first.path));
+        assert_eq!(1, collection.len());
+        assert_eq!(1, collection.resident());
+    }
+
+    /// Tests that a bounded collection keeps only the bound in memory.
+    #[test]
+    fn bounded_memory() {
+        let mut collection = ItemCollection::new(shared_monitor(
+            Monitor::new(Monitor::list(), Monitor::list()),
+        )).with_bound(10, Box::new(|_| None));
+        collection.extend((0..1000).map(|i| {
+            item(&format!("{}.jpg", i), 2000 + i / 365, 1, 1 + i % 28)
+        }));
+
+        assert_eq!(1000, collection.len());
+        assert_eq!(10, collection.resident());
+        assert_eq!(
+            10,
+            collection.entries.iter().filter(|e| e.1.is_some()).count(),
+        );
+        assert!(
+            collection.keys().map(mem::size_of_val).sum::<usize>() * 2
+                < collection.len() * mem::size_of::<Item>()
+        );
+    }
 }
@@ -0,0 +1,338 @@
+use std::fmt;
+use std::str;
+
+use super::Timestamp;
+
+/// The English month names.
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// A part of a directory name template.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A literal character.
+    Literal(char),
+
+    /// The four digit year; `%Y`.
+    Year,
+
+    /// The zero-padded month; `%m`.
+    Month,
+
+    /// The English month name; `%B`.
+    MonthName,
+
+    /// The zero-padded day; `%d`.
+    Day,
+}
+
+/// The year, month and day parsed from a directory name.
+type Fields = (Option<i32>, Option<i32>, Option<i32>);
+
+/// The layout of the date directory tree.
+///
+/// A layout consists of one template for each of the year, month and day
+/// directory levels. Templates may contain the directives `%Y` for the four
+/// digit year, `%m` for the zero-padded month, `%B` for the English month
+/// name, `%d` for the zero-padded day and `%%` for a literal `%`. Numeric
+/// fields have a fixed width, so they need no separator between them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateLayout {
+    /// The templates for the year, month and day levels.
+    levels: Vec<Vec<Token>>,
+}
+
+impl DateLayout {
+    const SEPARATOR: char = '/';
+
+    /// Generates the directory names for a timestamp.
+    ///
+    /// # Arguments
+    /// *  `timestamp` - The timestamp for which to generate names.
+    pub fn directories(&self, timestamp: &Timestamp) -> Vec<String> {
+        (0..self.levels.len())
+            .map(|level| self.label(level, timestamp))
+            .collect()
+    }
+
+    /// Generates the directory name for a single level.
+    ///
+    /// # Arguments
+    /// *  `level` - The directory level; `0` for years, `1` for months and
+    ///    `2` for days.
+    /// *  `timestamp` - The timestamp for which to generate a name.
+    ///
+    /// # Panics
+    /// This method will panic if `level` is out of range.
+    pub fn label(&self, level: usize, timestamp: &Timestamp) -> String {
+        self.levels[level]
+            .iter()
+            .fold(String::new(), |mut acc, token| {
+                match *token {
+                    Token::Literal(c) => acc.push(c),
+                    Token::Year => {
+                        acc.push_str(&format!("{:04}", timestamp.year()))
+                    }
+                    Token::Month => {
+                        acc.push_str(&format!("{:02}", timestamp.month()))
+                    }
+                    Token::MonthName => acc.push_str(
+                        MONTH_NAMES[(timestamp.month() as usize - 1) % 12],
+                    ),
+                    Token::Day => {
+                        acc.push_str(&format!("{:02}", timestamp.day()))
+                    }
+                }
+                acc
+            })
+    }
+
+    /// Parses a directory name for a specific level.
+    ///
+    /// The value corresponding to the level is returned, that is the year for
+    /// level `0`, the month for level `1` and the day for level `2`. If the
+    /// name does not match the template, `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `level` - The directory level.
+    /// *  `label` - The directory name.
+    pub fn parse(&self, level: usize, label: &str) -> Option<i32> {
+        self.levels
+            .get(level)
+            .and_then(|tokens| Self::fields(tokens, label))
+            .and_then(|(year, month, day)| match level {
+                0 => year,
+                1 => month,
+                2 => day,
+                _ => None,
+            })
+    }
+
+    /// Extracts the fields from a directory name.
+    ///
+    /// # Arguments
+    /// *  `tokens` - The template.
+    /// *  `label` - The directory name.
+    fn fields(tokens: &[Token], label: &str) -> Option<Fields> {
+        let (mut year, mut month, mut day) = (None, None, None);
+        let mut rest = label;
+        for token in tokens {
+            match *token {
+                Token::Literal(c) => {
+                    if rest.starts_with(c) {
+                        rest = &rest[c.len_utf8()..];
+                    } else {
+                        return None;
+                    }
+                }
+                Token::Year => {
+                    year = Some(Self::digits(rest, 4, 0, 9999)?);
+                    rest = &rest[4..];
+                }
+                Token::Month => {
+                    month = Some(Self::digits(rest, 2, 1, 12)?);
+                    rest = &rest[2..];
+                }
+                Token::MonthName => {
+                    let index = MONTH_NAMES
+                        .iter()
+                        .position(|name| rest.starts_with(name))?;
+                    month = Some(index as i32 + 1);
+                    rest = &rest[MONTH_NAMES[index].len()..];
+                }
+                Token::Day => {
+                    day = Some(Self::digits(rest, 2, 1, 31)?);
+                    rest = &rest[2..];
+                }
+            }
+        }
+
+        if rest.is_empty() {
+            Some((year, month, day))
+        } else {
+            None
+        }
+    }
+
+    /// Parses a number with a fixed number of digits at the start of a
+    /// string.
+    ///
+    /// Fields must have a fixed width, since templates may place them next
+    /// to each other, as in `%Y%m%d`.
+    ///
+    /// # Arguments
+    /// *  `s` - The string to parse.
+    /// *  `count` - The number of digits.
+    /// *  `min` - The minimum allowed value.
+    /// *  `max` - The maximum allowed value.
+    fn digits(s: &str, count: usize, min: i32, max: i32) -> Option<i32> {
+        s.get(..count)
+            .filter(|v| v.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|v| v.parse::<i32>().ok())
+            .filter(|&v| v >= min && v <= max)
+    }
+
+    /// Converts a template to tokens.
+    ///
+    /// # Arguments
+    /// *  `template` - The template to convert.
+    fn tokens(template: &str) -> Result<Vec<Token>, ()> {
+        let mut result = Vec::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            result.push(if c == '%' {
+                match chars.next() {
+                    Some('Y') => Token::Year,
+                    Some('m') => Token::Month,
+                    Some('B') => Token::MonthName,
+                    Some('d') => Token::Day,
+                    Some('%') => Token::Literal('%'),
+                    _ => return Err(()),
+                }
+            } else {
+                Token::Literal(c)
+            });
+        }
+
+        Ok(result)
+    }
+}
+
+impl Default for DateLayout {
+    /// The default layout; `"%Y/%m/%d"`.
+    fn default() -> Self {
+        "%Y/%m/%d".parse().unwrap()
+    }
+}
+
+impl fmt::Display for DateLayout {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (i, tokens) in self.levels.iter().enumerate() {
+            if i > 0 {
+                write!(formatter, "{}", Self::SEPARATOR)?;
+            }
+            for token in tokens {
+                match *token {
+                    Token::Literal('%') => write!(formatter, "%%")?,
+                    Token::Literal(c) => write!(formatter, "{}", c)?,
+                    Token::Year => write!(formatter, "%Y")?,
+                    Token::Month => write!(formatter, "%m")?,
+                    Token::MonthName => write!(formatter, "%B")?,
+                    Token::Day => write!(formatter, "%d")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl str::FromStr for DateLayout {
+    type Err = ();
+
+    /// Converts a string to a date layout.
+    ///
+    /// The string must contain exactly three templates separated by `"/"`.
+    /// The first template must contain the year, the second the month and the
+    /// third the day.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let levels = s.split(Self::SEPARATOR)
+            .map(Self::tokens)
+            .collect::<Result<Vec<_>, _>>()?;
+        let valid = levels.len() == 3
+            && levels[0].contains(&Token::Year)
+            && (levels[1].contains(&Token::Month)
+                || levels[1].contains(&Token::MonthName))
+            && levels[2].contains(&Token::Day);
+        if valid {
+            Ok(Self { levels })
+        } else {
+            Err(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that invalid layouts are rejected.
+    #[test]
+    fn from_str_invalid() {
+        assert_eq!(Err(()), "%Y/%m".parse::<DateLayout>());
+        assert_eq!(Err(()), "%Y/%m/%d/%d".parse::<DateLayout>());
+        assert_eq!(Err(()), "%m/%Y/%d".parse::<DateLayout>());
+        assert_eq!(Err(()), "%Y/%m/%x".parse::<DateLayout>());
+    }
+
+    /// Tests that layouts are converted back to strings.
+    #[test]
+    fn to_string() {
+        assert_eq!("%Y/%m/%d", DateLayout::default().to_string());
+        assert_eq!(
+            "%Y/%Y-%B/100%% %d",
+            "%Y/%Y-%B/100%% %d"
+                .parse::<DateLayout>()
+                .unwrap()
+                .to_string(),
+        );
+    }
+
+    /// Tests that the numeric layout round-trips.
+    #[test]
+    fn numeric() {
+        let layout = "%Y/%Y-%m/%Y-%m-%d".parse::<DateLayout>().unwrap();
+        let timestamp = Timestamp::from((2020, 1, 15));
+        assert_eq!(
+            vec!["2020", "2020-01", "2020-01-15"],
+            layout.directories(&timestamp),
+        );
+        assert_eq!(Some(2020), layout.parse(0, "2020"));
+        assert_eq!(Some(1), layout.parse(1, "2020-01"));
+        assert_eq!(Some(15), layout.parse(2, "2020-01-15"));
+        assert_eq!(None, layout.parse(1, "2020-1"));
+        assert_eq!(None, layout.parse(1, "2020-13"));
+        assert_eq!(None, layout.parse(2, "2020-01-15 "));
+    }
+
+    /// Tests that fields without separators are parsed by their width.
+    #[test]
+    fn adjacent_fields() {
+        let layout = "%Y/%Y%m/%Y%m%d".parse::<DateLayout>().unwrap();
+        let timestamp = Timestamp::from((2020, 1, 15));
+        assert_eq!(
+            vec!["2020", "202001", "20200115"],
+            layout.directories(&timestamp),
+        );
+        assert_eq!(Some(2020), layout.parse(0, "2020"));
+        assert_eq!(Some(1), layout.parse(1, "202001"));
+        assert_eq!(Some(15), layout.parse(2, "20200115"));
+        assert_eq!(None, layout.parse(0, "20200"));
+        assert_eq!(None, layout.parse(1, "20201"));
+        assert_eq!(None, layout.parse(2, "2020011"));
+    }
+
+    /// Tests that the month name layout round-trips.
+    #[test]
+    fn month_name() {
+        let layout = "%Y/%B/%d".parse::<DateLayout>().unwrap();
+        let timestamp = Timestamp::from((2020, 12, 1));
+        assert_eq!(
+            vec!["2020", "December", "01"],
+            layout.directories(&timestamp),
+        );
+        assert_eq!(Some(12), layout.parse(1, "December"));
+        assert_eq!(None, layout.parse(1, "12"));
+    }
+}
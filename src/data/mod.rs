@@ -1,18 +1,44 @@
 pub mod cache;
 pub mod traits;
 
+mod albums;
+pub use self::albums::Albums;
+
+mod dimensions;
+pub use self::dimensions::dimensions;
+
+mod filter;
+pub use self::filter::{shared_filter, Filter, SharedFilter};
+
+mod hashes;
+pub use self::hashes::HashDatabase;
+
+mod identity;
+pub use self::identity::{Identifier, Identity, IdentityKey};
+
 mod item;
-pub use self::item::{shared_collection, shared_monitor, Item, ItemCollection,
-                     ItemMonitor, SharedCollection, SharedMonitor};
+pub use self::item::{shared_collection, shared_monitor, Caption, DateSource,
+                     Item, ItemCollection, ItemKey, ItemLoader, ItemMonitor,
+                     SharedCollection, SharedMonitor};
+
+mod layout;
+pub use self::layout::DateLayout;
 
 mod path;
-pub use self::path::{name, FileBase, FileExtension, Path};
+pub use self::path::{name, normalize_extension, sidecars, FileBase,
+                     FileExtension, Path};
+
+mod query;
+pub use self::query::{Comparison, Query};
 
 mod tag;
 pub use self::tag::Tag;
 
 mod time;
-pub use self::time::{system_time_to_timespec, timestamp, Timestamp};
+pub use self::time::{system_time_to_timespec, timestamp, Timestamp, Zone};
+
+mod type_groups;
+pub use self::type_groups::{TypeGroups, OTHER_GROUP};
 
 #[cfg(test)]
 pub mod tests {
@@ -6,6 +6,9 @@ use std::path;
 use mime;
 use mime_guess;
 
+/// The extensions of sidecar files exposed next to items, in lower case.
+const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae"];
+
 /// A path.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Path(path::PathBuf);
@@ -146,6 +149,46 @@ pub fn name<B: FileBase, E: FileExtension>(
     }
 }
 
+/// Converts the extension of a path to lower case.
+///
+/// Paths without an extension, or with an extension that is not valid
+/// UTF-8, are returned unchanged.
+///
+/// # Arguments
+/// *  `path` - The path to normalise.
+pub fn normalize_extension(path: &path::Path) -> path::PathBuf {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => path.with_extension(extension.to_lowercase()),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Finds the sidecar files of a source file.
+///
+/// Sidecar files share the file stem of the source file, and have one of
+/// the [`SIDECAR_EXTENSIONS`] in either lower or upper case.
+///
+/// # Arguments
+/// *  `source` - The path of the source file.
+/// *  `exists` - Whether a file exists. This is typically answered from a
+///    directory listing, so that no files are accessed.
+///
+/// [`SIDECAR_EXTENSIONS`]: constant.SIDECAR_EXTENSIONS.html
+pub fn sidecars<F>(source: &path::Path, exists: F) -> Vec<path::PathBuf>
+where
+    F: Fn(&path::Path) -> bool,
+{
+    SIDECAR_EXTENSIONS
+        .iter()
+        .filter_map(|&extension| {
+            [extension.to_string(), extension.to_uppercase()]
+                .iter()
+                .map(|e| source.with_extension(e))
+                .find(|path| exists(path.as_path()))
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use mime;
@@ -176,4 +219,42 @@ mod tests {
             name(&String::from("test3"), &mime::IMAGE_PNG, 2),
         );
     }
+
+    /// Tests that only the extension is converted to lower case.
+    #[test]
+    fn normalize_extension_case() {
+        assert_eq!(
+            path::PathBuf::from("/A/IMG_01.jpg"),
+            normalize_extension(path::Path::new("/A/IMG_01.JPG")),
+        );
+        assert_eq!(
+            path::PathBuf::from("/A/IMG_01.jpg"),
+            normalize_extension(path::Path::new("/A/IMG_01.jpg")),
+        );
+        assert_eq!(
+            path::PathBuf::from("/A/IMG_01"),
+            normalize_extension(path::Path::new("/A/IMG_01")),
+        );
+    }
+
+    /// Tests that sidecars of either case are found for known extensions
+    /// only.
+    #[test]
+    fn sidecars_found() {
+        let files = [
+            path::PathBuf::from("/A/IMG_01.XMP"),
+            path::PathBuf::from("/A/IMG_01.aae"),
+            path::PathBuf::from("/A/IMG_01.txt"),
+            path::PathBuf::from("/A/IMG_02.xmp"),
+        ];
+        assert_eq!(
+            vec![
+                path::PathBuf::from("/A/IMG_01.XMP"),
+                path::PathBuf::from("/A/IMG_01.aae"),
+            ],
+            sidecars(path::Path::new("/A/IMG_01.jpg"), |p| {
+                files.iter().any(|f| f == p)
+            }),
+        );
+    }
 }
@@ -0,0 +1,375 @@
+use std::iter;
+use std::str;
+
+use super::Item;
+
+/// A comparison of a value with a reference value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// The value is less than the reference; `<`.
+    Less,
+
+    /// The value is less than or equal to the reference; `<=`.
+    LessOrEqual,
+
+    /// The value is equal to the reference; `=`.
+    Equal,
+
+    /// The value is greater than or equal to the reference; `>=`.
+    GreaterOrEqual,
+
+    /// The value is greater than the reference; `>`.
+    Greater,
+}
+
+impl Comparison {
+    /// The operators, ordered so that no operator is preceded by one of its
+    /// prefixes.
+    const OPERATORS: &'static [(&'static str, Comparison)] = &[
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+        ("=", Comparison::Equal),
+    ];
+
+    /// Applies this comparison.
+    ///
+    /// # Arguments
+    /// *  `value` - The value to compare.
+    /// *  `reference` - The reference value.
+    pub fn apply<T: Ord>(self, value: T, reference: T) -> bool {
+        match self {
+            Comparison::Less => value < reference,
+            Comparison::LessOrEqual => value <= reference,
+            Comparison::Equal => value == reference,
+            Comparison::GreaterOrEqual => value >= reference,
+            Comparison::Greater => value > reference,
+        }
+    }
+}
+
+/// A predicate on items parsed from a query expression.
+///
+/// An expression consists of terms combined with `and`, `or` and `not`, where
+/// `not` binds tighter than `and`, which in turn binds tighter than `or`.
+/// Parentheses may be used for grouping. The supported terms are:
+///
+/// *  `tag:<tag>` - The item has the tag.
+/// *  `year:<year>` - The item was generated during the year.
+/// *  `month:<month>` - The item was generated during the month, `1` to `12`.
+/// *  `type:<type>` - The item has the media type. This is either a top level
+///    type, such as `image`, or a full type, such as `image/jpeg`.
+/// *  `rating<op><rating>` - The item has a rating, and it compares to the
+///    value using one of the operators `<`, `<=`, `=`, `>=` or `>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+    /// The item has a tag.
+    Tag(String),
+
+    /// The item was generated during a year.
+    Year(i32),
+
+    /// The item was generated during a month.
+    Month(i32),
+
+    /// The item has a media type.
+    Type(String),
+
+    /// The item rating compares to a value.
+    Rating(Comparison, u32),
+
+    /// Both queries match.
+    And(Box<Query>, Box<Query>),
+
+    /// Either query matches.
+    Or(Box<Query>, Box<Query>),
+
+    /// The query does not match.
+    Not(Box<Query>),
+}
+
+impl Query {
+    /// Returns whether an item matches this query.
+    ///
+    /// # Arguments
+    /// *  `item` - The item to check.
+    pub fn matches(&self, item: &Item) -> bool {
+        match *self {
+            Query::Tag(ref tag) => item.tags.contains(tag),
+            Query::Year(year) => item.timestamp.year() == year,
+            Query::Month(month) => item.timestamp.month() == month,
+            Query::Type(ref media_type) => {
+                if media_type.contains('/') {
+                    format!(
+                        "{}/{}",
+                        item.media_type.type_(),
+                        item.media_type.subtype(),
+                    ) == *media_type
+                } else {
+                    item.media_type.type_() == media_type.as_str()
+                }
+            }
+            Query::Rating(comparison, rating) => item.rating
+                .map(|r| comparison.apply(r, rating))
+                .unwrap_or(false),
+            Query::And(ref a, ref b) => a.matches(item) && b.matches(item),
+            Query::Or(ref a, ref b) => a.matches(item) || b.matches(item),
+            Query::Not(ref a) => !a.matches(item),
+        }
+    }
+
+    /// Parses a sequence of alternatives.
+    ///
+    /// # Arguments
+    /// *  `tokens` - The remaining tokens.
+    fn alternatives<I: Iterator<Item = String>>(
+        tokens: &mut iter::Peekable<I>,
+    ) -> Result<Self, String> {
+        let mut result = Self::conjunction(tokens)?;
+        while tokens.peek().map(|t| t == "or").unwrap_or(false) {
+            tokens.next();
+            result = Query::Or(
+                Box::new(result),
+                Box::new(Self::conjunction(tokens)?),
+            );
+        }
+        Ok(result)
+    }
+
+    /// Parses a sequence of terms that must all match.
+    ///
+    /// # Arguments
+    /// *  `tokens` - The remaining tokens.
+    fn conjunction<I: Iterator<Item = String>>(
+        tokens: &mut iter::Peekable<I>,
+    ) -> Result<Self, String> {
+        let mut result = Self::unary(tokens)?;
+        while tokens.peek().map(|t| t == "and").unwrap_or(false) {
+            tokens.next();
+            result =
+                Query::And(Box::new(result), Box::new(Self::unary(tokens)?));
+        }
+        Ok(result)
+    }
+
+    /// Parses a negation, a parenthesised expression or a term.
+    ///
+    /// # Arguments
+    /// *  `tokens` - The remaining tokens.
+    fn unary<I: Iterator<Item = String>>(
+        tokens: &mut iter::Peekable<I>,
+    ) -> Result<Self, String> {
+        match tokens.next() {
+            Some(ref token) if token == "not" => {
+                Ok(Query::Not(Box::new(Self::unary(tokens)?)))
+            }
+            Some(ref token) if token == "(" => {
+                let result = Self::alternatives(tokens)?;
+                match tokens.next() {
+                    Some(ref token) if token == ")" => Ok(result),
+                    _ => Err("missing )".into()),
+                }
+            }
+            Some(token) => Self::term(&token),
+            None => Err("unexpected end of query".into()),
+        }
+    }
+
+    /// Parses a single term.
+    ///
+    /// # Arguments
+    /// *  `token` - The term.
+    fn term(token: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid term: {}", token);
+        if let Some(rest) = token.strip_prefix("rating") {
+            return Comparison::OPERATORS
+                .iter()
+                .find(|&&(operator, _)| rest.starts_with(operator))
+                .and_then(|&(operator, comparison)| {
+                    rest[operator.len()..]
+                        .parse()
+                        .ok()
+                        .map(|rating| Query::Rating(comparison, rating))
+                })
+                .ok_or_else(invalid);
+        }
+
+        let mut parts = token.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (_, Some("")) => Err(invalid()),
+            (Some("tag"), Some(tag)) => Ok(Query::Tag(tag.into())),
+            (Some("year"), Some(year)) => {
+                year.parse().map(Query::Year).map_err(|_| invalid())
+            }
+            (Some("month"), Some(month)) => month
+                .parse()
+                .ok()
+                .filter(|&m| (1..=12).contains(&m))
+                .map(Query::Month)
+                .ok_or_else(invalid),
+            (Some("type"), Some(media_type)) => {
+                Ok(Query::Type(media_type.into()))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Splits an expression into tokens.
+    ///
+    /// Tokens are separated by whitespace, and parentheses are always
+    /// separate tokens.
+    ///
+    /// # Arguments
+    /// *  `s` - The expression.
+    fn tokens(s: &str) -> Vec<String> {
+        let mut result = Vec::new();
+        let mut current = String::new();
+        for c in s.chars() {
+            if c.is_whitespace() || c == '(' || c == ')' {
+                if !current.is_empty() {
+                    result.push(current.split_off(0));
+                }
+                if !c.is_whitespace() {
+                    result.push(c.to_string());
+                }
+            } else {
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            result.push(current);
+        }
+        result
+    }
+}
+
+impl str::FromStr for Query {
+    type Err = String;
+
+    /// Parses a query expression.
+    ///
+    /// The error describes the first problem encountered.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Self::tokens(s).into_iter().peekable();
+        let result = Self::alternatives(&mut tokens)?;
+        match tokens.next() {
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Ok(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use data::tests::*;
+    use super::*;
+
+    /// Creates an item with tags and a rating.
+    ///
+    /// # Arguments
+    /// *  `name` - The item source name.
+    /// *  `year` - The year part.
+    /// *  `month` - The month part.
+    /// *  `tags` - The item tags.
+    /// *  `rating` - The item rating.
+    fn rated(
+        name: &str,
+        year: i32,
+        month: i32,
+        tags: &[&str],
+        rating: Option<u32>,
+    ) -> Item {
+        let mut result = item(name, year, month, 1);
+        result.tags.extend(tags.iter().map(|t| String::from(*t)));
+        result.rating = rating;
+        result
+    }
+
+    /// Tests that terms are parsed.
+    #[test]
+    fn from_str_terms() {
+        assert_eq!(Ok(Query::Tag("Family".into())), "tag:Family".parse());
+        assert_eq!(Ok(Query::Year(2020)), "year:2020".parse());
+        assert_eq!(Ok(Query::Month(12)), "month:12".parse());
+        assert_eq!(Ok(Query::Type("image".into())), "type:image".parse());
+        assert_eq!(
+            Ok(Query::Rating(Comparison::GreaterOrEqual, 3)),
+            "rating>=3".parse(),
+        );
+        assert_eq!(
+            Ok(Query::Rating(Comparison::Less, 2)),
+            "rating<2".parse(),
+        );
+    }
+
+    /// Tests that invalid expressions are rejected.
+    #[test]
+    fn from_str_invalid() {
+        assert!("".parse::<Query>().is_err());
+        assert!("tag:".parse::<Query>().is_err());
+        assert!("year:last".parse::<Query>().is_err());
+        assert!("month:13".parse::<Query>().is_err());
+        assert!("rating~3".parse::<Query>().is_err());
+        assert!("colour:red".parse::<Query>().is_err());
+        assert!("tag:a and".parse::<Query>().is_err());
+        assert!("tag:a tag:b".parse::<Query>().is_err());
+        assert!("(tag:a or tag:b".parse::<Query>().is_err());
+        assert!("tag:a)".parse::<Query>().is_err());
+    }
+
+    /// Tests that and binds tighter than or, and not tighter than both.
+    #[test]
+    fn from_str_precedence() {
+        let tag = |t: &str| Box::new(Query::Tag(t.into()));
+        assert_eq!(
+            Ok(Query::Or(tag("a"), Box::new(Query::And(tag("b"), tag("c"))))),
+            "tag:a or tag:b and tag:c".parse(),
+        );
+        assert_eq!(
+            Ok(Query::Or(Box::new(Query::And(tag("a"), tag("b"))), tag("c"))),
+            "tag:a and tag:b or tag:c".parse(),
+        );
+        assert_eq!(
+            Ok(Query::And(Box::new(Query::Or(tag("a"), tag("b"))), tag("c"))),
+            "(tag:a or tag:b) and tag:c".parse(),
+        );
+        assert_eq!(
+            Ok(Query::And(Box::new(Query::Not(tag("a"))), tag("b"))),
+            "not tag:a and tag:b".parse(),
+        );
+    }
+
+    /// Tests that queries match the expected items.
+    #[test]
+    fn matches() {
+        let family2020 = rated("a.jpg", 2020, 6, &["Family"], Some(4));
+        let family2019 = rated("b.jpg", 2019, 6, &["Family"], Some(2));
+        let work2020 = rated("c.png", 2020, 1, &["Work"], None);
+        let video = rated("d.mp4", 2020, 6, &["Family"], Some(5));
+        let all = [&family2020, &family2019, &work2020, &video];
+        let matching = |s: &str| {
+            let query = s.parse::<Query>().unwrap();
+            all.iter()
+                .filter(|i| query.matches(i))
+                .map(|i| i.path.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec!["a.jpg"],
+            matching("tag:Family and year:2020 and type:image"),
+        );
+        assert_eq!(
+            vec!["a.jpg", "c.png"],
+            matching("type:image and (year:2020 or tag:Work)"),
+        );
+        assert_eq!(
+            vec!["a.jpg", "c.png", "d.mp4"],
+            matching("tag:Work or tag:Family and rating>=4"),
+        );
+        assert_eq!(vec!["b.jpg"], matching("not year:2020"));
+        assert_eq!(vec!["c.png"], matching("month:1 and type:image/png"));
+        assert_eq!(vec!["b.jpg"], matching("rating<=2"));
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections;
 use std::fmt;
+use std::path;
 use std::str;
 
 /// A tag.
@@ -12,17 +14,31 @@ pub struct Tag {
 }
 
 impl Tag {
-    const SEPARATOR: char = '/';
+    /// The separator used unless another one is given.
+    pub const DEFAULT_SEPARATOR: char = '/';
 
     /// Creates a named tag.
     ///
+    /// Whitespace surrounding each part is removed.
+    ///
     /// # Arguments
     /// *  `path` - The full name of the tag.
     ///
-    ///    This value may contain any number of separators.
+    ///    This value may contain any number of default separators.
     pub fn new(path: &str) -> Self {
+        Self::with_separator(path, Self::DEFAULT_SEPARATOR)
+    }
+
+    /// Creates a named tag using a specific separator.
+    ///
+    /// Whitespace surrounding each part is removed.
+    ///
+    /// # Arguments
+    /// *  `path` - The full name of the tag.
+    /// *  `separator` - The separator between the parts of `path`.
+    pub fn with_separator(path: &str, separator: char) -> Self {
         Self {
-            parts: path.split(Self::SEPARATOR).map(String::from).collect(),
+            parts: Self::split(path, separator),
         }
     }
 
@@ -35,10 +51,47 @@ impl Tag {
     /// *  `name` - The name of the leaf tag.
     pub fn leaf(&self, name: &str) -> Self {
         let mut parts = self.parts.clone();
-        parts.extend(name.split(Self::SEPARATOR).map(String::from));
+        parts.extend(Self::split(name, Self::DEFAULT_SEPARATOR));
         Self { parts }
     }
 
+    /// Parses a tag using a specific separator.
+    ///
+    /// Whitespace surrounding each part is removed. This function will
+    /// succeed unless any part is empty once trimmed, which includes empty
+    /// strings and strings starting or ending with a separator.
+    ///
+    /// # Arguments
+    /// *  `s` - The full name of the tag.
+    /// *  `separator` - The separator between the parts of `s`.
+    pub fn parse(s: &str, separator: char) -> Result<Self, ()> {
+        let tag = Self::with_separator(s, separator);
+        if tag.parts.iter().any(String::is_empty) {
+            Err(())
+        } else {
+            Ok(tag)
+        }
+    }
+
+    /// Generates the full name of this tag using a specific separator.
+    ///
+    /// # Arguments
+    /// *  `separator` - The separator between the parts.
+    pub fn join(&self, separator: char) -> String {
+        self.parts.join(&separator.to_string())
+    }
+
+    /// Splits a tag name into trimmed parts.
+    ///
+    /// # Arguments
+    /// *  `path` - The tag name.
+    /// *  `separator` - The separator between parts.
+    fn split(path: &str, separator: char) -> Vec<String> {
+        path.split(separator)
+            .map(|part| part.trim().to_string())
+            .collect()
+    }
+
     /// Returns whether this is a root tag.
     pub fn is_root(&self) -> bool {
         self.parts.len() < 2
@@ -63,6 +116,32 @@ impl Tag {
     pub fn name(&self) -> Option<&String> {
         self.parts.last()
     }
+
+    /// Lists every tag in the tree formed by a collection of tags.
+    ///
+    /// The result contains every tag along with all its parents, without
+    /// duplicates and sorted part by part, so that every tag is immediately
+    /// followed by the tags nested under it.
+    ///
+    /// # Arguments
+    /// *  `tags` - The full names of the tags.
+    /// *  `separator` - The separator between the parts of `tags`.
+    pub fn hierarchy<'a, T: IntoIterator<Item = &'a String>>(
+        tags: T,
+        separator: char,
+    ) -> Vec<Self> {
+        tags.into_iter()
+            .map(|tag| Self::with_separator(tag, separator))
+            .flat_map(|tag| {
+                (1..tag.parts.len() + 1)
+                    .map(|length| tag.parts[..length].to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|parts| Self { parts })
+            .collect()
+    }
 }
 
 impl fmt::Display for Tag {
@@ -71,28 +150,45 @@ impl fmt::Display for Tag {
             if i == 0 {
                 write!(formatter, "{}", part)?;
             } else {
-                write!(formatter, "{}{}", Self::SEPARATOR, part)?;
+                write!(formatter, "{}{}", Self::DEFAULT_SEPARATOR, part)?;
             }
         }
         Ok(())
     }
 }
 
+impl<'a> From<&'a path::Path> for Tag {
+    /// Converts a relative path to a tag.
+    ///
+    /// Every normal component of the path becomes a part of the tag.
+    ///
+    /// # Arguments
+    /// *  `source` - The path to convert.
+    fn from(source: &'a path::Path) -> Self {
+        Self {
+            parts: source
+                .components()
+                .filter_map(|c| match c {
+                    path::Component::Normal(part) => {
+                        Some(part.to_string_lossy().into_owned())
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
 impl str::FromStr for Tag {
     type Err = ();
 
-    /// Converts a string to a tag.
+    /// Converts a string to a tag using the default separator.
     ///
-    /// This function will succeed unless the string is empty, or starts or ends
-    /// with a separator.
+    /// See [`Tag::parse`].
+    ///
+    /// [`Tag::parse`]: struct.Tag.html#method.parse
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() == 0 || s.chars().next() == Some(Self::SEPARATOR)
-            || s.chars().last() == Some(Self::SEPARATOR)
-        {
-            Err(())
-        } else {
-            Ok(Self::new(s))
-        }
+        Self::parse(s, Self::DEFAULT_SEPARATOR)
     }
 }
 
@@ -135,6 +231,16 @@ mod test {
         assert_eq!("root/leaf/sub", leaf2.to_string());
     }
 
+    /// Tests that a path is converted to a nested tag.
+    #[test]
+    fn from_path() {
+        assert_eq!(
+            "Vacation/Beach",
+            Tag::from(path::Path::new("Vacation/Beach")).to_string(),
+        );
+        assert!(Tag::from(path::Path::new("")).parts.is_empty());
+    }
+
     /// Tests that an invalid string cannot be parsed.
     #[test]
     fn from_str_invalid() {
@@ -143,6 +249,16 @@ mod test {
         assert_eq!(Err(()), "ends/".parse::<Tag>());
     }
 
+    /// Tests that whitespace surrounding parts is removed.
+    #[test]
+    fn from_str_whitespace() {
+        assert_eq!(Ok(Tag::new("Beach")), " Beach ".parse::<Tag>());
+        assert_eq!("a/b/c", "a/ b /c".parse::<Tag>().unwrap().to_string());
+        assert_eq!(Err(()), "a/ /c".parse::<Tag>());
+        assert_eq!(Err(()), " \t".parse::<Tag>());
+        assert_eq!(Tag::new("a/b"), Tag::new("a").leaf(" b "));
+    }
+
     /// Tests parsing of flat tags.
     #[test]
     fn from_str_no_parent() {
@@ -162,6 +278,55 @@ mod test {
         assert_eq!("root/leaf/sub", leaf2.to_string());
     }
 
+    /// Tests that a tag with another separator is split into nested parts.
+    #[test]
+    fn with_separator() {
+        let tag = Tag::with_separator("Places>Europe> France", '>');
+        assert_eq!(vec!["Places", "Europe", "France"], tag.parts);
+        assert!(Tag::new("Places/Europe").is_parent_of(&tag));
+        assert_eq!(
+            vec!["Places/Europe"],
+            Tag::with_separator("Places/Europe", '>').parts,
+        );
+        assert_eq!("Places>Europe>France", tag.join('>'));
+        assert_eq!(Ok(tag), Tag::parse("Places > Europe>France", '>'));
+        assert_eq!(Err(()), Tag::parse("Places>", '>'));
+    }
+
+    /// Tests that the tag tree lists all parents, sorted.
+    #[test]
+    fn hierarchy() {
+        let tags = vec![
+            "Places/Europe/France".to_string(),
+            "Family".to_string(),
+            "Places/Asia".to_string(),
+            "Places/Europe".to_string(),
+            "Places Visited".to_string(),
+        ];
+        assert_eq!(
+            vec![
+                "Family",
+                "Places",
+                "Places/Asia",
+                "Places/Europe",
+                "Places/Europe/France",
+                "Places Visited",
+            ],
+            Tag::hierarchy(&tags, '/')
+                .iter()
+                .map(Tag::to_string)
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            vec!["a/b", "a/b>c"],
+            Tag::hierarchy(&vec!["a/b>c".to_string()], '>')
+                .iter()
+                .map(|tag| tag.join('>'))
+                .collect::<Vec<_>>(),
+        );
+        assert!(Tag::hierarchy(&Vec::new(), '/').is_empty());
+    }
+
     #[test]
     fn is_parent_of() {
         assert!(Tag::new("a").is_parent_of(&Tag::new("a/b")));
@@ -1,40 +1,51 @@
 use std;
 use std::fmt;
 use std::path;
+use std::str;
 
 use time;
 
 /// The time format used for item timestamps.
 const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
 
+/// The number of nanoseconds in a second.
+const NSEC_PER_SEC: i32 = 1_000_000_000;
+
+/// The name used for undated timestamps.
+const UNDATED: &str = "undated";
+
 /// Extracts the modified timestamp from a file.
 ///
-/// If no meta data can be extracted, the current time is returned.
+/// If no meta data can be extracted, `None` is returned.
 ///
 /// # Arguments
 /// *  `path` - The path of the file.
-pub fn timestamp<P: AsRef<path::Path>>(path: &P) -> std::time::SystemTime {
+pub fn timestamp<P: AsRef<path::Path>>(
+    path: &P,
+) -> Option<std::time::SystemTime> {
     let path: &path::Path = path.as_ref();
-    path.metadata()
-        .and_then(|meta| meta.modified())
-        .unwrap_or(std::time::SystemTime::now())
+    path.metadata().and_then(|meta| meta.modified()).ok()
 }
 
 /// Converts a system time to a time spec.
 ///
+/// The sub-second part of the system time is kept.
+///
 /// # Arguments
 /// *  `st` - The system time to convert.
 pub fn system_time_to_timespec(st: std::time::SystemTime) -> time::Timespec {
-    // If the source is before the Unix epoch, we must manually invert it
+    // If the source is before the Unix epoch, we must manually invert it; the
+    // nanoseconds of a time spec are always positive, so a fraction of a
+    // second borrows from the seconds
     let (sec, ns) =
         if let Ok(duration) = st.duration_since(std::time::UNIX_EPOCH) {
             (duration.as_secs() as i64, duration.subsec_nanos() as i32)
         } else {
             let duration = std::time::UNIX_EPOCH.duration_since(st).unwrap();
-            (
-                -(duration.as_secs() as i64),
-                -(duration.subsec_nanos() as i32),
-            )
+            match duration.subsec_nanos() as i32 {
+                0 => (-(duration.as_secs() as i64), 0),
+                ns => (-(duration.as_secs() as i64) - 1, NSEC_PER_SEC - ns),
+            }
         };
 
     time::Timespec::new(sec, ns).into()
@@ -59,21 +70,88 @@ impl Timestamp {
     pub fn day(&self) -> i32 {
         return self.0.tm_mday;
     }
+
+    /// The timestamp used for items whose time of generation is unknown.
+    ///
+    /// This is the first day of year `0`, and it is displayed as `undated`.
+    pub fn undated() -> Self {
+        (0, 1, 1).into()
+    }
+
+    /// Returns whether this is the timestamp used for items whose time of
+    /// generation is unknown.
+    pub fn is_undated(&self) -> bool {
+        self.year() == 0
+            && self.month() == 1
+            && self.day() == 1
+            && self.0.tm_hour == 0
+            && self.0.tm_min == 0
+            && self.0.tm_sec == 0
+    }
+
+    /// Converts this timestamp to a time spec.
+    ///
+    /// Unlike [`time::Tm::to_timespec`], the offset of the calendar time is
+    /// always used, so the result does not depend on the time zone of the
+    /// host.
+    ///
+    /// [`time::Tm::to_timespec`]: https://docs.rs/time/0.1/time/struct.Tm.html#method.to_timespec
+    pub fn to_timespec(&self) -> time::Timespec {
+        let utc = time::Tm {
+            tm_utcoff: 0,
+            ..self.0
+        }.to_timespec();
+        time::Timespec::new(utc.sec - i64::from(self.0.tm_utcoff), utc.nsec)
+    }
+
+    /// Returns the ISO 8601 week-numbering year and week.
+    ///
+    /// Since weeks start on Monday, the first days of January may belong to
+    /// the last week of the previous year, and the last days of December to
+    /// the first week of the next year.
+    pub fn iso_week(&self) -> (i32, u32) {
+        let year = self.year();
+        let days = days(year, self.month(), self.day());
+        let ordinal = days - self::days(year, 1, 1) + 1;
+        let week = (ordinal - weekday(days) + 10) / 7;
+        if week < 1 {
+            (year - 1, weeks(year - 1))
+        } else if week as u32 > weeks(year) {
+            (year + 1, 1)
+        } else {
+            (year, week as u32)
+        }
+    }
 }
 
 impl fmt::Display for Timestamp {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        write!(fmt, "{}", self.0.strftime(TIME_FORMAT).unwrap())
+        if self.is_undated() {
+            write!(fmt, "{}", UNDATED)
+        } else {
+            write!(fmt, "{}", self.0.strftime(TIME_FORMAT).unwrap())
+        }
     }
 }
 
 impl From<time::Tm> for Timestamp {
-    /// Converts a calendar time by wrapping it.
+    /// Converts a calendar time by normalising and wrapping it.
+    ///
+    /// Fields out of range, such as the second `60` of a leap second, are
+    /// carried into the next field, so that the year, month and day always
+    /// agree with the time spec. The offset is kept.
     ///
     /// # Arguments
     /// *  `source` - The source time.
     fn from(source: time::Tm) -> Self {
-        Timestamp(source)
+        let utc = time::Tm {
+            tm_utcoff: 0,
+            ..source
+        }.to_timespec();
+        Timestamp(time::Tm {
+            tm_utcoff: source.tm_utcoff,
+            ..time::at_utc(utc)
+        })
     }
 }
 
@@ -112,7 +190,7 @@ impl From<(i32, i32, i32, i32, i32, i32)> for Timestamp {
         let tm_hour = hour;
         let tm_min = min;
         let tm_sec = sec;
-        Timestamp(time::Tm {
+        Timestamp::from(time::Tm {
             tm_year,
             tm_mon,
             tm_mday,
@@ -153,3 +231,296 @@ impl AsRef<time::Tm> for Timestamp {
         &self.0
     }
 }
+
+/// The time zone in which calendar times without an offset, such as EXIF
+/// timestamps, are interpreted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    /// Coordinated universal time.
+    Utc,
+
+    /// The time zone of the host at the time in question.
+    Local,
+
+    /// A fixed offset east of UTC, in seconds.
+    Offset(i32),
+}
+
+impl Zone {
+    /// Assigns this zone to a calendar time.
+    ///
+    /// Only the offset is changed, so the calendar fields are kept. Calendar
+    /// times whose offset was parsed along with them are returned unchanged,
+    /// also when the offset is zero.
+    ///
+    /// # Arguments
+    /// *  `tm` - The calendar time.
+    /// *  `parsed_offset` - Whether the offset of `tm` was parsed.
+    pub fn apply(&self, tm: time::Tm, parsed_offset: bool) -> time::Tm {
+        if parsed_offset {
+            return tm;
+        }
+        let tm_utcoff = match *self {
+            Zone::Utc => 0,
+            Zone::Offset(offset) => offset,
+            Zone::Local => {
+                // The offset at the calendar time read as UTC is off by the
+                // offset itself, so look it up again at the corrected time
+                let utc = tm.to_timespec();
+                let offset = time::at(utc).tm_utcoff;
+                time::at(time::Timespec::new(utc.sec - i64::from(offset), 0))
+                    .tm_utcoff
+            }
+        };
+        time::Tm { tm_utcoff, ..tm }
+    }
+}
+
+impl Default for Zone {
+    /// The default zone; [`Zone::Utc`].
+    ///
+    /// [`Zone::Utc`]: enum.Zone.html#variant.Utc
+    fn default() -> Self {
+        Zone::Utc
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Zone::Utc => write!(fmt, "utc"),
+            Zone::Local => write!(fmt, "local"),
+            Zone::Offset(offset) => write!(
+                fmt,
+                "{}{:02}:{:02}",
+                if offset < 0 { '-' } else { '+' },
+                offset.abs() / 3600,
+                offset.abs() % 3600 / 60,
+            ),
+        }
+    }
+}
+
+impl str::FromStr for Zone {
+    type Err = String;
+
+    /// Parses a zone.
+    ///
+    /// The string is either `utc`, `local` or an offset on the form
+    /// `+HH:MM` or `-HH:MM`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid zone: {}", s);
+        match s {
+            "utc" => Ok(Zone::Utc),
+            "local" => Ok(Zone::Local),
+            _ => {
+                let sign = match s.chars().next() {
+                    Some('+') => 1,
+                    Some('-') => -1,
+                    _ => return Err(invalid()),
+                };
+                let mut parts = s[1..].splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(hours), Some(minutes))
+                        if hours.len() == 2 && minutes.len() == 2 =>
+                    {
+                        match (hours.parse::<i32>(), minutes.parse::<i32>()) {
+                            (Ok(hours), Ok(minutes))
+                                if hours < 24 && minutes < 60 =>
+                            {
+                                Ok(Zone::Offset(
+                                    sign * (hours * 3600 + minutes * 60),
+                                ))
+                            }
+                            _ => Err(invalid()),
+                        }
+                    }
+                    _ => Err(invalid()),
+                }
+            }
+        }
+    }
+}
+
+/// Calculates the number of ISO 8601 weeks in a week-numbering year.
+///
+/// A year has 53 weeks if it starts on a Thursday, or if it is a leap year
+/// starting on a Wednesday.
+///
+/// # Arguments
+/// *  `year` - The year.
+fn weeks(year: i32) -> u32 {
+    let first = weekday(days(year, 1, 1));
+    let leap = days(year + 1, 1, 1) - days(year, 1, 1) == 366;
+    if first == 4 || (leap && first == 3) {
+        53
+    } else {
+        52
+    }
+}
+
+/// Calculates the ISO 8601 day of the week, where Monday is `1` and Sunday
+/// is `7`.
+///
+/// # Arguments
+/// *  `days` - The number of days since 1970-01-01.
+fn weekday(days: i64) -> i64 {
+    // 1970-01-01 was a Thursday
+    (days + 3).rem_euclid(7) + 1
+}
+
+/// Calculates the number of days since 1970-01-01 of a date in the
+/// proleptic Gregorian calendar.
+///
+/// # Arguments
+/// *  `year` - The year.
+/// *  `month` - The month.
+/// *  `day` - The day.
+fn days(year: i32, month: i32, day: i32) -> i64 {
+    let year = i64::from(if month <= 2 { year - 1 } else { year });
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5
+            + i64::from(day) - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    /// Tests that the sub-second part of system times is kept.
+    #[test]
+    fn system_time_subsecond() {
+        assert_eq!(
+            time::Timespec::new(1, 250_000_000),
+            system_time_to_timespec(UNIX_EPOCH + Duration::new(1, 250_000_000)),
+        );
+        assert_eq!(
+            time::Timespec::new(-2, 750_000_000),
+            system_time_to_timespec(UNIX_EPOCH - Duration::new(1, 250_000_000)),
+        );
+        assert_eq!(
+            time::Timespec::new(-1, 0),
+            system_time_to_timespec(UNIX_EPOCH - Duration::new(1, 0)),
+        );
+    }
+
+    /// Tests that zones are parsed and formatted.
+    #[test]
+    fn zone_from_str() {
+        assert_eq!(Ok(Zone::Utc), "utc".parse::<Zone>());
+        assert_eq!(Ok(Zone::Local), "local".parse::<Zone>());
+        assert_eq!(Ok(Zone::Offset(5400)), "+01:30".parse::<Zone>());
+        assert_eq!(Ok(Zone::Offset(-18000)), "-05:00".parse::<Zone>());
+        assert_eq!("-05:00", Zone::Offset(-18000).to_string());
+        assert!("".parse::<Zone>().is_err());
+        assert!("01:00".parse::<Zone>().is_err());
+        assert!("+1:00".parse::<Zone>().is_err());
+        assert!("+24:00".parse::<Zone>().is_err());
+        assert!("+01:60".parse::<Zone>().is_err());
+    }
+
+    /// Tests that the time spec of an EXIF timestamp depends only on the
+    /// zone.
+    #[test]
+    fn zone_timespec() {
+        let exif = time::strptime("2010:05:06 07:08:09", "%Y:%m:%d %H:%M:%S")
+            .unwrap();
+        let utc = Timestamp::from(Zone::Utc.apply(exif, false));
+        let offset = Timestamp::from(Zone::Offset(7200).apply(exif, false));
+
+        assert_eq!(time::Timespec::new(1273129689, 0), utc.to_timespec());
+        assert_eq!(time::Timespec::new(1273122489, 0), offset.to_timespec());
+        assert_eq!("2010-05-06 07:08", offset.to_string());
+        assert_eq!(
+            offset.to_timespec(),
+            Timestamp::from(Zone::Utc.apply(
+                time::Tm {
+                    tm_utcoff: 7200,
+                    ..exif
+                },
+                true,
+            )).to_timespec(),
+        );
+    }
+
+    /// Tests that an explicit zero offset is not replaced by the zone.
+    #[test]
+    fn zone_parsed_utc() {
+        let parsed =
+            time::strptime("2010-05-06T07:08:09Z", "%Y-%m-%dT%H:%M:%S%z")
+                .unwrap();
+
+        assert_eq!(
+            time::Timespec::new(1273129689, 0),
+            Timestamp::from(Zone::Offset(7200).apply(parsed, true))
+                .to_timespec(),
+        );
+        assert_eq!(
+            time::Timespec::new(1273122489, 0),
+            Timestamp::from(Zone::Offset(7200).apply(parsed, false))
+                .to_timespec(),
+        );
+    }
+
+    /// Tests that a leap second is carried into the next day, and thus the
+    /// next year.
+    #[test]
+    fn leap_second() {
+        let exif = time::strptime("2016:12:31 23:59:60", "%Y:%m:%d %H:%M:%S")
+            .unwrap();
+        let timestamp = Timestamp::from(Zone::Utc.apply(exif, false));
+
+        assert_eq!(
+            (2017, 1, 1),
+            (timestamp.year(), timestamp.month(), timestamp.day()),
+        );
+        assert_eq!("2017-01-01 00:00", timestamp.to_string());
+        assert_eq!(
+            Timestamp::from((2017, 1, 1)).to_timespec(),
+            timestamp.to_timespec(),
+        );
+        assert_eq!(timestamp, Timestamp::from((2016, 12, 31, 23, 59, 60)));
+    }
+
+    /// Tests that a late night timestamp on the last day of a year is filed
+    /// under that day regardless of its offset.
+    #[test]
+    fn year_end() {
+        let exif = time::strptime("2020:12:31 23:59:59", "%Y:%m:%d %H:%M:%S")
+            .unwrap();
+        for zone in &[Zone::Utc, Zone::Offset(3600), Zone::Offset(-18000)] {
+            let timestamp = Timestamp::from(zone.apply(exif, false));
+            assert_eq!(
+                (2020, 12, 31),
+                (timestamp.year(), timestamp.month(), timestamp.day()),
+            );
+            assert_eq!("2020-12-31 23:59", timestamp.to_string());
+        }
+        assert!(
+            Timestamp::from(Zone::Utc.apply(exif, false))
+                < Timestamp::from((2021, 1, 1))
+        );
+    }
+
+    /// Tests that weeks are calculated correctly around year boundaries.
+    #[test]
+    fn iso_week() {
+        let of =
+            |year, month, day| Timestamp::from((year, month, day)).iso_week();
+        assert_eq!((1970, 1), of(1970, 1, 1));
+        assert_eq!((2009, 1), of(2008, 12, 29));
+        assert_eq!((2009, 53), of(2010, 1, 3));
+        assert_eq!((2010, 1), of(2010, 1, 4));
+        assert_eq!((2020, 53), of(2021, 1, 1));
+        assert_eq!((2024, 52), of(2024, 12, 29));
+        assert_eq!((2025, 1), of(2024, 12, 30));
+    }
+}
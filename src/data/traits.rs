@@ -20,7 +20,7 @@ impl<'a> From<&'a data::cache::Entry> for fuse_mt::ResultEntry {
         let ttl = time::Timespec::new(0x7FFFFFFF, 0);
         let timestamp = source.timestamp();
         match source {
-            &data::cache::Entry::Directory(_) => Ok((
+            &data::cache::Entry::Directory(_, _) => Ok((
                 ttl,
                 fuse_mt::FileAttr {
                     size: 0,
@@ -38,7 +38,8 @@ impl<'a> From<&'a data::cache::Entry> for fuse_mt::ResultEntry {
                     flags: 0,
                 },
             )),
-            &data::cache::Entry::Item(ref item) => item.path
+            &data::cache::Entry::Item(data::Item { ref path, .. })
+            | &data::cache::Entry::Sidecar(_, ref path) => path
                 .metadata()
                 .map(|meta| {
                     (
@@ -61,6 +62,24 @@ impl<'a> From<&'a data::cache::Entry> for fuse_mt::ResultEntry {
                     )
                 })
                 .map_err(util::map_error),
+            &data::cache::Entry::Index(_) => Ok((
+                ttl,
+                fuse_mt::FileAttr {
+                    size: 0,
+                    blocks: 0,
+                    atime: timestamp,
+                    mtime: timestamp,
+                    ctime: timestamp,
+                    crtime: timestamp,
+                    kind: fuse_mt::FileType::RegularFile,
+                    perm: 0o444,
+                    nlink: 1,
+                    uid: 0,
+                    gid: 0,
+                    rdev: 0,
+                    flags: 0,
+                },
+            )),
             &data::cache::Entry::Link(_, _) => Ok((
                 ttl,
                 fuse_mt::FileAttr {
@@ -87,28 +106,36 @@ impl<'a> From<&'a data::cache::Entry> for fuse_mt::ResultReaddir {
     /// Converts a cache entry to a directory listing.
     ///
     /// If the entry is not a directory, `ENOTDIR` will be returned, otherwise
-    /// a listing of all child entries is returned.
+    /// a listing of all child entries is returned. The listing is sorted by
+    /// name, with embedded numbers ordered by value.
     ///
     /// # Arguments
     /// *  `source` - The entry to convert.
     fn from(source: &'a data::cache::Entry) -> fuse_mt::ResultReaddir {
         match source {
-            &data::cache::Entry::Directory(ref tree) => Ok(tree.iter()
-                .map(|(name, entry)| fuse_mt::DirectoryEntry {
-                    name: name.to_os_string(),
-                    kind: match entry {
-                        &data::cache::Entry::Directory(_) => {
-                            fuse_mt::FileType::Directory
-                        }
-                        &data::cache::Entry::Item(_) => {
-                            fuse_mt::FileType::RegularFile
-                        }
-                        &data::cache::Entry::Link(_, _) => {
-                            fuse_mt::FileType::Symlink
-                        }
-                    },
-                })
-                .collect()),
+            data::cache::Entry::Directory(_, tree) => {
+                let mut children = tree.iter().collect::<Vec<_>>();
+                children.sort_by(|a, b| util::natural_cmp(a.0, b.0));
+                Ok(children
+                    .into_iter()
+                    .map(|(name, entry)| fuse_mt::DirectoryEntry {
+                        name: name.to_os_string(),
+                        kind: match entry {
+                            &data::cache::Entry::Directory(_, _) => {
+                                fuse_mt::FileType::Directory
+                            }
+                            &data::cache::Entry::Item(_)
+                            | &data::cache::Entry::Sidecar(_, _)
+                            | &data::cache::Entry::Index(_) => {
+                                fuse_mt::FileType::RegularFile
+                            }
+                            &data::cache::Entry::Link(_, _) => {
+                                fuse_mt::FileType::Symlink
+                            }
+                        },
+                    })
+                    .collect())
+            }
             _ => Err(libc::ENOTDIR),
         }
     }
@@ -0,0 +1,149 @@
+use std::collections;
+use std::str;
+
+use mime_guess;
+
+use data::cache;
+
+/// The group of media types not mapped to any group.
+pub const OTHER_GROUP: &str = "Other";
+
+/// A mapping of media types to friendly groups.
+///
+/// A mapping is read from a configuration with one `media/type = Group`
+/// setting per line. Empty lines and lines starting with `#` are ignored. The
+/// subtype may be `*` to map all media types of a type not mapped
+/// explicitly, so `image/* = Photos` and `image/x-canon-cr2 = RAW` map all
+/// images except CR2 files to `Photos`.
+///
+/// Media types are matched without regard to case, and media types not
+/// mapped belong to [`OTHER_GROUP`].
+///
+/// [`OTHER_GROUP`]: constant.OTHER_GROUP.html
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeGroups {
+    /// The group of each media type, keyed by lower case media type.
+    groups: collections::HashMap<String, String>,
+}
+
+impl TypeGroups {
+    /// Finds the group of a media type.
+    ///
+    /// # Arguments
+    /// *  `media_type` - The media type.
+    pub fn group(&self, media_type: &mime_guess::Mime) -> &str {
+        let type_ = media_type.type_().as_str().to_lowercase();
+        let subtype = media_type.subtype().as_str().to_lowercase();
+        self.groups
+            .get(&format!("{}/{}", type_, subtype))
+            .or_else(|| self.groups.get(&format!("{}/*", type_)))
+            .map(String::as_str)
+            .unwrap_or(OTHER_GROUP)
+    }
+}
+
+impl str::FromStr for TypeGroups {
+    type Err = String;
+
+    /// Parses a media type mapping.
+    ///
+    /// The error describes the first invalid line.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|&(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .fold(Ok(TypeGroups::default()), |acc, (number, line)| {
+                acc.and_then(|mut groups| {
+                    let mut parts = line.splitn(2, '=').map(str::trim);
+                    match (parts.next(), parts.next()) {
+                        (Some(media_type), Some(group))
+                            if valid(media_type) && valid_group(group) =>
+                        {
+                            groups.groups.insert(
+                                media_type.to_lowercase(),
+                                group.into(),
+                            );
+                            Ok(groups)
+                        }
+                        _ => Err(format!(
+                            "invalid setting on line {}: {}",
+                            number, line
+                        )),
+                    }
+                })
+            })
+    }
+}
+
+/// Checks whether a string is a valid media type pattern.
+///
+/// A pattern is a type and a subtype separated by `/`, and neither may be
+/// empty or contain white space.
+///
+/// # Arguments
+/// *  `media_type` - The media type pattern.
+fn valid(media_type: &str) -> bool {
+    let mut parts = media_type.splitn(2, '/');
+    match (parts.next(), parts.next()) {
+        (Some(type_), Some(subtype)) => [type_, subtype].iter().all(|part| {
+            !part.is_empty()
+                && !part.contains('/')
+                && !part.contains(char::is_whitespace)
+        }),
+        _ => false,
+    }
+}
+
+/// Checks whether a string is a valid group name.
+///
+/// A group name must be usable unchanged as the name of a single directory.
+///
+/// # Arguments
+/// *  `group` - The group name.
+fn valid_group(group: &str) -> bool {
+    cache::directory_name(group).is_some_and(|name| name == group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that invalid mappings are rejected.
+    #[test]
+    fn from_str_invalid() {
+        assert!("image/jpeg".parse::<TypeGroups>().is_err());
+        assert!("= Photos".parse::<TypeGroups>().is_err());
+        assert!("image = Photos".parse::<TypeGroups>().is_err());
+        assert!("image/jpeg = ".parse::<TypeGroups>().is_err());
+        assert!("image/jpeg = Photos/JPEG".parse::<TypeGroups>().is_err());
+        assert!("image/jpeg = .".parse::<TypeGroups>().is_err());
+        assert!("image/jpeg = ..".parse::<TypeGroups>().is_err());
+    }
+
+    /// Tests that media types are mapped exactly before by type, and that
+    /// unmapped media types belong to the other group.
+    #[test]
+    fn group() {
+        let groups = concat!(
+            "# Groups\n",
+            "image/* = Photos\n",
+            "\n",
+            "image/X-Canon-CR2 = RAW\n",
+            "video/mp4 = Videos\n",
+        ).parse::<TypeGroups>()
+            .unwrap();
+        let group = |media_type: &str| {
+            groups.group(&media_type.parse().unwrap()).to_string()
+        };
+
+        assert_eq!("Photos", group("image/jpeg"));
+        assert_eq!("RAW", group("image/x-canon-cr2"));
+        assert_eq!("Videos", group("video/mp4"));
+        assert_eq!(OTHER_GROUP, group("video/quicktime"));
+        assert_eq!(
+            OTHER_GROUP,
+            TypeGroups::default().group(&"image/jpeg".parse().unwrap()),
+        );
+    }
+}
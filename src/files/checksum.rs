@@ -0,0 +1,118 @@
+use std::collections;
+use std::io;
+use std::path;
+use std::sync;
+use std::time;
+
+use util;
+
+/// The state of a file at the time it was hashed; its modification time and
+/// size.
+type Stamp = (time::SystemTime, u64);
+
+/// A cache of SHA-256 checksums of source files.
+///
+/// Checksums are calculated when first requested and reused as long as the
+/// modification time and size of the file are unchanged. No lock is held
+/// while a file is being read, so concurrent requests for other files are
+/// not blocked.
+pub struct Checksums {
+    /// The checksums already calculated, along with the state of each file
+    /// when it was hashed.
+    digests: sync::Mutex<collections::HashMap<path::PathBuf, (Stamp, String)>>,
+}
+
+impl Checksums {
+    /// Creates an empty checksum cache.
+    pub fn new() -> Self {
+        Self {
+            digests: sync::Mutex::new(collections::HashMap::new()),
+        }
+    }
+
+    /// Retrieves the checksum of a file as a lower case hexadecimal string.
+    ///
+    /// If a checksum for the current state of the file is cached, it is
+    /// returned, otherwise the file is read and the result cached.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the file.
+    pub fn get(&self, path: &path::Path) -> io::Result<String> {
+        let metadata = path.metadata()?;
+        let stamp = (metadata.modified()?, metadata.len());
+
+        let cached = self.digests
+            .lock()
+            .ok()
+            .and_then(|digests| {
+                digests
+                    .get(path)
+                    .filter(|&(s, _)| s == &stamp)
+                    .map(|(_, digest)| digest.clone())
+            });
+        if let Some(digest) = cached {
+            return Ok(digest);
+        }
+
+        let digest = util::sha256(path)?;
+        if let Ok(mut digests) = self.digests.lock() {
+            digests.insert(path.to_path_buf(), (stamp, digest.clone()));
+        }
+        Ok(digest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use tempdir;
+
+    use super::*;
+
+    /// The SHA-256 checksum of `"hello world\n"`.
+    const HELLO_WORLD: &str = concat!(
+        "a948904f2f0f479b8f8197694b30184b",
+        "0d2ed1c1cd2a1ec0fb85d299a192a447",
+    );
+
+    /// Tests that the checksum of a known file is correct and stable.
+    #[test]
+    fn known() {
+        let dir = tempdir::TempDir::new("medifs-checksum").unwrap();
+        let path = dir.path().join("test.jpg");
+        fs::File::create(&path)
+            .and_then(|mut f| f.write_all(b"hello world\n"))
+            .unwrap();
+        let checksums = Checksums::new();
+
+        assert_eq!(HELLO_WORLD, checksums.get(&path).unwrap());
+        assert_eq!(HELLO_WORLD, checksums.get(&path).unwrap());
+    }
+
+    /// Tests that a changed file is hashed again.
+    #[test]
+    fn changed() {
+        let dir = tempdir::TempDir::new("medifs-checksum").unwrap();
+        let path = dir.path().join("test.jpg");
+        fs::File::create(&path)
+            .and_then(|mut f| f.write_all(b"hello"))
+            .unwrap();
+        let checksums = Checksums::new();
+        let before = checksums.get(&path).unwrap();
+        fs::File::create(&path)
+            .and_then(|mut f| f.write_all(b"hello world\n"))
+            .unwrap();
+
+        assert_ne!(before, checksums.get(&path).unwrap());
+        assert_eq!(HELLO_WORLD, checksums.get(&path).unwrap());
+    }
+
+    /// Tests that missing files are reported.
+    #[test]
+    fn missing() {
+        let checksums = Checksums::new();
+        assert!(checksums.get(path::Path::new("/missing.jpg")).is_err());
+    }
+}
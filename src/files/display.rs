@@ -0,0 +1,533 @@
+use std::collections;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+use std::process;
+use std::sync;
+use std::time;
+
+/// The command used to transform JPEG images losslessly.
+const JPEGTRAN: &str = "jpegtran";
+
+/// The exit status of `jpegtran` when it succeeded with warnings.
+const JPEGTRAN_WARNING: i32 = 2;
+
+/// The maximum number of bytes read when looking for the orientation.
+///
+/// This covers an EXIF segment of the maximum size following a JFIF segment.
+const HEADER_LIMIT: u64 = 128 * 1024;
+
+/// The EXIF tag holding the orientation.
+const ORIENTATION_TAG: u16 = 0x0112;
+
+/// The EXIF orientation of an image that needs no transformation.
+const NORMAL_ORIENTATION: u16 = 1;
+
+/// The maximum number of sizes kept.
+const MAX_SIZES: usize = 64 * 1024;
+
+/// The maximum total size of the display versions kept.
+const MAX_CONTENT: usize = 64 * 1024 * 1024;
+
+/// The state of a file at the time it was transformed; its modification
+/// time and size.
+type Stamp = (time::SystemTime, u64);
+
+/// The display versions of images.
+///
+/// Display versions are generated when first requested. Their sizes, and the
+/// most recently generated versions themselves, are reused as long as the
+/// modification time and size of the file are unchanged, so that opening an
+/// image after its size was read does not transform it again. Neither is
+/// kept without bound; the oldest are dropped first.
+pub struct Versions {
+    /// The sizes and content of the display versions.
+    state: sync::Mutex<State>,
+}
+
+/// The display versions already generated.
+#[derive(Default)]
+struct State {
+    /// The sizes of display versions, along with the state of each file
+    /// when it was transformed. The size is `None` for images served
+    /// unchanged.
+    sizes: collections::HashMap<path::PathBuf, (Stamp, Option<u64>)>,
+
+    /// The order in which sizes were added, oldest first.
+    order: collections::VecDeque<path::PathBuf>,
+
+    /// The most recently generated display versions, oldest first, along
+    /// with the state of each file when it was transformed.
+    content: collections::VecDeque<(path::PathBuf, Stamp, sync::Arc<Vec<u8>>)>,
+}
+
+impl State {
+    /// Finds the size of a display version.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    /// *  `stamp` - The current state of the file.
+    fn size(&self, path: &path::Path, stamp: &Stamp) -> Option<Option<u64>> {
+        self.sizes
+            .get(path)
+            .filter(|&(s, _)| s == stamp)
+            .map(|&(_, size)| size)
+    }
+
+    /// Finds a display version.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    /// *  `stamp` - The current state of the file.
+    fn content(
+        &self,
+        path: &path::Path,
+        stamp: &Stamp,
+    ) -> Option<sync::Arc<Vec<u8>>> {
+        self.content
+            .iter()
+            .find(|&(p, s, _)| p == path && s == stamp)
+            .map(|(_, _, content)| content.clone())
+    }
+
+    /// Stores a generated display version.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    /// *  `stamp` - The state of the file when it was transformed.
+    /// *  `content` - The display version, or `None` if the image is served
+    ///    unchanged.
+    fn insert(
+        &mut self,
+        path: &path::Path,
+        stamp: Stamp,
+        content: Option<sync::Arc<Vec<u8>>>,
+    ) {
+        let size = content.as_ref().map(|content| content.len() as u64);
+        if self.sizes
+            .insert(path.to_path_buf(), (stamp, size))
+            .is_none()
+        {
+            self.order.push_back(path.to_path_buf());
+        }
+        while self.sizes.len() > MAX_SIZES {
+            match self.order.pop_front() {
+                Some(oldest) => self.sizes.remove(&oldest),
+                None => break,
+            };
+        }
+
+        self.content.retain(|(p, _, _)| p != path);
+        if let Some(content) = content {
+            self.content.push_back((path.to_path_buf(), stamp, content));
+        }
+        while self.content.len() > 1
+            && self.content
+                .iter()
+                .map(|(_, _, content)| content.len())
+                .sum::<usize>() > MAX_CONTENT
+        {
+            self.content.pop_front();
+        }
+    }
+}
+
+impl Versions {
+    /// Creates an empty cache of display versions.
+    pub fn new() -> Self {
+        Self {
+            state: sync::Mutex::new(State::default()),
+        }
+    }
+
+    /// Retrieves the size of the display version of an image.
+    ///
+    /// Only images with an orientation other than the normal one are
+    /// transformed; for other images only the header is read, and `None` is
+    /// returned since they are served unchanged.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    pub fn size(&self, path: &path::Path) -> io::Result<Option<u64>> {
+        let stamp = stamp(path)?;
+        let cached = self.state
+            .lock()
+            .ok()
+            .and_then(|state| state.size(path, &stamp));
+        match cached {
+            Some(size) => Ok(size),
+            None => self.generate(path, stamp)
+                .map(|content| content.map(|content| content.len() as u64)),
+        }
+    }
+
+    /// Retrieves the display version of an image.
+    ///
+    /// If the image is served unchanged, `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    pub fn content(
+        &self,
+        path: &path::Path,
+    ) -> io::Result<Option<sync::Arc<Vec<u8>>>> {
+        let stamp = stamp(path)?;
+        let cached = self.state.lock().ok().and_then(|state| {
+            match state.size(path, &stamp) {
+                Some(None) => Some(None),
+                Some(Some(_)) => state.content(path, &stamp).map(Some),
+                None => None,
+            }
+        });
+        match cached {
+            Some(content) => Ok(content),
+            None => self.generate(path, stamp),
+        }
+    }
+
+    /// Generates the display version of an image and stores it.
+    ///
+    /// No lock is held while the image is transformed.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the image.
+    /// *  `stamp` - The state of the file before it is transformed.
+    fn generate(
+        &self,
+        path: &path::Path,
+        stamp: Stamp,
+    ) -> io::Result<Option<sync::Arc<Vec<u8>>>> {
+        let content = generate(path)?.map(sync::Arc::new);
+        if let Ok(mut state) = self.state.lock() {
+            state.insert(path, stamp, content.clone());
+        }
+        Ok(content)
+    }
+}
+
+/// Checks whether `jpegtran`, which generates display versions, can be run.
+pub fn can_transform() -> bool {
+    process::Command::new(JPEGTRAN)
+        .arg("-version")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// Generates the display version of an image.
+///
+/// The display version of a JPEG image with an EXIF orientation is the image
+/// transformed losslessly by `jpegtran` so that it appears correctly without
+/// applying the orientation, which is reset. Edge blocks that would be
+/// misplaced by the transformation are trimmed.
+///
+/// If the image is not a JPEG image, needs no transformation or cannot be
+/// transformed by `jpegtran`, `None` is returned and the image should be
+/// served unchanged. If `jpegtran` cannot be run at all, an error of kind
+/// `Other` is returned.
+///
+/// # Arguments
+/// *  `path` - The path of the image.
+pub fn generate(path: &path::Path) -> io::Result<Option<Vec<u8>>> {
+    let mut header = Vec::new();
+    fs::File::open(path)
+        .and_then(|f| f.take(HEADER_LIMIT).read_to_end(&mut header))?;
+    let transform: &[&str] = match orientation(&header) {
+        Some(2) => &["-flip", "horizontal"],
+        Some(3) => &["-rotate", "180"],
+        Some(4) => &["-flip", "vertical"],
+        Some(5) => &["-transpose"],
+        Some(6) => &["-rotate", "90"],
+        Some(7) => &["-transverse"],
+        Some(8) => &["-rotate", "270"],
+        _ => return Ok(None),
+    };
+
+    let output = process::Command::new(JPEGTRAN)
+        .args(["-copy", "all", "-trim"])
+        .args(transform)
+        .arg(path)
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|e| {
+            io::Error::other(
+                format!("cannot run {}: {}", JPEGTRAN, e),
+            )
+        })?;
+    if output.status.success()
+        || output.status.code() == Some(JPEGTRAN_WARNING)
+    {
+        let mut content = output.stdout;
+        reset_orientation(&mut content);
+        Ok(Some(content))
+    } else {
+        warn!(
+            "{} failed for {}: {}",
+            JPEGTRAN,
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        Ok(None)
+    }
+}
+
+/// Reads the state of a file.
+///
+/// # Arguments
+/// *  `path` - The path of the file.
+fn stamp(path: &path::Path) -> io::Result<Stamp> {
+    let metadata = path.metadata()?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Reads the EXIF orientation of JPEG data.
+///
+/// # Arguments
+/// *  `data` - The JPEG data, or its beginning.
+fn orientation(data: &[u8]) -> Option<u16> {
+    exif(data).map(|(value, _, _)| value)
+}
+
+/// Resets the EXIF orientation of JPEG data to the normal orientation.
+///
+/// Data without an orientation is left unchanged.
+///
+/// # Arguments
+/// *  `data` - The JPEG data.
+fn reset_orientation(data: &mut [u8]) {
+    if let Some((_, offset, little_endian)) = exif(data) {
+        let value = NORMAL_ORIENTATION;
+        let bytes = if little_endian {
+            [value as u8, (value >> 8) as u8]
+        } else {
+            [(value >> 8) as u8, value as u8]
+        };
+        data[offset..offset + 2].copy_from_slice(&bytes);
+    }
+}
+
+/// Finds the EXIF orientation in JPEG data.
+///
+/// The segments before the image data are searched for an EXIF application
+/// segment. The orientation, the position of its value in the data and
+/// whether it is little endian are returned.
+///
+/// # Arguments
+/// *  `data` - The JPEG data, or its beginning.
+fn exif(data: &[u8]) -> Option<(u16, usize, bool)> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut position = 2;
+    loop {
+        let (marker, start, end) = segment(data, position)?;
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        if marker == 0xE1 {
+            if let Some((value, offset, little_endian)) =
+                exif_orientation(&data[start..end])
+            {
+                return Some((value, start + offset, little_endian));
+            }
+        }
+        position = end;
+    }
+}
+
+/// Finds a marker segment.
+///
+/// The marker, and the start and end of the segment content, are returned.
+/// Markers without content have an empty content.
+///
+/// # Arguments
+/// *  `data` - The JPEG data.
+/// *  `position` - The position of the marker.
+fn segment(data: &[u8], mut position: usize) -> Option<(u8, usize, usize)> {
+    if *data.get(position)? != 0xFF {
+        return None;
+    }
+
+    // Markers may be preceded by any number of fill bytes
+    while *data.get(position + 1)? == 0xFF {
+        position += 1;
+    }
+    let marker = data[position + 1];
+    if marker == 0xD9 || marker == 0x01 || (0xD0..=0xD7).contains(&marker)
+    {
+        return Some((marker, position + 2, position + 2));
+    }
+    let length = usize::from(be16(data.get(position + 2..position + 4)?));
+    if length < 2 || position + 2 + length > data.len() {
+        return None;
+    }
+    Some((marker, position + 4, position + 2 + length))
+}
+
+/// Finds the EXIF orientation in an application segment.
+///
+/// The orientation, the position of its value in the segment content and
+/// whether it is little endian are returned.
+///
+/// # Arguments
+/// *  `content` - The segment content.
+fn exif_orientation(content: &[u8]) -> Option<(u16, usize, bool)> {
+    if !content.starts_with(b"Exif\0\0") {
+        return None;
+    }
+    let tiff = &content[6..];
+    let little_endian = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let value = |bytes: &[u8]| {
+        if little_endian {
+            bytes.iter().rev().fold(0, |acc, &b| acc << 8 | u32::from(b))
+        } else {
+            bytes.iter().fold(0, |acc, &b| acc << 8 | u32::from(b))
+        }
+    };
+
+    let directory = value(tiff.get(4..8)?) as usize;
+    let count = value(tiff.get(directory..directory + 2)?) as usize;
+    (0..count)
+        .map(|i| directory + 2 + 12 * i)
+        .filter_map(|entry| tiff.get(entry..entry + 12).map(|e| (entry, e)))
+        .find(|&(_, e)| value(&e[0..2]) == u32::from(ORIENTATION_TAG))
+        .map(|(entry, e)| {
+            (value(&e[8..10]) as u16, 6 + entry + 8, little_endian)
+        })
+}
+
+/// Reads a big endian 16 bit value.
+///
+/// # Arguments
+/// *  `bytes` - The two bytes of the value.
+fn be16(bytes: &[u8]) -> u16 {
+    u16::from(bytes[0]) << 8 | u16::from(bytes[1])
+}
+
+#[cfg(test)]
+pub mod tests {
+    use tempdir;
+
+    use super::*;
+
+    /// A grayscale JPEG image of 16 by 8 pixels, with the orientation `6`.
+    const ROTATED: &[u8] = &[
+        255, 216, 255, 225, 0, 34, 69, 120, 105, 102, 0, 0, 73, 73, 42, 0,
+        8, 0, 0, 0, 1, 0, 18, 1, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0,
+        255, 219, 0, 67, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+        1, 1, 1, 1, 255, 192, 0, 11, 8, 0, 8, 0, 16, 1, 1, 17, 0, 255, 196,
+        0, 21, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+        255, 196, 0, 21, 16, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 3, 255, 218, 0, 8, 1, 1, 0, 0, 63, 0, 86, 234, 255, 217,
+    ];
+
+    /// Generates a grayscale JPEG image of 16 by 8 pixels with an
+    /// orientation.
+    ///
+    /// # Arguments
+    /// *  `orientation` - The orientation.
+    pub fn jpeg_data(orientation: u16) -> Vec<u8> {
+        let mut result = ROTATED.to_vec();
+        let (_, offset, _) = exif(&result).unwrap();
+        result[offset] = orientation as u8;
+        result
+    }
+
+    /// Reads the EXIF orientation of JPEG data.
+    ///
+    /// # Arguments
+    /// *  `data` - The JPEG data.
+    pub fn orientation_of(data: &[u8]) -> Option<u16> {
+        orientation(data)
+    }
+
+    /// Tests that the orientation is read from the EXIF segment.
+    #[test]
+    fn read_orientation() {
+        assert_eq!(Some(6), orientation(ROTATED));
+        assert_eq!(Some(3), orientation(&jpeg_data(3)));
+        assert_eq!(None, orientation(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(None, orientation(&ROTATED[..20]));
+    }
+
+    /// Tests that the orientation is reset in place.
+    #[test]
+    fn reset() {
+        let mut data = jpeg_data(8);
+        reset_orientation(&mut data);
+        assert_eq!(Some(NORMAL_ORIENTATION), orientation(&data));
+        assert_eq!(ROTATED.len(), data.len());
+    }
+
+    /// Tests that images that need no transformation are served unchanged,
+    /// without running `jpegtran`.
+    #[test]
+    fn unchanged() {
+        let dir = tempdir::TempDir::new("medifs-display").unwrap();
+        let upright = dir.path().join("upright.jpg");
+        let other = dir.path().join("other.png");
+        fs::write(&upright, jpeg_data(NORMAL_ORIENTATION)).unwrap();
+        fs::write(&other, b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let versions = Versions::new();
+        for path in [&upright, &other] {
+            assert_eq!(None, versions.size(path).unwrap());
+            assert_eq!(None, versions.content(path).unwrap());
+        }
+        assert!(versions.size(&dir.path().join("missing.jpg")).is_err());
+    }
+
+    /// Tests that the size of a transformed image is kept, so that it is not
+    /// transformed again until it is opened.
+    #[test]
+    fn cached() {
+        if !can_transform() {
+            return;
+        }
+        let dir = tempdir::TempDir::new("medifs-display").unwrap();
+        let rotated = dir.path().join("rotated.jpg");
+        fs::write(&rotated, jpeg_data(6)).unwrap();
+
+        let versions = Versions::new();
+        let size = versions.size(&rotated).unwrap();
+        assert!(size.is_some());
+        let stamp = stamp(&rotated).unwrap();
+        let state = versions.state.lock().unwrap();
+        assert_eq!(Some(size), state.size(&rotated, &stamp));
+    }
+
+    /// Tests that sizes and content are dropped oldest first.
+    #[test]
+    fn bounded() {
+        let stamp = (time::UNIX_EPOCH, 0);
+        let path = |i: usize| path::PathBuf::from(format!("/{}.jpg", i));
+        let mut state = State::default();
+        for i in 0..MAX_SIZES + 10 {
+            state.insert(&path(i), stamp, None);
+        }
+        assert_eq!(MAX_SIZES, state.sizes.len());
+        assert_eq!(None, state.size(&path(9), &stamp));
+        assert_eq!(Some(None), state.size(&path(10), &stamp));
+
+        let content = sync::Arc::new(vec![0u8; MAX_CONTENT / 2]);
+        for i in 0..3 {
+            state.insert(&path(i), stamp, Some(content.clone()));
+        }
+        assert_eq!(2, state.content.len());
+        assert_eq!(None, state.content(&path(0), &stamp));
+        assert!(state.content(&path(2), &stamp).is_some());
+        assert_eq!(
+            Some(Some(content.len() as u64)),
+            state.size(&path(2), &stamp),
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::ffi;
+use std::os::unix::ffi::OsStrExt;
+use std::path;
+
+use time;
+
+use data;
+
+/// Generates an HTML index of the items below a directory.
+///
+/// Items are listed in chronological order, grouped by month, and linked by
+/// their path relative to the directory.
+///
+/// # Arguments
+/// *  `title` - The title of the index, typically the name of the directory.
+/// *  `directory` - The directory entry.
+pub fn generate(title: &str, directory: &data::cache::Entry) -> Vec<u8> {
+    let mut items = Vec::new();
+    collect(path::Path::new(""), directory, &mut items);
+    items.sort_by(|&(ref a_path, a), &(ref b_path, b)| {
+        a.timestamp
+            .cmp(&b.timestamp)
+            .then_with(|| a_path.cmp(b_path))
+    });
+
+    let mut result = String::new();
+    result.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
+    result.push_str("<meta charset=\"utf-8\">\n");
+    result.push_str(&format!("<title>{}</title>\n", escape(title)));
+    result.push_str("</head>\n<body>\n");
+    result.push_str(&format!("<h1>{}</h1>\n", escape(title)));
+    let mut month = None;
+    for (path, item) in items {
+        let current = time::strftime("%B %Y", item.timestamp.as_ref())
+            .unwrap_or_default();
+        if month.as_ref() != Some(&current) {
+            if month.is_some() {
+                result.push_str("</ul>\n");
+            }
+            result.push_str(&format!("<h2>{}</h2>\n<ul>\n", escape(&current)));
+            month = Some(current);
+        }
+        result.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            encode(path.as_os_str()),
+            escape(&path.file_name().unwrap_or_default().to_string_lossy()),
+        ));
+    }
+    if month.is_some() {
+        result.push_str("</ul>\n");
+    }
+    result.push_str("</body>\n</html>\n");
+
+    result.into_bytes()
+}
+
+/// Collects all items below a directory along with their relative paths.
+///
+/// # Arguments
+/// *  `prefix` - The path of `entry` relative to the indexed directory.
+/// *  `entry` - The entry to collect.
+/// *  `result` - The collected items.
+fn collect<'a>(
+    prefix: &path::Path,
+    entry: &'a data::cache::Entry,
+    result: &mut Vec<(path::PathBuf, &'a data::Item)>,
+) {
+    match entry {
+        data::cache::Entry::Directory(_, tree) => {
+            for (name, child) in tree {
+                collect(&prefix.join(name), child, result);
+            }
+        }
+        data::cache::Entry::Item(item) => {
+            result.push((prefix.to_path_buf(), item))
+        }
+        &data::cache::Entry::Link(_, _)
+        | &data::cache::Entry::Sidecar(_, _)
+        | &data::cache::Entry::Index(_) => (),
+    }
+}
+
+/// Escapes a string for use as HTML text.
+///
+/// # Arguments
+/// *  `s` - The string to escape.
+fn escape(s: &str) -> String {
+    s.chars().fold(String::with_capacity(s.len()), |mut acc, c| {
+        match c {
+            '&' => acc.push_str("&amp;"),
+            '<' => acc.push_str("&lt;"),
+            '>' => acc.push_str("&gt;"),
+            '"' => acc.push_str("&quot;"),
+            c => acc.push(c),
+        }
+        acc
+    })
+}
+
+/// Percent-encodes a relative path for use as a link.
+///
+/// Path separators are kept.
+///
+/// # Arguments
+/// *  `path` - The path to encode.
+fn encode(path: &ffi::OsStr) -> String {
+    path.as_bytes().iter().fold(String::new(), |mut acc, &b| {
+        match b {
+            b if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) => {
+                acc.push(b as char)
+            }
+            b => acc.push_str(&format!("%{:02X}", b)),
+        }
+        acc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that text is escaped and paths encoded.
+    #[test]
+    fn escape_encode() {
+        assert_eq!("a &lt;b&gt; &amp; &quot;c&quot;", escape("a <b> & \"c\""));
+        assert_eq!(
+            "01/2000-01-01%2000%3A00.jpeg",
+            encode(ffi::OsStr::new("01/2000-01-01 00:00.jpeg")),
+        );
+    }
+
+    /// Tests that sidecar files are not listed.
+    #[test]
+    fn sidecars_skipped() {
+        let item = data::Item::new(
+            "/photos/a.jpg",
+            (2000, 1, 1),
+            Default::default(),
+        );
+        let timestamp = item.timestamp.to_timespec();
+        let directory = data::cache::Entry::Directory(
+            timestamp,
+            vec![
+                ("a.jpeg".into(), data::cache::Entry::Item(item)),
+                (
+                    "a.xmp".into(),
+                    data::cache::Entry::Sidecar(
+                        timestamp,
+                        "/photos/a.xmp".into(),
+                    ),
+                ),
+            ].into_iter()
+                .collect(),
+        );
+
+        let index = String::from_utf8(generate("2000", &directory)).unwrap();
+        assert!(index.contains("a.jpeg"));
+        assert!(!index.contains("a.xmp"));
+    }
+}
@@ -30,18 +30,17 @@ macro_rules! lookup {
     }
 }
 
-/// Acquires a write lock on a source and sends a notification.
+/// Sends a notification to a source, unless it is busy.
 ///
-/// If locking fails, this macro will cause the current method to return
-/// [`libc::EDEADLK`].
-///
-/// [`libc::EDEADLK`]: https://doc.rust-lang.org/libc/x86_64-unknown-linux-gnu/libc/constant.EDEADLK.html
+/// If the source is locked, typically because it is being started or
+/// reloaded, the notification is skipped and the current method proceeds with
+/// the cached data rather than waiting or failing.
 macro_rules! notify {
     ($source:expr) => {
-        if let Ok(ref mut source) = $source.write() {
+        if let Ok(ref source) = $source.try_read() {
             source.notify();
         } else {
-            return Err(libc::EDEADLK);
+            trace!("Source busy; skipping notification");
         }
     }
 }
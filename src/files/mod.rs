@@ -1,6 +1,6 @@
+use std::collections;
+use std::ffi;
 use std::fs;
-use std::io;
-use std::io::{Read, Seek};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::io::{FromRawFd, IntoRawFd};
 use std::path;
@@ -8,30 +8,96 @@ use std::sync;
 
 use fuse_mt;
 use libc;
+use time;
 
 use data;
 use sources;
 use util;
 
+mod checksum;
+
+mod display;
+pub use self::display::can_transform;
+
+mod index;
+
 mod traits;
 use self::traits::*;
 
+mod xattr;
+
 #[macro_use]
 mod macros;
 
 /// The type used as cache.
 pub type Cache = sync::Arc<sync::RwLock<data::cache::Cache>>;
 
+/// The first file handle used for generated files.
+///
+/// Handles of opened items are file descriptors, which never reach this value.
+const GENERATED_HANDLE: u64 = 1 << 32;
+
 /// The type used as source.
 pub type Source = sync::Arc<sync::RwLock<Box<sources::Source>>>;
 
+/// A generated index along with the generation of the cache for which it was
+/// generated.
+type Index = (u64, sync::Arc<Vec<u8>>);
+
 /// The actual FUSE implementation.
+///
+/// Inode numbers are assigned by `fuse_mt` per path, so an entry keeps its
+/// inode across refreshes as long as its path is unchanged.
 pub struct MediaFS {
     /// The backing file system cache.
     cache: Cache,
 
     /// The source providing actual items.
     source: Source,
+
+    /// The user and group owning all entries. If this is not set, entries
+    /// are owned by the requesting user.
+    owner: Option<(u32, u32)>,
+
+    /// The time for which the attributes of directories may be cached. If
+    /// this is not set, they are cached indefinitely.
+    directory_ttl: Option<time::Timespec>,
+
+    /// The time for which the attributes of files and links may be cached.
+    /// If this is not set, they are cached indefinitely.
+    file_ttl: Option<time::Timespec>,
+
+    /// The cache of checksums of source files. If this is not set, checksums
+    /// are not available.
+    checksums: Option<checksum::Checksums>,
+
+    /// Whether tagging operations update the cache instead of being
+    /// rejected. Source files are never written.
+    dry_run_writes: bool,
+
+    /// Whether directories report the number of their children as size.
+    directory_size_counts: bool,
+
+    /// The content of opened generated files, keyed by file handle.
+    generated: sync::Mutex<collections::HashMap<u64, sync::Arc<Vec<u8>>>>,
+
+    /// The generated indices along with the generation of the cache for
+    /// which they were generated, keyed by path.
+    indices: sync::Mutex<collections::HashMap<path::PathBuf, Index>>,
+
+    /// The lengths of opened items, taken when they are opened, keyed by file
+    /// handle.
+    lengths: sync::Mutex<collections::HashMap<u64, u64>>,
+
+    /// The display versions of images.
+    display: display::Versions,
+
+    /// The maximum number of items open at the same time. If this is not
+    /// set, the number is limited only by the process.
+    max_open_files: Option<usize>,
+
+    /// The number of items currently open.
+    open_files: sync::atomic::AtomicUsize,
 }
 
 impl MediaFS {
@@ -41,7 +107,206 @@ impl MediaFS {
     /// This method panics if the write lock on `source` cannot be taken.
     pub fn new(cache: Cache, source: Source) -> MediaFS {
         source.write().unwrap().start();
-        Self { cache, source }
+        Self {
+            cache,
+            source,
+            owner: None,
+            directory_ttl: None,
+            file_ttl: None,
+            checksums: None,
+            dry_run_writes: false,
+            directory_size_counts: false,
+            generated: sync::Mutex::new(collections::HashMap::new()),
+            indices: sync::Mutex::new(collections::HashMap::new()),
+            lengths: sync::Mutex::new(collections::HashMap::new()),
+            display: display::Versions::new(),
+            max_open_files: None,
+            open_files: sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the user and group owning all entries.
+    ///
+    /// This is useful when the file system is accessible to other users than
+    /// the one mounting it, since entries would otherwise appear to be owned
+    /// by whichever user is accessing them.
+    ///
+    /// # Arguments
+    /// *  `uid` - The user ID.
+    /// *  `gid` - The group ID.
+    pub fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.owner = Some((uid, gid));
+        self
+    }
+
+    /// Sets the time for which the attributes of directories may be cached.
+    ///
+    /// Directories change as items are added, so a short time lets new
+    /// children appear sooner.
+    ///
+    /// # Arguments
+    /// *  `ttl` - The time to live.
+    pub fn with_directory_ttl(mut self, ttl: time::Timespec) -> Self {
+        self.directory_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the time for which the attributes of files and links may be
+    /// cached.
+    ///
+    /// # Arguments
+    /// *  `ttl` - The time to live.
+    pub fn with_file_ttl(mut self, ttl: time::Timespec) -> Self {
+        self.file_ttl = Some(ttl);
+        self
+    }
+
+    /// Exposes the SHA-256 checksum of the source file of items and links as
+    /// an extended attribute.
+    ///
+    /// Checksums are calculated when first read and cached until the source
+    /// file changes.
+    pub fn with_checksums(mut self) -> Self {
+        self.checksums = Some(checksum::Checksums::new());
+        self
+    }
+
+    /// Accepts tagging operations, but only logs the change that would be
+    /// written to the source file and updates the cache.
+    ///
+    /// Creating a link to an item in a tag directory adds the tag, removing
+    /// a link from a tag directory removes it, and moving a link between tag
+    /// directories does both. New links are named like all other links,
+    /// regardless of the requested name. Changes last until the items are
+    /// next refreshed.
+    pub fn with_dry_run_writes(mut self) -> Self {
+        self.dry_run_writes = true;
+        self
+    }
+
+    /// Reports the number of immediate children of directories as their
+    /// size, instead of `0`.
+    ///
+    /// This lets tools listing directory sizes show how much each contains.
+    pub fn with_directory_size_counts(mut self) -> Self {
+        self.directory_size_counts = true;
+        self
+    }
+
+    /// Limits the number of items open at the same time.
+    ///
+    /// Every open item holds a file descriptor or its generated content until
+    /// released, so a client that never releases them could otherwise
+    /// exhaust the descriptors or memory of the process. Opening an item
+    /// beyond the limit fails with `EMFILE`.
+    ///
+    /// # Arguments
+    /// *  `max_open_files` - The maximum number of open items.
+    pub fn with_max_open_files(mut self, max_open_files: usize) -> Self {
+        self.max_open_files = Some(max_open_files);
+        self
+    }
+
+    /// Finds the path of the item targeted by a link.
+    ///
+    /// # Arguments
+    /// *  `cache` - The cache containing the link.
+    /// *  `link` - The path of the link.
+    fn link_target(
+        cache: &data::cache::Cache,
+        link: &path::Path,
+    ) -> Option<path::PathBuf> {
+        match cache.lookup(&link)? {
+            data::cache::Entry::Link(_, target) => {
+                cache.target(&link, path::Path::new(target))
+            }
+            _ => None,
+        }
+    }
+
+    /// Generates the content of a generated index.
+    ///
+    /// The content is kept until the items of the cache change, so an index
+    /// is generated only once per refresh.
+    ///
+    /// # Arguments
+    /// *  `cache` - The cache containing the index.
+    /// *  `path` - The path of the index.
+    fn index(
+        &self,
+        cache: &data::cache::Cache,
+        path: &path::Path,
+    ) -> Option<sync::Arc<Vec<u8>>> {
+        let generation = cache.generation();
+        let mut indices = self.indices.lock().ok()?;
+        if let Some(&(current, ref content)) = indices.get(path) {
+            if current == generation {
+                return Some(content.clone());
+            }
+        }
+
+        let directory = path.parent()?;
+        let title = directory.file_name()?.to_string_lossy();
+        let content = sync::Arc::new(index::generate(
+            &title,
+            cache.lookup(&directory)?,
+        ));
+        indices.retain(|_, &mut (current, _)| current == generation);
+        indices.insert(path.to_path_buf(), (generation, content.clone()));
+        Some(content)
+    }
+
+    /// Opens an item or generated content that was looked up.
+    ///
+    /// Display versions are generated without holding the cache lock, since
+    /// transforming a large image takes a while; those of images that need no
+    /// transformation are opened like their items.
+    ///
+    /// # Arguments
+    /// *  `content` - Generated content to serve.
+    /// *  `item` - The source file of an item, and whether its display
+    ///    version is served.
+    fn open_content(
+        &self,
+        content: Option<sync::Arc<Vec<u8>>>,
+        item: Option<(path::PathBuf, bool)>,
+    ) -> fuse_mt::ResultOpen {
+        let content = match item {
+            Some((ref source, true)) => {
+                self.display.content(source).map_err(util::map_error)?
+            }
+            _ => content,
+        };
+        if let Some(content) = content {
+            let mut generated =
+                self.generated.lock().map_err(|_| libc::EDEADLK)?;
+            let fh = (GENERATED_HANDLE..)
+                .find(|fh| !generated.contains_key(fh))
+                .unwrap();
+            generated.insert(fh, content);
+            return Ok((fh, 0));
+        }
+
+        match item {
+            Some((source, _)) => {
+                let file = fs::File::open(&source).map_err(util::map_error)?;
+                let length = file.metadata().map_err(util::map_error)?.len();
+                let mut lengths =
+                    self.lengths.lock().map_err(|_| libc::EDEADLK)?;
+                let fd = file.into_raw_fd();
+
+                // Items are typically read from start to end, so let the
+                // kernel read ahead aggressively; failure is harmless
+                unsafe {
+                    libc::posix_fadvise(fd, 0, 0, libc::POSIX_FADV_SEQUENTIAL);
+                }
+
+                // The reply flags are FUSE flags, not open flags
+                lengths.insert(fd as u64, length);
+                Ok((fd as u64, 0))
+            }
+            None => Err(libc::EINVAL),
+        }
     }
 }
 
@@ -50,16 +315,69 @@ impl fuse_mt::FilesystemMT for MediaFS {
         Ok(())
     }
 
+    fn destroy(&self, _req: fuse_mt::RequestInfo) {
+        trace!("destroy");
+        match self.source.read() {
+            Ok(source) => source.stop(),
+            Err(_) => warn!("Failed to stop source"),
+        }
+    }
+
     fn getattr(
         &self,
         req: fuse_mt::RequestInfo,
         path: &path::Path,
         _fh: Option<u64>,
     ) -> fuse_mt::ResultEntry {
+        trace!("getattr {}", path.display());
         notify!(self.source);
-        let result: fuse_mt::ResultEntry =
-            lookup!(cache!(self.cache), &path).into();
-        result.map(|(ttl, fa)| (ttl, fa.for_user(req.uid, req.gid)))
+        let (size, display, result) = {
+            let cache = cache!(self.cache);
+            let entry = lookup!(cache, &path);
+            let (size, display) = match entry {
+                data::cache::Entry::Index(_) => (
+                    self.index(&cache, path)
+                        .map(|content| content.len() as u64),
+                    None,
+                ),
+                data::cache::Entry::Item(item)
+                    if cache.is_display(path) =>
+                {
+                    (None, Some(item.path.clone()))
+                }
+                data::cache::Entry::Directory(_, tree)
+                    if self.directory_size_counts =>
+                {
+                    (Some(tree.len() as u64), None)
+                }
+                _ => (None, None),
+            };
+            let result: fuse_mt::ResultEntry = entry.into();
+            (size, display, result)
+        };
+
+        // Display versions are generated without holding the cache lock,
+        // since transforming a large image takes a while; images served
+        // unchanged keep the size of their source file
+        let size = match display {
+            Some(source) => {
+                self.display.size(&source).map_err(util::map_error)?
+            }
+            None => size,
+        };
+        let (uid, gid) = self.owner.unwrap_or((req.uid, req.gid));
+        result.map(|(ttl, fa)| {
+            let ttl = if fa.kind == fuse_mt::FileType::Directory {
+                self.directory_ttl.unwrap_or(ttl)
+            } else {
+                self.file_ttl.unwrap_or(ttl)
+            };
+            let fa = fuse_mt::FileAttr {
+                size: size.unwrap_or(fa.size),
+                ..fa
+            };
+            (ttl, fa.for_user(uid, gid))
+        })
     }
 
     fn readlink(
@@ -67,6 +385,7 @@ impl fuse_mt::FilesystemMT for MediaFS {
         _req: fuse_mt::RequestInfo,
         path: &path::Path,
     ) -> fuse_mt::ResultData {
+        trace!("readlink {}", path.display());
         notify!(self.source);
         match lookup!(cache!(self.cache), &path) {
             &data::cache::Entry::Link(_, ref path) => {
@@ -82,9 +401,10 @@ impl fuse_mt::FilesystemMT for MediaFS {
         path: &path::Path,
         _flags: u32,
     ) -> fuse_mt::ResultOpen {
+        trace!("opendir {}", path.display());
         notify!(self.source);
         match lookup!(cache!(self.cache), &path) {
-            &data::cache::Entry::Directory(_) => Ok((0, 0)),
+            &data::cache::Entry::Directory(_, _) => Ok((0, 0)),
             _ => Err(libc::ENOTDIR),
         }
     }
@@ -95,6 +415,7 @@ impl fuse_mt::FilesystemMT for MediaFS {
         path: &path::Path,
         _fh: u64,
     ) -> fuse_mt::ResultReaddir {
+        trace!("readdir {}", path.display());
         notify!(self.source);
         lookup!(cache!(self.cache), &path).into()
     }
@@ -105,13 +426,49 @@ impl fuse_mt::FilesystemMT for MediaFS {
         path: &path::Path,
         flags: u32,
     ) -> fuse_mt::ResultOpen {
+        trace!("open {}", path.display());
         notify!(self.source);
-        match lookup!(cache!(self.cache), &path) {
-            &data::cache::Entry::Item(ref item) => fs::File::open(&item.path)
-                .map(|f| (f.into_raw_fd() as u64, flags))
-                .map_err(util::map_error),
-            _ => Err(libc::EINVAL),
+        let flags = flags as i32;
+        let write = flags & libc::O_ACCMODE != libc::O_RDONLY
+            || flags & (libc::O_TRUNC | libc::O_APPEND) != 0;
+        let (content, item) = {
+            let cache = cache!(self.cache);
+            match lookup!(cache, &path) {
+                &data::cache::Entry::Item(_)
+                | &data::cache::Entry::Sidecar(_, _)
+                | &data::cache::Entry::Index(_)
+                    if write =>
+                {
+                    return Err(libc::EROFS);
+                }
+                &data::cache::Entry::Index(_) => (
+                    Some(self.index(&cache, path).ok_or(libc::ENOENT)?),
+                    None,
+                ),
+                data::cache::Entry::Item(item) => {
+                    (None, Some((item.path.clone(), cache.is_display(path))))
+                }
+                data::cache::Entry::Sidecar(_, source) => {
+                    (None, Some((source.clone(), false)))
+                }
+                _ => (None, None),
+            }
+        };
+
+        if content.is_none() && item.is_none() {
+            return Err(libc::EINVAL);
+        }
+        let open = self.open_files.fetch_add(1, sync::atomic::Ordering::SeqCst);
+        if self.max_open_files.is_some_and(|max| open >= max) {
+            self.open_files.fetch_sub(1, sync::atomic::Ordering::SeqCst);
+            warn!("Too many open files: {}", path.display());
+            return Err(libc::EMFILE);
+        }
+        let result = self.open_content(content, item);
+        if result.is_err() {
+            self.open_files.fetch_sub(1, sync::atomic::Ordering::SeqCst);
         }
+        result
     }
 
     fn read(
@@ -122,19 +479,37 @@ impl fuse_mt::FilesystemMT for MediaFS {
         offset: u64,
         size: u32,
     ) -> fuse_mt::ResultData {
+        trace!("read {} at {}", fh, offset);
+
+        if fh >= GENERATED_HANDLE {
+            let generated = self.generated.lock().map_err(|_| libc::EDEADLK)?;
+            let content = generated.get(&fh).ok_or(libc::EBADF)?;
+            let start = (offset as usize).min(content.len());
+            let end = start.saturating_add(size as usize).min(content.len());
+            return Ok(content[start..end].to_vec());
+        }
+
+        // Read no more than what remains of the file as it was when opened,
+        // so that the reply is not allocated at the full requested size for
+        // small files
+        let size = self.lengths
+            .lock()
+            .map_err(|_| libc::EDEADLK)?
+            .get(&fh)
+            .ok_or(libc::EBADF)?
+            .saturating_sub(offset)
+            .min(size as u64);
+
         // Recreate file
-        let mut file = unsafe { fs::File::from_raw_fd(fh as i32) };
-
-        // Read the file
-        let result = file.seek(io::SeekFrom::Start(offset))
-            .and_then(|_| {
-                let mut buffer = vec![0u8; size as usize];
-                file.read(&mut buffer).map(|size| {
-                    buffer.resize(size, 0u8);
-                    buffer
-                })
-            })
-            .map_err(util::map_error);
+        let file = unsafe { fs::File::from_raw_fd(fh as i32) };
+
+        // Short reads and interruptions are retried so that only the end of
+        // the file results in a short reply. Positional reads are used since
+        // the handle may be read concurrently, and the file offset is shared
+        let result = util::read_fully(
+            &mut util::PositionalReader::new(&file, offset),
+            size as usize,
+        ).map_err(util::map_error);
 
         // Release file
         file.into_raw_fd();
@@ -151,19 +526,310 @@ impl fuse_mt::FilesystemMT for MediaFS {
         _lock_owner: u64,
         _flush: bool,
     ) -> fuse_mt::ResultEmpty {
+        trace!("release {}", fh);
+
+        if fh >= GENERATED_HANDLE {
+            if let Ok(mut generated) = self.generated.lock() {
+                if generated.remove(&fh).is_some() {
+                    self.open_files
+                        .fetch_sub(1, sync::atomic::Ordering::SeqCst);
+                }
+            }
+            return Ok(());
+        }
+
         // Recreate file and drop it
+        if let Ok(mut lengths) = self.lengths.lock() {
+            lengths.remove(&fh);
+        }
         unsafe { fs::File::from_raw_fd(fh as i32) };
+        self.open_files.fetch_sub(1, sync::atomic::Ordering::SeqCst);
 
         Ok(())
     }
+
+    fn write(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _fh: u64,
+        _offset: u64,
+        _data: Vec<u8>,
+        _flags: u32,
+    ) -> fuse_mt::ResultWrite {
+        trace!("write {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn create(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+        _mode: u32,
+        _flags: u32,
+    ) -> fuse_mt::ResultCreate {
+        trace!("create {}", parent.join(name).display());
+        Err(libc::EROFS)
+    }
+
+    fn mknod(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+        _mode: u32,
+        _rdev: u32,
+    ) -> fuse_mt::ResultEntry {
+        trace!("mknod {}", parent.join(name).display());
+        Err(libc::EROFS)
+    }
+
+    fn mkdir(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+        _mode: u32,
+    ) -> fuse_mt::ResultEntry {
+        trace!("mkdir {}", parent.join(name).display());
+        Err(libc::EROFS)
+    }
+
+    fn unlink(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("unlink {}", parent.join(name).display());
+        if !self.dry_run_writes {
+            return Err(libc::EROFS);
+        }
+        let mut cache = self.cache.write().map_err(|_| libc::EDEADLK)?;
+        let (item, tag) =
+            cache.remove_tag(&parent.join(name)).ok_or(libc::EROFS)?;
+        info!(
+            "Dry run: would remove {} from the keywords of {}",
+            tag,
+            item.path.display(),
+        );
+        Ok(())
+    }
+
+    fn rmdir(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("rmdir {}", parent.join(name).display());
+        Err(libc::EROFS)
+    }
+
+    fn symlink(
+        &self,
+        req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+        target: &path::Path,
+    ) -> fuse_mt::ResultEntry {
+        trace!("symlink {}", parent.join(name).display());
+        if !self.dry_run_writes {
+            return Err(libc::EROFS);
+        }
+        let mut cache = self.cache.write().map_err(|_| libc::EDEADLK)?;
+        let target = cache
+            .target(&parent.join(name), target)
+            .ok_or(libc::EROFS)?;
+        let (path, tag) =
+            cache.add_tag(&parent, &target).ok_or(libc::EROFS)?;
+        info!(
+            "Dry run: would add {} to the keywords of {}",
+            tag,
+            cache.resolve(&path).ok_or(libc::EIO)?.path.display(),
+        );
+        let result: fuse_mt::ResultEntry = lookup!(cache, &path).into();
+        let (uid, gid) = self.owner.unwrap_or((req.uid, req.gid));
+        result.map(|(ttl, fa)| (ttl, fa.for_user(uid, gid)))
+    }
+
+    fn rename(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        parent: &path::Path,
+        name: &ffi::OsStr,
+        newparent: &path::Path,
+        _newname: &ffi::OsStr,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("rename {}", parent.join(name).display());
+        if !self.dry_run_writes {
+            return Err(libc::EROFS);
+        }
+        let mut cache = self.cache.write().map_err(|_| libc::EDEADLK)?;
+        let link = parent.join(name);
+        let target = Self::link_target(&cache, &link).ok_or(libc::EROFS)?;
+
+        // Add the new tag first, since that fails if the link is moved
+        // within a tag directory, and undo it if the old link is not tagging
+        let (path, tag) =
+            cache.add_tag(&newparent, &target).ok_or(libc::EROFS)?;
+        match cache.remove_tag(&link) {
+            Some((item, old)) => {
+                info!(
+                    "Dry run: would replace {} with {} in the keywords of {}",
+                    old,
+                    tag,
+                    item.path.display(),
+                );
+                Ok(())
+            }
+            None => {
+                cache.remove_tag(&path);
+                Err(libc::EROFS)
+            }
+        }
+    }
+
+    fn link(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _newparent: &path::Path,
+        _newname: &ffi::OsStr,
+    ) -> fuse_mt::ResultEntry {
+        trace!("link {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn chmod(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _fh: Option<u64>,
+        _mode: u32,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("chmod {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn chown(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _fh: Option<u64>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("chown {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn truncate(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _fh: Option<u64>,
+        _size: u64,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("truncate {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn utimens(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _fh: Option<u64>,
+        _atime: Option<time::Timespec>,
+        _mtime: Option<time::Timespec>,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("utimens {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn setxattr(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _name: &ffi::OsStr,
+        _value: &[u8],
+        _flags: u32,
+        _position: u32,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("setxattr {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn removexattr(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        _name: &ffi::OsStr,
+    ) -> fuse_mt::ResultEmpty {
+        trace!("removexattr {}", path.display());
+        Err(libc::EROFS)
+    }
+
+    fn getxattr(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        name: &ffi::OsStr,
+        size: u32,
+    ) -> fuse_mt::ResultXattr {
+        trace!("getxattr {} {:?}", path.display(), name);
+        notify!(self.source);
+        if let (Some(checksums), Some(xattr::SHA256)) =
+            (self.checksums.as_ref(), name.to_str())
+        {
+            // Release the cache before reading the source file
+            let source = {
+                let cache = cache!(self.cache);
+                lookup!(cache, &path);
+                xattr::source(&cache, path)
+            };
+            return source.ok_or(libc::ENODATA).and_then(|source| {
+                checksums
+                    .get(&source)
+                    .map_err(|e| e.raw_os_error().unwrap_or(libc::EIO))
+                    .and_then(|digest| xattr::reply(digest.into_bytes(), size))
+            });
+        }
+        let cache = cache!(self.cache);
+        xattr::get(&cache, path, lookup!(cache, &path), name)
+            .map(|value| xattr::reply(value, size))
+            .unwrap_or(Err(libc::ENODATA))
+    }
+
+    fn listxattr(
+        &self,
+        _req: fuse_mt::RequestInfo,
+        path: &path::Path,
+        size: u32,
+    ) -> fuse_mt::ResultXattr {
+        trace!("listxattr {}", path.display());
+        notify!(self.source);
+        let cache = cache!(self.cache);
+        let mut names = xattr::list(&cache, path, lookup!(cache, &path));
+        if self.checksums.is_some() && xattr::source(&cache, path).is_some() {
+            names.extend(xattr::SHA256.as_bytes());
+            names.push(0);
+        }
+        xattr::reply(names, size)
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
+    use std::io;
+    use std::io::Read;
     use std::os::unix::fs::PermissionsExt;
     use std::sync;
+    use std::thread;
 
     use fuse;
+    use fuse_mt::FilesystemMT;
     use tempdir;
 
     use super::*;
@@ -192,6 +858,50 @@ mod tests {
         assert_eq!(source_meta.len(), target_meta.len(),);
     }
 
+    /// Tests that an item keeps its attributes across a refresh adding an
+    /// item with the same timestamp and a lower source path.
+    #[test]
+    fn test_getattr_stable() {
+        let dir = tempdir::TempDir::new("medifs-files").unwrap();
+        let existing =
+            item_with_data(dir.path().join("b.jpg"), b"existing", 2000, 1, 1);
+        let added =
+            item_with_data(dir.path().join("a.jpg"), b"new", 2000, 1, 1);
+        let first = path::Path::new("/All/2000/01/01/2000-01-01 00:00.jpeg");
+        let second =
+            path::Path::new("/All/2000/01/01/2000-01-01 00:00 (1).jpeg");
+
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let mediafs = MediaFS::new(
+            cache.clone(),
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        let size = |path: &path::Path| {
+            mediafs
+                .getattr(request(0, 0), path, None)
+                .map(|(_, attr)| attr.size)
+        };
+
+        cache
+            .write()
+            .unwrap()
+            .replace_all(vec![existing.clone()].into_iter())
+            .unwrap();
+        let before = size(first);
+        assert_eq!(Ok(8), before);
+
+        cache
+            .write()
+            .unwrap()
+            .replace_all(vec![added, existing].into_iter())
+            .unwrap();
+        assert_eq!(before, size(first));
+        assert_eq!(Ok(3), size(second));
+    }
+
     /// Tests that reading from the FUSE file system yields the same data as
     /// reading from the actual file.
     #[test]
@@ -246,6 +956,391 @@ mod tests {
         assert_eq!(read_file(source), read_file(target),);
     }
 
+    /// Tests that opening more items than allowed fails until one is
+    /// released.
+    #[test]
+    fn test_max_open_files() {
+        let dir = tempdir::TempDir::new("medifs-files").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let paths = (1..4)
+            .map(|day| {
+                let item = item_with_data(
+                    dir.path().join(format!("test{}.jpg", day)),
+                    b"data",
+                    2000,
+                    1,
+                    day,
+                );
+                path::Path::new("/")
+                    .join(cache.write().unwrap().add(item).unwrap())
+            })
+            .collect::<Vec<_>>();
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        ).with_max_open_files(2);
+
+        let (first, _) = mediafs.open(request(0, 0), &paths[0], 0).unwrap();
+        let (second, _) = mediafs.open(request(0, 0), &paths[1], 0).unwrap();
+        assert_eq!(
+            Some(libc::EMFILE),
+            mediafs.open(request(0, 0), &paths[2], 0).err(),
+        );
+
+        mediafs
+            .release(request(0, 0), &paths[0], first, 0, 0, false)
+            .unwrap();
+        let (third, _) = mediafs.open(request(0, 0), &paths[2], 0).unwrap();
+        for (path, fh) in [(&paths[1], second), (&paths[2], third)] {
+            mediafs.release(request(0, 0), path, fh, 0, 0, false).unwrap();
+        }
+    }
+
+    /// Tests that replies to reads of an open item are sized by the data
+    /// read, and not by the full requested size.
+    #[test]
+    fn test_read_reply_size() {
+        let dir = tempdir::TempDir::new("medifs-files").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let data = (0..100u8).collect::<Vec<_>>();
+        let item =
+            item_with_data(dir.path().join("test.jpg"), &data, 2000, 1, 1);
+        let path = path::Path::new("/")
+            .join(cache.write().unwrap().add(item).unwrap());
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+
+        let (fh, _) = mediafs.open(request(0, 0), &path, 0).unwrap();
+        for offset in 0..=100 {
+            let reply = mediafs
+                .read(request(0, 0), &path, fh, offset, 131072)
+                .unwrap();
+            assert_eq!(&data[offset as usize..], &reply[..]);
+            assert_eq!(reply.len(), reply.capacity());
+        }
+        mediafs.release(request(0, 0), &path, fh, 0, 0, false).unwrap();
+    }
+
+    /// Tests that concurrent reads at different offsets on the same handle do
+    /// not affect each other.
+    #[test]
+    fn test_read_concurrent() {
+        let dir = tempdir::TempDir::new("medifs-files").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let data = (0..8192u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let item =
+            item_with_data(dir.path().join("test.jpg"), &data, 2000, 1, 1);
+        let path = path::Path::new("/")
+            .join(cache.write().unwrap().add(item).unwrap());
+        let mediafs = sync::Arc::new(MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        ));
+
+        let (fh, _) = mediafs.open(request(0, 0), &path, 0).unwrap();
+        let barrier = sync::Arc::new(sync::Barrier::new(8));
+        let threads = (0..8u64)
+            .map(|i| {
+                let mediafs = mediafs.clone();
+                let path = path.clone();
+                let data = data.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    for j in 0..1000u64 {
+                        let offset = (i * 1024 + j * 7) % data.len() as u64;
+                        let reply = mediafs
+                            .read(request(0, 0), &path, fh, offset, 512)
+                            .unwrap();
+                        let start = offset as usize;
+                        let end = (start + 512).min(data.len());
+                        assert_eq!(&data[start..end], &reply[..]);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        mediafs.release(request(0, 0), &path, fh, 0, 0, false).unwrap();
+    }
+
+    /// Tests that all write operations are rejected.
+    #[test]
+    fn test_write_rejected() {
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let source = Source::new(sync::RwLock::new(Box::new(MockSource {})));
+        let mediafs = MediaFS::new(cache.clone(), source);
+        let req = || request(0, 0);
+        let root = path::Path::new("/");
+        let name = ffi::OsStr::new("test");
+        let item = cache
+            .write()
+            .unwrap()
+            .add(item("test.jpg", 2000, 1, 1))
+            .unwrap();
+        let item = path::Path::new("/").join(item);
+
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.create(req(), root, name, 0o644, 0).err(),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.open(req(), &item, libc::O_RDWR as u32).err(),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.write(req(), &item, 0, 0, vec![0], 0).err(),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.mkdir(req(), root, name, 0o755).err(),
+        );
+        assert_eq!(Some(libc::EROFS), mediafs.unlink(req(), root, name).err());
+        assert_eq!(Some(libc::EROFS), mediafs.rmdir(req(), root, name).err());
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.truncate(req(), &item, None, 0).err(),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.rename(req(), root, name, root, name).err(),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.chmod(req(), &item, None, 0o777).err(),
+        );
+    }
+
+    /// Tests that only read-only opens are accepted.
+    #[test]
+    fn test_open_flags() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let source = Source::new(sync::RwLock::new(Box::new(MockSource {})));
+        let mediafs = MediaFS::new(cache.clone(), source);
+        let item = path::Path::new("/").join(
+            cache
+                .write()
+                .unwrap()
+                .add(item_with_data(
+                    source_dir.path().join("test.jpg"),
+                    b"data",
+                    2000,
+                    1,
+                    1,
+                ))
+                .unwrap(),
+        );
+
+        for flags in &[
+            libc::O_WRONLY,
+            libc::O_RDWR,
+            libc::O_RDONLY | libc::O_TRUNC,
+            libc::O_RDONLY | libc::O_APPEND,
+        ] {
+            assert_eq!(
+                Some(libc::EROFS),
+                mediafs.open(request(0, 0), &item, *flags as u32).err(),
+            );
+        }
+
+        let (fh, flags) = mediafs
+            .open(request(0, 0), &item, libc::O_RDONLY as u32)
+            .unwrap();
+        assert_eq!(0, flags);
+        assert_eq!(
+            b"data".to_vec(),
+            mediafs.read(request(0, 0), &item, fh, 0, 100).unwrap(),
+        );
+        mediafs.release(request(0, 0), &item, fh, 0, 0, false).unwrap();
+    }
+
+    /// Tests that directories report the number of their children as size
+    /// only when enabled.
+    #[test]
+    fn test_directory_size_counts() {
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        for day in 1..4 {
+            cache
+                .write()
+                .unwrap()
+                .add(item("/source/test.jpg", 2000, 1, day))
+                .unwrap();
+        }
+        cache
+            .write()
+            .unwrap()
+            .add(item("/source/test.jpg", 2000, 2, 1))
+            .unwrap();
+        let size = |mediafs: &MediaFS, path: &str| {
+            mediafs
+                .getattr(request(0, 0), path::Path::new(path), None)
+                .unwrap()
+                .1
+                .size
+        };
+
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        assert_eq!(0, size(&mediafs, "/All/2000"));
+        assert_eq!(0, size(&mediafs, "/All/2000/01"));
+
+        let mediafs = mediafs.with_directory_size_counts();
+        assert_eq!(2, size(&mediafs, "/All/2000"));
+        assert_eq!(3, size(&mediafs, "/All/2000/01"));
+        assert_eq!(1, size(&mediafs, "/All/2000/01/01"));
+    }
+
+    /// Tests that tagging in dry run mode updates only the cache.
+    #[test]
+    fn test_dry_run_writes() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let source_path = source_dir.path().join("test.jpg");
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let item = path::Path::new("/").join(
+            cache
+                .write()
+                .unwrap()
+                .add(item_with_data(&source_path, b"data", 2000, 1, 1))
+                .unwrap(),
+        );
+        let modified = source_path.metadata().unwrap().modified().unwrap();
+        let parent = path::Path::new("/Tagged/new");
+        let name = ffi::OsStr::new("link");
+        let target =
+            path::Path::new("../..").join(item.strip_prefix("/").unwrap());
+        let link = parent.join("2000-01-01 00:00.jpeg");
+
+        let mediafs = MediaFS::new(
+            cache.clone(),
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs.symlink(request(0, 0), parent, name, &target).err(),
+        );
+
+        let mediafs = mediafs.with_dry_run_writes();
+        let (_, attr) = mediafs
+            .symlink(request(0, 0), parent, name, &target)
+            .unwrap();
+        assert_eq!(fuse_mt::FileType::Symlink, attr.kind);
+        assert_eq!(b"data".to_vec(), fs::read(&source_path).unwrap());
+        assert_eq!(
+            modified,
+            source_path.metadata().unwrap().modified().unwrap(),
+        );
+        {
+            let cache = cache.read().unwrap();
+            assert_eq!(
+                Some(&data::cache::Entry::Link(
+                    data::Timestamp::from((2000, 1, 1)).to_timespec(),
+                    target.into_os_string(),
+                )),
+                cache.lookup(&link),
+            );
+            assert!(cache.resolve(&item).unwrap().tags.contains("new"));
+        }
+
+        mediafs
+            .rename(
+                request(0, 0),
+                parent,
+                link.file_name().unwrap(),
+                path::Path::new("/Tagged/other"),
+                name,
+            )
+            .unwrap();
+        mediafs
+            .unlink(
+                request(0, 0),
+                path::Path::new("/Tagged/other"),
+                link.file_name().unwrap(),
+            )
+            .unwrap();
+        assert!(cache.read().unwrap().resolve(&item).unwrap().tags.is_empty());
+        assert_eq!(b"data".to_vec(), fs::read(&source_path).unwrap());
+    }
+
+    /// Tests that all kinds of entries are owned by the configured owner, or
+    /// the requesting user if none is configured.
+    #[test]
+    fn test_getattr_owner() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let mut tagged =
+            item_with_data(source_dir.path().join("test.jpg"), b"", 2000, 1, 1);
+        tagged.tags.insert("tag".into());
+        let item = path::Path::new("/")
+            .join(cache.write().unwrap().add(tagged).unwrap());
+        let paths = [
+            item.clone(),
+            item.parent().unwrap().to_path_buf(),
+            path::Path::new("/Tagged/tag/2000-01-01 00:00.jpeg").to_path_buf(),
+        ];
+
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        for path in paths.iter() {
+            let (_, attr) =
+                mediafs.getattr(request(1000, 100), path, None).unwrap();
+            assert_eq!((1000, 100), (attr.uid, attr.gid));
+        }
+
+        let mediafs = mediafs.with_owner(500, 50);
+        for path in paths.iter() {
+            let (_, attr) =
+                mediafs.getattr(request(1000, 100), path, None).unwrap();
+            assert_eq!((500, 50), (attr.uid, attr.gid));
+        }
+    }
+
+    /// Creates request information for a user.
+    ///
+    /// # Arguments
+    /// *  `uid` - The user ID of the requester.
+    /// *  `gid` - The group ID of the requester.
+    fn request(uid: u32, gid: u32) -> fuse_mt::RequestInfo {
+        fuse_mt::RequestInfo {
+            unique: 0,
+            uid,
+            gid,
+            pid: 0,
+        }
+    }
+
     /// An item to populate a file system.
     ///
     /// This is the tuple `(name, data, year, moth, day)`.
@@ -263,11 +1358,12 @@ mod tests {
     );
 
     /// A mock source providing fixed data.
-    struct MockSource {}
+    pub struct MockSource {}
 
     impl sources::Source for MockSource {
         fn start(&mut self) {}
-        fn notify(&mut self) {}
+        fn notify(&self) {}
+        fn reload(&self) {}
     }
 
     /// Mounts a file system on a temporary mount point.
@@ -340,4 +1436,527 @@ mod tests {
 
         result
     }
+
+    /// Creates a file system with a cache containing items.
+    ///
+    /// The absolute paths of the added items are returned along with the file
+    /// system.
+    ///
+    /// # Arguments
+    /// *  `items` - The items to add.
+    fn xattr_fixture(
+        items: Vec<data::Item>,
+    ) -> (MediaFS, Vec<path::PathBuf>) {
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let paths = items
+            .into_iter()
+            .map(|item| {
+                path::Path::new("/")
+                    .join(cache.write().unwrap().add(item).unwrap())
+            })
+            .collect();
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        (mediafs, paths)
+    }
+
+    /// Reads an extended attribute of an entry.
+    ///
+    /// # Arguments
+    /// *  `mediafs` - The file system.
+    /// *  `path` - The path of the entry.
+    /// *  `name` - The name of the attribute.
+    ///
+    /// # Panics
+    /// This function panics if the attribute size is returned instead of its
+    /// value.
+    fn getxattr(
+        mediafs: &MediaFS,
+        path: &path::Path,
+        name: &str,
+    ) -> Result<Vec<u8>, libc::c_int> {
+        let name = ffi::OsStr::new(name);
+        match mediafs.getxattr(request(0, 0), path, name, 1024) {
+            Ok(fuse_mt::Xattr::Data(value)) => Ok(value),
+            Ok(_) => panic!("unexpected size for {}", path.display()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Tests that the source path is available for items and links.
+    #[test]
+    fn test_getxattr_source() {
+        let mut tagged = item("/source/test.jpg", 2000, 1, 1);
+        tagged.tags.insert("tag".into());
+        let (mediafs, paths) = xattr_fixture(vec![tagged]);
+        let link = path::Path::new("/Tagged/tag/2000-01-01 00:00.jpeg");
+        let get = |path: &path::Path| getxattr(&mediafs, path, xattr::SOURCE);
+
+        assert_eq!(Ok(b"/source/test.jpg".to_vec()), get(&paths[0]));
+        assert_eq!(Ok(b"/source/test.jpg".to_vec()), get(link));
+        assert_eq!(Err(libc::ENODATA), get(paths[0].parent().unwrap()));
+    }
+
+    /// Tests that the reference count of items includes both links and
+    /// skipped duplicates.
+    #[test]
+    fn test_getxattr_refcount() {
+        let cache = Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_normalize_ext(true),
+        ));
+        let mut tagged = item("/source/test.JPG", 2000, 1, 1);
+        tagged.tags.insert("tag".into());
+        let item_path = path::Path::new("/")
+            .join(cache.write().unwrap().add(tagged).unwrap());
+        let duplicate = item("/source/test.jpg", 2000, 1, 1);
+        assert_eq!(
+            item_path,
+            path::Path::new("/")
+                .join(cache.write().unwrap().add(duplicate).unwrap()),
+        );
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+
+        assert_eq!(
+            Ok(b"2".to_vec()),
+            getxattr(&mediafs, &item_path, xattr::REFCOUNT),
+        );
+    }
+
+    /// Tests that the dimensions are available for items only, and that the
+    /// image header is not read when they are unknown.
+    #[test]
+    fn test_getxattr_dimensions() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let mut header = item_with_data(
+            source_dir.path().join("test1.png"),
+            b"\x89PNG\r\n\x1a\n\0\0\0\x0DIHDR\0\0\x01\x40\0\0\0\xF0",
+            2000,
+            1,
+            1,
+        );
+        header.tags.insert("tag".into());
+        let (mediafs, paths) = xattr_fixture(vec![
+            header,
+            data::Item {
+                dimensions: Some((4000, 3000)),
+                ..item("/source/test2.png", 2000, 1, 2)
+            },
+        ]);
+        let link = path::Path::new("/Tagged/tag/2000-01-01 00:00.png");
+        let get =
+            |path: &path::Path| getxattr(&mediafs, path, xattr::DIMENSIONS);
+
+        assert_eq!(Err(libc::ENODATA), get(&paths[0]));
+        assert_eq!(Ok(b"4000x3000".to_vec()), get(&paths[1]));
+        assert_eq!(Err(libc::ENODATA), get(link));
+        assert_eq!(Err(libc::ENODATA), get(paths[0].parent().unwrap()));
+    }
+
+    /// Tests that the title and description are available for items that
+    /// have them.
+    #[test]
+    fn test_getxattr_captions() {
+        let (mediafs, paths) = xattr_fixture(vec![data::Item {
+            captions: vec![(
+                data::Caption::Description,
+                "Sunset at the beach".to_string(),
+            )].into_iter()
+                .collect(),
+            ..item("/source/test.jpg", 2000, 1, 1)
+        }]);
+        let get = |name| getxattr(&mediafs, &paths[0], name);
+
+        assert_eq!(
+            Ok(b"Sunset at the beach".to_vec()),
+            get(xattr::DESCRIPTION),
+        );
+        assert_eq!(Err(libc::ENODATA), get(xattr::TITLE));
+    }
+
+    /// Tests that the page count is available for multi-page items only.
+    #[test]
+    fn test_getxattr_pages() {
+        let (mediafs, paths) = xattr_fixture(vec![
+            data::Item {
+                pages: Some(2),
+                ..item("/source/multi.tif", 2000, 1, 1)
+            },
+            item("/source/single.tif", 2000, 1, 2),
+        ]);
+        let get = |path: &path::Path| getxattr(&mediafs, path, xattr::PAGES);
+
+        assert_eq!(Ok(b"2".to_vec()), get(&paths[0]));
+        assert_eq!(Err(libc::ENODATA), get(&paths[1]));
+    }
+
+    /// Tests that the colour space is available for items with a known colour
+    /// space only.
+    #[test]
+    fn test_getxattr_colorspace() {
+        let (mediafs, paths) = xattr_fixture(vec![
+            data::Item {
+                colorspace: Some("Adobe RGB (1998)".into()),
+                ..item("/source/known.jpg", 2000, 1, 1)
+            },
+            item("/source/unknown.jpg", 2000, 1, 2),
+        ]);
+        let get =
+            |path: &path::Path| getxattr(&mediafs, path, xattr::COLORSPACE);
+
+        assert_eq!(Ok(b"Adobe RGB (1998)".to_vec()), get(&paths[0]));
+        assert_eq!(Err(libc::ENODATA), get(&paths[1]));
+    }
+
+    /// Tests that the exposure settings are available for items with known
+    /// settings only.
+    #[test]
+    fn test_getxattr_settings() {
+        let (mediafs, paths) = xattr_fixture(vec![
+            data::Item {
+                settings: Some("f/2.8 1/250 ISO400 50mm".into()),
+                ..item("/source/known.jpg", 2000, 1, 1)
+            },
+            item("/source/unknown.jpg", 2000, 1, 2),
+        ]);
+        let get =
+            |path: &path::Path| getxattr(&mediafs, path, xattr::SETTINGS);
+
+        assert_eq!(Ok(b"f/2.8 1/250 ISO400 50mm".to_vec()), get(&paths[0]));
+        assert_eq!(Err(libc::ENODATA), get(&paths[1]));
+    }
+
+    /// Tests that the altitude and direction are available for geotagged
+    /// items only.
+    #[test]
+    fn test_getxattr_gps() {
+        let (mediafs, paths) = xattr_fixture(vec![
+            data::Item {
+                altitude: Some("-12.5".into()),
+                direction: Some("90.5".into()),
+                ..item("/source/known.jpg", 2000, 1, 1)
+            },
+            item("/source/unknown.jpg", 2000, 1, 2),
+        ]);
+        let (known, unknown) = (&paths[0], &paths[1]);
+
+        assert_eq!(
+            Ok(b"-12.5".to_vec()),
+            getxattr(&mediafs, known, xattr::ALTITUDE),
+        );
+        assert_eq!(
+            Ok(b"90.5".to_vec()),
+            getxattr(&mediafs, known, xattr::DIRECTION),
+        );
+        assert_eq!(
+            Err(libc::ENODATA),
+            getxattr(&mediafs, unknown, xattr::ALTITUDE),
+        );
+        assert_eq!(
+            Err(libc::ENODATA),
+            getxattr(&mediafs, unknown, xattr::DIRECTION),
+        );
+    }
+
+    /// Tests that the checksum of the source file is available for items and
+    /// links when enabled, and that it is stable across reads.
+    #[test]
+    fn test_getxattr_sha256() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let mut tagged = item_with_data(
+            source_dir.path().join("test.jpg"),
+            b"hello world\n",
+            2000,
+            1,
+            1,
+        );
+        tagged.tags.insert("tag".into());
+        let link = path::Path::new("/Tagged/tag/2000-01-01 00:00.jpeg");
+
+        let (mediafs, paths) = xattr_fixture(vec![tagged.clone()]);
+        assert_eq!(
+            Err(libc::ENODATA),
+            getxattr(&mediafs, &paths[0], xattr::SHA256),
+        );
+
+        let (mediafs, paths) = xattr_fixture(vec![tagged]);
+        let mediafs = mediafs.with_checksums();
+        for path in &[paths[0].as_path(), link, paths[0].as_path()] {
+            assert_eq!(
+                Ok(b"a948904f2f0f479b8f8197694b30184b\
+                     0d2ed1c1cd2a1ec0fb85d299a192a447"
+                    .to_vec()),
+                getxattr(&mediafs, path, xattr::SHA256),
+            );
+        }
+    }
+
+    /// Tests that operations succeed using the cache while the source is
+    /// locked.
+    #[test]
+    fn test_getattr_source_locked() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let item = path::Path::new("/").join(
+            cache
+                .write()
+                .unwrap()
+                .add(item_with_data(
+                    source_dir.path().join("test.jpg"),
+                    b"data",
+                    2000,
+                    1,
+                    1,
+                ))
+                .unwrap(),
+        );
+        let source = Source::new(sync::RwLock::new(Box::new(MockSource {})));
+        let mediafs = MediaFS::new(cache, source.clone());
+
+        let _lock = source.write().unwrap();
+        let (_, attr) = mediafs.getattr(request(0, 0), &item, None).unwrap();
+        assert_eq!(4, attr.size);
+        assert_eq!(
+            1,
+            mediafs
+                .readdir(request(0, 0), path::Path::new("/"), 0)
+                .unwrap()
+                .len(),
+        );
+    }
+
+    /// Tests that the source is stopped when the file system is unmounted.
+    #[test]
+    fn test_destroy() {
+        struct StoppableSource(sync::Arc<sync::atomic::AtomicBool>);
+
+        impl sources::Source for StoppableSource {
+            fn start(&mut self) {}
+            fn notify(&self) {}
+            fn reload(&self) {}
+            fn stop(&self) {
+                self.0.store(true, sync::atomic::Ordering::SeqCst);
+            }
+        }
+
+        let stopped = sync::Arc::new(sync::atomic::AtomicBool::new(false));
+        let mediafs = MediaFS::new(
+            Cache::new(sync::RwLock::new(data::cache::Cache::new(
+                "All".into(),
+                "Tagged".into(),
+            ))),
+            Source::new(sync::RwLock::new(Box::new(StoppableSource(
+                stopped.clone(),
+            )))),
+        );
+
+        assert!(!stopped.load(sync::atomic::Ordering::SeqCst));
+        mediafs.destroy(request(0, 0));
+        assert!(stopped.load(sync::atomic::Ordering::SeqCst));
+    }
+
+    /// Tests that the attribute TTL depends on the kind of entry.
+    #[test]
+    fn test_getattr_ttl() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let cache = Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )));
+        let mut tagged = item_with_data(
+            source_dir.path().join("test.jpg"),
+            b"data",
+            2000,
+            1,
+            1,
+        );
+        tagged.tags.insert("tag".into());
+        let item = path::Path::new("/")
+            .join(cache.write().unwrap().add(tagged).unwrap());
+        let link = path::Path::new("/Tagged/tag/2000-01-01 00:00.jpeg");
+        let ttl = |mediafs: &MediaFS, path: &path::Path| {
+            mediafs.getattr(request(0, 0), path, None).unwrap().0
+        };
+
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        let default = ttl(&mediafs, &item);
+        assert_eq!(default, ttl(&mediafs, item.parent().unwrap()));
+
+        let mediafs = mediafs
+            .with_directory_ttl(time::Timespec::new(1, 0))
+            .with_file_ttl(time::Timespec::new(60, 0));
+        assert_eq!(
+            time::Timespec::new(1, 0),
+            ttl(&mediafs, item.parent().unwrap()),
+        );
+        assert_eq!(time::Timespec::new(60, 0), ttl(&mediafs, &item));
+        assert_eq!(time::Timespec::new(60, 0), ttl(&mediafs, link));
+    }
+
+    /// Tests that year directories contain a generated index of their items
+    /// when enabled.
+    #[test]
+    fn test_html_index() {
+        let cache = Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_html_index(),
+        ));
+        let paths = vec![
+            item("/source/test1.jpg", 2000, 1, 1),
+            item("/source/test2.jpg", 2000, 2, 3),
+            item("/source/test3.jpg", 2001, 1, 1),
+        ].into_iter()
+            .map(|item| cache.write().unwrap().add(item).unwrap())
+            .collect::<Vec<_>>();
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        let index = path::Path::new("/All/2000/index.html");
+
+        let size = mediafs.getattr(request(0, 0), index, None).unwrap().1.size;
+        let (fh, _) = mediafs.open(request(0, 0), index, 0).unwrap();
+        let content = mediafs.read(request(0, 0), index, fh, 0, 4096).unwrap();
+        mediafs.release(request(0, 0), index, fh, 0, 0, false).unwrap();
+        let content = String::from_utf8(content).unwrap();
+
+        assert_eq!(size, content.len() as u64);
+        assert!(content.contains("<h2>January 2000</h2>"));
+        assert!(content.contains("<h2>February 2000</h2>"));
+        for path in &paths[..2] {
+            let link = path.strip_prefix("All/2000").unwrap();
+            assert!(content.contains(&format!(
+                "href=\"{}\"",
+                link.to_string_lossy().replace(' ', "%20").replace(':', "%3A"),
+            )));
+        }
+        assert!(!content.contains("2001-01-01"));
+        assert_eq!(
+            Some(libc::EBADF),
+            mediafs.read(request(0, 0), index, fh, 0, 4096).err(),
+        );
+        assert_eq!(
+            Some(libc::EROFS),
+            mediafs
+                .open(request(0, 0), index, libc::O_WRONLY as u32)
+                .err(),
+        );
+    }
+
+    /// Tests that generated indices are kept until the items change.
+    #[test]
+    fn test_html_index_cached() {
+        let cache = Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_html_index(),
+        ));
+        let items = vec![
+            item("/source/test1.jpg", 2000, 1, 1),
+            item("/source/test2.jpg", 2000, 2, 3),
+        ];
+        cache.write().unwrap().add(items[0].clone()).unwrap();
+        let mediafs = MediaFS::new(
+            cache.clone(),
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        let index = path::Path::new("/All/2000/index.html");
+        let generate = || mediafs.index(&cache.read().unwrap(), index).unwrap();
+
+        let first = generate();
+        assert!(sync::Arc::ptr_eq(&first, &generate()));
+
+        cache.write().unwrap().replace_all(items.into_iter()).unwrap();
+        let second = generate();
+        assert!(!sync::Arc::ptr_eq(&first, &second));
+        assert!(String::from_utf8_lossy(&second).contains("February 2000"));
+    }
+
+    /// Tests that the display versions of rotated images are served with
+    /// their orientation applied, and that other images are served
+    /// unchanged.
+    #[test]
+    fn test_display_tree() {
+        // Display versions are generated by jpegtran
+        if !display::can_transform() {
+            return;
+        }
+        let dir = tempdir::TempDir::new("medifs-files").unwrap();
+        let rotated = dir.path().join("rotated.jpg");
+        let upright = dir.path().join("upright.jpg");
+        fs::write(&rotated, display::tests::jpeg_data(6)).unwrap();
+        fs::write(&upright, display::tests::jpeg_data(1)).unwrap();
+
+        let cache = Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_display_tree(true),
+        ));
+        for (path, day) in [(&rotated, 1), (&upright, 2)] {
+            let item = item(path.to_str().unwrap(), 2000, 1, day);
+            cache.write().unwrap().add(item).unwrap();
+        }
+        let mediafs = MediaFS::new(
+            cache,
+            Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        ).with_max_open_files(1);
+        let content = |path: &path::Path| {
+            let size =
+                mediafs.getattr(request(0, 0), path, None).unwrap().1.size;
+            let (fh, _) = mediafs.open(request(0, 0), path, 0).unwrap();
+            let content =
+                mediafs.read(request(0, 0), path, fh, 0, 4096).unwrap();
+            mediafs.release(request(0, 0), path, fh, 0, 0, false).unwrap();
+            assert_eq!(size, content.len() as u64);
+            content
+        };
+
+        let rotated_display = path::Path::new(
+            "/Display/2000/01/01/2000-01-01 00:00.jpeg",
+        );
+        let normalized = content(rotated_display);
+        assert_eq!(Some(1), display::tests::orientation_of(&normalized));
+        let written = dir.path().join("normalized.jpg");
+        fs::write(&written, &normalized).unwrap();
+        assert_eq!(Some((8, 16)), data::dimensions(&written));
+
+        assert_eq!(
+            fs::read(&upright).unwrap(),
+            content(path::Path::new(
+                "/Display/2000/01/02/2000-01-02 00:00.jpeg",
+            )),
+        );
+        assert_eq!(
+            fs::read(&rotated).unwrap(),
+            content(path::Path::new("/All/2000/01/01/2000-01-01 00:00.jpeg")),
+        );
+
+        // Display versions count as open files
+        let req = || request(0, 0);
+        let (fh, _) = mediafs.open(req(), rotated_display, 0).unwrap();
+        assert_eq!(
+            Some(libc::EMFILE),
+            mediafs.open(req(), rotated_display, 0).err(),
+        );
+        mediafs
+            .release(req(), rotated_display, fh, 0, 0, false)
+            .unwrap();
+
+        // Failures are reported instead of the size of the source file
+        fs::remove_file(&rotated).unwrap();
+        assert!(mediafs.getattr(req(), rotated_display, None).is_err());
+        assert!(mediafs.open(req(), rotated_display, 0).is_err());
+    }
 }
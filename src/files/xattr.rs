@@ -0,0 +1,178 @@
+use std::ffi;
+use std::os::unix::ffi::OsStrExt;
+use std::path;
+
+use fuse_mt;
+use libc;
+
+use data;
+
+/// The extended attribute holding the number of links and skipped duplicates
+/// referencing an item.
+pub const REFCOUNT: &str = "user.medifs.refcount";
+
+/// The extended attribute holding the absolute path of the source file of an
+/// item or link.
+pub const SOURCE: &str = "user.medifs.source";
+
+/// The extended attribute holding the SHA-256 checksum of the source file of
+/// an item or link, if checksums are enabled.
+pub const SHA256: &str = "user.medifs.sha256";
+
+/// The extended attribute holding the width and height in pixels of an item,
+/// on the form `WIDTHxHEIGHT`.
+pub const DIMENSIONS: &str = "user.medifs.dimensions";
+
+/// The extended attribute holding the title of an item.
+pub const TITLE: &str = "user.medifs.title";
+
+/// The extended attribute holding the description of an item.
+pub const DESCRIPTION: &str = "user.medifs.description";
+
+/// The extended attribute holding the number of pages or frames of a
+/// multi-page item.
+pub const PAGES: &str = "user.medifs.pages";
+
+/// The extended attribute holding the name of the colour space of an item.
+pub const COLORSPACE: &str = "user.medifs.colorspace";
+
+/// The extended attribute holding the exposure settings of an item.
+pub const SETTINGS: &str = "user.medifs.settings";
+
+/// The extended attribute holding the altitude in metres of an item.
+pub const ALTITUDE: &str = "user.medifs.altitude";
+
+/// The extended attribute holding the direction in degrees of an item.
+pub const DIRECTION: &str = "user.medifs.direction";
+
+/// All extended attributes always supported.
+const NAMES: &[&str] = &[
+    REFCOUNT,
+    SOURCE,
+    DIMENSIONS,
+    TITLE,
+    DESCRIPTION,
+    PAGES,
+    COLORSPACE,
+    SETTINGS,
+    ALTITUDE,
+    DIRECTION,
+];
+
+/// Reads an extended attribute of a cache entry.
+///
+/// If the attribute is not supported for the entry, `None` is returned.
+///
+/// # Arguments
+/// *  `cache` - The cache containing the entry.
+/// *  `path` - The path of the entry.
+/// *  `entry` - The entry whose attribute to read.
+/// *  `name` - The name of the attribute.
+pub fn get(
+    cache: &data::cache::Cache,
+    path: &path::Path,
+    entry: &data::cache::Entry,
+    name: &ffi::OsStr,
+) -> Option<Vec<u8>> {
+    match (name.to_str(), entry) {
+        (Some(REFCOUNT), data::cache::Entry::Item(item)) => {
+            Some(cache.references(item).to_string().into_bytes())
+        }
+        (Some(SOURCE), _) => source(cache, path)
+            .map(|source| source.as_os_str().as_bytes().to_vec()),
+        (Some(DIMENSIONS), data::cache::Entry::Item(item)) => item
+            .dimensions
+            .map(|(width, height)| {
+                format!("{}x{}", width, height).into_bytes()
+            }),
+        (Some(TITLE), data::cache::Entry::Item(item)) => item
+            .captions
+            .get(&data::Caption::Title)
+            .map(|title| title.clone().into_bytes()),
+        (Some(DESCRIPTION), data::cache::Entry::Item(item)) => item
+            .captions
+            .get(&data::Caption::Description)
+            .map(|description| description.clone().into_bytes()),
+        (Some(PAGES), data::cache::Entry::Item(item)) => {
+            item.pages.map(|pages| pages.to_string().into_bytes())
+        }
+        (Some(COLORSPACE), data::cache::Entry::Item(item)) => item
+            .colorspace
+            .as_ref()
+            .map(|colorspace| colorspace.clone().into_bytes()),
+        (Some(SETTINGS), data::cache::Entry::Item(item)) => item
+            .settings
+            .as_ref()
+            .map(|settings| settings.clone().into_bytes()),
+        (Some(ALTITUDE), data::cache::Entry::Item(item)) => item
+            .altitude
+            .as_ref()
+            .map(|altitude| altitude.clone().into_bytes()),
+        (Some(DIRECTION), data::cache::Entry::Item(item)) => item
+            .direction
+            .as_ref()
+            .map(|direction| direction.clone().into_bytes()),
+        _ => None,
+    }
+}
+
+/// Finds the absolute path of the source file of an item or link.
+///
+/// Sources canonicalize their root when constructed, so the paths of items
+/// are already absolute. If the entry is neither, `None` is returned.
+///
+/// # Arguments
+/// *  `cache` - The cache containing the entry.
+/// *  `path` - The path of the entry.
+pub fn source(
+    cache: &data::cache::Cache,
+    path: &path::Path,
+) -> Option<path::PathBuf> {
+    cache.resolve(&path).map(|item| item.path.clone())
+}
+
+/// Lists the extended attributes of a cache entry.
+///
+/// The result is the concatenation of all supported names, each terminated
+/// by a null byte.
+///
+/// # Arguments
+/// *  `cache` - The cache containing the entry.
+/// *  `path` - The path of the entry.
+/// *  `entry` - The entry whose attributes to list.
+pub fn list(
+    cache: &data::cache::Cache,
+    path: &path::Path,
+    entry: &data::cache::Entry,
+) -> Vec<u8> {
+    NAMES
+        .iter()
+        .filter(|name| {
+            get(cache, path, entry, ffi::OsStr::new(name)).is_some()
+        })
+        .fold(vec![], |mut acc, name| {
+            acc.extend(name.as_bytes());
+            acc.push(0);
+            acc
+        })
+}
+
+/// Converts an attribute value to a reply.
+///
+/// If `size` is `0`, only the size of the value is returned. If the value does
+/// not fit in `size` bytes, [`libc::ERANGE`] is returned.
+///
+/// # Arguments
+/// *  `value` - The attribute value.
+/// *  `size` - The size requested by the caller.
+///
+/// [`libc::ERANGE`]: https://doc.rust-lang.org/libc/x86_64-unknown-linux-gnu/libc/constant.ERANGE.html
+pub fn reply(value: Vec<u8>, size: u32) -> fuse_mt::ResultXattr {
+    if size == 0 {
+        Ok(fuse_mt::Xattr::Size(value.len() as u32))
+    } else if (size as usize) < value.len() {
+        Err(libc::ERANGE)
+    } else {
+        Ok(fuse_mt::Xattr::Data(value))
+    }
+}
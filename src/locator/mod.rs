@@ -9,6 +9,9 @@ use data;
 mod dispatch;
 pub use self::dispatch::DispatchLocator;
 
+mod tags;
+pub use self::tags::TagsLocator;
+
 mod timestamps;
 pub use self::timestamps::TimestampsLocator;
 
@@ -35,7 +38,7 @@ impl Entry {
             &Entry::Directory(timestamp, _) | &Entry::Link(timestamp, _) => {
                 timestamp
             }
-            &Entry::Item(ref item) => item.timestamp.as_ref().to_timespec(),
+            Entry::Item(item) => item.timestamp.to_timespec(),
         }
     }
 
@@ -56,6 +59,40 @@ impl Entry {
     }
 }
 
+/// Generates unique file names for a collection of items.
+///
+/// The items are sorted by timestamp, and an index is incorporated into the
+/// name of any item whose name would otherwise collide with a previous one.
+///
+/// # Arguments
+/// *  `items` - The items to name.
+fn item_names(
+    mut items: Vec<&data::ItemKey>,
+) -> Vec<(ffi::OsString, &data::ItemKey)> {
+    items.sort_by_key(|i| &i.timestamp);
+
+    let mut names = Tree::new();
+    items
+        .into_iter()
+        .map(|item| {
+            let name = (0..)
+                .map(|index| data::name(item, item, index).into_os_string())
+                .find(|name| !names.contains(name))
+                .unwrap();
+            names.insert(name.clone());
+            (name, item)
+        })
+        .collect()
+}
+
+/// Generates the root directory of a view without any items.
+///
+/// The root of a view exists even when the collection is empty, so that it
+/// can be listed rather than reported as missing.
+fn empty_root() -> Entry {
+    Entry::Directory(time::Timespec::new(0, 0), Tree::new())
+}
+
 /// A proxy for some tree structure.
 pub trait Locator: data::ItemMonitor {
     /// Locates an item by path components.
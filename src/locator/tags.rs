@@ -0,0 +1,254 @@
+use std::ffi;
+use std::path;
+
+use super::{empty_root, item_names, Entry, Locator, Tree};
+
+use data;
+
+/// A tree locator for tagged items.
+///
+/// Paths are resolved as a sequence of tag parts, optionally followed by the
+/// name of an item tagged with exactly the tag formed by the preceding parts.
+pub struct TagsLocator;
+
+impl Locator for TagsLocator {
+    fn locate(
+        &self,
+        items: &data::SharedCollection,
+        path: &mut dyn Iterator<Item = path::Component>,
+    ) -> Option<Entry> {
+        let items = items.read().unwrap();
+        let tag = data::Tag {
+            parts: path.map(|p| p.as_os_str().to_str().map(String::from))
+                .collect::<Option<Vec<_>>>()?,
+        };
+        if tag.parts.is_empty() {
+            self.locate_tag(&items, &tag).or_else(|| Some(empty_root()))
+        } else {
+            self.locate_tag(&items, &tag)
+                .or_else(|| self.locate_item(&items, &tag))
+        }
+    }
+}
+
+impl data::ItemMonitor for TagsLocator {}
+
+impl Default for TagsLocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TagsLocator {
+    /// Creates a new tags locator.
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Locates the directory for a tag.
+    ///
+    /// The directory lists the next part of all tags nested under `tag`, and
+    /// the items tagged with exactly `tag`. If no such tags or items exist,
+    /// `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `items` - The items through which to search.
+    /// *  `tag` - The tag to locate. This may be empty to locate the root.
+    fn locate_tag(
+        &self,
+        items: &data::ItemCollection,
+        tag: &data::Tag,
+    ) -> Option<Entry> {
+        let depth = tag.parts.len();
+        let (children, timestamps): (Vec<_>, Vec<_>) = items
+            .keys()
+            .flat_map(|i| i.tags.iter().map(move |t| (i, data::Tag::new(t))))
+            .filter(|(_, t)| tag.is_parent_of(t))
+            .map(|(i, t)| (ffi::OsString::from(&t.parts[depth]), &i.timestamp))
+            .unzip();
+        let members = item_names(self.members(items, tag));
+
+        timestamps
+            .into_iter()
+            .chain(members.iter().map(|&(_, i)| &i.timestamp))
+            .max()
+            .map(|timestamp| {
+                Entry::Directory(
+                    timestamp.to_timespec(),
+                    children
+                        .into_iter()
+                        .chain(members.into_iter().map(|(name, _)| name))
+                        .collect::<Tree>(),
+                )
+            })
+    }
+
+    /// Locates a specific item.
+    ///
+    /// The last part of `tag` is interpreted as an item name, and the
+    /// remaining parts as the tag of the item.
+    ///
+    /// # Arguments
+    /// *  `items` - The items through which to search.
+    /// *  `tag` - The tag parts followed by the item name.
+    fn locate_item(
+        &self,
+        items: &data::ItemCollection,
+        tag: &data::Tag,
+    ) -> Option<Entry> {
+        let (name, parts) = tag.parts.split_last()?;
+        let parent = data::Tag {
+            parts: parts.to_vec(),
+        };
+        item_names(self.members(items, &parent))
+            .into_iter()
+            .find(|(n, _)| n.as_os_str() == ffi::OsStr::new(name))
+            .and_then(|(_, i)| items.get(&i.path))
+            .map(|i| Entry::Item(i.into_owned()))
+    }
+
+    /// Lists all items tagged with exactly a specific tag.
+    ///
+    /// # Arguments
+    /// *  `items` - The items through which to search.
+    /// *  `tag` - The tag.
+    fn members<'a>(
+        &self,
+        items: &'a data::ItemCollection,
+        tag: &data::Tag,
+    ) -> Vec<&'a data::ItemKey> {
+        if tag.parts.is_empty() {
+            Vec::new()
+        } else {
+            items
+                .keys()
+                .filter(|i| i.tags.iter().any(|t| &data::Tag::new(t) == tag))
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections;
+
+    use locator::tests::*;
+    use super::*;
+
+    /// Creates a tagged item.
+    ///
+    /// # Arguments
+    /// *  `name` - The item source name.
+    /// *  `day` - The day of January 2000.
+    /// *  `tags` - The item tags.
+    fn tagged(name: &str, day: i32, tags: &[&str]) -> data::Item {
+        let mut result = item(name, 2000, 1, day);
+        result.tags.extend(tags.iter().map(|t| String::from(*t)));
+        result
+    }
+
+    /// Creates a directory tree from names.
+    ///
+    /// # Arguments
+    /// *  `names` - The names in the tree.
+    fn tree(names: &[&str]) -> collections::HashSet<ffi::OsString> {
+        names.iter().map(ffi::OsString::from).collect()
+    }
+
+    /// Tests that a locator with no items yields only an empty root.
+    #[test]
+    fn locate_empty() {
+        let locator = TagsLocator::new();
+        let items = no_items();
+        assert_eq!(
+            Some(Entry::Directory(time::Timespec::new(0, 0), Tree::new())),
+            locator.locate(&items, &mut path::PathBuf::from("").components()),
+        );
+        assert_eq!(
+            None,
+            locator
+                .locate(&items, &mut path::PathBuf::from("test").components()),
+        );
+    }
+
+    /// Tests that an unknown child yields nothing.
+    #[test]
+    fn locate_non_existing() {
+        let locator = TagsLocator::new();
+        let items = no_items();
+        items.write().unwrap().extend(vec![
+            tagged("test1.jpg", 1, &["a"]),
+            tagged("test2.jpg", 2, &["a/b"]),
+        ]);
+        assert_eq!(
+            None,
+            locator.locate(&items, &mut path::PathBuf::from("b").components()),
+        );
+        assert_eq!(
+            None,
+            locator
+                .locate(&items, &mut path::PathBuf::from("a/c").components()),
+        );
+        assert_eq!(
+            None,
+            locator.locate(
+                &items,
+                &mut path::PathBuf::from("a/2000-01-02 00:00.jpeg")
+                    .components(),
+            ),
+        );
+    }
+
+    /// Tests that a child can be found.
+    #[test]
+    fn locate_existing() {
+        let locator = TagsLocator::new();
+        let items = no_items();
+        let item1 = tagged("test1.jpg", 1, &["a"]);
+        let item2 = tagged("test2.jpg", 2, &["a/b", "c"]);
+        let item3 = tagged("test3.jpg", 2, &["a/b"]);
+        items.write().unwrap().extend(vec![
+            item1.clone(),
+            item2.clone(),
+            item3,
+        ]);
+        assert_eq!(
+            Some(Entry::Directory(
+                data::Timestamp::from((2000, 1, 2)).to_timespec(),
+                tree(&["a", "c"]),
+            )),
+            locator.locate(&items, &mut path::PathBuf::from("").components()),
+        );
+        assert_eq!(
+            Some(Entry::Directory(
+                data::Timestamp::from((2000, 1, 2)).to_timespec(),
+                tree(&["b", "2000-01-01 00:00.jpeg"]),
+            )),
+            locator.locate(&items, &mut path::PathBuf::from("a").components()),
+        );
+        assert_eq!(
+            Some(Entry::Directory(
+                data::Timestamp::from((2000, 1, 2)).to_timespec(),
+                tree(&["2000-01-02 00:00.jpeg", "2000-01-02 00:00 (1).jpeg"]),
+            )),
+            locator
+                .locate(&items, &mut path::PathBuf::from("a/b").components()),
+        );
+        assert_eq!(
+            Some(Entry::Item(item1)),
+            locator.locate(
+                &items,
+                &mut path::PathBuf::from("a/2000-01-01 00:00.jpeg")
+                    .components(),
+            ),
+        );
+        assert_eq!(
+            Some(Entry::Item(item2)),
+            locator.locate(
+                &items,
+                &mut path::PathBuf::from("c/2000-01-02 00:00.jpeg")
+                    .components(),
+            ),
+        );
+    }
+}
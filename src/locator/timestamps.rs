@@ -5,7 +5,7 @@ use std::str;
 
 use regex;
 
-use super::{Entry, Locator};
+use super::{empty_root, item_names, Entry, Locator};
 
 use data;
 
@@ -13,7 +13,10 @@ use data;
 type Day = (i32, i32, i32);
 
 /// A tree locator for timestamped items.
-pub struct TimestampsLocator;
+pub struct TimestampsLocator {
+    /// The layout of the date directories.
+    layout: data::DateLayout,
+}
 
 impl Locator for TimestampsLocator {
     fn locate(
@@ -22,12 +25,14 @@ impl Locator for TimestampsLocator {
         path: &mut Iterator<Item = path::Component>,
     ) -> Option<Entry> {
         let items = items.read().unwrap();
-        Location::try_from(path).and_then(|location| match location {
-            Location::Root => self.locate_root(&items),
-            Location::Year(y) => self.locate_year(&items, y),
-            Location::Month(y, m) => self.locate_month(&items, y, m),
-            Location::Day(y, m, d) => self.locate_day(&items, y, m, d),
-            Location::Item(i) => self.locate_item(&items, i),
+        Location::try_from(path, &self.layout).and_then(|location| {
+            match location {
+                Location::Root => self.locate_root(&items),
+                Location::Year(y) => self.locate_year(&items, y),
+                Location::Month(y, m) => self.locate_month(&items, y, m),
+                Location::Day(y, m, d) => self.locate_day(&items, y, m, d),
+                Location::Item(i) => self.locate_item(&items, i),
+            }
         })
     }
 }
@@ -37,7 +42,18 @@ impl data::ItemMonitor for TimestampsLocator {}
 impl TimestampsLocator {
     /// Creates a new timestamp locator.
     pub fn new() -> Self {
-        Self {}
+        Self {
+            layout: data::DateLayout::default(),
+        }
+    }
+
+    /// Sets the layout of the date directories.
+    ///
+    /// # Arguments
+    /// *  `layout` - The date directory layout.
+    pub fn with_layout(mut self, layout: data::DateLayout) -> Self {
+        self.layout = layout;
+        self
     }
 
     /// Locates all root items.
@@ -49,12 +65,12 @@ impl TimestampsLocator {
     fn locate_root(&self, items: &data::ItemCollection) -> Option<Entry> {
         self.directory(
             items
-                .items
-                .iter()
+                .keys()
                 .map(|i| (i.timestamp.year(), 1i32, 1i32))
                 .collect(),
-            |&(v, _, _)| format!("{}", v).into(),
+            0,
         )
+        .or_else(|| Some(empty_root()))
     }
 
     /// Locates all months for a year.
@@ -69,12 +85,11 @@ impl TimestampsLocator {
     ) -> Option<Entry> {
         self.directory(
             items
-                .items
-                .iter()
+                .keys()
                 .filter(|i| i.timestamp.year() == year)
                 .map(|i| (i.timestamp.year(), i.timestamp.month(), 1i32))
                 .collect(),
-            |&(_, v, _)| format!("{:02}", v).into(),
+            1,
         )
     }
 
@@ -92,15 +107,14 @@ impl TimestampsLocator {
     ) -> Option<Entry> {
         self.directory(
             items
-                .items
-                .iter()
+                .keys()
                 .filter(|i| i.timestamp.year() == year)
                 .filter(|i| i.timestamp.month() == month)
                 .map(|i| {
                     (i.timestamp.year(), i.timestamp.month(), i.timestamp.day())
                 })
                 .collect(),
-            |&(_, _, v)| format!("{:02}", v).into(),
+            2,
         )
     }
 
@@ -118,41 +132,20 @@ impl TimestampsLocator {
         month: i32,
         day: i32,
     ) -> Option<Entry> {
-        // Extract all items for the specific day and sort them on timestamp
-        let mut items = items
-            .items
-            .iter()
+        // Extract all items for the specific day
+        let items = items
+            .keys()
             .filter(|i| i.timestamp.year() == year)
             .filter(|i| i.timestamp.month() == month)
             .filter(|i| i.timestamp.day() == day)
             .collect::<Vec<_>>();
-        items.sort_by_key(|i| &i.timestamp);
 
         if !items.is_empty() {
-            // If any items match, fold the list to correctly implement indice
+            // If any items match, name them to correctly implement indice
+            let names = item_names(items);
             Some(Entry::Directory(
-                items.last().unwrap().timestamp.as_ref().to_timespec(),
-                items
-                    .iter()
-                    .fold(Vec::new(), |mut acc, i| {
-                        let mut index = 0;
-                        loop {
-                            let name = data::name(*i, *i, index);
-                            match acc.last() {
-                                Some(n) if n == name.as_os_str() => {
-                                    index += 1;
-                                    continue;
-                                }
-                                _ => (),
-                            }
-                            acc.push(name.into_os_string());
-                            break;
-                        }
-
-                        acc
-                    })
-                    .into_iter()
-                    .collect(),
+                names.last().unwrap().1.timestamp.to_timespec(),
+                names.into_iter().map(|(name, _)| name).collect(),
             ))
         } else {
             // If not items match, this directory does not exist
@@ -171,35 +164,39 @@ impl TimestampsLocator {
         item: ItemLocator,
     ) -> Option<Entry> {
         items
-            .items
-            .iter()
+            .keys()
             .filter(|i| i.timestamp.year() == item.timestamp.year())
             .filter(|i| i.timestamp.month() == item.timestamp.month())
             .filter(|i| i.timestamp.day() == item.timestamp.day())
-            .skip(item.index)
-            .map(|i| Entry::Item(i.clone()))
-            .next()
+            .nth(item.index)
+            .and_then(|i| items.get(&i.path))
+            .map(|i| Entry::Item(i.into_owned()))
     }
 
     /// Generates a generic directory entry from a collection of days.
     ///
     /// # Arguments
     /// *  `items` - The individual days.
-    /// *  `mapper` - A function convering a day to a string.
-    fn directory<F>(
+    /// *  `level` - The level of the directory layout used to convert a day to
+    ///    a string.
+    fn directory(
         &self,
         items: collections::HashSet<Day>,
-        mapper: F,
-    ) -> Option<Entry>
-    where
-        F: FnMut(&Day) -> ffi::OsString,
-    {
+        level: usize,
+    ) -> Option<Entry> {
         if !items.is_empty() {
             Some(Entry::Directory(
                 data::Timestamp::from(*items.iter().max().unwrap())
-                    .as_ref()
                     .to_timespec(),
-                items.iter().map(mapper).collect(),
+                items
+                    .iter()
+                    .map(|&day| {
+                        ffi::OsString::from(
+                            self.layout
+                                .label(level, &data::Timestamp::from(day)),
+                        )
+                    })
+                    .collect(),
             ))
         } else {
             None
@@ -265,8 +262,10 @@ impl Location {
     ///
     /// # Arguments
     /// *  `source` - The components to convert.
+    /// *  `layout` - The layout of the date directories.
     pub fn try_from(
         source: &mut Iterator<Item = path::Component>,
+        layout: &data::DateLayout,
     ) -> Option<Self> {
         // Parse the path components; missing values will be None, and invalid
         // values will be Some(Err)
@@ -274,21 +273,15 @@ impl Location {
             source
                 .next()
                 .and_then(|p| p.as_os_str().to_str())
-                .map(|p| p.parse::<i32>()),
-            source.next().and_then(|p| p.as_os_str().to_str()).map(|p| {
-                if p.len() == 2 {
-                    p.parse::<i32>().map_err(|_| ())
-                } else {
-                    Err(())
-                }
-            }),
-            source.next().and_then(|p| p.as_os_str().to_str()).map(|p| {
-                if p.len() == 2 {
-                    p.parse::<i32>().map_err(|_| ())
-                } else {
-                    Err(())
-                }
-            }),
+                .map(|p| layout.parse(0, p).ok_or(())),
+            source
+                .next()
+                .and_then(|p| p.as_os_str().to_str())
+                .map(|p| layout.parse(1, p).ok_or(())),
+            source
+                .next()
+                .and_then(|p| p.as_os_str().to_str())
+                .map(|p| layout.parse(2, p).ok_or(())),
             source
                 .next()
                 .and_then(|p| p.as_os_str().to_str())
@@ -386,6 +379,17 @@ mod test {
     use locator::tests::*;
     use super::*;
 
+    /// Converts a path to a location using the default layout.
+    ///
+    /// # Arguments
+    /// *  `path` - The path to convert.
+    fn location(path: &str) -> Option<Location> {
+        Location::try_from(
+            &mut path::PathBuf::from(path).components(),
+            &data::DateLayout::default(),
+        )
+    }
+
     /// Asserts that an item locator cannot be created from an invalid string.
     #[test]
     fn item_locator_parse_invalid_str() {
@@ -446,66 +450,27 @@ mod test {
     /// Asserts that a location cannot be created from an invalid string.
     #[test]
     fn location_from_str_invalid() {
-        assert_eq!(
-            None,
-            Location::try_from(&mut path::PathBuf::from("a").components()),
-        );
-        assert_eq!(
-            None,
-            Location::try_from(&mut path::PathBuf::from("1/2").components()),
-        );
-        assert_eq!(
-            None,
-            Location::try_from(&mut path::PathBuf::from("1/02/3").components()),
-        );
-        assert_eq!(
-            None,
-            Location::try_from(&mut path::PathBuf::from(
-                "1/02/03/1-02-03 04:05.jpeg"
-            ).components()),
-        );
-        assert_eq!(
-            None,
-            Location::try_from(&mut path::PathBuf::from(
-                "1/02/03/1-02-03 04:05:06 (a).jpeg"
-            ).components()),
-        );
-        assert_eq!(
-            None,
-            Location::try_from(&mut path::PathBuf::from(
-                "1/02/03/2-02-03 04:05:06 (7).jpeg"
-            ).components()),
-        );
+        assert_eq!(None, location("a"));
+        assert_eq!(None, location("1/2"));
+        assert_eq!(None, location("1/02/3"));
+        assert_eq!(None, location("1/02/03/1-02-03 04:05.jpeg"));
+        assert_eq!(None, location("1/02/03/1-02-03 04:05:06 (a).jpeg"));
+        assert_eq!(None, location("1/02/03/2-02-03 04:05:06 (7).jpeg"));
     }
 
     /// Asserts that a location can be created from a valid string.
     #[test]
     fn location_from_str_valid() {
-        assert_eq!(
-            Location::Year(1),
-            Location::try_from(&mut path::PathBuf::from("1").components())
-                .unwrap(),
-        );
-        assert_eq!(
-            Location::Month(1, 2),
-            Location::try_from(&mut path::PathBuf::from("1/02").components())
-                .unwrap(),
-        );
-        assert_eq!(
-            Location::Day(1, 2, 3),
-            Location::try_from(&mut path::PathBuf::from("1/02/03").components())
-                .unwrap(),
-        );
+        assert_eq!(Location::Year(1), location("0001").unwrap());
+        assert_eq!(Location::Month(1, 2), location("0001/02").unwrap());
+        assert_eq!(Location::Day(1, 2, 3), location("0001/02/03").unwrap());
         assert_eq!(
             Location::Item(ItemLocator {
                 timestamp: (1, 2, 3, 4, 5, 6).into(),
                 index: 0,
                 extension: String::from("jpeg"),
             }),
-            Location::try_from(&mut path::PathBuf::from(
-                "1/02/03/1-02-03 04:05:06.jpeg"
-            ).components())
-                .unwrap(),
+            location("0001/02/03/1-02-03 04:05:06.jpeg").unwrap(),
         );
         assert_eq!(
             Location::Item(ItemLocator {
@@ -513,10 +478,7 @@ mod test {
                 index: 7,
                 extension: String::from("jpeg"),
             }),
-            Location::try_from(&mut path::PathBuf::from(
-                "1/02/03/1-02-03 04:05:06 (7).jpeg"
-            ).components())
-                .unwrap(),
+            location("0001/02/03/1-02-03 04:05:06 (7).jpeg").unwrap(),
         );
     }
 
@@ -532,12 +494,27 @@ mod test {
         );
     }
 
+    /// Tests that the root of a locator with no children is an empty
+    /// directory.
+    #[test]
+    fn locate_empty_root() {
+        let locator = TimestampsLocator::new();
+        let items = no_items();
+        assert_eq!(
+            Some(Entry::Directory(
+                time::Timespec::new(0, 0),
+                collections::HashSet::new(),
+            )),
+            locator.locate(&items, &mut path::PathBuf::from("").components()),
+        );
+    }
+
     /// Tests that an unknown child yields nothing.
     #[test]
     fn locate_non_existing() {
         let locator = TimestampsLocator::new();
         let items = no_items();
-        items.write().unwrap().items.extend(vec![
+        items.write().unwrap().extend(vec![
             item("test1.jpg", 2000, 1, 1),
             item("test2.jpg", 2000, 1, 2),
         ]);
@@ -560,14 +537,14 @@ mod test {
     fn locate_existing() {
         let locator = TimestampsLocator::new();
         let items = no_items();
-        items.write().unwrap().items.extend(vec![
+        items.write().unwrap().extend(vec![
             item("test1.jpg", 2000, 1, 1),
             item("test2.jpg", 2000, 2, 1),
             item("test3.jpg", 2001, 1, 2),
         ]);
         assert_eq!(
             Some(Entry::Directory(
-                data::Timestamp::from((2001, 1, 1)).as_ref().to_timespec(),
+                data::Timestamp::from((2001, 1, 1)).to_timespec(),
                 [ffi::OsString::from("2000"), ffi::OsString::from("2001"),]
                     .iter()
                     .cloned()
@@ -577,7 +554,7 @@ mod test {
         );
         assert_eq!(
             Some(Entry::Directory(
-                data::Timestamp::from((2000, 2, 1)).as_ref().to_timespec(),
+                data::Timestamp::from((2000, 2, 1)).to_timespec(),
                 [ffi::OsString::from("01"), ffi::OsString::from("02"),]
                     .iter()
                     .cloned()
@@ -588,7 +565,7 @@ mod test {
         );
         assert_eq!(
             Some(Entry::Directory(
-                data::Timestamp::from((2000, 1, 1)).as_ref().to_timespec(),
+                data::Timestamp::from((2000, 1, 1)).to_timespec(),
                 [ffi::OsString::from("01"),]
                     .iter()
                     .cloned()
@@ -601,7 +578,7 @@ mod test {
         );
         assert_eq!(
             Some(Entry::Directory(
-                data::Timestamp::from((2000, 1, 1)).as_ref().to_timespec(),
+                data::Timestamp::from((2000, 1, 1)).to_timespec(),
                 [ffi::OsString::from("2000-01-01 00:00.jpeg"),]
                     .iter()
                     .cloned()
@@ -613,4 +590,49 @@ mod test {
             ),
         );
     }
+
+    /// Tests that a child can be found using a custom layout.
+    #[test]
+    fn locate_existing_with_layout() {
+        let locator = TimestampsLocator::new()
+            .with_layout("%Y/%B/%Y-%m-%d".parse().unwrap());
+        let items = no_items();
+        items.write().unwrap().extend(vec![
+            item("test1.jpg", 2000, 1, 1),
+            item("test2.jpg", 2000, 2, 1),
+        ]);
+        assert_eq!(
+            Some(Entry::Directory(
+                data::Timestamp::from((2000, 2, 1)).to_timespec(),
+                [
+                    ffi::OsString::from("January"),
+                    ffi::OsString::from("February"),
+                ].iter()
+                    .cloned()
+                    .collect::<collections::HashSet<_>>(),
+            )),
+            locator
+                .locate(&items, &mut path::PathBuf::from("2000").components(),),
+        );
+        assert_eq!(
+            Some(Entry::Directory(
+                data::Timestamp::from((2000, 2, 1)).to_timespec(),
+                [ffi::OsString::from("2000-02-01"),]
+                    .iter()
+                    .cloned()
+                    .collect::<collections::HashSet<_>>(),
+            )),
+            locator.locate(
+                &items,
+                &mut path::PathBuf::from("2000/February").components(),
+            ),
+        );
+        assert_eq!(
+            None,
+            locator.locate(
+                &items,
+                &mut path::PathBuf::from("2000/02").components(),
+            ),
+        );
+    }
 }
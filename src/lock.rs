@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path;
+
+use libc;
+use sha2;
+use sha2::Digest;
+
+/// An advisory lock on a mount point, held while a file system is mounted
+/// on it.
+///
+/// The lock is a `flock` lock on a file outside of the mount point, named
+/// after the canonical path of the mount point, so that all paths leading to
+/// the same directory share a lock. It is released when this value is
+/// dropped, or when the process exits.
+pub struct MountLock {
+    /// The locked file.
+    _file: fs::File,
+}
+
+impl MountLock {
+    /// Acquires the lock for a mount point.
+    ///
+    /// The lock file is created in `$XDG_RUNTIME_DIR`, or the temporary
+    /// directory if that is not set.
+    ///
+    /// # Arguments
+    /// *  `mount_point` - The mount point.
+    pub fn acquire(mount_point: &path::Path) -> io::Result<Self> {
+        let directory = env::var_os("XDG_RUNTIME_DIR")
+            .map(path::PathBuf::from)
+            .unwrap_or_else(env::temp_dir);
+        Self::acquire_in(&directory, mount_point)
+    }
+
+    /// Acquires the lock for a mount point using a lock file in a specific
+    /// directory.
+    ///
+    /// If another process, or another lock in this process, holds the lock,
+    /// an error of kind [`io::ErrorKind::WouldBlock`] is returned.
+    ///
+    /// # Arguments
+    /// *  `directory` - The directory containing the lock file.
+    /// *  `mount_point` - The mount point.
+    ///
+    /// [`io::ErrorKind::WouldBlock`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.WouldBlock
+    pub fn acquire_in(
+        directory: &path::Path,
+        mount_point: &path::Path,
+    ) -> io::Result<Self> {
+        let mount_point = mount_point.canonicalize()?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(directory.join(Self::name(&mount_point)))?;
+        let flags = libc::LOCK_EX | libc::LOCK_NB;
+        if unsafe { libc::flock(file.as_raw_fd(), flags) } == 0 {
+            Ok(Self { _file: file })
+        } else {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::WouldBlock {
+                Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "{} is already in use by another instance",
+                        mount_point.display(),
+                    ),
+                ))
+            } else {
+                Err(error)
+            }
+        }
+    }
+
+    /// Generates the name of the lock file for a mount point.
+    ///
+    /// # Arguments
+    /// *  `mount_point` - The canonical path of the mount point.
+    fn name(mount_point: &path::Path) -> String {
+        let mut hasher = sha2::Sha256::default();
+        hasher.input(mount_point.as_os_str().as_bytes());
+        hasher.result().iter().take(8).fold(
+            String::from("medifs-"),
+            |mut acc, b| {
+                acc.push_str(&format!("{:02x}", b));
+                acc
+            },
+        ) + ".lock"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir;
+
+    use super::*;
+
+    /// Tests that a held lock prevents a second mount of the same point,
+    /// also through another path, until released.
+    #[test]
+    fn acquire_held() {
+        let locks = tempdir::TempDir::new("medifs-locks").unwrap();
+        let mount_point = tempdir::TempDir::new("medifs-mount").unwrap();
+        let other = tempdir::TempDir::new("medifs-mount").unwrap();
+        let alias = mount_point.path().join(".");
+
+        let lock =
+            MountLock::acquire_in(locks.path(), mount_point.path()).unwrap();
+        assert_eq!(
+            io::ErrorKind::WouldBlock,
+            MountLock::acquire_in(locks.path(), &alias)
+                .err()
+                .unwrap()
+                .kind(),
+        );
+        assert!(MountLock::acquire_in(locks.path(), other.path()).is_ok());
+
+        drop(lock);
+        assert!(MountLock::acquire_in(locks.path(), &alias).is_ok());
+    }
+}
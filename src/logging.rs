@@ -0,0 +1,108 @@
+use log;
+
+/// A logger writing records to standard error.
+struct Logger;
+
+/// The global logger instance.
+static LOGGER: Logger = Logger;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "{:<5} {}: {}",
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Converts a verbosity to a log level filter.
+///
+/// With no verbosity, only warnings and errors are logged. A verbosity of `1`
+/// enables debug messages and any higher verbosity enables trace messages.
+///
+/// # Arguments
+/// *  `verbosity` - The number of times the verbose flag was passed.
+pub fn level(verbosity: u64) -> log::LevelFilter {
+    match verbosity {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    }
+}
+
+/// Installs the global logger.
+///
+/// If a logger has already been installed, this function does nothing.
+///
+/// # Arguments
+/// *  `verbosity` - The number of times the verbose flag was passed.
+pub fn init(verbosity: u64) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level(verbosity));
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync;
+
+    use super::*;
+
+    /// A logger storing all messages.
+    struct CapturingLogger;
+
+    /// The capturing logger instance.
+    static CAPTURING_LOGGER: CapturingLogger = CapturingLogger;
+
+    lazy_static! {
+        /// The messages logged so far.
+        static ref RECORDS: sync::Mutex<Vec<(log::Level, String)>> =
+            sync::Mutex::new(Vec::new());
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            RECORDS
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Returns all messages logged so far.
+    ///
+    /// The first time this function is called, a capturing logger is
+    /// installed. Since tests run concurrently, callers should filter the
+    /// messages.
+    pub fn records() -> Vec<(log::Level, String)> {
+        if log::set_logger(&CAPTURING_LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+        RECORDS.lock().unwrap().clone()
+    }
+
+    /// Tests that the verbosity maps to the expected levels.
+    #[test]
+    fn verbosity() {
+        assert_eq!(log::LevelFilter::Warn, level(0));
+        assert_eq!(log::LevelFilter::Debug, level(1));
+        assert_eq!(log::LevelFilter::Trace, level(2));
+        assert_eq!(log::LevelFilter::Trace, level(3));
+    }
+}
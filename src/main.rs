@@ -5,10 +5,14 @@ extern crate fuse_mt;
 #[macro_use]
 extern crate lazy_static;
 extern crate libc;
+#[macro_use]
+extern crate log;
 extern crate mime;
 extern crate mime_guess;
 extern crate regex;
+#[cfg(feature = "tags")]
 extern crate rexiv2;
+extern crate sha2;
 extern crate time;
 extern crate walkdir;
 
@@ -16,74 +20,351 @@ extern crate walkdir;
 extern crate tempdir;
 
 use std::ffi;
+use std::fs;
+use std::io;
 use std::process;
 use std::sync;
+use std::sync::mpsc;
+use std::thread;
 
-use sources::WithSources;
-
+pub mod config;
 pub mod data;
 pub mod files;
-pub mod sources;
+pub mod lock;
 pub mod locator;
+pub mod logging;
+pub mod manifest;
+pub mod probe;
+pub mod selfcheck;
+pub mod signals;
+pub mod sources;
 pub mod types;
 pub mod util;
+pub mod watchdog;
 
 fn main() {
-    let matches = clap::App::new(crate_name!())
-        .version(crate_version!())
-        .author(crate_authors!())
-        .about(crate_description!())
-        .arg(
-            clap::Arg::with_name("MOUNT_POINT")
-                .help("The target mount point.")
-                .required(true),
-        )
-        .arg(
-            clap::Arg::with_name("FUSE_OPTION")
-                .help("Options passed to FUSE")
-                .multiple(true)
-                .value_delimiter(",")
-                .short("o")
-                .takes_value(true),
-        )
-        .with_sources()
-        .get_matches();
+    let matches = config::app().get_matches();
+    let config = config::Config::from_matches(&matches).unwrap_or_else(|e| {
+        e.exit();
+    });
+    logging::init(config.verbosity);
 
-    let mount_point = matches
-        .value_of("MOUNT_POINT")
-        .unwrap()
-        .parse::<types::MountPoint>()
-        .unwrap_or_else(|e| {
-            e.exit();
-        });
-    let fuse_options = matches
-        .values_of("FUSE_OPTION")
-        .map(|values| {
-            values.fold(vec![], |mut acc, o| {
-                acc.push(ffi::OsString::from("-o"));
-                acc.push(ffi::OsString::from(o));
-                acc
+    let cache = files::Cache::new(sync::RwLock::new(config.cache()));
+    let query = config.filter.as_ref().map(|(_, query)| query.clone());
+    let filter = data::shared_filter(data::Filter {
+        query: query.clone(),
+        ..config
+            .config
+            .as_ref()
+            .map(|path| {
+                read_filter(path).unwrap_or_else(|e| {
+                    clap::Error::with_description(
+                        &e,
+                        clap::ErrorKind::InvalidValue,
+                    ).exit();
+                })
             })
-        })
-        .unwrap_or(vec![]);
-    let cache = files::Cache::new(sync::RwLock::new(data::cache::Cache::new(
-        "All".into(),
-        "Tagged".into(),
-    )));
-    let source =
-        files::Source::new(sync::RwLock::new((cache.clone(), matches).into()));
+            .unwrap_or_default()
+    });
+    let source = files::Source::new(sync::RwLock::new(
+        (cache.clone(), filter.clone(), matches).into(),
+    ));
+    if let Some(ref path) = config.probe {
+        match source.read().unwrap().probe(path) {
+            Some(item) => print!("{}", probe::describe(&item)),
+            None => clap::Error::with_description(
+                &format!("no item could be read from {}", path.display()),
+                clap::ErrorKind::InvalidValue,
+            ).exit(),
+        }
+        return;
+    }
+
+    if config.print_config {
+        print!("{}", config);
+        for (key, value) in source.read().unwrap().settings() {
+            println!("{} = {}", key, value);
+        }
+        return;
+    }
+
+    if config.list_tags {
+        source.write().unwrap().start();
+        let cache = cache.read().unwrap();
+        let items = cache.items();
+        let tags = items.iter().flat_map(|item| item.tags.iter());
+        for tag in data::Tag::hierarchy(tags, config.tag_separator) {
+            println!("{}", tag.join(config.tag_separator));
+        }
+        return;
+    }
+
+    if config.emit_manifest {
+        source.write().unwrap().start();
+        print!("{}", manifest::generate(&cache.read().unwrap()));
+        return;
+    }
+
+    if config.verify_reads {
+        let mediafs = files::MediaFS::new(cache.clone(), source.clone());
+        process::exit(verify_reads(&mediafs, &cache));
+    }
+
+    // The mount point is only optional when probing or verifying reads
+    let mount_point = config.mount_point.as_ref().unwrap();
+    if source.read().unwrap().overlaps(mount_point.as_ref()) {
+        clap::Error::with_description(
+            "the source directory must not contain or be contained in the \
+             mount point",
+            clap::ErrorKind::InvalidValue,
+        ).exit();
+    }
+
+    // Display versions cannot be generated without jpegtran
+    if config.display_tree && !files::can_transform() {
+        clap::Error::with_description(
+            "--display-tree requires jpegtran, which could not be run",
+            clap::ErrorKind::InvalidValue,
+        ).exit();
+    }
+
+    // Reload the configuration and all items on SIGHUP
+    {
+        let source = source.clone();
+        let path = config.config.clone();
+        signals::on_hangup(move || {
+            if let Some(ref path) = path {
+                match read_filter(path) {
+                    Ok(value) => {
+                        *filter.write().unwrap() = data::Filter {
+                            query: query.clone(),
+                            ..value
+                        }
+                    }
+                    Err(e) => error!("Failed to reload configuration: {}", e),
+                }
+            }
+            source.read().unwrap().reload();
+        });
+    }
+
     let mediafs = files::MediaFS::new(cache.clone(), source.clone());
+    let fuse_options = config
+        .fuse_options
+        .iter()
+        .fold(vec![], |mut acc, o| {
+            acc.push(ffi::OsString::from("-o"));
+            acc.push(ffi::OsString::from(o));
+            acc
+        });
+    let fuse_options = fuse_options
+        .iter()
+        .map(|s| s.as_os_str())
+        .collect::<Vec<&ffi::OsStr>>();
+
+    // When other users may access the file system, present a single owner
+    let shared = fuse_options
+        .iter()
+        .any(|&o| o == "allow_other" || o == "allow_root");
+    let mediafs = if shared {
+        mediafs.with_owner(unsafe { libc::getuid() }, unsafe {
+            libc::getgid()
+        })
+    } else {
+        mediafs
+    };
+    let mediafs = match config.directory_ttl {
+        Some(ttl) => mediafs.with_directory_ttl(ttl),
+        None => mediafs,
+    };
+    let mediafs = match config.file_ttl {
+        Some(ttl) => mediafs.with_file_ttl(ttl),
+        None => mediafs,
+    };
+    let mediafs = match config.max_open_files {
+        Some(max_open_files) => mediafs.with_max_open_files(max_open_files),
+        None => mediafs,
+    };
+    let mediafs = if config.checksums {
+        mediafs.with_checksums()
+    } else {
+        mediafs
+    };
+    let mediafs = if config.dry_run_writes {
+        mediafs.with_dry_run_writes()
+    } else {
+        mediafs
+    };
+    let mediafs = if config.dir_size_counts {
+        mediafs.with_directory_size_counts()
+    } else {
+        mediafs
+    };
 
-    if let Err(e) = fuse_mt::mount(
-        fuse_mt::FuseMT::new(mediafs, 1),
-        &mount_point,
-        fuse_options
+    // Prevent mounting twice on the same point; the lock is released when
+    // the process exits
+    let _lock = lock::MountLock::acquire(mount_point.as_ref())
+        .unwrap_or_else(|e| {
+            error!("Failed to lock mount point: {}", e);
+            process::exit(1);
+        });
+
+    // Exit with a clear error rather than failing every operation if the
+    // mount point disappears while mounted; the main thread unmounts the
+    // file system so that it is shut down cleanly
+    let (tx, rx) = mpsc::channel();
+    {
+        let tx = tx.clone();
+        let path = mount_point.as_ref().to_path_buf();
+        watchdog::on_unavailable(
+            mount_point.as_ref(),
+            watchdog::DEFAULT_INTERVAL,
+            move |e| {
+                error!(
+                    "Mount point {} is no longer available: {}",
+                    path.display(),
+                    e
+                );
+                tx.send(Stop::Unavailable).ok();
+            },
+        );
+    }
+
+    if config.self_check {
+        process::exit(self_check(mediafs, mount_point, &fuse_options));
+    }
+
+    let session = {
+        let path = mount_point.as_ref().to_path_buf();
+        let fuse_options = fuse_options
             .iter()
-            .map(|s| s.as_os_str())
-            .collect::<Vec<&ffi::OsStr>>()
-            .as_slice(),
-    ) {
-        println!("Failed to mount media file system: {}", e);
-        process::exit(1);
+            .map(|&o| o.to_os_string())
+            .collect::<Vec<ffi::OsString>>();
+        thread::spawn(move || {
+            let fuse_options = fuse_options
+                .iter()
+                .map(|o| o.as_os_str())
+                .collect::<Vec<&ffi::OsStr>>();
+            let result = fuse_mt::mount(
+                fuse_mt::FuseMT::new(mediafs, 1),
+                &path,
+                fuse_options.as_slice(),
+            );
+            tx.send(Stop::Unmounted(result)).ok();
+        })
+    };
+
+    match rx.recv() {
+        Ok(Stop::Unmounted(Ok(()))) => {}
+        Ok(Stop::Unmounted(Err(e))) => {
+            error!("Failed to mount media file system: {}", e);
+            process::exit(1);
+        }
+        Ok(Stop::Unavailable) | Err(_) => {
+            // Wait for the session to end so that the file system is dropped
+            match watchdog::unmount(mount_point.as_ref()) {
+                Ok(()) => {
+                    session.join().ok();
+                }
+                Err(e) => warn!("Failed to unmount: {}", e),
+            }
+            process::exit(1);
+        }
+    }
+}
+
+/// The reason a mounted file system stopped.
+enum Stop {
+    /// The file system was unmounted, or could not be mounted.
+    Unmounted(io::Result<()>),
+
+    /// The mount point is no longer available.
+    Unavailable,
+}
+
+/// Mounts the file system in the background and verifies that all write
+/// operations are rejected.
+///
+/// The result of every check is printed, and the exit code is returned.
+///
+/// # Arguments
+/// *  `mediafs` - The file system to mount.
+/// *  `mount_point` - The mount point.
+/// *  `fuse_options` - Options passed to FUSE.
+fn self_check(
+    mediafs: files::MediaFS,
+    mount_point: &types::MountPoint,
+    fuse_options: &[&ffi::OsStr],
+) -> i32 {
+    let session = unsafe {
+        fuse_mt::spawn_mount(
+            fuse_mt::FuseMT::new(mediafs, 1),
+            mount_point,
+            fuse_options,
+        )
+    };
+    match session {
+        Ok(session) => {
+            let results = selfcheck::run(mount_point.as_ref());
+            drop(session);
+
+            for &(name, ref result) in results.iter() {
+                match *result {
+                    Ok(()) => println!("{}: ok", name),
+                    Err(ref e) => println!("{}: FAILED ({})", name, e),
+                }
+            }
+            if results.iter().all(|(_, result)| result.is_ok()) {
+                println!("Self-check passed");
+                0
+            } else {
+                println!("Self-check failed");
+                1
+            }
+        }
+        Err(e) => {
+            error!("Failed to mount media file system: {}", e);
+            1
+        }
     }
 }
+
+/// Verifies that reading a sample of files through the file system yields
+/// the content of their source files.
+///
+/// The result of every check is printed, and the exit code is returned. The
+/// verification fails if no files were verified.
+///
+/// # Arguments
+/// *  `mediafs` - The file system.
+/// *  `cache` - The cache backing the file system.
+fn verify_reads(mediafs: &files::MediaFS, cache: &files::Cache) -> i32 {
+    let results =
+        selfcheck::verify_reads(mediafs, cache, selfcheck::DEFAULT_READ_SAMPLE);
+    for (path, result) in results.iter() {
+        match *result {
+            Ok(()) => println!("{}: ok", path.display()),
+            Err(ref e) => println!("{}: FAILED ({})", path.display(), e),
+        }
+    }
+    if results.is_empty() {
+        println!("Read verification failed (no files verified)");
+        1
+    } else if results.iter().all(|(_, result)| result.is_ok()) {
+        println!("Read verification passed");
+        0
+    } else {
+        println!("Read verification failed");
+        1
+    }
+}
+
+/// Reads a filter from a configuration file.
+///
+/// # Arguments
+/// *  `path` - The path of the configuration file.
+fn read_filter(path: &ffi::OsStr) -> Result<data::Filter, String> {
+    fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))
+        .and_then(|s| s.parse())
+}
@@ -0,0 +1,135 @@
+use std::fmt::Write;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path;
+
+use data;
+
+/// The value written for a size or modification time that cannot be read.
+const UNKNOWN: &str = "-";
+
+/// Lists every file in a cache for comparison with an earlier listing.
+///
+/// Every item and link is written on a line of its own on the form
+/// `size<TAB>mtime<TAB>path`, where `size` is the size in bytes and `mtime`
+/// the modification time in seconds since the epoch of the source file, and
+/// `path` is the absolute path in the cache. Attributes of source files that
+/// cannot be read are written as `-`. The lines are sorted by path, so
+/// listings of an unchanged library are identical.
+///
+/// Directories are implied by the paths of their contents, and generated
+/// indices are omitted.
+///
+/// # Arguments
+/// *  `cache` - The cache to list.
+pub fn generate(cache: &data::cache::Cache) -> String {
+    let mut paths = Vec::new();
+    if let Some(entry) = cache.lookup(&"/") {
+        collect(path::Path::new("/"), entry, &mut paths);
+    }
+    paths.sort_by(|a, b| {
+        a.as_os_str().as_bytes().cmp(b.as_os_str().as_bytes())
+    });
+
+    let mut result = String::new();
+    for path in paths {
+        let metadata = cache
+            .resolve(&path)
+            .and_then(|item| fs::metadata(&item.path).ok());
+        let (size, mtime) = match metadata {
+            Some(metadata) => {
+                (metadata.len().to_string(), metadata.mtime().to_string())
+            }
+            None => (UNKNOWN.into(), UNKNOWN.into()),
+        };
+        writeln!(result, "{}\t{}\t{}", size, mtime, path.display()).unwrap();
+    }
+    result
+}
+
+/// Collects the paths of all items and links below an entry.
+///
+/// # Arguments
+/// *  `path` - The path of the entry.
+/// *  `entry` - The entry.
+/// *  `paths` - The collected paths.
+fn collect(
+    path: &path::Path,
+    entry: &data::cache::Entry,
+    paths: &mut Vec<path::PathBuf>,
+) {
+    match *entry {
+        data::cache::Entry::Directory(_, ref tree) => {
+            for (name, child) in tree.iter() {
+                collect(&path.join(name), child, paths);
+            }
+        }
+        data::cache::Entry::Item(_) | data::cache::Entry::Link(_, _) => {
+            paths.push(path.to_path_buf())
+        }
+        data::cache::Entry::Sidecar(_, _) | data::cache::Entry::Index(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir;
+
+    use data::tests::*;
+
+    use super::*;
+
+    /// Tests that the listing includes items and links with the attributes
+    /// of their source files.
+    #[test]
+    fn listing() {
+        let root = tempdir::TempDir::new("medifs-manifest").unwrap();
+        let mut item =
+            item_with_data(root.path().join("a.jpg"), b"data", 2000, 1, 1);
+        item.tags.insert("Family".into());
+        let mtime = fs::metadata(&item.path).unwrap().mtime();
+        let mut cache = data::cache::Cache::new("All".into(), "Tagged".into());
+        cache.add(item).unwrap();
+
+        assert_eq!(
+            format!(
+                "4\t{0}\t/All/2000/01/01/2000-01-01 00:00.jpeg\n\
+                 4\t{0}\t/Tagged/Family/2000-01-01 00:00.jpeg\n",
+                mtime,
+            ),
+            generate(&cache),
+        );
+    }
+
+    /// Tests that listings of an unchanged library are identical regardless
+    /// of the order in which items are found.
+    #[test]
+    fn stable() {
+        let root = tempdir::TempDir::new("medifs-manifest").unwrap();
+        let items = (1..10)
+            .map(|i| {
+                let mut item = item_with_data(
+                    root.path().join(format!("{}.jpg", i)),
+                    &vec![0; i],
+                    2000,
+                    1 + i as i32 % 3,
+                    i as i32,
+                );
+                item.tags.insert(format!("Tag{}", i % 2));
+                item
+            })
+            .collect::<Vec<_>>();
+        let manifest = |items: Vec<data::Item>| {
+            let mut cache =
+                data::cache::Cache::new("All".into(), "Tagged".into());
+            cache.replace_all(items.into_iter()).unwrap();
+            generate(&cache)
+        };
+
+        let first = manifest(items.clone());
+        let second = manifest(items.into_iter().rev().collect());
+        assert_eq!(first, second);
+        assert_eq!(18, first.lines().count());
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+use data;
+
+/// The format of timestamps in a description.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+/// Describes the metadata of an item as read by a source.
+///
+/// Every property is written on a line of its own on the form
+/// `key = value`, like the output of `--print-config`. Properties that are
+/// unknown are omitted.
+///
+/// # Arguments
+/// *  `item` - The item to describe.
+pub fn describe(item: &data::Item) -> String {
+    let mut result = String::new();
+    let mut line = |key: &str, value: &str| {
+        writeln!(result, "{} = {}", key, value).unwrap();
+    };
+
+    line("path", &item.path.display().to_string());
+    line("name", &data::name(item, item, 0).display().to_string());
+    line(
+        "timestamp",
+        &if item.timestamp.is_undated() {
+            item.timestamp.to_string()
+        } else {
+            item.timestamp
+                .as_ref()
+                .strftime(TIMESTAMP_FORMAT)
+                .map(|t| t.to_string())
+                .unwrap_or_default()
+        },
+    );
+    line("date-source", &item.date_source.to_string());
+    line("media-type", item.media_type.as_ref());
+    line("tags", &sorted(item.tags.iter()).join(", "));
+    if !item.people.is_empty() {
+        line("people", &sorted(item.people.iter()).join(", "));
+    }
+    if let Some(rating) = item.rating {
+        line("rating", &rating.to_string());
+    }
+    if let Some(ref camera) = item.camera {
+        line("camera", camera);
+    }
+    if let Some(ref lens) = item.lens {
+        line("lens", lens);
+    }
+    if let Some(ref colorspace) = item.colorspace {
+        line("colorspace", colorspace);
+    }
+    if let Some(ref settings) = item.settings {
+        line("settings", settings);
+    }
+    if let Some(ref altitude) = item.altitude {
+        line("altitude", altitude);
+    }
+    if let Some(ref direction) = item.direction {
+        line("direction", direction);
+    }
+    if let Some((width, height)) = item.dimensions {
+        line("dimensions", &format!("{}x{}", width, height));
+    }
+    if let Some(title) = item.captions.get(&data::Caption::Title) {
+        line("title", title);
+    }
+    if let Some(description) = item.captions.get(&data::Caption::Description)
+    {
+        line("description", description);
+    }
+    if let Some(pages) = item.pages {
+        line("pages", &pages.to_string());
+    }
+
+    result
+}
+
+/// Sorts strings.
+///
+/// # Arguments
+/// *  `values` - The strings to sort.
+fn sorted<'a, I: Iterator<Item = &'a String>>(values: I) -> Vec<&'a str> {
+    let mut result = values.map(String::as_str).collect::<Vec<_>>();
+    result.sort();
+    result
+}
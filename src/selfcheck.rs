@@ -0,0 +1,304 @@
+use std::ffi;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path;
+
+use fuse_mt;
+use fuse_mt::FilesystemMT;
+use libc;
+use walkdir;
+
+use data;
+use files;
+
+/// The name of the entry the self-check attempts to create.
+const CHECK_NAME: &str = ".medifs-self-check";
+
+/// The size of each read when verifying reads; this is the largest read
+/// requested by FUSE by default.
+pub const READ_SIZE: u32 = 128 * 1024;
+
+/// The default number of files whose reads are verified.
+pub const DEFAULT_READ_SAMPLE: usize = 100;
+
+/// The result of a single check.
+pub type CheckResult = Result<(), String>;
+
+/// Attempts all write operations against a mounted file system.
+///
+/// Every operation is expected to fail with [`libc::EROFS`] or
+/// [`libc::EPERM`]. The name of each operation is returned along with its
+/// result.
+///
+/// # Arguments
+/// *  `mount_point` - The mount point of the file system.
+///
+/// [`libc::EROFS`]: https://doc.rust-lang.org/libc/x86_64-unknown-linux-gnu/libc/constant.EROFS.html
+/// [`libc::EPERM`]: https://doc.rust-lang.org/libc/x86_64-unknown-linux-gnu/libc/constant.EPERM.html
+pub fn run(mount_point: &path::Path) -> Vec<(&'static str, CheckResult)> {
+    let target = mount_point.join(CHECK_NAME);
+    let item = walkdir::WalkDir::new(mount_point)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_type().is_file())
+        .map(|e| e.path().to_path_buf());
+    let with_item = |f: &dyn Fn(&path::Path) -> io::Result<()>| {
+        item.as_ref()
+            .map(|item| check(f(item)))
+            .unwrap_or_else(|| Err("no item available to check".into()))
+    };
+
+    vec![
+        ("create", check(fs::File::create(&target).map(|_| ()))),
+        (
+            "write",
+            with_item(&|item| {
+                fs::OpenOptions::new().write(true).open(item).map(|_| ())
+            }),
+        ),
+        ("mkdir", check(fs::create_dir(&target))),
+        ("unlink", with_item(&|item| fs::remove_file(item))),
+        ("truncate", with_item(&truncate)),
+    ]
+}
+
+/// Checks that an operation was rejected as expected.
+///
+/// # Arguments
+/// *  `result` - The result of the operation.
+pub fn check<T>(result: io::Result<T>) -> CheckResult {
+    match result {
+        Ok(_) => Err("operation succeeded".into()),
+        Err(ref e)
+            if e.raw_os_error() == Some(libc::EROFS)
+                || e.raw_os_error() == Some(libc::EPERM) =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(format!("unexpected error: {}", e)),
+    }
+}
+
+/// Verifies that reading files through the file system yields the content of
+/// their source files.
+///
+/// Up to `sample` items, spread evenly over the file system, are opened, read
+/// from start to end and released using the handlers called by FUSE, and
+/// their content is compared with that read directly from the source file.
+/// Display versions of images are skipped, since they are generated. The path
+/// of each file is returned along with its result.
+///
+/// # Arguments
+/// *  `mediafs` - The file system.
+/// *  `cache` - The cache backing the file system.
+/// *  `sample` - The maximum number of files to verify.
+pub fn verify_reads(
+    mediafs: &files::MediaFS,
+    cache: &files::Cache,
+    sample: usize,
+) -> Vec<(path::PathBuf, CheckResult)> {
+    let files = {
+        let cache = cache.read().unwrap();
+        let mut files = Vec::new();
+        if let Some(entry) = cache.lookup(&"/") {
+            collect(&cache, path::Path::new("/"), entry, &mut files);
+        }
+        files.sort();
+        files
+    };
+    let step = (files.len() / sample.max(1)).max(1);
+
+    files
+        .into_iter()
+        .step_by(step)
+        .take(sample)
+        .map(|(path, source)| {
+            let result = verify_read(mediafs, &path, &source);
+            (path, result)
+        })
+        .collect()
+}
+
+/// Collects the paths and source paths of all items below an entry.
+///
+/// # Arguments
+/// *  `cache` - The cache containing the entry.
+/// *  `path` - The path of the entry.
+/// *  `entry` - The entry.
+/// *  `files` - The collected paths.
+fn collect(
+    cache: &data::cache::Cache,
+    path: &path::Path,
+    entry: &data::cache::Entry,
+    files: &mut Vec<(path::PathBuf, path::PathBuf)>,
+) {
+    match *entry {
+        data::cache::Entry::Directory(_, ref tree) => {
+            for (name, child) in tree.iter() {
+                collect(cache, &path.join(name), child, files);
+            }
+        }
+        data::cache::Entry::Item(ref item) if !cache.is_display(path) => {
+            files.push((path.to_path_buf(), item.path.clone()))
+        }
+        _ => {}
+    }
+}
+
+/// Reads a file through the file system and compares it with its source.
+///
+/// # Arguments
+/// *  `mediafs` - The file system.
+/// *  `path` - The path of the file in the file system.
+/// *  `source` - The source file.
+fn verify_read(
+    mediafs: &files::MediaFS,
+    path: &path::Path,
+    source: &path::Path,
+) -> CheckResult {
+    let expected = fs::read(source)
+        .map_err(|e| format!("failed to read source: {}", e))?;
+    let (fh, _) = mediafs
+        .open(request(), path, libc::O_RDONLY as u32)
+        .map_err(|e| format!("failed to open: {}", error(e)))?;
+    let mut actual = Vec::new();
+    let result = loop {
+        match mediafs.read(request(), path, fh, actual.len() as u64, READ_SIZE)
+        {
+            Ok(ref data) if data.is_empty() => break Ok(()),
+            Ok(data) => actual.extend(data),
+            Err(e) => break Err(format!("failed to read: {}", error(e))),
+        }
+    };
+    mediafs
+        .release(request(), path, fh, 0, 0, false)
+        .map_err(|e| format!("failed to release: {}", error(e)))?;
+    result?;
+
+    if let Some(offset) =
+        expected.iter().zip(actual.iter()).position(|(a, b)| a != b)
+    {
+        Err(format!("content differs at offset {}", offset))
+    } else if expected.len() != actual.len() {
+        Err(format!(
+            "read {} bytes, expected {}",
+            actual.len(),
+            expected.len(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// The request passed to the file system handlers when verifying reads.
+fn request() -> fuse_mt::RequestInfo {
+    fuse_mt::RequestInfo {
+        unique: 0,
+        uid: unsafe { libc::getuid() },
+        gid: unsafe { libc::getgid() },
+        pid: 0,
+    }
+}
+
+/// Describes an error returned by a file system handler.
+///
+/// # Arguments
+/// *  `errno` - The error number.
+fn error(errno: libc::c_int) -> io::Error {
+    io::Error::from_raw_os_error(errno)
+}
+
+/// Truncates a file without opening it.
+///
+/// # Arguments
+/// *  `path` - The file to truncate.
+fn truncate(path: &path::Path) -> io::Result<()> {
+    let path = ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if unsafe { libc::truncate(path.as_ptr(), 0) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync;
+
+    use tempdir;
+
+    use data::tests::*;
+    use files::tests::MockSource;
+
+    use super::*;
+
+    /// Tests that only rejections are accepted.
+    #[test]
+    fn check_errors() {
+        assert!(check(Err::<(), _>(io::Error::from_raw_os_error(libc::EROFS)))
+            .is_ok());
+        assert!(check(Err::<(), _>(io::Error::from_raw_os_error(libc::EPERM)))
+            .is_ok());
+        assert!(
+            check(Err::<(), _>(io::Error::from_raw_os_error(libc::ENOSYS)))
+                .is_err()
+        );
+        assert!(check(Ok(())).is_err());
+    }
+
+    /// Tests that reads of files of varying sizes, including files larger
+    /// than a single read, are verified, and that failures are reported.
+    #[test]
+    fn verify_reads_sizes() {
+        let source_dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        let sizes = [
+            0,
+            1,
+            4096,
+            READ_SIZE as usize - 1,
+            READ_SIZE as usize,
+            READ_SIZE as usize + 1,
+            3 * READ_SIZE as usize + 12345,
+        ];
+        for (i, size) in sizes.iter().enumerate() {
+            let data =
+                (0..*size).map(|j| (j * 31 + i) as u8).collect::<Vec<_>>();
+            cache
+                .write()
+                .unwrap()
+                .add(item_with_data(
+                    source_dir.path().join(format!("{}.jpg", i)),
+                    &data,
+                    2000,
+                    1,
+                    1 + i as i32,
+                ))
+                .unwrap();
+        }
+        let mediafs = files::MediaFS::new(
+            cache.clone(),
+            files::Source::new(sync::RwLock::new(Box::new(MockSource {}))),
+        );
+        let empty = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        assert!(verify_reads(&mediafs, &empty, DEFAULT_READ_SAMPLE).is_empty());
+
+        let results = verify_reads(&mediafs, &cache, DEFAULT_READ_SAMPLE);
+        assert_eq!(sizes.len(), results.len());
+        for (path, result) in results {
+            assert_eq!(Ok(()), result, "{}", path.display());
+        }
+
+        assert_eq!(2, verify_reads(&mediafs, &cache, 2).len());
+
+        fs::remove_file(source_dir.path().join("0.jpg")).unwrap();
+        let results = verify_reads(&mediafs, &cache, DEFAULT_READ_SAMPLE);
+        assert_eq!(1, results.iter().filter(|&(_, r)| r.is_err()).count());
+    }
+}
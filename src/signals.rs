@@ -0,0 +1,42 @@
+use std::mem;
+use std::ptr;
+use std::thread;
+
+use libc;
+
+/// Generates a signal set containing only `SIGHUP`.
+fn hangup() -> libc::sigset_t {
+    unsafe {
+        let mut result: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut result);
+        libc::sigaddset(&mut result, libc::SIGHUP);
+        result
+    }
+}
+
+/// Calls a function every time the process receives `SIGHUP`.
+///
+/// This function blocks `SIGHUP` for the calling thread and spawns a thread
+/// waiting for the signal. Since the signal mask is inherited, this function
+/// should be called before any other threads are spawned, or the signal may
+/// be delivered to a thread not waiting for it.
+///
+/// # Arguments
+/// *  `callback` - The function to call.
+pub fn on_hangup<F>(callback: F) -> thread::JoinHandle<()>
+where
+    F: Fn() + Send + 'static,
+{
+    let signals = hangup();
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, ptr::null_mut());
+    }
+
+    thread::spawn(move || loop {
+        let mut signal = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+            debug!("Received signal {}", signal);
+            callback();
+        }
+    })
+}
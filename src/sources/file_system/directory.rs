@@ -32,13 +32,27 @@ impl ConfigurableSource for DirectorySource {
 impl ConstructableSource for DirectorySource {
     fn construct<'a>(
         cache: files::Cache,
+        filter: data::SharedFilter,
         args: &clap::ArgMatches<'a>,
     ) -> Result<Self, String> {
-        let root = args.value_of(OPT_ROOT).map(|v| v.into()).unwrap();
+        let root = root(args)?;
+        let tag_separator = cache.read().unwrap().tag_separator();
         Ok(DirectorySource {
             cache,
             root,
-            timestamp: None,
+            timestamp: std::sync::Mutex::new(None),
+            scanning: std::sync::atomic::AtomicBool::new(false),
+            reload_pending: std::sync::atomic::AtomicBool::new(false),
+            filter,
+            tags_from_path: args.is_present(OPT_TAGS_FROM_PATH),
+            scan_depth: scan_depth(args)?,
+            limit: limit(args)?,
+            skip_hidden: args.is_present(OPT_SKIP_HIDDEN),
+            tag_aliases: tag_aliases(args)?,
+            tag_separator,
+            undated: undated(args),
+            min_date: min_date(args)?,
+            progress: progress(args)?,
         })
     }
 }
@@ -7,7 +7,19 @@ macro_rules! file_system_base {
         pub struct $source_type {
             root: path::PathBuf,
             cache: files::Cache,
-            timestamp: Option<std::time::SystemTime>,
+            timestamp: std::sync::Mutex<Option<std::time::SystemTime>>,
+            scanning: std::sync::atomic::AtomicBool,
+            reload_pending: std::sync::atomic::AtomicBool,
+            filter: data::SharedFilter,
+            tags_from_path: bool,
+            scan_depth: Option<usize>,
+            limit: Option<usize>,
+            skip_hidden: bool,
+            tag_aliases: std::collections::HashMap<String, String>,
+            tag_separator: char,
+            undated: Undated,
+            min_date: Option<MinDate>,
+            progress: Option<ProgressReporter>,
             $($field_name: $field_type,)*
         }
 
@@ -16,8 +28,60 @@ macro_rules! file_system_base {
                 &self.cache
             }
 
-            fn timestamp(&mut self) -> &mut Option<std::time::SystemTime> {
-                &mut self.timestamp
+            fn timestamp(
+                &self,
+            ) -> &std::sync::Mutex<Option<std::time::SystemTime>> {
+                &self.timestamp
+            }
+
+            fn scanning(&self) -> &std::sync::atomic::AtomicBool {
+                &self.scanning
+            }
+
+            fn reload_pending(&self) -> &std::sync::atomic::AtomicBool {
+                &self.reload_pending
+            }
+
+            fn filter(&self) -> &data::SharedFilter {
+                &self.filter
+            }
+
+            fn tags_from_path(&self) -> bool {
+                self.tags_from_path
+            }
+
+            fn scan_depth(&self) -> Option<usize> {
+                self.scan_depth
+            }
+
+            fn limit(&self) -> Option<usize> {
+                self.limit
+            }
+
+            fn skip_hidden(&self) -> bool {
+                self.skip_hidden
+            }
+
+            fn tag_aliases(
+                &self,
+            ) -> &std::collections::HashMap<String, String> {
+                &self.tag_aliases
+            }
+
+            fn tag_separator(&self) -> char {
+                self.tag_separator
+            }
+
+            fn undated(&self) -> Undated {
+                self.undated
+            }
+
+            fn min_date(&self) -> Option<&MinDate> {
+                self.min_date.as_ref()
+            }
+
+            fn progress(&self) -> Option<&ProgressReporter> {
+                self.progress.as_ref()
             }
 
             fn root(&self) -> &path::PathBuf {
@@ -1,9 +1,14 @@
 use std;
+use std::cell;
 use std::collections;
+use std::ffi;
+use std::os::unix::fs::MetadataExt;
 use std::path;
+use std::sync::atomic;
 
 use clap;
 use mime_guess;
+use time;
 use walkdir;
 
 use data;
@@ -15,24 +20,435 @@ mod macros;
 mod directory;
 pub use self::directory::*;
 
+#[cfg(feature = "tags")]
+mod pages;
+
+mod progress;
+pub use self::progress::*;
+
+#[cfg(feature = "tags")]
 mod tags;
+#[cfg(feature = "tags")]
 pub use self::tags::*;
 
 /// The name of the argument specifying the root.
 const OPT_ROOT: &'static str = &"ROOT";
 
+/// The name of the argument limiting the depth of the scan.
+const OPT_SCAN_DEPTH: &str = "SCAN_DEPTH";
+
+/// The name of the argument limiting the number of items.
+const OPT_LIMIT: &str = "LIMIT";
+
+/// The name of the argument excluding hidden files and directories.
+const OPT_SKIP_HIDDEN: &str = "SKIP_HIDDEN";
+
+/// The name of the argument specifying tag aliases.
+const OPT_TAG_ALIAS: &str = "TAG_ALIAS";
+
+/// The name of the argument specifying a file of tag aliases.
+const OPT_TAG_ALIASES: &str = "TAG_ALIASES";
+
+/// The name of the argument specifying how to handle undated items.
+const OPT_UNDATED: &str = "UNDATED";
+
+/// The name of the argument specifying the earliest plausible date of items.
+const OPT_MIN_DATE: &str = "MIN_DATE";
+
+/// The name of the argument specifying how to handle items dated before the
+/// earliest plausible date.
+const OPT_TOO_OLD: &str = "TOO_OLD";
+
+/// The format of the earliest plausible date of items.
+const MIN_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The name of the argument specifying the file to which to report progress.
+const OPT_PROGRESS_FILE: &str = "PROGRESS_FILE";
+
+/// The name of the argument enabling tags derived from directory names.
+const OPT_TAGS_FROM_PATH: &str = "TAGS_FROM_PATH";
+
+/// The name of the environment variable specifying the default root.
+const ENV_ROOT: &str = "MEDIFS_ROOT";
+
 /// Adds the base options for a file system source.
 ///
 /// # Arguments
 /// *  `app` - The application to which to add the arguments.
 fn options<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
-    app.arg(
-        clap::Arg::with_name(OPT_ROOT)
-            .help("The source directory.")
-            .required(true),
+    app.arg(clap::Arg::with_name(OPT_ROOT).help(
+        "The source directory. If not specified, MEDIFS_ROOT is used.",
+    )).arg(
+        clap::Arg::with_name(OPT_TAGS_FROM_PATH)
+            .help(concat!(
+                "Tags items with the names of the directories containing ",
+                "them, relative to the source directory.",
+            ))
+            .long("tags-from-path"),
+    ).arg(
+        clap::Arg::with_name(OPT_SCAN_DEPTH)
+            .help(concat!(
+                "The maximum depth of directories to scan; 1 includes only ",
+                "files directly in the source directory.",
+            ))
+            .long("scan-depth")
+            .takes_value(true),
+    ).arg(
+        clap::Arg::with_name(OPT_LIMIT)
+            .help(concat!(
+                "The maximum number of items to add. Files are considered ",
+                "in order of their paths, so the same items are selected ",
+                "every time.",
+            ))
+            .long("limit")
+            .takes_value(true),
+    ).arg(
+        clap::Arg::with_name(OPT_SKIP_HIDDEN)
+            .help(concat!(
+                "Skips hidden files and directories, that is those whose ",
+                "names start with \".\".",
+            ))
+            .long("skip-hidden"),
+    ).arg(
+        clap::Arg::with_name(OPT_TAG_ALIAS)
+            .help(concat!(
+                "Replaces a tag with another, for example ",
+                "\"Holiday=Vacation\". Nested tags are replaced as well. ",
+                "This may be repeated.",
+            ))
+            .long("tag-alias")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1),
+    ).arg(
+        clap::Arg::with_name(OPT_TAG_ALIASES)
+            .help(concat!(
+                "A file with one tag alias on the form used by --tag-alias ",
+                "per line. Empty lines and lines starting with # are ",
+                "ignored.",
+            ))
+            .long("tag-aliases")
+            .takes_value(true),
+    ).arg(
+        clap::Arg::with_name(OPT_UNDATED)
+            .help(concat!(
+                "How to handle items whose date cannot be read; now files ",
+                "them under the current date, bucket under a separate ",
+                "undated directory and drop excludes them.",
+            ))
+            .long("undated")
+            .takes_value(true)
+            .possible_values(&["now", "bucket", "drop"])
+            .default_value("now"),
+    ).arg(
+        clap::Arg::with_name(OPT_MIN_DATE)
+            .help(concat!(
+                "The earliest plausible date of items, on the form ",
+                "YYYY-MM-DD. Items dated before it, such as scans carrying ",
+                "a default date of 1904 or 1970, are handled as specified ",
+                "by --too-old.",
+            ))
+            .long("min-date")
+            .takes_value(true),
+    ).arg(
+        clap::Arg::with_name(OPT_TOO_OLD)
+            .help(concat!(
+                "How to handle items dated before --min-date; clamp files ",
+                "them under their modification time, or handles them as ",
+                "undated if it is also too early, and drop excludes them.",
+            ))
+            .long("too-old")
+            .takes_value(true)
+            .possible_values(&["clamp", "drop"])
+            .default_value("clamp"),
+    ).arg(
+        clap::Arg::with_name(OPT_PROGRESS_FILE)
+            .help(concat!(
+                "A file to which the progress of scans is written as one ",
+                "JSON object per line. Use /dev/fd/N to write to an open ",
+                "file descriptor.",
+            ))
+            .long("progress-file")
+            .takes_value(true),
     )
 }
 
+/// The handling of items whose time of generation is unknown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Undated {
+    /// The items are given the current time.
+    Now,
+
+    /// The items are kept undated, and are thus added to the undated
+    /// directory.
+    Bucket,
+
+    /// The items are excluded.
+    Drop,
+}
+
+impl Undated {
+    /// Applies this handling to an item.
+    ///
+    /// Items that are not undated are returned unchanged.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    fn apply(self, item: data::Item) -> Option<data::Item> {
+        if !item.timestamp.is_undated() {
+            return Some(item);
+        }
+        match self {
+            Undated::Now => Some(data::Item {
+                timestamp: std::time::SystemTime::now().into(),
+                date_source: data::DateSource::Modified,
+                ..item
+            }),
+            Undated::Bucket => Some(item),
+            Undated::Drop => None,
+        }
+    }
+}
+
+/// The handling of items dated before the earliest plausible date.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TooOld {
+    /// The items are given the modification time of their source file, or
+    /// are made undated if that is also before the earliest plausible date.
+    Clamp,
+
+    /// The items are excluded.
+    Drop,
+}
+
+/// The earliest plausible date of items, and the handling of items dated
+/// before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MinDate {
+    /// The earliest plausible date.
+    date: data::Timestamp,
+
+    /// The handling of items dated before `date`.
+    too_old: TooOld,
+}
+
+impl MinDate {
+    /// Applies this limit to an item.
+    ///
+    /// Undated items and items dated on or after the limit are returned
+    /// unchanged. Clamped items whose modification time is also before the
+    /// limit are returned undated, to be handled as such.
+    ///
+    /// # Arguments
+    /// *  `item` - The item.
+    fn apply(&self, item: data::Item) -> Option<data::Item> {
+        if item.timestamp.is_undated() || item.timestamp >= self.date {
+            return Some(item);
+        }
+        match self.too_old {
+            TooOld::Clamp => Some(data::Item {
+                timestamp: data::timestamp(&item.path)
+                    .map(data::Timestamp::from)
+                    .filter(|timestamp| timestamp >= &self.date)
+                    .unwrap_or_else(data::Timestamp::undated),
+                date_source: data::DateSource::Modified,
+                ..item
+            }),
+            TooOld::Drop => None,
+        }
+    }
+}
+
+/// Reads the earliest plausible date of items and the handling of items
+/// dated before it from command line arguments.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn min_date<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<Option<MinDate>, String> {
+    args.value_of(OPT_MIN_DATE)
+        .map(|value| {
+            time::strptime(value, MIN_DATE_FORMAT)
+                .map(|date| MinDate {
+                    date: date.into(),
+                    too_old: match args.value_of(OPT_TOO_OLD) {
+                        Some("drop") => TooOld::Drop,
+                        _ => TooOld::Clamp,
+                    },
+                })
+                .map_err(|e| format!("invalid minimum date {}: {}", value, e))
+        })
+        .map_or(Ok(None), |min_date| min_date.map(Some))
+}
+
+/// Reads the handling of undated items from command line arguments.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn undated<'a>(args: &clap::ArgMatches<'a>) -> Undated {
+    match args.value_of(OPT_UNDATED) {
+        Some("bucket") => Undated::Bucket,
+        Some("drop") => Undated::Drop,
+        _ => Undated::Now,
+    }
+}
+
+/// Reads the root directory from command line arguments.
+///
+/// If no root is passed, the value of the environment variable [`ENV_ROOT`]
+/// is used instead.
+///
+/// The root is canonicalized, so that the paths of all items are absolute.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+///
+/// [`ENV_ROOT`]: constant.ENV_ROOT.html
+fn root<'a>(args: &clap::ArgMatches<'a>) -> Result<path::PathBuf, String> {
+    root_from(args, |name| std::env::var_os(name)).and_then(|root| {
+        root.canonicalize()
+            .map_err(|e| format!("{}: {}", root.display(), e))
+    })
+}
+
+/// Reads the root directory from command line arguments, falling back on an
+/// environment variable read with `var`.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+/// *  `var` - A function returning the value of an environment variable.
+fn root_from<'a, F>(
+    args: &clap::ArgMatches<'a>,
+    var: F,
+) -> Result<path::PathBuf, String>
+where
+    F: Fn(&str) -> Option<ffi::OsString>,
+{
+    args.value_of_os(OPT_ROOT)
+        .map(path::PathBuf::from)
+        .or_else(|| var(ENV_ROOT).map(path::PathBuf::from))
+        .ok_or_else(|| {
+            format!(
+                "no source root specified; pass {} or set {}",
+                OPT_ROOT, ENV_ROOT
+            )
+        })
+}
+
+/// Reads the progress reporter from command line arguments.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn progress<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<Option<ProgressReporter>, String> {
+    args.value_of_os(OPT_PROGRESS_FILE)
+        .map(|path| {
+            json_lines(path.as_ref()).map_err(|e| {
+                format!("{}: {}", path.to_string_lossy(), e)
+            })
+        })
+        .map_or(Ok(None), |reporter| reporter.map(Some))
+}
+
+/// Reads the maximum scan depth from command line arguments.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn scan_depth<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<Option<usize>, String> {
+    args.value_of(OPT_SCAN_DEPTH)
+        .map(|value| {
+            value
+                .parse()
+                .ok()
+                .filter(|&depth| depth > 0)
+                .ok_or_else(|| format!("invalid scan depth: {}", value))
+        })
+        .map_or(Ok(None), |depth| depth.map(Some))
+}
+
+/// Reads the maximum number of items from command line arguments.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn limit<'a>(args: &clap::ArgMatches<'a>) -> Result<Option<usize>, String> {
+    args.value_of(OPT_LIMIT)
+        .map(|value| {
+            value
+                .parse()
+                .ok()
+                .filter(|&limit| limit > 0)
+                .ok_or_else(|| format!("invalid limit: {}", value))
+        })
+        .map_or(Ok(None), |limit| limit.map(Some))
+}
+
+/// Reads the tag aliases from command line arguments.
+///
+/// Aliases are read from the alias file first, and then from the individual
+/// arguments, so the latter take precedence.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn tag_aliases<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<collections::HashMap<String, String>, String> {
+    let lines = match args.value_of_os(OPT_TAG_ALIASES) {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?,
+        None => String::new(),
+    };
+    lines
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .chain(args.values_of(OPT_TAG_ALIAS).into_iter().flatten())
+        .map(|alias| {
+            let mut parts = alias.splitn(2, '=').map(str::trim);
+            match (parts.next(), parts.next()) {
+                (Some(from), Some(to))
+                    if !from.is_empty() && !to.is_empty() =>
+                {
+                    Ok((from.to_string(), to.to_string()))
+                }
+                _ => Err(format!("invalid tag alias: {}", alias)),
+            }
+        })
+        .collect()
+}
+
+/// Replaces a tag with its alias.
+///
+/// A tag nested under an aliased tag has its aliased part replaced. Tags
+/// without an alias are returned unchanged.
+///
+/// # Arguments
+/// *  `aliases` - The tag aliases.
+/// *  `tag` - The tag to replace.
+/// *  `separator` - The separator between the levels of tags.
+fn alias(
+    aliases: &collections::HashMap<String, String>,
+    tag: String,
+    separator: char,
+) -> String {
+    let parts = data::Tag::with_separator(&tag, separator).parts;
+    let separator = separator.to_string();
+    (1..parts.len() + 1)
+        .rev()
+        .filter_map(|length| {
+            aliases.get(&parts[..length].join(&separator)).map(|to| {
+                parts[length..]
+                    .iter()
+                    .fold(to.clone(), |acc, part| acc + &separator + part)
+            })
+        })
+        .next()
+        .unwrap_or(tag)
+}
+
 /// Generates an item from a path.
 ///
 /// This trait must be implemented by file system sources.
@@ -42,31 +458,261 @@ pub trait FileSystemItemGenerator {
     /// # Arguments
     /// *  `path` - The path for which to generate an item.
     fn item(&self, path: &path::Path) -> data::Item;
+
+    /// Discards any state kept for paths no longer present in the source.
+    ///
+    /// This is called at the end of each scan. The default implementation
+    /// does nothing.
+    ///
+    /// # Arguments
+    /// *  `paths` - The paths found during the scan.
+    fn retain(&self, _paths: &collections::HashSet<path::PathBuf>) {}
+
+    /// Lists settings specific to this kind of source.
+    ///
+    /// These are appended to the settings common to all file system sources.
+    /// The default implementation returns no settings.
+    fn settings(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
 }
 
 pub trait FileSystemSource: super::Source + FileSystemItemGenerator {
     /// Populates the cache with items from this source.
     ///
-    /// This method will completely replace the items.
+    /// This method will completely replace the items. Items that cannot be
+    /// added are reported and skipped, as are items not passing the filter
+    /// and empty files. Files that cannot be read are reported and skipped,
+    /// so that no entries exist that cannot be opened. If requested, hidden
+    /// files and the contents of hidden directories are skipped as well.
+    ///
+    /// Empty and unreadable files, as well as undated items dropped by the
+    /// undated policy but otherwise passing the filter, are passed to the
+    /// cache as problems once the items have been replaced, so that they can
+    /// be linked from the quarantine.
+    ///
+    /// The identities used to detect duplicates are calculated along with
+    /// the items. Source files are read without holding the cache lock,
+    /// which is held only while the items are replaced.
     ///
-    /// # Panics
-    /// If an item fails to be added.
+    /// All candidate files are listed before any item is generated, so that
+    /// progress can be reported as the number of files read of the total.
+    ///
+    /// If the number of items is limited, candidates are considered in order
+    /// of their paths, and the scan stops once the limit is reached.
     fn populate(&self) {
+        let filter = match self.filter().read() {
+            Ok(filter) => filter.clone(),
+            Err(_) => return,
+        };
+        let identifier = match self.cache().read() {
+            Ok(cache) => cache.identifier(),
+            Err(_) => return,
+        };
+        let (found, skipped, errors) =
+            (cell::Cell::new(0), cell::Cell::new(0), cell::Cell::new(0));
+        let scanned = cell::RefCell::new(collections::HashSet::new());
+        let listed = cell::RefCell::new(collections::HashSet::new());
+        let problems = cell::RefCell::new(Vec::new());
+        let problem = |problem, path: &path::Path| {
+            problems.borrow_mut().push((problem, path.to_path_buf()));
+        };
+
+        // Report errors when listing and ignore non-image files
+        let walker = walkdir::WalkDir::new(self.root());
+        let walker = match self.scan_depth() {
+            Some(depth) => walker.max_depth(depth),
+            None => walker,
+        };
+        let skip_hidden = self.skip_hidden();
+        let mut candidates = walker
+            .into_iter()
+            .filter_entry(|e| {
+                let hidden = skip_hidden && e.depth() > 0 && hidden(e);
+                if hidden {
+                    trace!("Skipping hidden {}", e.path().display());
+                    skipped.set(skipped.get() + 1);
+                }
+                !hidden
+            })
+            .filter_map(|e| {
+                e.map_err(|e| {
+                    warn!("Failed to scan: {}", e);
+                    errors.set(errors.get() + 1);
+                }).ok()
+            })
+            .filter(|e| !e.file_type().is_dir())
+            .filter_map(|e| {
+                listed.borrow_mut().insert(e.path().to_path_buf());
+                let image = mime_guess::from_path(e.path())
+                    .first_or_octet_stream()
+                    .type_() == "image";
+                let metadata = e.metadata().ok();
+                let size = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+                let inode = metadata.map(|m| (m.dev(), m.ino()));
+                let empty = size == 0;
+                if !image || empty {
+                    trace!("Skipping {}", e.path().display());
+                    skipped.set(skipped.get() + 1);
+                }
+                if image && empty {
+                    problem(data::cache::Problem::Empty, e.path());
+                }
+                if image && !empty {
+                    Some((e, size, inode))
+                } else {
+                    None
+                }
+            })
+            .filter(|(e, _, _)| match std::fs::File::open(e.path()) {
+                Ok(_) => true,
+                Err(err) => {
+                    warn!("Cannot read {}: {}", e.path().display(), err);
+                    skipped.set(skipped.get() + 1);
+                    problem(data::cache::Problem::Unreadable, e.path());
+                    false
+                }
+            })
+            .collect::<Vec<_>>();
+        if self.limit().is_some() {
+            candidates.sort_by(|a, b| a.0.path().cmp(b.0.path()));
+        }
+
+        // Sidecars are found among the files listed, and the cache decides
+        // which missing items to retain from them, so that neither requires
+        // further file access
+        let listed = listed.into_inner();
+
+        let total = candidates.len();
+        let report = |scanned, done| {
+            if let Some(progress) = self.progress() {
+                progress(&Progress {
+                    scanned,
+                    total,
+                    found: found.get(),
+                    done,
+                });
+            }
+        };
+        let items = candidates
+            .into_iter()
+            .enumerate()
+            .map(|(index, (e, size, inode))| {
+                scanned.borrow_mut().insert(e.path().to_path_buf());
+                let mut item = self.generate(e.path());
+                item.size = Some(size);
+                item.inode = inode;
+                item.sidecars =
+                    data::sidecars(e.path(), |p| listed.contains(p));
+                report(index + 1, false);
+                item
+            })
+            .filter_map(|item| {
+                let path = item.path.clone();
+                let item = match self.min_date() {
+                    Some(min_date) => match min_date.apply(item) {
+                        Some(item) => item,
+                        None => {
+                            trace!("Dropping old {}", path.display());
+                            skipped.set(skipped.get() + 1);
+                            return None;
+                        }
+                    },
+                    None => item,
+                };
+                // Undated items kept by the undated policy are not problems
+                if item.timestamp.is_undated()
+                    && self.undated() == Undated::Drop
+                    && filter.matches(&item)
+                {
+                    problem(data::cache::Problem::NoDate, &path);
+                }
+                let result = self.undated().apply(item);
+                if result.is_none() {
+                    trace!("Dropping undated {}", path.display());
+                    skipped.set(skipped.get() + 1);
+                }
+                result
+            })
+            .filter(|item| {
+                let included = filter.matches(item);
+                if included {
+                    found.set(found.get() + 1);
+                } else {
+                    trace!("Filtering {}", item.path.display());
+                    skipped.set(skipped.get() + 1);
+                }
+                included
+            })
+            .take(self.limit().unwrap_or(usize::MAX))
+            .map(|mut item| {
+                item.identity = identifier.key(&item);
+                item
+            })
+            .collect::<Vec<_>>();
+
+        // The source files have been read, so the cache is locked only while
+        // its items are replaced
         if let Ok(mut cache) = self.cache().write() {
-            // Ignore errors when listing and ignore non-image files
-            cache
-                .replace_all(
-                    walkdir::WalkDir::new(self.root())
-                        .into_iter()
-                        .filter_map(|e| e.ok())
-                        .filter(|e| {
-                            mime_guess::guess_mime_type(e.path()).type_()
-                                == "image"
-                        })
-                        .map(|e| self.item(e.path())),
-                )
-                .unwrap();
+            if let Err(items) =
+                cache.replace_listed(items.into_iter(), &listed)
+            {
+                for item in items {
+                    warn!("Failed to add {}", item.path.display());
+                    errors.set(errors.get() + 1);
+                }
+            }
+            for (problem, path) in problems.into_inner() {
+                cache.add_problem(problem, &path);
+            }
         }
+        report(total, true);
+        self.retain(&scanned.into_inner());
+
+        debug!(
+            "Scanned {}: {} found, {} skipped, {} errors",
+            self.root().display(),
+            found.get(),
+            skipped.get(),
+            errors.get()
+        );
+    }
+
+    /// Generates the item for a source file.
+    ///
+    /// The item is generated by the item generator, and then given its path
+    /// relative to the root, its dimensions read from the image header unless
+    /// known from its metadata, the tag derived from its directory if
+    /// enabled, and has its tags replaced by their aliases.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the source file.
+    fn generate(&self, path: &path::Path) -> data::Item {
+        let mut item = self.item(path);
+        if item.dimensions.is_none() {
+            item.dimensions = data::dimensions(path);
+        }
+        item.rel_path = path.strip_prefix(self.root())
+            .ok()
+            .map(path::PathBuf::from);
+        if self.tags_from_path() {
+            let tag = item.rel_path
+                .as_ref()
+                .and_then(|p| p.parent())
+                .map(data::Tag::from)
+                .filter(|t| !t.parts.is_empty());
+            if let Some(tag) = tag {
+                item.tags.insert(tag.join(self.tag_separator()));
+            }
+        }
+        if !self.tag_aliases().is_empty() {
+            let separator = self.tag_separator();
+            item.tags = item.tags
+                .drain()
+                .map(|tag| alias(self.tag_aliases(), tag, separator))
+                .collect();
+        }
+        item
     }
 
     /// The cache.
@@ -75,7 +721,45 @@ pub trait FileSystemSource: super::Source + FileSystemItemGenerator {
     /// The timestamp of the last refresh.
     ///
     /// The timestamp is taken from the root directory.
-    fn timestamp(&mut self) -> &mut Option<std::time::SystemTime>;
+    fn timestamp(&self) -> &std::sync::Mutex<Option<std::time::SystemTime>>;
+
+    /// A flag indicating whether a scan is currently in progress.
+    fn scanning(&self) -> &atomic::AtomicBool;
+
+    /// A flag indicating whether a reload has been requested but not yet
+    /// started.
+    fn reload_pending(&self) -> &atomic::AtomicBool;
+
+    /// The filter selecting which items to add.
+    fn filter(&self) -> &data::SharedFilter;
+
+    /// Whether to tag items with the names of their parent directories.
+    fn tags_from_path(&self) -> bool;
+
+    /// The maximum depth of directories to scan, where `1` includes only
+    /// files directly in the root.
+    fn scan_depth(&self) -> Option<usize>;
+
+    /// The maximum number of items to add, if limited.
+    fn limit(&self) -> Option<usize>;
+
+    /// Whether to skip hidden files and directories.
+    fn skip_hidden(&self) -> bool;
+
+    /// The tag aliases, mapping tags to the tags replacing them.
+    fn tag_aliases(&self) -> &collections::HashMap<String, String>;
+
+    /// The separator between the levels of hierarchical tags.
+    fn tag_separator(&self) -> char;
+
+    /// The handling of items whose time of generation is unknown.
+    fn undated(&self) -> Undated;
+
+    /// The earliest plausible date of items, if limited.
+    fn min_date(&self) -> Option<&MinDate>;
+
+    /// The receiver of the progress of scans, if any.
+    fn progress(&self) -> Option<&ProgressReporter>;
 
     /// The directory root from which to load items.
     fn root(&self) -> &path::PathBuf;
@@ -89,39 +773,1329 @@ where
     ///
     /// This method will perform a complete recursive scan of the source
     /// directory.
-    ///
-    /// # Panics
-    /// If an item fails to be added.
     fn start(&mut self) {
         self.notify();
     }
 
     /// Reloads items from the file system if the root directory has been
-    /// modified since the last time it was reloaded.
-    fn notify(&mut self) {
-        if let Ok(timestamp) = self.root().metadata().and_then(|m| m.modified())
-        {
-            if self.timestamp().map(|t| t < timestamp).unwrap_or(true) {
-                self.populate();
-                *self.timestamp() = Some(timestamp)
+    /// modified since the last time it was reloaded, or if a reload has been
+    /// requested.
+    ///
+    /// If a scan is already in progress, this method returns immediately. A
+    /// reload requested during a scan is performed once the scan finishes.
+    fn notify(&self) {
+        loop {
+            let timestamp = match self.root()
+                .metadata()
+                .and_then(|m| m.modified())
+            {
+                Ok(timestamp) => timestamp,
+                Err(_) => return,
+            };
+            let stale = self.reload_pending().load(atomic::Ordering::SeqCst)
+                || self.timestamp()
+                    .lock()
+                    .map(|t| t.map(|t| t < timestamp).unwrap_or(true))
+                    .unwrap_or(false);
+            if !stale
+                || self.scanning()
+                    .compare_exchange(
+                        false,
+                        true,
+                        atomic::Ordering::SeqCst,
+                        atomic::Ordering::SeqCst,
+                    )
+                    .is_err()
+            {
+                return;
+            }
+
+            self.reload_pending().store(false, atomic::Ordering::SeqCst);
+            self.populate();
+            if let Ok(mut t) = self.timestamp().lock() {
+                *t = Some(timestamp);
+            }
+            self.scanning().store(false, atomic::Ordering::SeqCst);
+
+            // Rescan if a reload was requested while scanning
+            if !self.reload_pending().load(atomic::Ordering::SeqCst) {
+                return;
+            }
+        }
+    }
+
+    /// Requests a reload of all items, regardless of the time of the last
+    /// refresh.
+    ///
+    /// If a scan is in progress, the reload is performed once it finishes.
+    fn reload(&self) {
+        self.reload_pending().store(true, atomic::Ordering::SeqCst);
+        self.notify();
+    }
+
+    /// Writes the state of the cache persisted between runs, such as
+    /// checksums that could not be saved after the last scan.
+    ///
+    /// Metadata already read from source files is kept in memory only, so
+    /// there is nothing else to write; it is read again by the first scan
+    /// after a restart.
+    fn stop(&self) {
+        match self.cache().write() {
+            Ok(mut cache) => {
+                if let Err(e) = cache.flush() {
+                    warn!("Failed to save checksums: {}", e);
+                }
             }
+            Err(_) => warn!("Failed to lock the cache"),
+        }
+    }
+
+    /// Generates the item for a source file exactly as a scan would, but
+    /// without adding it.
+    ///
+    /// Undated items and items dated before the earliest plausible date are
+    /// handled as configured, so `None` is returned for items that are
+    /// dropped.
+    fn probe(&self, path: &path::Path) -> Option<data::Item> {
+        let item = self.generate(path);
+        match self.min_date() {
+            Some(min_date) => min_date.apply(item),
+            None => Some(item),
+        }.and_then(|item| self.undated().apply(item))
+    }
+
+    /// Checks whether the root directory overlaps a path.
+    fn overlaps(&self, path: &path::Path) -> bool {
+        overlapping(self.root(), path)
+    }
+
+    /// Lists the root, scan settings and tag aliases, followed by any
+    /// settings specific to the item generator.
+    fn settings(&self) -> Vec<(String, String)> {
+        let mut result = vec![
+            ("root".into(), self.root().display().to_string()),
+            ("tags-from-path".into(), self.tags_from_path().to_string()),
+            ("skip-hidden".into(), self.skip_hidden().to_string()),
+            (
+                "scan-depth".into(),
+                self.scan_depth()
+                    .map(|depth| depth.to_string())
+                    .unwrap_or_else(|| "unlimited".into()),
+            ),
+            (
+                "limit".into(),
+                self.limit()
+                    .map(|limit| limit.to_string())
+                    .unwrap_or_else(|| "unlimited".into()),
+            ),
+            (
+                "undated".into(),
+                match self.undated() {
+                    Undated::Now => "now",
+                    Undated::Bucket => "bucket",
+                    Undated::Drop => "drop",
+                }.into(),
+            ),
+        ];
+        if let Some(min_date) = self.min_date() {
+            result.push((
+                "min-date".into(),
+                format!(
+                    "{:04}-{:02}-{:02}",
+                    min_date.date.year(),
+                    min_date.date.month(),
+                    min_date.date.day(),
+                ),
+            ));
+            result.push((
+                "too-old".into(),
+                match min_date.too_old {
+                    TooOld::Clamp => "clamp",
+                    TooOld::Drop => "drop",
+                }.into(),
+            ));
         }
+        let mut aliases = self.tag_aliases().iter().collect::<Vec<_>>();
+        aliases.sort();
+        result.extend(aliases.into_iter().map(|(from, to)| {
+            ("tag-alias".into(), format!("{}={}", from, to))
+        }));
+        result.extend(FileSystemItemGenerator::settings(self));
+        result
     }
 }
 
+/// Returns whether a directory entry is hidden, that is whether its name
+/// starts with `"."`.
+///
+/// # Arguments
+/// *  `entry` - The directory entry.
+fn hidden(entry: &walkdir::DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .map(|name| name.starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// Returns whether two directories overlap, that is whether they are the
+/// same or either contains the other.
+///
+/// The paths are canonicalized before being compared. Paths that cannot be
+/// canonicalized are compared as they are.
+///
+/// # Arguments
+/// *  `a` - The first directory.
+/// *  `b` - The second directory.
+fn overlapping(a: &path::Path, b: &path::Path) -> bool {
+    let canonical =
+        |p: &path::Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    let (a, b) = (canonical(a), canonical(b));
+    a.starts_with(&b) || b.starts_with(&a)
+}
+
 impl<P: AsRef<path::Path>> From<P> for data::Item {
     /// Converts as path to an item by simply reading the modification time.
     ///
-    /// If the modification time cannot be read, the current time is used.
+    /// If the modification time cannot be read, the item is undated; see
+    /// [`Timestamp::undated`](../data/struct.Timestamp.html#method.undated).
     ///
     /// # Arguments
     /// *  `source` - The source path.
     fn from(source: P) -> Self {
         let path: &path::Path = source.as_ref();
-        data::Item::new(
-            path,
-            data::timestamp(&path),
-            collections::HashSet::new(),
-        )
+        data::Item {
+            date_source: data::DateSource::Modified,
+            ..data::Item::new(
+                path,
+                data::timestamp(&path)
+                    .map(data::Timestamp::from)
+                    .unwrap_or_else(data::Timestamp::undated),
+                collections::HashSet::new(),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::thread::JoinHandleExt;
+    use std::sync;
+    use std::thread;
+    use std::time;
+
+    use libc;
+    use tempdir;
+
+    use log;
+
+    use data::tests::*;
+    use logging;
+    use signals;
+    use sources::Source;
+    use super::*;
+
+    file_system_base!(
+        CountingSource,
+        // The number of items generated.
+        count: sync::atomic::AtomicUsize,
+    );
+
+    impl CountingSource {
+        /// Creates a source with default options.
+        ///
+        /// # Arguments
+        /// *  `root` - The source directory.
+        /// *  `cache` - The cache to populate.
+        fn new(root: &path::Path, cache: files::Cache) -> Self {
+            CountingSource {
+                root: root.to_path_buf(),
+                cache,
+                timestamp: sync::Mutex::new(None),
+                scanning: atomic::AtomicBool::new(false),
+                reload_pending: atomic::AtomicBool::new(false),
+                filter: data::shared_filter(data::Filter::default()),
+                tags_from_path: false,
+                scan_depth: None,
+                limit: None,
+                skip_hidden: false,
+                tag_aliases: collections::HashMap::new(),
+                tag_separator: data::Tag::DEFAULT_SEPARATOR,
+                undated: Undated::Now,
+                min_date: None,
+                progress: None,
+                count: atomic::AtomicUsize::new(0),
+            }
+        }
+    }
+
+    impl FileSystemItemGenerator for CountingSource {
+        fn item(&self, path: &path::Path) -> data::Item {
+            self.count.fetch_add(1, atomic::Ordering::SeqCst);
+            thread::sleep(time::Duration::from_millis(100));
+            path.into()
+        }
+    }
+
+    file_system_base!(UndatedSource,);
+
+    impl UndatedSource {
+        /// Creates a source with default options.
+        ///
+        /// # Arguments
+        /// *  `root` - The source directory.
+        /// *  `cache` - The cache to populate.
+        fn new(root: &path::Path, cache: files::Cache) -> Self {
+            UndatedSource {
+                root: root.to_path_buf(),
+                cache,
+                timestamp: sync::Mutex::new(None),
+                scanning: atomic::AtomicBool::new(false),
+                reload_pending: atomic::AtomicBool::new(false),
+                filter: data::shared_filter(data::Filter::default()),
+                tags_from_path: false,
+                scan_depth: None,
+                limit: None,
+                skip_hidden: false,
+                tag_aliases: collections::HashMap::new(),
+                tag_separator: data::Tag::DEFAULT_SEPARATOR,
+                undated: Undated::Now,
+                min_date: None,
+                progress: None,
+            }
+        }
+    }
+
+    impl FileSystemItemGenerator for UndatedSource {
+        fn item(&self, path: &path::Path) -> data::Item {
+            data::Item {
+                timestamp: data::Timestamp::undated(),
+                ..path.into()
+            }
+        }
+    }
+
+    file_system_base!(OldSource,);
+
+    impl OldSource {
+        /// Creates a source with a minimum date of 1990.
+        ///
+        /// # Arguments
+        /// *  `root` - The source directory.
+        /// *  `cache` - The cache to populate.
+        /// *  `too_old` - The handling of items dated before 1990.
+        fn new(
+            root: &path::Path,
+            cache: files::Cache,
+            too_old: TooOld,
+        ) -> Self {
+            OldSource {
+                root: root.to_path_buf(),
+                cache,
+                timestamp: sync::Mutex::new(None),
+                scanning: atomic::AtomicBool::new(false),
+                reload_pending: atomic::AtomicBool::new(false),
+                filter: data::shared_filter(data::Filter::default()),
+                tags_from_path: false,
+                scan_depth: None,
+                limit: None,
+                skip_hidden: false,
+                tag_aliases: collections::HashMap::new(),
+                tag_separator: data::Tag::DEFAULT_SEPARATOR,
+                undated: Undated::Drop,
+                min_date: Some(MinDate {
+                    date: (1990, 1, 1).into(),
+                    too_old,
+                }),
+                progress: None,
+            }
+        }
+    }
+
+    impl FileSystemItemGenerator for OldSource {
+        fn item(&self, path: &path::Path) -> data::Item {
+            data::Item {
+                timestamp: (1904, 1, 1).into(),
+                ..path.into()
+            }
+        }
+    }
+
+    /// Tests that the root falls back on the environment.
+    #[test]
+    fn root_from_environment() {
+        let app = || options(clap::App::new("test"));
+        let set = |name: &str| {
+            assert_eq!(ENV_ROOT, name);
+            Some(ffi::OsString::from("/from/environment"))
+        };
+        let unset = |_: &str| None;
+
+        assert_eq!(
+            Ok(path::PathBuf::from("/from/arguments")),
+            root_from(
+                &app().get_matches_from(vec!["test", "/from/arguments"]),
+                set,
+            ),
+        );
+        assert_eq!(
+            Ok(path::PathBuf::from("/from/environment")),
+            root_from(&app().get_matches_from(vec!["test"]), set),
+        );
+        assert!(
+            root_from(&app().get_matches_from(vec!["test"]), unset).is_err()
+        );
+    }
+
+    /// Tests that the root is canonicalized.
+    #[test]
+    fn root_canonical() {
+        let app = || options(clap::App::new("test"));
+
+        assert_eq!(
+            Ok(std::env::current_dir().unwrap().canonicalize().unwrap()),
+            root(&app().get_matches_from(vec!["test", "."])),
+        );
+        assert!(
+            root(&app().get_matches_from(vec!["test", "/missing"])).is_err()
+        );
+    }
+
+    /// Tests that concurrent notifications cause only a single scan.
+    #[test]
+    fn notify_single_flight() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        let source = sync::Arc::new(CountingSource::new(
+            root.path(),
+            files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into()),
+            )),
+        ));
+
+        let threads = (0..4)
+            .map(|_| {
+                let source = source.clone();
+                thread::spawn(move || source.notify())
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        assert_eq!(1, source.count.load(atomic::Ordering::SeqCst));
+    }
+
+    /// Tests that a scan logs the number of files found.
+    #[test]
+    fn populate_logs_counts() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("test1.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("test2.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("test.txt"), b"data", 2000, 1, 1);
+        let source = CountingSource::new(
+            root.path(),
+            files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into()),
+            )),
+        );
+
+        logging::tests::records();
+        source.populate();
+
+        let expected = (
+            log::Level::Debug,
+            format!(
+                "Scanned {}: 2 found, 1 skipped, 0 errors",
+                root.path().display()
+            ),
+        );
+        assert!(logging::tests::records().contains(&expected));
+    }
+
+    /// Tests that a scan records the size of the source files.
+    #[test]
+    fn populate_sets_size() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_views(vec![data::cache::View::Size]),
+        ));
+        CountingSource::new(root.path(), cache.clone()).populate();
+
+        let cache = cache.read().unwrap();
+        assert_eq!(Some(4), cache.items()[0].size);
+        assert!(cache.lookup(&"/Sizes/<1MB").is_some());
+    }
+
+    /// Tests that a scan reads the dimensions of images from their headers.
+    #[test]
+    fn populate_sets_dimensions() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(
+            root.path().join("test.png"),
+            b"\x89PNG\r\n\x1a\n\0\0\0\x0DIHDR\0\0\x01\x40\0\0\0\xF0",
+            2000,
+            1,
+            1,
+        );
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        CountingSource::new(root.path(), cache.clone()).populate();
+
+        assert_eq!(
+            Some((320, 240)),
+            cache.read().unwrap().items()[0].dimensions,
+        );
+    }
+
+    /// Tests that a scan finds the sidecar files of items among the files
+    /// listed.
+    #[test]
+    fn populate_finds_sidecars() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("a.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("a.XMP"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("b.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("b.txt"), b"data", 2000, 1, 1);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        CountingSource::new(root.path(), cache.clone()).populate();
+
+        let cache = cache.read().unwrap();
+        let sidecars = |name| {
+            cache
+                .items()
+                .into_iter()
+                .find(|i| i.path == root.path().join(name))
+                .map(|i| i.sidecars.clone())
+        };
+        assert_eq!(Some(vec![root.path().join("a.XMP")]), sidecars("a.jpg"));
+        assert_eq!(Some(vec![]), sidecars("b.jpg"));
+    }
+
+    /// Tests that a scan records the device and inode of source files.
+    #[test]
+    fn populate_records_inode() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = root.path().join("test.jpg");
+        item_with_data(&path, b"data", 2000, 1, 1);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        CountingSource::new(root.path(), cache.clone()).populate();
+
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(
+            Some((metadata.dev(), metadata.ino())),
+            cache.read().unwrap().items()[0].inode,
+        );
+    }
+
+    /// Tests that a scan reports its progress, ending with the total.
+    #[test]
+    fn populate_reports_progress() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("test1.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("test2.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("test.txt"), b"data", 2000, 1, 1);
+        let events = sync::Arc::new(sync::Mutex::new(Vec::new()));
+        let source = CountingSource {
+            progress: Some({
+                let events = events.clone();
+                Box::new(move |progress: &Progress| {
+                    events.lock().unwrap().push(*progress)
+                })
+            }),
+            ..CountingSource::new(
+                root.path(),
+                files::Cache::new(sync::RwLock::new(
+                    data::cache::Cache::new("All".into(), "Tagged".into()),
+                )),
+            )
+        };
+
+        source.populate();
+
+        let events = events.lock().unwrap();
+        let items = source.cache.read().unwrap().items().len();
+        assert_eq!(
+            vec![1, 2, 2],
+            events.iter().map(|p| p.scanned).collect::<Vec<_>>(),
+        );
+        assert!(events.iter().all(|p| p.total == 2));
+        assert_eq!(
+            Some(&Progress {
+                scanned: items,
+                total: items,
+                found: items,
+                done: true,
+            }),
+            events.last(),
+        );
+    }
+
+    /// Tests that a changed filter is applied by a rescan triggered by
+    /// SIGHUP.
+    #[test]
+    fn reload_on_hangup() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        let filter = data::shared_filter(data::Filter::default());
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        let source = sync::Arc::new(CountingSource {
+            filter: filter.clone(),
+            ..CountingSource::new(root.path(), cache.clone())
+        });
+        let is_empty = || match cache.read().unwrap().lookup(&"/") {
+            Some(data::cache::Entry::Directory(_, tree)) => {
+                tree.is_empty()
+            }
+            _ => false,
+        };
+
+        source.notify();
+        assert!(!is_empty());
+
+        let waiter = {
+            let source = source;
+            signals::on_hangup(move || source.reload())
+        };
+        filter.write().unwrap().before = Some((1990, 1, 1).into());
+        unsafe {
+            libc::pthread_kill(waiter.as_pthread_t(), libc::SIGHUP);
+        }
+
+        let deadline = time::Instant::now() + time::Duration::from_secs(10);
+        while !is_empty() && time::Instant::now() < deadline {
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        assert!(is_empty());
+    }
+
+    /// Tests that a reload requested during a scan causes a rescan once the
+    /// scan finishes.
+    #[test]
+    fn reload_during_scan() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        let source = sync::Arc::new(CountingSource::new(
+            root.path(),
+            files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into()),
+            )),
+        ));
+
+        let scan = {
+            let source = source.clone();
+            thread::spawn(move || source.notify())
+        };
+        while !source.scanning.load(atomic::Ordering::SeqCst) {
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        source.reload();
+        scan.join().unwrap();
+
+        assert_eq!(2, source.count.load(atomic::Ordering::SeqCst));
+        assert!(!source.reload_pending.load(atomic::Ordering::SeqCst));
+    }
+
+    /// Tests that nested source directories are reproduced in the folders
+    /// tree.
+    #[test]
+    fn populate_folders() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        std::fs::create_dir_all(root.path().join("a/b")).unwrap();
+        item_with_data(root.path().join("a/b/test.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("top.jpg"), b"data", 2000, 1, 1);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_folders_root("Folders".into()),
+        ));
+        let source = CountingSource::new(root.path(), cache.clone());
+
+        source.populate();
+
+        let cache = cache.read().unwrap();
+        match cache.lookup(&"/Folders/a/b/test.jpg") {
+            Some(data::cache::Entry::Item(item)) => {
+                assert_eq!(root.path().join("a/b/test.jpg"), item.path)
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+        match cache.lookup(&"/Folders/top.jpg") {
+            Some(data::cache::Entry::Item(item)) => {
+                assert_eq!(root.path().join("top.jpg"), item.path)
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+    }
+
+    /// Tests that a limited scan adds only the first items by path.
+    #[test]
+    fn populate_limit() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        for name in &["e.jpg", "b.jpg", "d.jpg", "a.jpg", "c.jpg"] {
+            item_with_data(root.path().join(name), b"data", 2000, 1, 1);
+        }
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_folders_root("Folders".into()),
+        ));
+        let source = CountingSource {
+            limit: Some(3),
+            ..CountingSource::new(root.path(), cache.clone())
+        };
+
+        source.populate();
+
+        assert_eq!(3, source.count.load(atomic::Ordering::SeqCst));
+        let cache = cache.read().unwrap();
+        match cache.lookup(&"/Folders") {
+            Some(data::cache::Entry::Directory(_, tree)) => {
+                let mut names = tree.keys()
+                    .map(|name| name.to_str().unwrap())
+                    .collect::<Vec<_>>();
+                names.sort();
+                assert_eq!(vec!["a.jpg", "b.jpg", "c.jpg"], names);
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+    }
+
+    /// Tests that only the directory source is available without the tags
+    /// feature, and that it still adds items.
+    #[cfg(not(feature = "tags"))]
+    #[test]
+    fn directory_source_without_tags() {
+        use sources::{ConfigurableSource, ConstructableSource, WithSources};
+
+        let root = tempdir::TempDir::new(&"medifs-source").unwrap();
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        let app = || clap::App::new("test").with_sources();
+        assert!(
+            app()
+                .get_matches_from_safe(vec!["test", "tags", "/"])
+                .is_err()
+        );
+
+        let matches = app().get_matches_from(vec![
+            "test",
+            DirectorySource::SUBCOMMAND_NAME,
+            root.path().to_str().unwrap(),
+        ]);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        let source = DirectorySource::construct(
+            cache.clone(),
+            data::shared_filter(data::Filter::default()),
+            matches
+                .subcommand_matches(DirectorySource::SUBCOMMAND_NAME)
+                .unwrap(),
+        ).unwrap();
+
+        source.populate();
+
+        assert_eq!(1, cache.read().unwrap().items().len());
+    }
+
+    /// Tests that directory names are converted to tags.
+    #[test]
+    fn populate_tags_from_path() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        std::fs::create_dir_all(root.path().join("Vacation/Beach")).unwrap();
+        item_with_data(
+            root.path().join("Vacation/Beach/test.jpg"),
+            b"data",
+            2000,
+            1,
+            1,
+        );
+        item_with_data(root.path().join("top.jpg"), b"data", 2000, 1, 1);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        let source = CountingSource {
+            tags_from_path: true,
+            ..CountingSource::new(root.path(), cache.clone())
+        };
+
+        source.populate();
+
+        let cache = cache.read().unwrap();
+        match cache.lookup(&"/Tagged/Vacation/Beach") {
+            Some(data::cache::Entry::Directory(_, tree)) => {
+                assert_eq!(1, tree.len());
+                assert!(
+                    tree.values()
+                        .all(|e| matches!(*e, data::cache::Entry::Link(..)))
+                );
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+        match cache.lookup(&"/Tagged") {
+            Some(data::cache::Entry::Directory(_, tree)) => {
+                assert_eq!(1, tree.len())
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+    }
+
+    /// Tests that empty files are skipped.
+    #[test]
+    fn populate_skips_empty() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("empty.jpg"), b"", 2000, 1, 1);
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_folders_root("Folders".into()),
+        ));
+        let source = CountingSource::new(root.path(), cache.clone());
+
+        source.populate();
+
+        let cache = cache.read().unwrap();
+        assert!(cache.lookup(&"/Folders/test.jpg").is_some());
+        assert!(cache.lookup(&"/Folders/empty.jpg").is_none());
+        assert_eq!(1, source.count.load(atomic::Ordering::SeqCst));
+    }
+
+    /// Tests that checksums that could not be saved after a scan are written
+    /// when the file system is unmounted.
+    #[test]
+    fn stop_saves_checksums() {
+        use fuse_mt::FilesystemMT;
+
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("a.jpg"), b"data", 2000, 1, 1);
+        item_with_data(root.path().join("b.jpg"), b"data", 2000, 1, 2);
+        let dir = tempdir::TempDir::new("medifs-hashes").unwrap();
+        let database = dir.path().join("hashes");
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_identity(data::Identity::Hash)
+                .with_hash_database(
+                    data::HashDatabase::open(&database).unwrap(),
+                ),
+        ));
+        let source = files::Source::new(sync::RwLock::new(Box::new(
+            CountingSource::new(root.path(), cache.clone()),
+        )));
+
+        // A directory in place of the temporary file makes saving fail
+        let blocker = dir.path().join("hashes.tmp");
+        fs::create_dir(&blocker).unwrap();
+        let mediafs = files::MediaFS::new(cache.clone(), source.clone());
+        assert_eq!(1, cache.read().unwrap().items().len());
+        assert!(!database.exists());
+
+        fs::remove_dir(&blocker).unwrap();
+        mediafs.destroy(fuse_mt::RequestInfo {
+            unique: 0,
+            uid: 0,
+            gid: 0,
+            pid: 0,
+        });
+        assert_eq!(
+            2,
+            fs::read_to_string(&database).unwrap().lines().count(),
+        );
+    }
+
+    /// Tests that the scan depth limits the items found.
+    #[test]
+    fn populate_scan_depth() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        std::fs::create_dir_all(root.path().join("a/b")).unwrap();
+        for name in &["top.jpg", "a/middle.jpg", "a/b/bottom.jpg"] {
+            item_with_data(root.path().join(name), b"data", 2000, 1, 1);
+        }
+        let found = |scan_depth| {
+            let cache = files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into())
+                    .with_folders_root("Folders".into()),
+            ));
+            let source = CountingSource {
+                scan_depth,
+                ..CountingSource::new(root.path(), cache.clone())
+            };
+            source.populate();
+            let cache = cache.read().unwrap();
+            [
+                "/Folders/top.jpg",
+                "/Folders/a/middle.jpg",
+                "/Folders/a/b/bottom.jpg",
+            ].iter()
+                .filter(|path| cache.lookup(path).is_some())
+                .count()
+        };
+
+        assert_eq!(1, found(Some(1)));
+        assert_eq!(2, found(Some(2)));
+        assert_eq!(3, found(Some(3)));
+        assert_eq!(3, found(None));
+    }
+
+    /// Tests that hidden files and directories are skipped only when
+    /// requested.
+    #[test]
+    fn populate_skip_hidden() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        std::fs::create_dir_all(root.path().join(".hidden")).unwrap();
+        for name in &["visible.jpg", ".hidden.jpg", ".hidden/nested.jpg"] {
+            item_with_data(root.path().join(name), b"data", 2000, 1, 1);
+        }
+        let found = |skip_hidden| {
+            let cache = files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into())
+                    .with_folders_root("Folders".into()),
+            ));
+            let source = CountingSource {
+                skip_hidden,
+                ..CountingSource::new(root.path(), cache.clone())
+            };
+            source.populate();
+            let cache = cache.read().unwrap();
+            [
+                "/Folders/visible.jpg",
+                "/Folders/.hidden.jpg",
+                "/Folders/.hidden/nested.jpg",
+            ].iter()
+                .map(|path| cache.lookup(path).is_some())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![true, false, false], found(true));
+        assert_eq!(vec![true, true, true], found(false));
+    }
+
+    /// Tests that invalid scan depths are rejected.
+    #[test]
+    fn scan_depth_from_args() {
+        let app = || options(clap::App::new("test"));
+
+        assert_eq!(
+            Ok(None),
+            scan_depth(&app().get_matches_from(vec!["test"])),
+        );
+        assert_eq!(
+            Ok(Some(2)),
+            scan_depth(&app().get_matches_from(vec![
+                "test",
+                "--scan-depth",
+                "2",
+            ])),
+        );
+        assert!(
+            scan_depth(&app().get_matches_from(vec![
+                "test",
+                "--scan-depth",
+                "0",
+            ])).is_err()
+        );
+    }
+
+    /// Tests that invalid limits are rejected.
+    #[test]
+    fn limit_from_args() {
+        let app = || options(clap::App::new("test"));
+
+        assert_eq!(Ok(None), limit(&app().get_matches_from(vec!["test"])));
+        assert_eq!(
+            Ok(Some(10)),
+            limit(&app().get_matches_from(vec!["test", "--limit", "10"])),
+        );
+        assert!(
+            limit(&app().get_matches_from(vec!["test", "--limit", "0"]))
+                .is_err()
+        );
+    }
+
+    /// Tests that unreadable files are skipped.
+    #[test]
+    fn populate_skips_unreadable() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = root.path().join("unreadable.jpg");
+        item_with_data(root.path().join("test.jpg"), b"data", 2000, 1, 1);
+        std::os::unix::fs::symlink(root.path().join("missing.jpg"), &path)
+            .unwrap();
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_folders_root("Folders".into()),
+        ));
+        let source = CountingSource::new(root.path(), cache.clone());
+
+        source.populate();
+
+        let cache = cache.read().unwrap();
+        assert!(cache.lookup(&"/Folders/test.jpg").is_some());
+        assert!(cache.lookup(&"/Folders/unreadable.jpg").is_none());
+        assert_eq!(1, source.count.load(atomic::Ordering::SeqCst));
+    }
+
+    /// Tests that undated and unreadable files are linked from the
+    /// quarantine.
+    #[test]
+    fn populate_quarantine() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let undated = root.path().join("undated.jpg");
+        let unreadable = root.path().join("unreadable.jpg");
+        item_with_data(&undated, b"data", 2000, 1, 1);
+        std::os::unix::fs::symlink(root.path().join("missing.jpg"), &unreadable)
+            .unwrap();
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into())
+                .with_quarantine(true),
+        ));
+        let source = UndatedSource {
+            undated: Undated::Drop,
+            ..UndatedSource::new(root.path(), cache.clone())
+        };
+
+        source.populate();
+
+        let cache = cache.read().unwrap();
+        let target = |path| match cache.lookup(&path) {
+            Some(data::cache::Entry::Link(_, target)) => {
+                Some(path::PathBuf::from(target))
+            }
+            _ => None,
+        };
+        assert_eq!(Some(undated), target("/Problems/no-date/undated.jpg"));
+        assert_eq!(
+            Some(unreadable),
+            target("/Problems/unreadable/unreadable.jpg"),
+        );
+        assert!(cache.items().is_empty());
+    }
+
+    /// Tests that undated items are linked from the quarantine only if they
+    /// are dropped by the undated policy and pass the filter.
+    #[test]
+    fn populate_quarantine_undated() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        item_with_data(root.path().join("undated.jpg"), b"data", 2000, 1, 1);
+        let filtered = data::Filter {
+            tags: vec!["tag".to_string()].into_iter().collect(),
+            ..data::Filter::default()
+        };
+        for (undated, filter) in [
+            (Undated::Now, data::Filter::default()),
+            (Undated::Bucket, data::Filter::default()),
+            (Undated::Drop, filtered),
+        ] {
+            let cache = files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into())
+                    .with_quarantine(true),
+            ));
+            UndatedSource {
+                undated,
+                filter: data::shared_filter(filter),
+                ..UndatedSource::new(root.path(), cache.clone())
+            }.populate();
+
+            assert_eq!(None, cache.read().unwrap().lookup(&"/Problems"));
+        }
+    }
+
+    /// Tests that aliased tags are replaced.
+    #[test]
+    fn alias_nested() {
+        let aliases = vec![("Holiday", "Vacation"), ("a/b", "c")]
+            .into_iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+
+        assert_eq!("Vacation", alias(&aliases, "Holiday".into(), '/'));
+        assert_eq!(
+            "Vacation/Beach",
+            alias(&aliases, "Holiday/Beach".into(), '/'),
+        );
+        assert_eq!("c/d", alias(&aliases, "a/b/d".into(), '/'));
+        assert_eq!("a/d", alias(&aliases, "a/d".into(), '/'));
+        assert_eq!("Holidays", alias(&aliases, "Holidays".into(), '/'));
+        assert_eq!("c>d", alias(&aliases, "a/b>d".into(), '>'));
+        assert_eq!("a>b>d", alias(&aliases, "a>b>d".into(), '>'));
+    }
+
+    /// Tests that tag aliases are read from arguments and files.
+    #[test]
+    fn tag_aliases_from_args() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("aliases");
+        std::fs::write(&path, "# Aliases\n\nHoliday = Vacation\na=b\n")
+            .unwrap();
+        let app = || options(clap::App::new("test"));
+
+        let aliases = tag_aliases(&app().get_matches_from(vec![
+            "test",
+            "--tag-aliases",
+            path.to_str().unwrap(),
+            "--tag-alias",
+            "a=c",
+        ])).unwrap();
+        assert_eq!(2, aliases.len());
+        assert_eq!(Some(&"Vacation".to_string()), aliases.get("Holiday"));
+        assert_eq!(Some(&"c".to_string()), aliases.get("a"));
+
+        assert!(
+            tag_aliases(&app().get_matches_from(vec![
+                "test",
+                "--tag-alias",
+                "a",
+            ])).is_err()
+        );
+    }
+
+    /// Tests that aliased tags are linked under the canonical tag.
+    #[test]
+    fn populate_tag_aliases() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        for name in &["Holiday", "Vacation"] {
+            std::fs::create_dir_all(root.path().join(name)).unwrap();
+            item_with_data(
+                root.path().join(name).join("test.jpg"),
+                b"data",
+                2000,
+                1,
+                1,
+            );
+        }
+        let cache = files::Cache::new(sync::RwLock::new(
+            data::cache::Cache::new("All".into(), "Tagged".into()),
+        ));
+        let source = CountingSource {
+            tags_from_path: true,
+            tag_aliases: vec![("Holiday".into(), "Vacation".into())]
+                .into_iter()
+                .collect(),
+            ..CountingSource::new(root.path(), cache.clone())
+        };
+
+        source.populate();
+
+        let cache = cache.read().unwrap();
+        match cache.lookup(&"/Tagged") {
+            Some(data::cache::Entry::Directory(_, tree)) => {
+                assert_eq!(1, tree.len())
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+        match cache.lookup(&"/Tagged/Vacation") {
+            Some(data::cache::Entry::Directory(_, tree)) => {
+                assert_eq!(2, tree.len())
+            }
+            e => panic!("unexpected entry {:?}", e),
+        }
+    }
+
+    /// Tests that overlapping directories are detected.
+    #[test]
+    fn overlapping_directories() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let outer = root.path().join("outer");
+        let inner = outer.join("inner");
+        let other = root.path().join("other");
+        std::fs::create_dir_all(&inner).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+
+        assert!(overlapping(&outer, &outer));
+        assert!(overlapping(&outer, &outer.join("..").join("outer")));
+        assert!(overlapping(&outer, &inner));
+        assert!(overlapping(&inner, &outer));
+        assert!(!overlapping(&outer, &other));
+        assert!(!overlapping(&root.path().join("out"), &outer));
+    }
+
+    /// Tests that items without a readable date are undated.
+    #[test]
+    fn item_undated() {
+        let item = data::Item::from("/does/not/exist.jpg");
+        assert!(item.timestamp.is_undated());
+    }
+
+    /// Tests that files modified within the same second keep distinct
+    /// timestamps.
+    #[test]
+    fn item_subsecond() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let second = time::UNIX_EPOCH + time::Duration::from_secs(946_684_800);
+        let items = [100_000_000, 900_000_000]
+            .iter()
+            .enumerate()
+            .map(|(i, &nanos)| {
+                let path = dir.path().join(format!("test{}.jpg", i));
+                std::fs::File::create(&path)
+                    .and_then(|f| {
+                        f.set_modified(second + time::Duration::new(0, nanos))
+                    })
+                    .unwrap();
+                data::Item::from(&path)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(100_000_000, items[0].timestamp.as_ref().tm_nsec);
+        assert_eq!(900_000_000, items[1].timestamp.as_ref().tm_nsec);
+        assert!(items[0].timestamp < items[1].timestamp);
+        assert_ne!(
+            data::cache::Entry::Item(items[0].clone()).timestamp(),
+            data::cache::Entry::Item(items[1].clone()).timestamp(),
+        );
+    }
+
+    /// Tests that undated items are handled as configured.
+    #[test]
+    fn undated_apply() {
+        let dated = item("test.jpg", 2000, 1, 1);
+        let undated = data::Item {
+            timestamp: data::Timestamp::undated(),
+            ..dated.clone()
+        };
+
+        for handling in &[Undated::Now, Undated::Bucket, Undated::Drop] {
+            assert_eq!(
+                Some(dated.timestamp.clone()),
+                handling.apply(dated.clone()).map(|i| i.timestamp),
+            );
+        }
+        assert!(
+            !Undated::Now
+                .apply(undated.clone())
+                .unwrap()
+                .timestamp
+                .is_undated()
+        );
+        assert!(
+            Undated::Bucket
+                .apply(undated.clone())
+                .unwrap()
+                .timestamp
+                .is_undated()
+        );
+        assert_eq!(None, Undated::Drop.apply(undated));
+    }
+
+    /// Tests that a scan and a probe clamp an item dated 1904 to the
+    /// modification time of its file, or drop it, as configured.
+    #[test]
+    fn populate_min_date() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = root.path().join("old.jpg");
+        item_with_data(&path, b"data", 1904, 1, 1);
+        let modified = data::Timestamp::from(data::timestamp(&path).unwrap());
+
+        for &(too_old, ref expected) in
+            &[(TooOld::Clamp, Some(modified)), (TooOld::Drop, None)]
+        {
+            let cache = files::Cache::new(sync::RwLock::new(
+                data::cache::Cache::new("All".into(), "Tagged".into()),
+            ));
+            let source = OldSource::new(root.path(), cache.clone(), too_old);
+            source.populate();
+
+            assert_eq!(
+                *expected,
+                cache
+                    .read()
+                    .unwrap()
+                    .items()
+                    .first()
+                    .map(|item| item.timestamp.clone()),
+            );
+            assert_eq!(
+                *expected,
+                source.probe(&path).map(|item| item.timestamp),
+            );
+        }
+    }
+
+    /// Tests that items dated before the minimum date are clamped to their
+    /// modification time, or made undated if that is also too early, or
+    /// dropped, and that other items are unchanged.
+    #[test]
+    fn min_date_apply() {
+        let root = tempdir::TempDir::new("medifs-source").unwrap();
+        let old =
+            item_with_data(root.path().join("old.jpg"), b"data", 1904, 1, 1);
+        let recent = item("recent.jpg", 2000, 1, 1);
+        let undated = data::Item {
+            timestamp: data::Timestamp::undated(),
+            ..recent.clone()
+        };
+        let min_date = |too_old| MinDate {
+            date: (1990, 1, 1).into(),
+            too_old,
+        };
+
+        let clamped = min_date(TooOld::Clamp).apply(old.clone()).unwrap();
+        assert_eq!(
+            data::Timestamp::from(data::timestamp(&old.path).unwrap()),
+            clamped.timestamp,
+        );
+        assert_eq!(data::DateSource::Modified, clamped.date_source);
+        assert_eq!(None, min_date(TooOld::Drop).apply(old.clone()));
+
+        fs::File::options()
+            .write(true)
+            .open(&old.path)
+            .and_then(|f| f.set_modified(std::time::UNIX_EPOCH))
+            .unwrap();
+        assert!(
+            min_date(TooOld::Clamp)
+                .apply(old)
+                .unwrap()
+                .timestamp
+                .is_undated()
+        );
+
+        for too_old in &[TooOld::Clamp, TooOld::Drop] {
+            assert_eq!(
+                Some(recent.clone()),
+                min_date(*too_old).apply(recent.clone()),
+            );
+            assert_eq!(
+                Some(undated.clone()),
+                min_date(*too_old).apply(undated.clone()),
+            );
+        }
+    }
+
+    /// Tests that the minimum date and its handling are read from arguments.
+    #[test]
+    fn min_date_from_args() {
+        let app = || options(clap::App::new("test"));
+
+        assert_eq!(
+            Ok(None),
+            min_date(&app().get_matches_from(vec!["test", "/"])),
+        );
+        assert_eq!(
+            Ok(Some(MinDate {
+                date: (1990, 1, 1).into(),
+                too_old: TooOld::Drop,
+            })),
+            min_date(&app().get_matches_from(vec![
+                "test",
+                "--min-date",
+                "1990-01-01",
+                "--too-old",
+                "drop",
+                "/",
+            ])),
+        );
+        assert!(
+            min_date(&app().get_matches_from(vec![
+                "test",
+                "--min-date",
+                "1990",
+                "/",
+            ])).is_err()
+        );
     }
 }
@@ -0,0 +1,257 @@
+use std::collections;
+use std::fs;
+use std::io;
+use std::io::{Read, Seek};
+use std::path;
+
+/// The extensions of the formats that may contain several pages or frames.
+const EXTENSIONS: &[&str] = &["gif", "tif", "tiff"];
+
+/// The maximum number of pages counted.
+///
+/// This guards against corrupt files with page chains that never end.
+const PAGE_LIMIT: u32 = 64 * 1024;
+
+/// The TIFF tag describing the kind of data in an image file directory.
+const TIFF_NEW_SUBFILE_TYPE: u16 = 254;
+
+/// The flag of [`TIFF_NEW_SUBFILE_TYPE`] marking a reduced resolution
+/// version of another image, such as a thumbnail.
+///
+/// [`TIFF_NEW_SUBFILE_TYPE`]: constant.TIFF_NEW_SUBFILE_TYPE.html
+const TIFF_REDUCED_RESOLUTION: u32 = 1;
+
+/// Counts the pages of a multi-page image.
+///
+/// Multi-page TIFF images and animated GIF images are single items, and this
+/// function counts the pages or frames of such files. Reduced resolution
+/// TIFF images, such as thumbnails, are not counted.
+///
+/// If the file is not of a multi-page format, contains only a single page or
+/// cannot be parsed, `None` is returned.
+///
+/// # Arguments
+/// *  `path` - The path of the image.
+pub fn read(path: &path::Path) -> Option<u32> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    if !EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+
+    let mut file = io::BufReader::new(fs::File::open(path).ok()?);
+    let mut magic = [0u8; 6];
+    file.read_exact(&mut magic).ok()?;
+    file.seek(io::SeekFrom::Start(0)).ok()?;
+    let pages = match &magic {
+        b"GIF87a" | b"GIF89a" => gif(&mut file),
+        _ if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") => {
+            tiff(&mut file)
+        }
+        _ => return None,
+    };
+    pages.ok().filter(|&pages| pages > 1)
+}
+
+/// Counts the image file directories of a TIFF image.
+///
+/// # Arguments
+/// *  `source` - The image data.
+fn tiff<R: Read + Seek>(source: &mut R) -> io::Result<u32> {
+    let mut header = [0u8; 8];
+    source.read_exact(&mut header)?;
+    let little_endian = header[0] == b'I';
+    let value = |bytes: &[u8]| {
+        if little_endian {
+            bytes.iter().rev().fold(0, |acc, &b| acc << 8 | u32::from(b))
+        } else {
+            bytes.iter().fold(0, |acc, &b| acc << 8 | u32::from(b))
+        }
+    };
+
+    let mut visited = collections::HashSet::new();
+    let mut offset = value(&header[4..8]);
+    let mut pages = 0;
+    while offset != 0 && pages < PAGE_LIMIT && visited.insert(offset) {
+        source.seek(io::SeekFrom::Start(u64::from(offset)))?;
+        let mut count = [0u8; 2];
+        source.read_exact(&mut count)?;
+        let mut reduced = false;
+        for _ in 0..value(&count) {
+            let mut entry = [0u8; 12];
+            source.read_exact(&mut entry)?;
+            if value(&entry[0..2]) == u32::from(TIFF_NEW_SUBFILE_TYPE) {
+                reduced = value(&entry[8..12]) & TIFF_REDUCED_RESOLUTION != 0;
+            }
+        }
+        if !reduced {
+            pages += 1;
+        }
+
+        let mut next = [0u8; 4];
+        source.read_exact(&mut next)?;
+        offset = value(&next);
+    }
+
+    Ok(pages)
+}
+
+/// Counts the frames of a GIF image.
+///
+/// # Arguments
+/// *  `source` - The image data.
+fn gif<R: Read>(source: &mut R) -> io::Result<u32> {
+    let mut header = [0u8; 13];
+    source.read_exact(&mut header)?;
+    skip_color_table(source, header[10])?;
+
+    let mut frames = 0;
+    let mut buffer = [0u8; 9];
+    loop {
+        source.read_exact(&mut buffer[..1])?;
+        match buffer[0] {
+            // Image descriptor
+            0x2C => {
+                source.read_exact(&mut buffer)?;
+                skip_color_table(source, buffer[8])?;
+
+                // The minimum code size precedes the image data
+                source.read_exact(&mut buffer[..1])?;
+                skip_blocks(source)?;
+                frames += 1;
+                if frames >= PAGE_LIMIT {
+                    return Ok(frames);
+                }
+            }
+
+            // Extension
+            0x21 => {
+                source.read_exact(&mut buffer[..1])?;
+                skip_blocks(source)?;
+            }
+
+            // Trailer
+            0x3B => return Ok(frames),
+
+            _ => return Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+/// Skips a GIF color table, if present.
+///
+/// # Arguments
+/// *  `source` - The image data.
+/// *  `flags` - The flags of the descriptor preceding the color table.
+fn skip_color_table<R: Read>(source: &mut R, flags: u8) -> io::Result<()> {
+    if flags & 0x80 != 0 {
+        let size = 3 * (2u64 << (flags & 0x07));
+        skip(source, size)
+    } else {
+        Ok(())
+    }
+}
+
+/// Skips a sequence of GIF data sub-blocks, including the terminator.
+///
+/// # Arguments
+/// *  `source` - The image data.
+fn skip_blocks<R: Read>(source: &mut R) -> io::Result<()> {
+    let mut size = [0u8; 1];
+    loop {
+        source.read_exact(&mut size)?;
+        if size[0] == 0 {
+            return Ok(());
+        }
+        skip(source, u64::from(size[0]))?;
+    }
+}
+
+/// Skips a number of bytes.
+///
+/// # Arguments
+/// *  `source` - The data.
+/// *  `size` - The number of bytes to skip.
+fn skip<R: Read>(source: &mut R, size: u64) -> io::Result<()> {
+    if io::copy(&mut source.by_ref().take(size), &mut io::sink())? == size {
+        Ok(())
+    } else {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use tempdir;
+
+    use super::*;
+
+    /// Generates a little endian TIFF image with a number of pages.
+    ///
+    /// Every page consists of an image file directory with a single entry.
+    ///
+    /// # Arguments
+    /// *  `subfile_types` - The new subfile type of every page.
+    pub fn tiff_data(subfile_types: &[u32]) -> Vec<u8> {
+        let mut result = b"II*\0".to_vec();
+        for (i, subfile_type) in subfile_types.iter().enumerate() {
+            let offset = 8 + 18 * i as u32;
+            result.extend(&offset.to_le_bytes());
+            result.extend(&1u16.to_le_bytes());
+            result.extend(&TIFF_NEW_SUBFILE_TYPE.to_le_bytes());
+            result.extend(&4u16.to_le_bytes());
+            result.extend(&1u32.to_le_bytes());
+            result.extend(&subfile_type.to_le_bytes());
+        }
+        result.extend(&0u32.to_le_bytes());
+        result
+    }
+
+    /// Tests that the pages of TIFF images are counted.
+    #[test]
+    fn tiff_pages() {
+        let count = |data: &[u8]| tiff(&mut io::Cursor::new(data)).ok();
+        assert_eq!(Some(1), count(&tiff_data(&[0])));
+        assert_eq!(Some(2), count(&tiff_data(&[2, 2])));
+        assert_eq!(Some(2), count(&tiff_data(&[0, 1, 0])));
+        assert_eq!(None, count(b"II*\0\x10\0\0\0"));
+
+        // A directory pointing to itself
+        let mut data = tiff_data(&[0]);
+        let length = data.len();
+        data[length - 4] = 8;
+        assert_eq!(Some(1), count(&data));
+    }
+
+    /// Tests that the frames of GIF images are counted.
+    #[test]
+    fn gif_frames() {
+        let frame = b"\x21\xF9\x04\x00\x0A\x00\x00\x00\
+                      \x2C\x00\x00\x00\x00\x01\x00\x01\x00\x00\
+                      \x02\x02\x44\x01\x00";
+        let mut data = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        data.extend(&[0u8; 6]);
+        data.extend(frame.iter());
+        data.extend(frame.iter());
+        data.push(0x3B);
+
+        let count = |data: &[u8]| gif(&mut io::Cursor::new(data)).ok();
+        assert_eq!(Some(2), count(&data));
+        assert_eq!(None, count(&data[..data.len() - 1]));
+    }
+
+    /// Tests that only files of multi-page formats with several pages are
+    /// counted.
+    #[test]
+    fn read_files() {
+        let dir = tempdir::TempDir::new("medifs-pages").unwrap();
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, data).unwrap();
+            path
+        };
+        assert_eq!(Some(2), read(&write("a.tif", &tiff_data(&[2, 2]))));
+        assert_eq!(None, read(&write("b.tiff", &tiff_data(&[0]))));
+        assert_eq!(None, read(&write("c.dng", &tiff_data(&[0, 0]))));
+        assert_eq!(None, read(&write("d.tif", b"hello world")));
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+use std::sync;
+
+/// The progress of a scan.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// The number of candidate files read so far.
+    pub scanned: usize,
+
+    /// The total number of candidate files found by the scan.
+    pub total: usize,
+
+    /// The number of items found so far.
+    pub found: usize,
+
+    /// Whether the scan has completed.
+    pub done: bool,
+}
+
+impl Progress {
+    /// Formats this progress as a single line JSON object.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"scanned\":{},\"total\":{},\"found\":{},\"done\":{}}}",
+            self.scanned, self.total, self.found, self.done,
+        )
+    }
+}
+
+/// A function receiving the progress of scans.
+pub type ProgressReporter = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// Creates a progress reporter writing every event as a line of JSON to a
+/// file.
+///
+/// The file is opened for appending, so a path like `/dev/fd/3` may be used
+/// to write to an already open file descriptor.
+///
+/// # Arguments
+/// *  `path` - The path of the file.
+pub fn json_lines(path: &path::Path) -> io::Result<ProgressReporter> {
+    let file = sync::Mutex::new(
+        fs::OpenOptions::new().create(true).append(true).open(path)?,
+    );
+    Ok(Box::new(move |progress| {
+        if let Ok(mut file) = file.lock() {
+            if let Err(e) = writeln!(file, "{}", progress.to_json()) {
+                trace!("Failed to report progress: {}", e);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir;
+
+    use super::*;
+
+    /// Tests that events are written as lines of JSON.
+    #[test]
+    fn json_lines_written() {
+        let dir = tempdir::TempDir::new("medifs-progress").unwrap();
+        let path = dir.path().join("progress");
+        let reporter = json_lines(&path).unwrap();
+        reporter(&Progress {
+            scanned: 1,
+            total: 2,
+            found: 1,
+            done: false,
+        });
+        reporter(&Progress {
+            scanned: 2,
+            total: 2,
+            found: 2,
+            done: true,
+        });
+
+        assert_eq!(
+            concat!(
+                "{\"scanned\":1,\"total\":2,\"found\":1,\"done\":false}\n",
+                "{\"scanned\":2,\"total\":2,\"found\":2,\"done\":true}\n",
+            ),
+            fs::read_to_string(&path).unwrap(),
+        );
+    }
+}
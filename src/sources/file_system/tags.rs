@@ -1,9 +1,11 @@
 use std;
 use std::collections;
 use std::path;
+use std::str;
 use std::sync;
 
 use clap;
+use regex;
 use rexiv2;
 use time;
 
@@ -15,26 +17,447 @@ use sources::*;
 
 file_system_base!(
     TagsSource,
-    // A cache of EXIF tags already read.
-    tags: sync::RwLock<collections::HashMap<path::PathBuf, ItemMeta>>,
+    // A cache of EXIF tags already read, along with the state of the file and
+    // any sidecar date file at the time. This is not persisted between runs.
+    tags: sync::RwLock<collections::HashMap<path::PathBuf, (Stamp, ItemMeta)>>,
+    // The handling of files changed since their tags were read.
+    changed: Changed,
+    // The tags from which to read the timestamp, tried in order.
+    timestamp_tags: Vec<TimestampTag>,
+    // The file name patterns from which to read the timestamp when no
+    // timestamp tag is present, tried in order.
+    filename_dates: Vec<FilenameDate>,
+    // The zone in which timestamps without an offset are interpreted.
+    exif_zone: data::Zone,
+    // The offsets of the clocks of cameras, by model.
+    camera_offsets: Vec<CameraOffset>,
 );
 
-/// The exiv2 tag designated for creation time.
+/// The name of the argument specifying the timestamp tags.
+const OPT_TIMESTAMP_TAG: &str = "TIMESTAMP_TAG";
+
+/// The name of the argument specifying the file name date patterns.
+const OPT_FILENAME_DATE: &str = "FILENAME_DATE";
+
+/// The name of the argument specifying the zone of timestamps without an
+/// offset.
+const OPT_EXIF_ZONE: &str = "EXIF_ZONE";
+
+/// The name of the argument specifying the clock offsets of cameras.
+const OPT_CAMERA_OFFSET: &str = "CAMERA_OFFSET";
+
+/// The name of the argument specifying the handling of changed files.
+const OPT_CHANGED_FILES: &str = "CHANGED_FILES";
+
+/// The name of the argument specifying the number of times to retry
+/// initializing the metadata library.
+const OPT_METADATA_RETRIES: &str = "METADATA_RETRIES";
+
+/// The delay between attempts to initialize the metadata library.
+const METADATA_RETRY_DELAY: std::time::Duration =
+    std::time::Duration::from_secs(1);
+
+/// A minimal valid PNG image, read to verify that the metadata library works.
+const PROBE_IMAGE: &[u8] = &[
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1,
+    0, 0, 0, 1, 8, 0, 0, 0, 0, 58, 126, 155, 85, 0, 0, 0, 10, 73, 68, 65, 84,
+    8, 215, 99, 248, 15, 0, 1, 1, 1, 0, 27, 182, 238, 86, 0, 0, 0, 0, 73, 69,
+    78, 68, 174, 66, 96, 130,
+];
+
+/// The exiv2 tag designated for creation time, used unless timestamp tags are
+/// specified.
 const EXIT_TIMESTAMP_TAG_NAME: &str = &"Exif.Photo.DateTimeOriginal";
 
-/// The format used for the creation time.
+/// The format used for the creation time, used for timestamp tags without an
+/// explicit format.
 const EXIT_TIMESTAMP_TAG_FORMAT: &str = &"%Y:%m:%d %H:%M:%S";
 
+/// The extension appended to the file name of an item to form the name of its
+/// sidecar date file.
+const SIDECAR_DATE_EXTENSION: &str = ".date";
+
+/// The formats accepted in sidecar date files, tried in order.
+const SIDECAR_DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d",
+];
+
+/// The file name date patterns used unless patterns are specified.
+///
+/// These match the names given by common phones and screenshot tools, such
+/// as `IMG_20200101_120000.jpg`, `Screenshot_20200101-120000.png`,
+/// `Screenshot 2020-01-01 at 12.00.00.png` and `IMG-20200101-WA0001.jpg`.
+const FILENAME_DATES: &[&str] = &[
+    concat!(
+        r"(?:^|[^0-9])(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})",
+        r"[-_](?P<hour>[0-9]{2})(?P<minute>[0-9]{2})(?P<second>[0-9]{2})",
+    ),
+    concat!(
+        r"(?:^|[^0-9])(?P<year>[0-9]{4})-(?P<month>[0-9]{2})-",
+        r"(?P<day>[0-9]{2})(?: at |[ _-])(?P<hour>[0-9]{2})[.-]",
+        r"(?P<minute>[0-9]{2})[.-](?P<second>[0-9]{2})",
+    ),
+    r"^(?:IMG|VID)-(?P<year>[0-9]{4})(?P<month>[0-9]{2})(?P<day>[0-9]{2})-WA",
+];
+
+/// The exiv2 tag designated for the rating.
+const XMP_RATING_TAG_NAME: &str = "Xmp.xmp.Rating";
+
+/// The exiv2 tag designated for the camera manufacturer.
+const EXIF_MAKE_TAG_NAME: &str = "Exif.Image.Make";
+
+/// The exiv2 tag designated for the camera model.
+const EXIF_MODEL_TAG_NAME: &str = "Exif.Image.Model";
+
+/// The exiv2 tag designated for the lens model.
+const EXIF_LENS_MODEL_TAG_NAME: &str = "Exif.Photo.LensModel";
+
+/// The exiv2 tag designated for the EXIF colour space.
+const EXIF_COLOR_SPACE_TAG_NAME: &str = "Exif.Photo.ColorSpace";
+
+/// The exiv2 tag designated for the interoperability index, which tells
+/// Adobe RGB images apart from other uncalibrated images.
+const EXIF_INTEROPERABILITY_INDEX_TAG_NAME: &str =
+    "Exif.Iop.InteroperabilityIndex";
+
+/// The exiv2 tag designated for the description of the ICC profile.
+const XMP_ICC_PROFILE_TAG_NAME: &str = "Xmp.photoshop.ICCProfile";
+
+/// The exiv2 tag designated for the aperture as an f-number.
+const EXIF_F_NUMBER_TAG_NAME: &str = "Exif.Photo.FNumber";
+
+/// The exiv2 tag designated for the exposure time in seconds.
+const EXIF_EXPOSURE_TIME_TAG_NAME: &str = "Exif.Photo.ExposureTime";
+
+/// The exiv2 tag designated for the ISO speed.
+const EXIF_ISO_SPEED_TAG_NAME: &str = "Exif.Photo.ISOSpeedRatings";
+
+/// The exiv2 tag designated for the focal length in millimetres.
+const EXIF_FOCAL_LENGTH_TAG_NAME: &str = "Exif.Photo.FocalLength";
+
+/// The exiv2 tag designated for the GPS altitude in metres.
+const EXIF_GPS_ALTITUDE_TAG_NAME: &str = "Exif.GPSInfo.GPSAltitude";
+
+/// The exiv2 tag designated for the reference of the GPS altitude; `1` means
+/// below sea level.
+const EXIF_GPS_ALTITUDE_REF_TAG_NAME: &str = "Exif.GPSInfo.GPSAltitudeRef";
+
+/// The exiv2 tag designated for the direction of the image in degrees.
+const EXIF_GPS_IMG_DIRECTION_TAG_NAME: &str = "Exif.GPSInfo.GPSImgDirection";
+
+/// The exiv2 tag designated for the image width.
+const EXIF_PIXEL_X_DIMENSION_TAG_NAME: &str = "Exif.Photo.PixelXDimension";
+
+/// The exiv2 tag designated for the image height.
+const EXIF_PIXEL_Y_DIMENSION_TAG_NAME: &str = "Exif.Photo.PixelYDimension";
+
+/// The exiv2 tag designated for the orientation of the image.
+const EXIF_ORIENTATION_TAG_NAME: &str = "Exif.Image.Orientation";
+
+/// The exiv2 tag designated for the sub-second part of the creation time.
+const EXIF_SUBSEC_TIME_TAG_NAME: &str = "Exif.Photo.SubSecTimeOriginal";
+
+/// The exiv2 tags from which to read the title, tried in order.
+const TITLE_TAG_NAMES: &[&str] =
+    &["Xmp.dc.title", "Iptc.Application2.ObjectName"];
+
+/// The exiv2 tags from which to read the description, tried in order.
+const DESCRIPTION_TAG_NAMES: &[&str] = &[
+    "Xmp.dc.description",
+    "Iptc.Application2.Caption",
+    "Exif.Image.ImageDescription",
+];
+
 /// The exiv2 tag designated for keywords.
 const IPTC_KEYWORDS_TAG_NAME: &str = &"Iptc.Application2.Keywords";
 
+/// The exiv2 tag designated for hierarchical keywords.
+const XMP_HIERARCHICAL_SUBJECT_TAG_NAME: &str = "Xmp.lr.hierarchicalSubject";
+
+/// The separator between levels of hierarchical keywords.
+const HIERARCHICAL_SUBJECT_SEPARATOR: char = '|';
+
+lazy_static! {
+    /// The regular expression matching exiv2 tags for names of image regions.
+    static ref XMP_REGION_NAME_TAG_RE: regex::Regex = regex::Regex::new(
+        r"^Xmp\.mwg-rs\.Regions/mwg-rs:RegionList\[[0-9]+\]/mwg-rs:Name$",
+    ).unwrap();
+}
+
+/// A tag from which to read the timestamp of an item.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimestampTag {
+    /// The exiv2 tag name.
+    name: String,
+
+    /// The format of the tag value.
+    format: String,
+}
+
+impl TimestampTag {
+    /// Parses a tag value.
+    ///
+    /// The calendar time is returned along with whether its offset was
+    /// parsed, which is the case when the format contains `%z`.
+    ///
+    /// # Arguments
+    /// *  `value` - The tag value.
+    fn parse(&self, value: &str) -> Option<(time::Tm, bool)> {
+        time::strptime(value, &self.format)
+            .ok()
+            .map(|tm| (tm, self.format.contains("%z")))
+    }
+}
+
+impl Default for TimestampTag {
+    /// The default timestamp tag; [`EXIT_TIMESTAMP_TAG_NAME`].
+    ///
+    /// [`EXIT_TIMESTAMP_TAG_NAME`]: constant.EXIT_TIMESTAMP_TAG_NAME.html
+    fn default() -> Self {
+        EXIT_TIMESTAMP_TAG_NAME.parse().unwrap()
+    }
+}
+
+impl str::FromStr for TimestampTag {
+    type Err = String;
+
+    /// Parses a timestamp tag.
+    ///
+    /// The string is either a tag name, or a tag name followed by `"="` and
+    /// the format of the tag value. If no format is specified,
+    /// [`EXIT_TIMESTAMP_TAG_FORMAT`] is used.
+    ///
+    /// [`EXIT_TIMESTAMP_TAG_FORMAT`]: constant.EXIT_TIMESTAMP_TAG_FORMAT.html
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(""), _) => {
+                Err(format!("invalid timestamp tag: {}", s))
+            }
+            (Some(name), Some(format)) if !format.is_empty() => Ok(Self {
+                name: name.into(),
+                format: format.into(),
+            }),
+            (Some(name), None) => Ok(Self {
+                name: name.into(),
+                format: EXIT_TIMESTAMP_TAG_FORMAT.into(),
+            }),
+            _ => Err(format!("invalid timestamp tag: {}", s)),
+        }
+    }
+}
+
+/// A pattern from which to read the timestamp of an item from its file name.
+///
+/// The pattern is a regular expression with the named groups `year`, `month`
+/// and `day`, and optionally `hour`, `minute` and `second`. Missing time
+/// groups are taken as zero.
+#[derive(Clone, Debug)]
+pub struct FilenameDate {
+    /// The regular expression.
+    regex: regex::Regex,
+}
+
+impl FilenameDate {
+    /// Reads a timestamp from a file name.
+    ///
+    /// If the name does not match, or the matched date is invalid, `None` is
+    /// returned.
+    ///
+    /// # Arguments
+    /// *  `name` - The file name.
+    fn timestamp(&self, name: &str) -> Option<time::Tm> {
+        let captures = self.regex.captures(name)?;
+        let group = |name| captures.name(name).map(|m| m.as_str());
+        time::strptime(
+            &format!(
+                "{}-{}-{} {}:{}:{}",
+                group("year")?,
+                group("month")?,
+                group("day")?,
+                group("hour").unwrap_or("00"),
+                group("minute").unwrap_or("00"),
+                group("second").unwrap_or("00"),
+            ),
+            "%Y-%m-%d %H:%M:%S",
+        ).ok()
+    }
+}
+
+impl str::FromStr for FilenameDate {
+    type Err = String;
+
+    /// Parses a file name date pattern.
+    ///
+    /// The pattern must be a valid regular expression with at least the
+    /// groups `year`, `month` and `day`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regex = regex::Regex::new(s)
+            .map_err(|e| format!("invalid file name date {}: {}", s, e))?;
+        let names = regex.capture_names().collect::<Vec<_>>();
+        if ["year", "month", "day"]
+            .iter()
+            .all(|name| names.contains(&Some(name)))
+        {
+            Ok(Self { regex })
+        } else {
+            Err(format!(
+                "file name date without year, month and day: {}",
+                s
+            ))
+        }
+    }
+}
+
+/// The offset of the clock of a camera.
+///
+/// Timestamps read from images taken with the camera are corrected by
+/// subtracting the offset, so a camera whose clock runs two hours fast has
+/// the offset `+02:00`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraOffset {
+    /// The camera model, as written in [`EXIF_MODEL_TAG_NAME`].
+    ///
+    /// [`EXIF_MODEL_TAG_NAME`]: constant.EXIF_MODEL_TAG_NAME.html
+    model: String,
+
+    /// The offset of the clock in seconds.
+    offset: i32,
+}
+
+impl CameraOffset {
+    /// Corrects a timestamp read from an image taken with this camera.
+    ///
+    /// The offset of the timestamp from UTC is kept.
+    ///
+    /// # Arguments
+    /// *  `timestamp` - The timestamp to correct.
+    fn apply(&self, timestamp: time::Tm) -> time::Tm {
+        let utcoff = timestamp.tm_utcoff;
+        let corrected = time::at_utc(
+            timestamp.to_timespec()
+                + time::Duration::seconds(i64::from(utcoff - self.offset)),
+        );
+        time::Tm {
+            tm_utcoff: utcoff,
+            ..corrected
+        }
+    }
+}
+
+impl str::FromStr for CameraOffset {
+    type Err = String;
+
+    /// Parses a camera offset.
+    ///
+    /// The string is a camera model followed by `"="` and an offset on the
+    /// form `+HH:MM` or `-HH:MM`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid camera offset: {}", s);
+        let mut parts = s.rsplitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(offset), Some(model)) if !model.trim().is_empty() => {
+                match offset.parse::<data::Zone>() {
+                    Ok(data::Zone::Offset(offset)) => Ok(Self {
+                        model: model.trim().into(),
+                        offset,
+                    }),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// The handling of source files whose content changes while their path stays
+/// the same.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Changed {
+    /// The metadata is read again when the modification time or size of the
+    /// file has changed, so that the item is filed anew on the next refresh.
+    Reread,
+
+    /// The metadata read first is kept until the file is removed.
+    Keep,
+}
+
+/// The state of a source file and its sidecar date file when its metadata was
+/// read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stamp {
+    /// The modification time and size of the file, if changes are detected.
+    file: Option<(std::time::SystemTime, u64)>,
+
+    /// The modification time of the sidecar date file, if any.
+    sidecar: Option<std::time::SystemTime>,
+}
+
 /// Information about a file.
 enum ItemMeta {
     /// The file does not contain any metadata.
-    Missing,
+    Missing {
+        /// The number of pages of a multi-page file.
+        pages: Option<u32>,
+
+        /// The size of the file in bytes.
+        size: Option<u64>,
+    },
+
+    /// Metadata read from the file.
+    Present {
+        /// The timestamp.
+        timestamp: time::Tm,
+
+        /// The tags.
+        tags: collections::HashSet<String>,
+
+        /// The names of people appearing in the image.
+        people: collections::HashSet<String>,
+
+        /// The rating.
+        rating: Option<u32>,
 
-    /// A timestamp and a collection of tags.
-    Present(time::Tm, collections::HashSet<String>),
+        /// The camera.
+        camera: Option<String>,
+
+        /// The lens.
+        lens: Option<String>,
+
+        /// The name of the colour space.
+        colorspace: Option<String>,
+
+        /// The exposure settings.
+        settings: Option<String>,
+
+        /// The altitude in metres.
+        altitude: Option<String>,
+
+        /// The direction in degrees.
+        direction: Option<String>,
+
+        /// The width and height in pixels.
+        dimensions: Option<(u32, u32)>,
+
+        /// The sub-second part of the timestamp in nanoseconds.
+        subsecond: Option<u32>,
+
+        /// The title and description.
+        captions: collections::HashMap<data::Caption, String>,
+
+        /// The source of the timestamp.
+        date_source: data::DateSource,
+
+        /// The number of pages of a multi-page file.
+        pages: Option<u32>,
+
+        /// The size of the file in bytes.
+        size: Option<u64>,
+    },
 }
 
 impl ItemMeta {
@@ -44,86 +467,858 @@ impl ItemMeta {
     /// *  `path` - The path of the source item.
     fn item(&self, path: &path::Path) -> data::Item {
         match self {
-            &ItemMeta::Missing => path.into(),
-            &ItemMeta::Present(timestamp, ref tags) => {
-                data::Item::new(path, timestamp, tags.clone())
-            }
+            &ItemMeta::Missing { pages, size } => data::Item {
+                pages,
+                size,
+                ..path.into()
+            },
+            &ItemMeta::Present {
+                timestamp,
+                ref tags,
+                ref people,
+                rating,
+                ref camera,
+                ref lens,
+                ref colorspace,
+                ref settings,
+                ref altitude,
+                ref direction,
+                dimensions,
+                subsecond,
+                ref captions,
+                date_source,
+                pages,
+                size,
+            } => data::Item {
+                people: people.clone(),
+                rating,
+                camera: camera.clone(),
+                lens: lens.clone(),
+                colorspace: colorspace.clone(),
+                settings: settings.clone(),
+                altitude: altitude.clone(),
+                direction: direction.clone(),
+                dimensions,
+                subsecond,
+                captions: captions.clone(),
+                date_source,
+                pages,
+                size,
+                ..data::Item::new(path, timestamp, tags.clone())
+            },
         }
     }
 
-    /// Converts a path to a timestamp.
+    /// Converts a path to a timestamp and its source.
     ///
-    /// This function first attempts to read the EXIF tag, and then falls back
-    /// on the file modification timestamp.
+    /// This function first attempts to read the timestamp tags in order, then
+    /// the file name date patterns, and finally falls back on the file
+    /// modification timestamp. Timestamps read from tags or file names are
+    /// interpreted in `zone`, unless they were read from a tag whose format
+    /// includes an offset.
     ///
     /// # Arguments
     /// *  `path` - The source path.
     /// *  `meta` - Image metadata.
+    /// *  `timestamp_tags` - The tags from which to read the timestamp.
+    /// *  `filename_dates` - The file name date patterns.
+    /// *  `zone` - The zone of timestamps without an offset.
     fn timestamp<P: AsRef<path::Path>>(
         path: &P,
         meta: &rexiv2::Metadata,
-    ) -> time::Tm {
-        meta.get_tag_string(EXIT_TIMESTAMP_TAG_NAME)
-            .ok()
-            .and_then(|s| time::strptime(&s, EXIT_TIMESTAMP_TAG_FORMAT).ok())
+        timestamp_tags: &[TimestampTag],
+        filename_dates: &[FilenameDate],
+        zone: data::Zone,
+    ) -> (time::Tm, data::DateSource) {
+        timestamp_tags
+            .iter()
+            .filter_map(|tag| {
+                meta.get_tag_string(&tag.name)
+                    .ok()
+                    .and_then(|s| tag.parse(&s))
+            })
+            .next()
+            .or_else(|| {
+                Self::filename_timestamp(path.as_ref(), filename_dates)
+                    .map(|timestamp| (timestamp, false))
+            })
+            .map(|(timestamp, parsed_offset)| {
+                (
+                    zone.apply(timestamp, parsed_offset),
+                    data::DateSource::Metadata,
+                )
+            })
             .unwrap_or_else(|| {
-                time::at(data::system_time_to_timespec(data::timestamp(path)))
+                let timestamp = data::timestamp(path)
+                    .map(|t| time::at(data::system_time_to_timespec(t)))
+                    .unwrap_or_else(|| {
+                        *data::Timestamp::undated().as_ref()
+                    });
+                (timestamp, data::DateSource::Modified)
             })
     }
 
-    /// Converts a path to a tag collection.
+    /// Reads the timestamp from the file name of a path.
+    ///
+    /// The patterns are tried in order, and if none matches, `None` is
+    /// returned.
+    ///
+    /// # Arguments
+    /// *  `path` - The source path.
+    /// *  `filename_dates` - The file name date patterns.
+    fn filename_timestamp(
+        path: &path::Path,
+        filename_dates: &[FilenameDate],
+    ) -> Option<time::Tm> {
+        let name = path.file_name()?.to_str()?;
+        filename_dates
+            .iter()
+            .filter_map(|pattern| pattern.timestamp(name))
+            .next()
+    }
+
+    /// Reads the timestamp from the sidecar date file of a path.
+    ///
+    /// The sidecar file is named as the source file with
+    /// [`SIDECAR_DATE_EXTENSION`] appended, and contains a date on the form
+    /// `YYYY-MM-DD`, optionally followed by a time.
     ///
-    /// This function first attempts to read to IPTC keywords, and then falls
-    /// back on an empty set.
+    /// If no sidecar file exists, or its content cannot be parsed, `None` is
+    /// returned.
     ///
     /// # Arguments
     /// *  `path` - The source path.
+    ///
+    /// [`SIDECAR_DATE_EXTENSION`]: constant.SIDECAR_DATE_EXTENSION.html
+    fn sidecar_timestamp(path: &path::Path) -> Option<time::Tm> {
+        std::fs::read_to_string(sidecar(path)).ok().and_then(|s| {
+            let s = s.trim();
+            SIDECAR_DATE_FORMATS
+                .iter()
+                .filter_map(|format| time::strptime(s, format).ok())
+                .next()
+        })
+    }
+
+    /// Converts a path to a tag collection.
+    ///
+    /// This function reads both the IPTC keywords and the XMP hierarchical
+    /// keywords, and merges them. Whitespace surrounding each level of a
+    /// keyword is removed, and keywords with empty levels are ignored. If
+    /// neither is present, an empty set is returned.
+    ///
+    /// # Arguments
     /// *  `meta` - Image metadata.
-    fn tags(meta: &rexiv2::Metadata) -> collections::HashSet<String> {
-        meta.get_tag_multiple_strings(IPTC_KEYWORDS_TAG_NAME)
+    /// *  `separator` - The separator between the levels of keywords.
+    fn tags(
+        meta: &rexiv2::Metadata,
+        separator: char,
+    ) -> collections::HashSet<String> {
+        let keywords = meta
+            .get_tag_multiple_strings(IPTC_KEYWORDS_TAG_NAME)
+            .unwrap_or_else(|_| Vec::new());
+        let subjects = meta
+            .get_tag_multiple_strings(XMP_HIERARCHICAL_SUBJECT_TAG_NAME)
+            .unwrap_or_else(|_| Vec::new());
+        keywords
+            .iter()
+            .filter_map(|s| data::Tag::parse(s, separator).ok())
+            .map(|tag| tag.join(separator))
+            .chain(subjects.iter().map(|s| hierarchical_subject(s, separator)))
+            .collect()
+    }
+
+    /// Reads the names of people appearing in an image.
+    ///
+    /// The names are read from the named image regions, and if none are
+    /// present, an empty set is returned.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn people(meta: &rexiv2::Metadata) -> collections::HashSet<String> {
+        meta.get_xmp_tags()
             .map(|tags| {
                 tags.iter()
-                    .map(|s| s.parse().unwrap_or_else(|_| s.clone()))
+                    .filter(|tag| XMP_REGION_NAME_TAG_RE.is_match(tag))
+                    .filter_map(|tag| meta.get_tag_string(tag).ok())
+                    .filter(|name| !name.is_empty())
                     .collect()
             })
             .unwrap_or_else(|_| collections::HashSet::new())
     }
-}
 
-impl<P: AsRef<path::Path>> From<P> for ItemMeta {
-    /// Converts a path to item metadata.
+    /// Reads the rating of an image.
+    ///
+    /// If no rating is present, `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn rating(meta: &rexiv2::Metadata) -> Option<u32> {
+        meta.get_tag_string(XMP_RATING_TAG_NAME)
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+    }
+
+    /// Reads the camera with which an image was taken.
+    ///
+    /// The camera is named by its model, prefixed by its manufacturer unless
+    /// the model already includes it. If no model is present, `None` is
+    /// returned.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn camera(meta: &rexiv2::Metadata) -> Option<String> {
+        Self::model(meta).map(|model| {
+            camera_name(trimmed(meta, EXIF_MAKE_TAG_NAME).as_ref(), model)
+        })
+    }
+
+    /// Reads the model of the camera with which an image was taken.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn model(meta: &rexiv2::Metadata) -> Option<String> {
+        trimmed(meta, EXIF_MODEL_TAG_NAME)
+    }
+
+    /// Finds the clock offset of the camera with which an image was taken.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    /// *  `camera_offsets` - The clock offsets of cameras.
+    fn camera_offset<'a>(
+        meta: &rexiv2::Metadata,
+        camera_offsets: &'a [CameraOffset],
+    ) -> Option<&'a CameraOffset> {
+        if camera_offsets.is_empty() {
+            return None;
+        }
+        let model = Self::model(meta)?;
+        camera_offsets.iter().find(|offset| offset.model == model)
+    }
+
+    /// Reads the model of the lens with which an image was taken.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn lens(meta: &rexiv2::Metadata) -> Option<String> {
+        meta.get_tag_string(EXIF_LENS_MODEL_TAG_NAME)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+
+    /// Reads the name of the colour space of an image.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn colorspace(meta: &rexiv2::Metadata) -> Option<String> {
+        let read = |name| {
+            meta.get_tag_string(name)
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        colorspace_name(
+            read(XMP_ICC_PROFILE_TAG_NAME),
+            read(EXIF_COLOR_SPACE_TAG_NAME).and_then(|s| s.parse().ok()),
+            read(EXIF_INTEROPERABILITY_INDEX_TAG_NAME),
+        )
+    }
+
+    /// Reads the exposure settings with which an image was taken.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn settings(meta: &rexiv2::Metadata) -> Option<String> {
+        let read = |name| {
+            meta.get_tag_string(name)
+                .ok()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        settings_description(
+            read(EXIF_F_NUMBER_TAG_NAME).and_then(|s| rational(&s)),
+            read(EXIF_EXPOSURE_TIME_TAG_NAME).and_then(|s| rational(&s)),
+            read(EXIF_ISO_SPEED_TAG_NAME).and_then(|s| {
+                s.split_whitespace().next().and_then(|s| s.parse().ok())
+            }),
+            read(EXIF_FOCAL_LENGTH_TAG_NAME).and_then(|s| rational(&s)),
+        )
+    }
+
+    /// Reads the GPS altitude at which an image was taken.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn altitude(meta: &rexiv2::Metadata) -> Option<String> {
+        let reference = trimmed(meta, EXIF_GPS_ALTITUDE_REF_TAG_NAME);
+        trimmed(meta, EXIF_GPS_ALTITUDE_TAG_NAME).and_then(|altitude| {
+            altitude_description(&altitude, reference.as_ref())
+        })
+    }
+
+    /// Reads the GPS direction in which an image was taken.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn direction(meta: &rexiv2::Metadata) -> Option<String> {
+        trimmed(meta, EXIF_GPS_IMG_DIRECTION_TAG_NAME)
+            .and_then(|direction| direction_description(&direction))
+    }
+
+    /// Reads the width and height of an image as displayed.
+    ///
+    /// If the orientation of the image indicates that it is rotated by 90
+    /// degrees, the width and height are swapped. If either is missing,
+    /// `None` is returned.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn dimensions(meta: &rexiv2::Metadata) -> Option<(u32, u32)> {
+        let read = |name| {
+            meta.get_tag_string(name)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+        };
+        read(EXIF_PIXEL_X_DIMENSION_TAG_NAME)
+            .and_then(|x| read(EXIF_PIXEL_Y_DIMENSION_TAG_NAME).map(|y| (x, y)))
+            .map(|dimensions| {
+                oriented(dimensions, read(EXIF_ORIENTATION_TAG_NAME))
+            })
+    }
+
+    /// Reads the sub-second part of the creation time of an image.
     ///
-    /// This implementation reads the ags from the source image, and if they are
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn subsecond(meta: &rexiv2::Metadata) -> Option<u32> {
+        meta.get_tag_string(EXIF_SUBSEC_TIME_TAG_NAME)
+            .ok()
+            .and_then(|s| subsecond(&s))
+    }
+
+    /// Reads the title and description of an image.
+    ///
+    /// Each is read from the first of its tags that is present and not
+    /// empty; missing texts are not included.
+    ///
+    /// # Arguments
+    /// *  `meta` - Image metadata.
+    fn captions(
+        meta: &rexiv2::Metadata,
+    ) -> collections::HashMap<data::Caption, String> {
+        let read = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| meta.get_tag_string(name).ok())
+                .filter_map(|s| caption(&s))
+                .next()
+        };
+        vec![
+            (data::Caption::Title, read(TITLE_TAG_NAMES)),
+            (data::Caption::Description, read(DESCRIPTION_TAG_NAMES)),
+        ].into_iter()
+            .filter_map(|(caption, text)| text.map(|text| (caption, text)))
+            .collect()
+    }
+
+    /// Reads item metadata for a path.
+    ///
+    /// This function reads the tags from the source image, and if they are
     /// present constructs metadata from them, otherwise only file metadata is
     /// used.
     ///
+    /// A timestamp read from a sidecar date file takes precedence over both,
+    /// and a timestamp read from the file name is used for files without
+    /// metadata.
+    ///
     /// # Arguments
     /// *  `source` - The source path.
-    fn from(source: P) -> Self {
-        rexiv2::Metadata::new_from_path(source.as_ref())
+    /// *  `timestamp_tags` - The tags from which to read the timestamp.
+    /// *  `filename_dates` - The file name date patterns.
+    /// *  `zone` - The zone of timestamps without an offset.
+    /// *  `camera_offsets` - The clock offsets of cameras.
+    /// *  `tag_separator` - The separator between the levels of keywords.
+    fn read<P: AsRef<path::Path>>(
+        source: P,
+        timestamp_tags: &[TimestampTag],
+        filename_dates: &[FilenameDate],
+        zone: data::Zone,
+        camera_offsets: &[CameraOffset],
+        tag_separator: char,
+    ) -> Self {
+        let pages = pages::read(source.as_ref());
+        let size = std::fs::metadata(source.as_ref()).ok().map(|m| m.len());
+        let meta = rexiv2::Metadata::new_from_path(source.as_ref())
             .map(|meta| {
-                ItemMeta::Present(
-                    Self::timestamp(&source, &meta),
-                    Self::tags(&meta),
-                )
+                let (timestamp, date_source) = Self::timestamp(
+                    &source,
+                    &meta,
+                    timestamp_tags,
+                    filename_dates,
+                    zone,
+                );
+                let timestamp = match date_source {
+                    data::DateSource::Metadata => {
+                        Self::camera_offset(&meta, camera_offsets)
+                            .map_or(timestamp, |offset| offset.apply(timestamp))
+                    }
+                    _ => timestamp,
+                };
+                ItemMeta::Present {
+                    timestamp,
+                    tags: Self::tags(&meta, tag_separator),
+                    people: Self::people(&meta),
+                    rating: Self::rating(&meta),
+                    camera: Self::camera(&meta),
+                    lens: Self::lens(&meta),
+                    colorspace: Self::colorspace(&meta),
+                    settings: Self::settings(&meta),
+                    altitude: Self::altitude(&meta),
+                    direction: Self::direction(&meta),
+                    dimensions: Self::dimensions(&meta),
+                    subsecond: Self::subsecond(&meta),
+                    captions: Self::captions(&meta),
+                    date_source,
+                    pages,
+                    size,
+                }
             })
-            .unwrap_or(ItemMeta::Missing)
+            .unwrap_or(ItemMeta::Missing { pages, size });
+
+        let timestamp = Self::sidecar_timestamp(source.as_ref()).or_else(|| {
+            match meta {
+                ItemMeta::Missing { .. } => {
+                    Self::filename_timestamp(source.as_ref(), filename_dates)
+                }
+                _ => None,
+            }
+        }).map(|timestamp| zone.apply(timestamp, false));
+        match (timestamp, meta) {
+            (
+                Some(timestamp),
+                ItemMeta::Present {
+                    tags,
+                    people,
+                    rating,
+                    camera,
+                    lens,
+                    colorspace,
+                    settings,
+                    altitude,
+                    direction,
+                    dimensions,
+                    subsecond,
+                    captions,
+                    pages,
+                    size,
+                    ..
+                },
+            ) => ItemMeta::Present {
+                timestamp,
+                tags,
+                people,
+                rating,
+                camera,
+                lens,
+                colorspace,
+                settings,
+                altitude,
+                direction,
+                dimensions,
+                subsecond,
+                captions,
+                date_source: data::DateSource::Metadata,
+                pages,
+                size,
+            },
+            (Some(timestamp), ItemMeta::Missing { pages, size }) => {
+                ItemMeta::Present {
+                    timestamp,
+                    tags: collections::HashSet::new(),
+                    people: collections::HashSet::new(),
+                    rating: None,
+                    camera: None,
+                    lens: None,
+                    colorspace: None,
+                    settings: None,
+                    altitude: None,
+                    direction: None,
+                    dimensions: None,
+                    subsecond: None,
+                    captions: collections::HashMap::new(),
+                    date_source: data::DateSource::Metadata,
+                    pages,
+                    size,
+                }
+            }
+            (None, meta) => meta,
+        }
+    }
+}
+
+/// Converts a hierarchical keyword to a tag.
+///
+/// The levels of the keyword are separated by
+/// [`HIERARCHICAL_SUBJECT_SEPARATOR`], and are converted to the parts of the
+/// tag. Empty levels are dropped.
+///
+/// # Arguments
+/// *  `subject` - The hierarchical keyword.
+/// *  `separator` - The separator between the levels of the tag.
+///
+/// [`HIERARCHICAL_SUBJECT_SEPARATOR`]:
+/// constant.HIERARCHICAL_SUBJECT_SEPARATOR.html
+fn hierarchical_subject(subject: &str, separator: char) -> String {
+    data::Tag {
+        parts: subject
+            .split(HIERARCHICAL_SUBJECT_SEPARATOR)
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(String::from)
+            .collect(),
+    }.join(separator)
+}
+
+/// Corrects the width and height of an image for its orientation.
+///
+/// The EXIF orientations `5` to `8` all include a rotation by 90 degrees, so
+/// for them the width and height are swapped. Other orientations, and a
+/// missing orientation, leave them unchanged.
+///
+/// # Arguments
+/// *  `dimensions` - The width and height as stored.
+/// *  `orientation` - The EXIF orientation, if known.
+fn oriented(dimensions: (u32, u32), orientation: Option<u32>) -> (u32, u32) {
+    match orientation {
+        Some(5..=8) => (dimensions.1, dimensions.0),
+        _ => dimensions,
+    }
+}
+
+/// Reads a tag as a string with surrounding whitespace removed.
+///
+/// If the tag is missing or empty, `None` is returned.
+///
+/// # Arguments
+/// *  `meta` - Image metadata.
+/// *  `name` - The exiv2 tag name.
+fn trimmed(meta: &rexiv2::Metadata, name: &str) -> Option<String> {
+    meta.get_tag_string(name)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// Generates the name of a camera from its manufacturer and model.
+///
+/// The manufacturer is omitted if the model already starts with it, ignoring
+/// case, as is common.
+///
+/// # Arguments
+/// *  `make` - The manufacturer, if known.
+/// *  `model` - The model.
+fn camera_name(make: Option<&String>, model: String) -> String {
+    match make {
+        Some(make)
+            if !model.to_lowercase().starts_with(&make.to_lowercase()) =>
+        {
+            format!("{} {}", make, model)
+        }
+        _ => model,
+    }
+}
+
+/// Generates the name of the colour space of an image.
+///
+/// The description of an embedded ICC profile names the colour space most
+/// precisely and is preferred. Otherwise the EXIF colour space is used; it
+/// only distinguishes sRGB from uncalibrated images, but by the DCF
+/// convention an uncalibrated image with the interoperability index `R03`
+/// is Adobe RGB.
+///
+/// # Arguments
+/// *  `profile` - The description of the ICC profile, if known.
+/// *  `color_space` - The EXIF colour space, if known.
+/// *  `interoperability` - The EXIF interoperability index, if known.
+fn colorspace_name(
+    profile: Option<String>,
+    color_space: Option<u32>,
+    interoperability: Option<String>,
+) -> Option<String> {
+    profile.or_else(|| match (color_space, interoperability) {
+        (Some(1), _) => Some("sRGB".into()),
+        (Some(0xFFFF), Some(ref index)) if index == "R03" => {
+            Some("Adobe RGB".into())
+        }
+        _ => None,
+    })
+}
+
+/// Describes the exposure settings with which an image was taken.
+///
+/// The settings are listed as `f/2.8 1/250 ISO400 50mm`. Exposure times
+/// that are close to a fraction of a second such as `1/2` or `1/250` are
+/// written as such fractions, and other ones in seconds. Unknown settings are
+/// omitted, and if all are unknown, `None` is returned.
+///
+/// # Arguments
+/// *  `f_number` - The aperture as an f-number, if known.
+/// *  `exposure_time` - The exposure time in seconds, if known.
+/// *  `iso` - The ISO speed, if known.
+/// *  `focal_length` - The focal length in millimetres, if known.
+fn settings_description(
+    f_number: Option<f64>,
+    exposure_time: Option<f64>,
+    iso: Option<u32>,
+    focal_length: Option<f64>,
+) -> Option<String> {
+    let parts = vec![
+        f_number.map(|f| format!("f/{}", decimal(f))),
+        exposure_time.map(|t| {
+            let reciprocal = 1.0 / t;
+            let rounded = reciprocal.round();
+            if rounded >= 2.0 && (reciprocal - rounded).abs() < rounded / 100.0
+            {
+                format!("1/{}", rounded)
+            } else {
+                format!("{}s", decimal(t))
+            }
+        }),
+        iso.map(|iso| format!("ISO{}", iso)),
+        focal_length.map(|l| format!("{}mm", decimal(l))),
+    ].into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+/// Describes a GPS altitude in metres.
+///
+/// The altitude is negated if the reference is `1`, meaning that it is
+/// below sea level. Invalid altitudes yield `None`.
+///
+/// # Arguments
+/// *  `altitude` - The value of the altitude tag.
+/// *  `reference` - The value of the altitude reference tag, if present.
+fn altitude_description(
+    altitude: &str,
+    reference: Option<&String>,
+) -> Option<String> {
+    let altitude = fraction(altitude).filter(|&altitude| altitude >= 0.0)?;
+    Some(match reference.map(String::as_str) {
+        Some("1") if altitude > 0.0 => format!("-{}", decimal(altitude)),
+        _ => decimal(altitude),
+    })
+}
+
+/// Describes a GPS direction in degrees.
+///
+/// Directions outside of `[0, 360)` yield `None`.
+///
+/// # Arguments
+/// *  `direction` - The value of the direction tag.
+fn direction_description(direction: &str) -> Option<String> {
+    fraction(direction)
+        .filter(|&direction| (0.0..360.0).contains(&direction))
+        .map(decimal)
+}
+
+/// Formats a number with at most one decimal.
+///
+/// # Arguments
+/// *  `value` - The number.
+fn decimal(value: f64) -> String {
+    let result = format!("{:.1}", value);
+    if result.ends_with(".0") {
+        result[..result.len() - 2].to_string()
+    } else {
+        result
+    }
+}
+
+/// Converts the value of a rational EXIF tag to a number.
+///
+/// The value is read as `numerator/denominator`, or as a plain number. Only
+/// positive values are accepted.
+///
+/// # Arguments
+/// *  `value` - The tag value.
+fn rational(value: &str) -> Option<f64> {
+    fraction(value).filter(|&result| result > 0.0)
+}
+
+/// Converts the value of a rational EXIF tag to a finite number.
+///
+/// The value is read as `numerator/denominator`, or as a plain number.
+///
+/// # Arguments
+/// *  `value` - The tag value.
+fn fraction(value: &str) -> Option<f64> {
+    let mut parts = value.trim().splitn(2, '/');
+    let numerator = parts.next()?.trim().parse::<f64>().ok()?;
+    let result = match parts.next() {
+        Some(denominator) => {
+            numerator / denominator.trim().parse::<f64>().ok()?
+        }
+        None => numerator,
+    };
+    Some(result).filter(|&result| result.is_finite())
+}
+
+/// Converts the value of a sub-second EXIF tag to nanoseconds.
+///
+/// The value holds the leading decimals of the fraction of a second, so
+/// `"5"` and `"500"` both denote half a second. Digits beyond nanoseconds are
+/// ignored.
+///
+/// # Arguments
+/// *  `value` - The tag value.
+fn subsecond(value: &str) -> Option<u32> {
+    let digits = value.trim();
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(Some(0u32), |acc, b| acc.map(|n| n * 10 + u32::from(b - b'0')))
+}
+
+/// Converts the value of a text tag to a caption.
+///
+/// XMP language alternatives are read as the text of their default language,
+/// prefixed by `lang="x-default" `, so any such prefix is removed. If the
+/// text is empty, `None` is returned.
+///
+/// # Arguments
+/// *  `value` - The tag value.
+fn caption(value: &str) -> Option<String> {
+    let value = value.trim();
+    let text = if let Some(rest) = value.strip_prefix("lang=\"") {
+        rest.find('"')
+            .map(|end| rest[end + 1..].trim_start())
+            .unwrap_or(value)
+    } else {
+        value
+    };
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
     }
 }
 
+/// Generates the path of the sidecar date file for a path.
+///
+/// # Arguments
+/// *  `path` - The source path.
+fn sidecar(path: &path::Path) -> path::PathBuf {
+    let mut result = path.as_os_str().to_os_string();
+    result.push(SIDECAR_DATE_EXTENSION);
+    result.into()
+}
+
 impl FileSystemItemGenerator for TagsSource {
     /// Generates an item from a path.
     ///
+    /// Cached metadata is reused unless the sidecar date file has been
+    /// added, removed or modified since it was read.
+    ///
     /// # Arguments
     /// *  `path` - The path for which to generate an item.
     fn item(&self, path: &path::Path) -> data::Item {
         let key = path.to_path_buf();
+        let stamp = Stamp {
+            file: match self.changed {
+                Changed::Reread => path.metadata()
+                    .and_then(|m| m.modified().map(|t| (t, m.len())))
+                    .ok(),
+                Changed::Keep => None,
+            },
+            sidecar: sidecar(path).metadata().and_then(|m| m.modified()).ok(),
+        };
         self.tags
             .write()
             .map(|mut tags| {
-                tags.entry(key).or_insert_with(|| path.into()).item(&path)
+                let read = || {
+                    ItemMeta::read(
+                        path,
+                        &self.timestamp_tags,
+                        &self.filename_dates,
+                        self.exif_zone,
+                        &self.camera_offsets,
+                        self.tag_separator,
+                    )
+                };
+                let entry = tags.entry(key).or_insert_with(|| (stamp, read()));
+                if entry.0 != stamp {
+                    *entry = (stamp, read());
+                }
+                entry.1.item(path)
+            })
+            .unwrap_or_else(|_| {
+                ItemMeta::read(
+                    path,
+                    &self.timestamp_tags,
+                    &self.filename_dates,
+                    self.exif_zone,
+                    &self.camera_offsets,
+                    self.tag_separator,
+                ).item(path)
             })
-            .unwrap_or_else(|_| path.into())
+    }
+
+    /// Discards cached metadata for files no longer present.
+    ///
+    /// # Arguments
+    /// *  `paths` - The paths found during the scan.
+    fn retain(&self, paths: &collections::HashSet<path::PathBuf>) {
+        if let Ok(mut tags) = self.tags.write() {
+            tags.retain(|path, _| paths.contains(path));
+        }
+    }
+
+    /// Lists the tags and file name patterns from which timestamps are read,
+    /// the zone in which they are interpreted and the handling of changed
+    /// files.
+    fn settings(&self) -> Vec<(String, String)> {
+        self.timestamp_tags
+            .iter()
+            .map(|tag| {
+                (
+                    "timestamp-tag".into(),
+                    format!("{}={}", tag.name, tag.format),
+                )
+            })
+            .chain(self.filename_dates.iter().map(|pattern| {
+                ("filename-date".into(), pattern.regex.as_str().into())
+            }))
+            .chain(Some(("exif-zone".into(), self.exif_zone.to_string())))
+            .chain(self.camera_offsets.iter().map(|offset| {
+                (
+                    "camera-offset".into(),
+                    format!(
+                        "{}={}",
+                        offset.model,
+                        data::Zone::Offset(offset.offset),
+                    ),
+                )
+            }))
+            .chain(Some((
+                "changed-files".into(),
+                match self.changed {
+                    Changed::Reread => "reread",
+                    Changed::Keep => "keep",
+                }.into(),
+            )))
+            .collect()
     }
 }
 
@@ -131,20 +1326,1168 @@ impl ConfigurableSource for TagsSource {
     const SUBCOMMAND_NAME: &'static str = "tags";
 
     fn options<'a>(app: clap::App<'a, 'a>) -> clap::App<'a, 'a> {
-        options(app)
+        options(app).arg(
+            clap::Arg::with_name(OPT_TIMESTAMP_TAG)
+                .help(concat!(
+                    "A tag from which to read the item timestamp, optionally ",
+                    "followed by \"=\" and the format of the value. This may ",
+                    "be repeated; the tags are tried in order before falling ",
+                    "back on the file modification time. The default is ",
+                    "Exif.Photo.DateTimeOriginal=%Y:%m:%d %H:%M:%S.",
+                ))
+                .long("timestamp-tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        ).arg(
+            clap::Arg::with_name(OPT_FILENAME_DATE)
+                .help(concat!(
+                    "A regular expression with the named groups year, month ",
+                    "and day, and optionally hour, minute and second, from ",
+                    "which to read the item timestamp when no timestamp tag ",
+                    "is present. This may be repeated; the patterns are ",
+                    "matched against the file name in order. The default ",
+                    "patterns match common phone and screenshot names.",
+                ))
+                .long("filename-date")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        ).arg(
+            clap::Arg::with_name(OPT_EXIF_ZONE)
+                .help(concat!(
+                    "The zone in which timestamps without an offset, such as ",
+                    "EXIF timestamps, are interpreted when presented as file ",
+                    "times; utc, local for the zone of the host, or an ",
+                    "offset on the form +HH:MM. Directory names always use ",
+                    "the timestamp as written.",
+                ))
+                .long("exif-zone")
+                .takes_value(true)
+                .default_value("utc"),
+        ).arg(
+            clap::Arg::with_name(OPT_CAMERA_OFFSET)
+                .help(concat!(
+                    "The offset of the clock of a camera, on the form ",
+                    "Model=+HH:MM where Model is the value of ",
+                    "Exif.Image.Model. Timestamps read from images taken ",
+                    "with the camera are corrected by subtracting the ",
+                    "offset, so +02:00 corrects a clock running two hours ",
+                    "fast. This may be repeated.",
+                ))
+                .long("camera-offset")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        ).arg(
+            clap::Arg::with_name(OPT_CHANGED_FILES)
+                .help(concat!(
+                    "How to handle files changed since their metadata was ",
+                    "read; reread reads it again when the modification time ",
+                    "or size of a file has changed, so that the next refresh ",
+                    "files the item anew, and keep keeps the metadata first ",
+                    "read.",
+                ))
+                .long("changed-files")
+                .takes_value(true)
+                .possible_values(&["reread", "keep"])
+                .default_value("reread"),
+        ).arg(
+            clap::Arg::with_name(OPT_METADATA_RETRIES)
+                .help(concat!(
+                    "The number of times to retry initializing the metadata ",
+                    "library, one second apart, before giving up. If it ",
+                    "cannot be initialized, no metadata could be read, so ",
+                    "the source fails to start.",
+                ))
+                .long("metadata-retries")
+                .takes_value(true)
+                .default_value("0"),
+        )
     }
 }
 
 impl ConstructableSource for TagsSource {
     fn construct<'a>(
         cache: files::Cache,
+        filter: data::SharedFilter,
         args: &clap::ArgMatches<'a>,
     ) -> Result<Self, String> {
+        let retries = args.value_of(OPT_METADATA_RETRIES)
+            .unwrap()
+            .parse()
+            .map_err(|_| "invalid number of metadata retries".to_string())?;
+        verify_metadata(probe, retries, METADATA_RETRY_DELAY)?;
+
+        let tag_separator = cache.read().unwrap().tag_separator();
         Ok(TagsSource {
             cache,
-            root: args.value_of(OPT_ROOT).map(|v| v.into()).unwrap(),
-            timestamp: None,
+            root: root(args)?,
+            timestamp: std::sync::Mutex::new(None),
+            scanning: std::sync::atomic::AtomicBool::new(false),
+            reload_pending: std::sync::atomic::AtomicBool::new(false),
+            filter,
+            tags_from_path: args.is_present(OPT_TAGS_FROM_PATH),
+            scan_depth: scan_depth(args)?,
+            limit: limit(args)?,
+            skip_hidden: args.is_present(OPT_SKIP_HIDDEN),
+            tag_aliases: tag_aliases(args)?,
+            tag_separator,
+            undated: undated(args),
+            min_date: min_date(args)?,
+            progress: progress(args)?,
             tags: sync::RwLock::new(collections::HashMap::new()),
+            changed: match args.value_of(OPT_CHANGED_FILES) {
+                Some("keep") => Changed::Keep,
+                _ => Changed::Reread,
+            },
+            timestamp_tags: timestamp_tags(args)?,
+            filename_dates: filename_dates(args)?,
+            exif_zone: args.value_of(OPT_EXIF_ZONE).unwrap().parse()?,
+            camera_offsets: camera_offsets(args)?,
         })
     }
 }
+
+/// Verifies that the metadata library is able to read metadata at all.
+///
+/// A known valid image fails to load only if the library itself is broken,
+/// which would otherwise make every file silently appear to lack metadata.
+/// After a failure, the check is retried up to `retries` times.
+///
+/// # Arguments
+/// *  `probe` - The check to perform.
+/// *  `retries` - The number of times to retry a failed check.
+/// *  `delay` - The delay before each retry.
+fn verify_metadata<F>(
+    probe: F,
+    retries: usize,
+    delay: std::time::Duration,
+) -> Result<(), String>
+where
+    F: Fn() -> Result<(), String>,
+{
+    let mut attempt = 0;
+    loop {
+        match probe() {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < retries => {
+                warn!("Failed to initialize metadata library: {}", e);
+                attempt += 1;
+                std::thread::sleep(delay);
+            }
+            Err(e) => {
+                return Err(format!(
+                    "failed to initialize the metadata library: {}",
+                    e
+                ))
+            }
+        }
+    }
+}
+
+/// Reads the metadata of [`PROBE_IMAGE`].
+///
+/// [`PROBE_IMAGE`]: constant.PROBE_IMAGE.html
+fn probe() -> Result<(), String> {
+    rexiv2::Metadata::new_from_buffer(PROBE_IMAGE)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Reads the timestamp tags from command line arguments.
+///
+/// If no tags are passed, only the default tag is used.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn timestamp_tags<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<Vec<TimestampTag>, String> {
+    args.values_of(OPT_TIMESTAMP_TAG)
+        .map(|values| values.map(str::parse).collect())
+        .unwrap_or_else(|| Ok(vec![TimestampTag::default()]))
+}
+
+/// Reads the file name date patterns from command line arguments.
+///
+/// If no patterns are passed, the default patterns are used.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn filename_dates<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<Vec<FilenameDate>, String> {
+    args.values_of(OPT_FILENAME_DATE)
+        .map(|values| values.map(str::parse).collect())
+        .unwrap_or_else(|| FILENAME_DATES.iter().map(|s| s.parse()).collect())
+}
+
+/// Reads the camera clock offsets from command line arguments.
+///
+/// # Arguments
+/// *  `args` - Command line arguments.
+fn camera_offsets<'a>(
+    args: &clap::ArgMatches<'a>,
+) -> Result<Vec<CameraOffset>, String> {
+    args.values_of(OPT_CAMERA_OFFSET)
+        .map(|values| values.map(str::parse).collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use tempdir;
+
+    use probe;
+
+    use super::*;
+
+    impl TagsSource {
+        /// Creates a source with default options and an empty cache.
+        ///
+        /// # Arguments
+        /// *  `root` - The source directory.
+        fn new(root: &path::Path) -> Self {
+            TagsSource {
+                root: root.to_path_buf(),
+                cache: files::Cache::new(sync::RwLock::new(
+                    data::cache::Cache::new("All".into(), "Tagged".into()),
+                )),
+                timestamp: sync::Mutex::new(None),
+                scanning: std::sync::atomic::AtomicBool::new(false),
+                reload_pending: std::sync::atomic::AtomicBool::new(false),
+                filter: data::shared_filter(data::Filter::default()),
+                tags_from_path: false,
+                scan_depth: None,
+                limit: None,
+                skip_hidden: false,
+                tag_aliases: collections::HashMap::new(),
+                tag_separator: data::Tag::DEFAULT_SEPARATOR,
+                undated: Undated::Now,
+                min_date: None,
+                progress: None,
+                tags: sync::RwLock::new(collections::HashMap::new()),
+                changed: Changed::Reread,
+                timestamp_tags: vec![TimestampTag::default()],
+                filename_dates: Vec::new(),
+                exif_zone: data::Zone::Utc,
+                camera_offsets: Vec::new(),
+            }
+        }
+    }
+
+    /// Writes a minimal JPEG file carrying an XMP packet.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the file to write.
+    /// *  `xmp` - The XMP packet.
+    fn jpeg_with_xmp<P: AsRef<path::Path>>(path: P, xmp: &str) {
+        let namespace = b"http://ns.adobe.com/xap/1.0/\0";
+        let length = 2 + namespace.len() + xmp.len();
+        let mut data =
+            vec![0xFF, 0xD8, 0xFF, 0xE1, (length >> 8) as u8, length as u8];
+        data.extend(namespace.iter());
+        data.extend(xmp.as_bytes());
+        data.extend(&[0xFF, 0xD9]);
+        fs::File::create(path)
+            .and_then(|mut f| f.write_all(&data))
+            .unwrap();
+    }
+
+    /// A value of an EXIF tag written by [`jpeg_with_exif`].
+    ///
+    /// [`jpeg_with_exif`]: fn.jpeg_with_exif.html
+    #[derive(Clone, Copy)]
+    enum ExifValue<'a> {
+        Byte(u8),
+        Ascii(&'a str),
+        Short(u16),
+        Long(u32),
+        Rational(u32, u32),
+    }
+
+    /// Writes a minimal JPEG file carrying EXIF metadata.
+    ///
+    /// The TIFF structure contains IFD0, followed by the EXIF and GPS IFDs if
+    /// they have any tags, and finally the values that do not fit in their
+    /// entries.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the file to write.
+    /// *  `ifd0` - The tags of IFD0.
+    /// *  `exif` - The tags of the EXIF IFD.
+    /// *  `gps` - The tags of the GPS IFD.
+    fn jpeg_with_exif<P: AsRef<path::Path>>(
+        path: P,
+        ifd0: &[(u16, ExifValue)],
+        exif: &[(u16, ExifValue)],
+        gps: &[(u16, ExifValue)],
+    ) {
+        let size = |ifd: &[(u16, ExifValue)]| {
+            if ifd.is_empty() {
+                0
+            } else {
+                2 + 12 * ifd.len() as u32 + 4
+            }
+        };
+        let mut entries = ifd0.to_vec();
+        let pointers = [(0x8769, exif), (0x8825, gps)];
+        entries.extend(
+            pointers
+                .iter()
+                .filter(|&&(_, ifd)| !ifd.is_empty())
+                .map(|&(tag, _)| (tag, ExifValue::Long(0))),
+        );
+        let exif_offset = 8 + size(&entries);
+        let gps_offset = exif_offset + size(exif);
+        let values_offset = gps_offset + size(gps);
+        for entry in entries.iter_mut() {
+            match entry.0 {
+                0x8769 => entry.1 = ExifValue::Long(exif_offset),
+                0x8825 => entry.1 = ExifValue::Long(gps_offset),
+                _ => {}
+            }
+        }
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(&8u32.to_le_bytes());
+        let mut values = Vec::new();
+        for ifd in [&entries[..], exif, gps].iter().filter(|i| !i.is_empty()) {
+            tiff.extend(&(ifd.len() as u16).to_le_bytes());
+            for &(tag, value) in ifd.iter() {
+                let (type_, count, mut bytes) = match value {
+                    ExifValue::Byte(v) => (1u16, 1, vec![v]),
+                    ExifValue::Ascii(v) => (
+                        2,
+                        v.len() as u32 + 1,
+                        v.bytes().chain(Some(0)).collect::<Vec<_>>(),
+                    ),
+                    ExifValue::Short(v) => (3, 1, v.to_le_bytes().to_vec()),
+                    ExifValue::Long(v) => (4, 1, v.to_le_bytes().to_vec()),
+                    ExifValue::Rational(n, d) => {
+                        let mut bytes = n.to_le_bytes().to_vec();
+                        bytes.extend(&d.to_le_bytes());
+                        (5, 1, bytes)
+                    }
+                };
+                tiff.extend(&tag.to_le_bytes());
+                tiff.extend(&type_.to_le_bytes());
+                tiff.extend(&count.to_le_bytes());
+                if bytes.len() <= 4 {
+                    bytes.resize(4, 0);
+                    tiff.extend(bytes);
+                } else {
+                    let offset = values_offset + values.len() as u32;
+                    tiff.extend(&offset.to_le_bytes());
+                    values.extend(bytes);
+                    if values.len() % 2 == 1 {
+                        values.push(0);
+                    }
+                }
+            }
+            tiff.extend(&0u32.to_le_bytes());
+        }
+        tiff.extend(values);
+
+        let length = 2 + 6 + tiff.len();
+        let mut data =
+            vec![0xFF, 0xD8, 0xFF, 0xE1, (length >> 8) as u8, length as u8];
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend(&[0xFF, 0xD9]);
+        fs::write(path, data).unwrap();
+    }
+
+    /// Tests that only region name tags are matched.
+    #[test]
+    fn region_name_tags() {
+        assert!(XMP_REGION_NAME_TAG_RE
+            .is_match("Xmp.mwg-rs.Regions/mwg-rs:RegionList[1]/mwg-rs:Name"));
+        assert!(XMP_REGION_NAME_TAG_RE
+            .is_match("Xmp.mwg-rs.Regions/mwg-rs:RegionList[12]/mwg-rs:Name"));
+        assert!(!XMP_REGION_NAME_TAG_RE
+            .is_match("Xmp.mwg-rs.Regions/mwg-rs:RegionList[1]/mwg-rs:Type"));
+    }
+
+    /// Tests that named image regions are read as people.
+    #[test]
+    fn people_from_regions() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_xmp(
+            &path,
+            concat!(
+                r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+                r#"<rdf:RDF xmlns:rdf="#,
+                r#""http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+                r#"<rdf:Description rdf:about="" xmlns:mwg-rs="#,
+                r#""http://www.metadataworkinggroup.com/schemas/regions/">"#,
+                r#"<mwg-rs:Regions rdf:parseType="Resource">"#,
+                r#"<mwg-rs:RegionList><rdf:Bag>"#,
+                r#"<rdf:li rdf:parseType="Resource">"#,
+                r#"<mwg-rs:Name>Alice</mwg-rs:Name></rdf:li>"#,
+                r#"<rdf:li rdf:parseType="Resource">"#,
+                r#"<mwg-rs:Name>Bob</mwg-rs:Name></rdf:li>"#,
+                r#"</rdf:Bag></mwg-rs:RegionList></mwg-rs:Regions>"#,
+                r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            ),
+        );
+
+        assert_eq!(
+            vec!["Alice", "Bob"]
+                .into_iter()
+                .map(String::from)
+                .collect::<collections::HashSet<_>>(),
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &[],
+                data::Zone::Utc,
+                &[],
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .people,
+        );
+    }
+
+    /// Tests that the title and description of an image are read from XMP,
+    /// and the description from EXIF when XMP is missing.
+    #[test]
+    fn captions_from_image() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let xmp = dir.path().join("xmp.jpg");
+        jpeg_with_xmp(
+            &xmp,
+            concat!(
+                r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+                r#"<rdf:RDF xmlns:rdf="#,
+                r#""http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+                r#"<rdf:Description rdf:about="" "#,
+                r#"xmlns:dc="http://purl.org/dc/elements/1.1/">"#,
+                r#"<dc:title><rdf:Alt>"#,
+                r#"<rdf:li xml:lang="x-default">Sunset</rdf:li>"#,
+                r#"</rdf:Alt></dc:title>"#,
+                r#"<dc:description><rdf:Alt>"#,
+                r#"<rdf:li xml:lang="x-default">Over the sea</rdf:li>"#,
+                r#"</rdf:Alt></dc:description>"#,
+                r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            ),
+        );
+        let exif = dir.path().join("exif.jpg");
+        jpeg_with_exif(
+            &exif,
+            &[(0x010E, ExifValue::Ascii("From the camera"))],
+            &[],
+            &[],
+        );
+        let source = TagsSource::new(dir.path());
+
+        assert_eq!(
+            vec![
+                (data::Caption::Title, "Sunset".to_string()),
+                (data::Caption::Description, "Over the sea".to_string()),
+            ].into_iter()
+                .collect::<collections::HashMap<_, _>>(),
+            source.item(&xmp).captions,
+        );
+        assert_eq!(
+            vec![(data::Caption::Description, "From the camera".to_string())]
+                .into_iter()
+                .collect::<collections::HashMap<_, _>>(),
+            source.item(&exif).captions,
+        );
+    }
+
+    /// Tests that a sidecar date file overrides the EXIF date and the file
+    /// modification time.
+    #[test]
+    fn sidecar_date() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_exif(
+            &path,
+            &[],
+            &[(0x9003, ExifValue::Ascii("2010:05:06 07:08:09"))],
+            &[],
+        );
+        let source = TagsSource::new(dir.path());
+        assert_eq!(
+            "2010-05-06 07:08",
+            source.item(&path).timestamp.to_string(),
+        );
+
+        fs::write(sidecar(&path), "1950-01-02\n").unwrap();
+        assert_eq!(
+            "1950-01-02 00:00",
+            source.item(&path).timestamp.to_string(),
+        );
+
+        fs::write(sidecar(&path), "1951-03-04 05:06").unwrap();
+        let stamp = sidecar(&path).metadata().unwrap().modified().unwrap();
+        source.tags.write().unwrap().get_mut(&path).unwrap().0.sidecar =
+            Some(stamp - std::time::Duration::from_secs(1));
+        assert_eq!(
+            "1951-03-04 05:06",
+            source.item(&path).timestamp.to_string(),
+        );
+    }
+
+    /// Tests that probing a file describes the item a scan would generate.
+    #[test]
+    fn probe_tagged() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("Holiday").join("test.jpg");
+        fs::create_dir(path.parent().unwrap()).unwrap();
+        fs::write(&path, [0xFF, 0xD8, 0xFF, 0xE1, 0x10, 0x00, 0x45]).unwrap();
+        fs::write(sidecar(&path), "2010-05-06 07:08").unwrap();
+        let source = TagsSource {
+            tags_from_path: true,
+            ..TagsSource::new(dir.path())
+        };
+
+        let description = probe::describe(&source.probe(&path).unwrap());
+        assert!(description.contains("name = 2010-05-06 07:08.jpeg\n"));
+        assert!(description.contains("timestamp = 2010-05-06 07:08:00"));
+        assert!(description.contains("date-source = metadata\n"));
+        assert!(description.contains("media-type = image/jpeg\n"));
+        assert!(description.contains("tags = Holiday\n"));
+        assert!(source.cache.read().unwrap().items().is_empty());
+    }
+
+    /// Tests that truncated images are included without metadata.
+    #[test]
+    fn truncated() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        fs::write(&path, [0xFF, 0xD8, 0xFF, 0xE1, 0x10, 0x00, 0x45]).unwrap();
+        let source = TagsSource::new(dir.path());
+
+        let item = source.item(&path);
+        assert_eq!(
+            data::Timestamp::from(data::timestamp(&path).unwrap()).to_string(),
+            item.timestamp.to_string(),
+        );
+        assert_eq!(data::DateSource::Modified, item.date_source);
+        assert!(item.tags.is_empty());
+        assert!(item.people.is_empty());
+    }
+
+    /// Tests that a multi-page image is a single item with a page count.
+    #[test]
+    fn multi_page() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("scan.tif");
+        fs::write(&path, pages::tests::tiff_data(&[2, 2])).unwrap();
+        let source = TagsSource::new(dir.path());
+
+        source.populate();
+        let cache = source.cache.read().unwrap();
+        let items = cache.items();
+        assert_eq!(1, items.len());
+        assert_eq!(Some(2), items[0].pages);
+        assert_eq!(Some(fs::metadata(&path).unwrap().len()), items[0].size);
+        assert!(probe::describe(items[0]).contains("pages = 2\n"));
+    }
+
+    /// Tests that timestamp tags are parsed.
+    #[test]
+    fn timestamp_tag_from_str() {
+        assert_eq!(
+            Ok(TimestampTag {
+                name: "Exif.Image.DateTime".into(),
+                format: EXIT_TIMESTAMP_TAG_FORMAT.into(),
+            }),
+            "Exif.Image.DateTime".parse::<TimestampTag>(),
+        );
+        assert_eq!(
+            Ok(TimestampTag {
+                name: "Xmp.xmp.CreateDate".into(),
+                format: "%Y-%m-%dT%H:%M:%S".into(),
+            }),
+            "Xmp.xmp.CreateDate=%Y-%m-%dT%H:%M:%S".parse::<TimestampTag>(),
+        );
+        assert!("".parse::<TimestampTag>().is_err());
+        assert!("=%Y".parse::<TimestampTag>().is_err());
+        assert!("Exif.Image.DateTime=".parse::<TimestampTag>().is_err());
+    }
+
+    /// Tests that tag values report whether their offset was parsed.
+    #[test]
+    fn timestamp_tag_parse() {
+        let tag = "Xmp.xmp.CreateDate=%Y-%m-%dT%H:%M:%S%z"
+            .parse::<TimestampTag>()
+            .unwrap();
+        let (tm, parsed_offset) = tag.parse("2010-05-06T07:08:09Z").unwrap();
+        assert_eq!((0, true), (tm.tm_utcoff, parsed_offset));
+        let (tm, parsed_offset) =
+            tag.parse("2010-05-06T07:08:09+02:00").unwrap();
+        assert_eq!((7200, true), (tm.tm_utcoff, parsed_offset));
+
+        let (_, parsed_offset) = TimestampTag::default()
+            .parse("2010:05:06 07:08:09")
+            .unwrap();
+        assert!(!parsed_offset);
+        assert_eq!(None, TimestampTag::default().parse("invalid"));
+    }
+
+    /// Tests that timestamp tags are tried in order.
+    #[test]
+    fn timestamp_tag_fallback() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_xmp(
+            &path,
+            concat!(
+                r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+                r#"<rdf:RDF xmlns:rdf="#,
+                r#""http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+                r#"<rdf:Description rdf:about="" xmlns:xmp="#,
+                r#""http://ns.adobe.com/xap/1.0/">"#,
+                r#"<xmp:CreateDate>2010-05-06T07:08:09</xmp:CreateDate>"#,
+                r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            ),
+        );
+        let source = TagsSource {
+            timestamp_tags: vec![
+                TimestampTag::default(),
+                "Xmp.xmp.CreateDate=%Y-%m-%dT%H:%M:%S".parse().unwrap(),
+            ],
+            ..TagsSource::new(dir.path())
+        };
+
+        assert_eq!(
+            "2010-05-06 07:08",
+            source.item(&path).timestamp.to_string(),
+        );
+    }
+
+    /// Tests that a file whose timestamp is edited is filed anew on the next
+    /// refresh.
+    #[test]
+    fn changed_file_refiled() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        let xmp = |timestamp: &str| {
+            format!(
+                concat!(
+                    r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+                    r#"<rdf:RDF xmlns:rdf="#,
+                    r#""http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+                    r#"<rdf:Description rdf:about="" xmlns:xmp="#,
+                    r#""http://ns.adobe.com/xap/1.0/">"#,
+                    r#"<xmp:CreateDate>{}</xmp:CreateDate>"#,
+                    r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+                ),
+                timestamp,
+            )
+        };
+        jpeg_with_xmp(&path, &xmp("2010-05-06T07:08:09"));
+        let source = TagsSource {
+            timestamp_tags: vec![
+                "Xmp.xmp.CreateDate=%Y-%m-%dT%H:%M:%S".parse().unwrap(),
+            ],
+            ..TagsSource::new(dir.path())
+        };
+        let exists = |path: &str| {
+            source.cache.read().unwrap().lookup(&path).is_some()
+        };
+
+        source.populate();
+        assert!(exists("/All/2010/05/06/2010-05-06 07:08.jpeg"));
+
+        // Pad the packet so that the size changes even if the modification
+        // time does not
+        jpeg_with_xmp(&path, &(xmp("2011-07-08T09:10:11") + " "));
+        source.populate();
+        assert!(!exists("/All/2010"));
+        assert!(exists("/All/2011/07/08/2011-07-08 09:10.jpeg"));
+    }
+
+    /// Tests that file name date patterns are parsed.
+    #[test]
+    fn filename_date_from_str() {
+        assert!(FILENAME_DATES
+            .iter()
+            .all(|s| s.parse::<FilenameDate>().is_ok()));
+        assert!("(?P<year>[0-9]{4})(".parse::<FilenameDate>().is_err());
+        assert!("(?P<year>[0-9]{4})(?P<month>[0-9]{2})"
+            .parse::<FilenameDate>()
+            .is_err());
+    }
+
+    /// Tests that timestamps are read from common file names.
+    #[test]
+    fn filename_date_defaults() {
+        let patterns = FILENAME_DATES
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<FilenameDate>>();
+        let timestamp = |name| {
+            ItemMeta::filename_timestamp(path::Path::new(name), &patterns)
+                .map(|t| data::Timestamp::from(t).to_string())
+        };
+
+        assert_eq!(
+            Some("2020-01-01 12:00".into()),
+            timestamp("/photos/Screenshot_20200101-120000.png"),
+        );
+        assert_eq!(
+            Some("2020-01-01 00:00".into()),
+            timestamp("/photos/IMG-20200101-WA0001.jpg"),
+        );
+        assert_eq!(
+            Some("2021-02-03 04:05".into()),
+            timestamp("/photos/IMG_20210203_040506.jpg"),
+        );
+        assert_eq!(
+            Some("2021-02-03 16:05".into()),
+            timestamp("/photos/Screenshot 2021-02-03 at 16.05.06.png"),
+        );
+        assert_eq!(None, timestamp("/photos/IMG-20201340-WA0001.jpg"));
+        assert_eq!(None, timestamp("/photos/IMG_1234.jpg"));
+    }
+
+    /// Tests that the file name is used for files without metadata.
+    #[test]
+    fn filename_date_fallback() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("IMG-20200101-WA0001.jpg");
+        fs::write(&path, [0xFF, 0xD8, 0xFF, 0xE1, 0x10, 0x00, 0x45]).unwrap();
+        let patterns = FILENAME_DATES
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect::<Vec<FilenameDate>>();
+
+        assert_eq!(
+            "2020-01-01 00:00",
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &patterns,
+                data::Zone::Utc,
+                &[],
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .timestamp
+                .to_string(),
+        );
+        assert_ne!(
+            "2020-01-01 00:00",
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &[],
+                data::Zone::Utc,
+                &[],
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .timestamp
+                .to_string(),
+        );
+    }
+
+    /// Tests that cached metadata for removed files is discarded.
+    #[test]
+    fn retain_scanned() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let kept = dir.path().join("kept.jpg");
+        let removed = dir.path().join("removed.jpg");
+        jpeg_with_xmp(&kept, "");
+        jpeg_with_xmp(&removed, "");
+        let source = TagsSource::new(dir.path());
+
+        source.populate();
+        assert!(source.tags.read().unwrap().contains_key(&kept));
+        assert!(source.tags.read().unwrap().contains_key(&removed));
+
+        fs::remove_file(&removed).unwrap();
+        source.populate();
+        assert!(source.tags.read().unwrap().contains_key(&kept));
+        assert!(!source.tags.read().unwrap().contains_key(&removed));
+    }
+
+    /// Tests that hierarchical keywords are converted to tags.
+    #[test]
+    fn hierarchical_subject_to_tag() {
+        assert_eq!(
+            "Places/Europe/France",
+            hierarchical_subject("Places|Europe|France", '/'),
+        );
+        assert_eq!("Places", hierarchical_subject("Places", '/'));
+        assert_eq!(
+            "Places/France",
+            hierarchical_subject("Places||France|", '/'),
+        );
+        assert_eq!(
+            "Places>Europe/Asia",
+            hierarchical_subject("Places|Europe/Asia", '>'),
+        );
+    }
+
+    /// Tests that sub-second values are converted to nanoseconds.
+    #[test]
+    fn subsecond_values() {
+        assert_eq!(Some(500_000_000), subsecond("5"));
+        assert_eq!(Some(50_000_000), subsecond("05 "));
+        assert_eq!(Some(123_456_789), subsecond("1234567891"));
+        assert_eq!(None, subsecond(""));
+        assert_eq!(None, subsecond("1.5"));
+    }
+
+    /// Tests that language prefixes are removed from captions.
+    #[test]
+    fn caption_values() {
+        assert_eq!(
+            Some("A beach".to_string()),
+            caption("lang=\"x-default\" A beach"),
+        );
+        assert_eq!(Some("A beach".to_string()), caption(" A beach\n"));
+        assert_eq!(None, caption("lang=\"x-default\" "));
+        assert_eq!(None, caption(""));
+    }
+
+    /// Tests that rotated images have their width and height swapped.
+    #[test]
+    fn oriented_dimensions() {
+        assert_eq!((4000, 3000), oriented((4000, 3000), None));
+        assert_eq!((4000, 3000), oriented((4000, 3000), Some(1)));
+        assert_eq!((4000, 3000), oriented((4000, 3000), Some(3)));
+        assert_eq!((3000, 4000), oriented((4000, 3000), Some(6)));
+        assert_eq!((3000, 4000), oriented((4000, 3000), Some(8)));
+    }
+
+    /// Tests that the manufacturer is included in camera names only once.
+    #[test]
+    fn camera_names() {
+        assert_eq!(
+            "Canon EOS 5D",
+            camera_name(Some(&"Canon".into()), "Canon EOS 5D".into()),
+        );
+        assert_eq!(
+            "NIKON CORPORATION D750",
+            camera_name(Some(&"NIKON CORPORATION".into()), "D750".into()),
+        );
+        assert_eq!("D750", camera_name(None, "D750".into()));
+    }
+
+    /// Tests that colour space names prefer the ICC profile.
+    #[test]
+    fn colorspace_names() {
+        assert_eq!(
+            Some("ProPhoto RGB".to_string()),
+            colorspace_name(Some("ProPhoto RGB".into()), Some(1), None),
+        );
+        assert_eq!(
+            Some("sRGB".to_string()),
+            colorspace_name(None, Some(1), None),
+        );
+        assert_eq!(
+            Some("Adobe RGB".to_string()),
+            colorspace_name(None, Some(0xFFFF), Some("R03".into())),
+        );
+        assert_eq!(
+            None,
+            colorspace_name(None, Some(0xFFFF), Some("R98".into())),
+        );
+        assert_eq!(None, colorspace_name(None, None, None));
+    }
+
+    /// Tests that the colour space is read from the ICC profile description.
+    #[test]
+    fn colorspace_from_profile() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_xmp(
+            &path,
+            concat!(
+                r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+                r#"<rdf:RDF xmlns:rdf="#,
+                r#""http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+                r#"<rdf:Description rdf:about="" xmlns:photoshop="#,
+                r#""http://ns.adobe.com/photoshop/1.0/">"#,
+                r#"<photoshop:ICCProfile>Adobe RGB (1998)"#,
+                r#"</photoshop:ICCProfile>"#,
+                r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            ),
+        );
+
+        assert_eq!(
+            Some("Adobe RGB (1998)".to_string()),
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &[],
+                data::Zone::Utc,
+                &[],
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .colorspace,
+        );
+    }
+
+    /// Tests that exposure settings are formatted and that unknown settings
+    /// are omitted.
+    #[test]
+    fn settings_descriptions() {
+        assert_eq!(
+            Some("f/2.8 1/250 ISO400 50mm".to_string()),
+            settings_description(
+                rational("28/10"),
+                rational("1/250"),
+                Some(400),
+                rational("50/1"),
+            ),
+        );
+        assert_eq!(
+            Some("f/8 2.5s 4.5mm".to_string()),
+            settings_description(
+                rational("8"),
+                rational("5/2"),
+                None,
+                rational("45/10"),
+            ),
+        );
+        assert_eq!(
+            Some("ISO100".to_string()),
+            settings_description(None, rational("0/1"), Some(100), None),
+        );
+        for &(exposure_time, expected) in &[
+            ("1/2", "1/2"),
+            ("10/30", "1/3"),
+            ("1666667/100000000", "1/60"),
+            ("8/10", "0.8s"),
+            ("4/10", "0.4s"),
+            ("1/1", "1s"),
+        ] {
+            assert_eq!(
+                Some(expected.to_string()),
+                settings_description(None, rational(exposure_time), None, None),
+            );
+        }
+        assert_eq!(None, settings_description(None, None, None, None));
+        assert_eq!(None, rational("1/0"));
+        assert_eq!(None, rational("bad"));
+    }
+
+    /// Tests that GPS altitudes and directions are formatted, and that
+    /// invalid values are rejected.
+    #[test]
+    fn gps_descriptions() {
+        let below = "1".to_string();
+        let above = "0".to_string();
+        assert_eq!(
+            Some("1234.5".to_string()),
+            altitude_description("12345/10", Some(&above)),
+        );
+        assert_eq!(
+            Some("-12".to_string()),
+            altitude_description("12/1", Some(&below)),
+        );
+        assert_eq!(
+            Some("0".to_string()),
+            altitude_description("0/1", Some(&below)),
+        );
+        assert_eq!(Some("30".to_string()), altitude_description("30", None));
+        assert_eq!(None, altitude_description("1/0", None));
+        assert_eq!(None, altitude_description("-5/1", None));
+
+        assert_eq!(Some("0".to_string()), direction_description("0/100"));
+        assert_eq!(
+            Some("271.3".to_string()),
+            direction_description("27130/100"),
+        );
+        assert_eq!(None, direction_description("360/1"));
+        assert_eq!(None, direction_description("bad"));
+    }
+
+    /// Tests that the GPS altitude and direction are read from EXIF tags.
+    #[test]
+    fn gps_from_exif() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_exif(
+            &path,
+            &[],
+            &[],
+            &[
+                (0x0005, ExifValue::Byte(1)),
+                (0x0006, ExifValue::Rational(125, 10)),
+                (0x0011, ExifValue::Rational(9050, 100)),
+            ],
+        );
+
+        let item = ItemMeta::read(
+            &path,
+            &[TimestampTag::default()],
+            &[],
+            data::Zone::Utc,
+            &[],
+            data::Tag::DEFAULT_SEPARATOR,
+        ).item(&path);
+        assert_eq!(Some("-12.5".to_string()), item.altitude);
+        assert_eq!(Some("90.5".to_string()), item.direction);
+    }
+
+    /// Tests that exposure settings are read from EXIF tags.
+    #[test]
+    fn settings_from_exif() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_exif(
+            &path,
+            &[],
+            &[
+                (0x829A, ExifValue::Rational(1, 250)),
+                (0x829D, ExifValue::Rational(28, 10)),
+                (0x8827, ExifValue::Short(400)),
+                (0x920A, ExifValue::Rational(50, 1)),
+            ],
+            &[],
+        );
+
+        assert_eq!(
+            Some("f/2.8 1/250 ISO400 50mm".to_string()),
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &[],
+                data::Zone::Utc,
+                &[],
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .settings,
+        );
+    }
+
+    /// Tests that camera offsets are parsed.
+    #[test]
+    fn camera_offset_from_str() {
+        assert_eq!(
+            Ok(CameraOffset {
+                model: "Camera 2".into(),
+                offset: 7200,
+            }),
+            "Camera 2=+02:00".parse::<CameraOffset>(),
+        );
+        assert_eq!(
+            Ok(CameraOffset {
+                model: "A=B".into(),
+                offset: -1800,
+            }),
+            "A=B=-00:30".parse::<CameraOffset>(),
+        );
+        assert!("Camera".parse::<CameraOffset>().is_err());
+        assert!("=+02:00".parse::<CameraOffset>().is_err());
+        assert!("Camera=utc".parse::<CameraOffset>().is_err());
+    }
+
+    /// Tests that timestamps of images taken with a camera with an offset
+    /// are corrected, and that those of other cameras are not.
+    #[test]
+    fn camera_offset_applied() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_exif(
+            &path,
+            &[(0x0110, ExifValue::Ascii("Second"))],
+            &[(0x9003, ExifValue::Ascii("2020:01:02 01:00:00"))],
+            &[],
+        );
+
+        let timestamp = |camera_offsets: &[CameraOffset]| {
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &[],
+                data::Zone::Utc,
+                camera_offsets,
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .timestamp
+                .to_string()
+        };
+        assert_eq!("2020-01-02 01:00", timestamp(&[]));
+        assert_eq!(
+            "2020-01-01 23:00",
+            timestamp(&["Second=+02:00".parse().unwrap()]),
+        );
+        assert_eq!(
+            "2020-01-02 01:00",
+            timestamp(&["First=+02:00".parse().unwrap()]),
+        );
+    }
+
+    /// Tests that hierarchical keywords are read as tags.
+    #[test]
+    fn hierarchical_subject_tags() {
+        let dir = tempdir::TempDir::new("medifs-source").unwrap();
+        let path = dir.path().join("test.jpg");
+        jpeg_with_xmp(
+            &path,
+            concat!(
+                r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">"#,
+                r#"<rdf:RDF xmlns:rdf="#,
+                r#""http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#,
+                r#"<rdf:Description rdf:about="" xmlns:lr="#,
+                r#""http://ns.adobe.com/lightroom/1.0/">"#,
+                r#"<lr:hierarchicalSubject><rdf:Bag>"#,
+                r#"<rdf:li>Places|Europe|France</rdf:li>"#,
+                r#"</rdf:Bag></lr:hierarchicalSubject>"#,
+                r#"</rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            ),
+        );
+
+        assert_eq!(
+            vec!["Places/Europe/France"]
+                .into_iter()
+                .map(String::from)
+                .collect::<collections::HashSet<_>>(),
+            ItemMeta::read(
+                &path,
+                &[TimestampTag::default()],
+                &[],
+                data::Zone::Utc,
+                &[],
+                data::Tag::DEFAULT_SEPARATOR,
+            ).item(&path)
+                .tags,
+        );
+    }
+
+    /// Tests that a broken metadata library is reported after the retries.
+    #[test]
+    fn metadata_init_failure() {
+        let attempts = std::cell::Cell::new(0);
+        let failing = || {
+            attempts.set(attempts.get() + 1);
+            Err("not initialized".to_string())
+        };
+
+        assert_eq!(
+            Err(
+                "failed to initialize the metadata library: not initialized"
+                    .to_string()
+            ),
+            verify_metadata(failing, 2, std::time::Duration::from_secs(0)),
+        );
+        assert_eq!(3, attempts.get());
+
+        attempts.set(0);
+        let recovering = || {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 2 {
+                Err("not initialized".to_string())
+            } else {
+                Ok(())
+            }
+        };
+        assert_eq!(
+            Ok(()),
+            verify_metadata(recovering, 1, std::time::Duration::from_secs(0)),
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use data;
+use files;
+
+use super::Source;
+
+/// A source of items held in memory.
+///
+/// This source does not read from any data store; the items are supplied
+/// when it is created. It is useful for reproducing the behaviour of the
+/// cache and the locators deterministically.
+pub struct MemorySource {
+    /// The cache to which to add items.
+    cache: files::Cache,
+
+    /// The filter selecting which items to add.
+    filter: data::SharedFilter,
+
+    /// The items of this source.
+    items: Vec<data::Item>,
+}
+
+impl MemorySource {
+    /// Creates a new memory source.
+    ///
+    /// # Arguments
+    /// *  `cache` - The cache to which to add items.
+    /// *  `items` - The items of this source.
+    pub fn new(cache: files::Cache, items: Vec<data::Item>) -> Self {
+        Self {
+            cache,
+            filter: data::shared_filter(data::Filter::default()),
+            items,
+        }
+    }
+
+    /// Sets the filter selecting which items to add.
+    ///
+    /// # Arguments
+    /// *  `filter` - The filter.
+    pub fn with_filter(mut self, filter: data::SharedFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Replaces all items in the cache with the items of this source passing
+    /// the filter.
+    ///
+    /// The identities used to detect duplicates are calculated before the
+    /// cache is locked.
+    fn populate(&self) {
+        let filter = match self.filter.read() {
+            Ok(filter) => filter.clone(),
+            Err(_) => return,
+        };
+        let identifier = match self.cache.read() {
+            Ok(cache) => cache.identifier(),
+            Err(_) => return,
+        };
+        let items = self.items
+            .iter()
+            .filter(|item| filter.matches(item))
+            .map(|item| data::Item {
+                identity: identifier.key(item),
+                ..item.clone()
+            })
+            .collect::<Vec<_>>();
+        if let Ok(mut cache) = self.cache.write() {
+            let result = cache.replace_all(items.into_iter());
+            if let Err(items) = result {
+                for item in items {
+                    warn!("Failed to add {}", item.path.display());
+                }
+            }
+        }
+    }
+}
+
+impl Source for MemorySource {
+    /// Starts this source by adding all items to the cache.
+    fn start(&mut self) {
+        self.populate();
+    }
+
+    /// The items never change, so this does nothing.
+    fn notify(&self) {}
+
+    /// Adds all items to the cache again, applying the current filter.
+    fn reload(&self) {
+        self.populate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync;
+
+    use data::tests::*;
+    use super::*;
+
+    /// Creates an empty cache.
+    fn cache() -> files::Cache {
+        files::Cache::new(sync::RwLock::new(data::cache::Cache::new(
+            "All".into(),
+            "Tagged".into(),
+        )))
+    }
+
+    /// Tests that starting the source adds all items.
+    #[test]
+    fn start() {
+        let cache = cache();
+        let mut source = MemorySource::new(
+            cache.clone(),
+            vec![item("test1.jpg", 2000, 1, 1), item("test2.jpg", 2000, 1, 2)],
+        );
+
+        source.start();
+
+        let cache = cache.read().unwrap();
+        assert!(
+            cache
+                .lookup(&"/All/2000/01/01/2000-01-01 00:00.jpeg")
+                .is_some()
+        );
+        assert!(
+            cache
+                .lookup(&"/All/2000/01/02/2000-01-02 00:00.jpeg")
+                .is_some()
+        );
+    }
+
+    /// Tests that reloading applies a changed filter.
+    #[test]
+    fn reload_filter() {
+        let cache = cache();
+        let filter = data::shared_filter(data::Filter::default());
+        let mut source = MemorySource::new(
+            cache.clone(),
+            vec![item("test1.jpg", 2000, 1, 1), item("test2.jpg", 2000, 1, 2)],
+        ).with_filter(filter.clone());
+
+        source.start();
+        filter.write().unwrap().after = Some((2000, 1, 2).into());
+        source.reload();
+
+        let cache = cache.read().unwrap();
+        assert!(cache.lookup(&"/All/2000/01/01").is_none());
+        assert!(
+            cache
+                .lookup(&"/All/2000/01/02/2000-01-02 00:00.jpeg")
+                .is_some()
+        );
+    }
+}
@@ -1,10 +1,18 @@
+use std::path;
+
 use clap;
 
+use data;
 use files;
 
 mod file_system;
 pub use self::file_system::*;
 
+#[cfg(test)]
+mod memory;
+#[cfg(test)]
+pub use self::memory::*;
+
 /// A source of media files.
 pub trait Source: Send + Sync {
     /// Starts this source.
@@ -16,7 +24,54 @@ pub trait Source: Send + Sync {
     ///
     /// This method should check whether the underlying data store has been
     /// updated, and in that case update the cache.
-    fn notify(&mut self);
+    ///
+    /// This method may be called concurrently from several threads.
+    fn notify(&self);
+
+    /// Reloads all items from this source, even if the underlying data store
+    /// has not been updated.
+    ///
+    /// This is used to apply a changed configuration.
+    fn reload(&self);
+
+    /// Stops this source.
+    ///
+    /// This is called when the file system is unmounted, and is where state
+    /// that must survive a restart should be written. The default
+    /// implementation does nothing.
+    fn stop(&self) {}
+
+    /// Generates the item for a single source file without adding it.
+    ///
+    /// This is used to inspect the metadata read from a file. If the file
+    /// would not be added, or this source does not read files, `None` is
+    /// returned. The default implementation returns `None`.
+    ///
+    /// # Arguments
+    /// *  `path` - The path of the source file.
+    fn probe(&self, _path: &path::Path) -> Option<data::Item> {
+        None
+    }
+
+    /// Returns whether this source reads items from a directory containing,
+    /// or contained in, `path`.
+    ///
+    /// A source overlapping the mount point would read its own output. The
+    /// default implementation returns `false`.
+    ///
+    /// # Arguments
+    /// *  `path` - The path to check.
+    fn overlaps(&self, _path: &path::Path) -> bool {
+        false
+    }
+
+    /// Lists the settings of this source as key and value pairs.
+    ///
+    /// Keys are named after the corresponding command line options. The
+    /// default implementation returns no settings.
+    fn settings(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
 }
 
 /// A source of media files.
@@ -32,8 +87,14 @@ pub trait ConfigurableSource: Source + Sized {
 /// A source that can be constructed from command line arguments.
 pub trait ConstructableSource: ConfigurableSource {
     /// Constructs a source from command line arguments.
+    ///
+    /// # Arguments
+    /// *  `cache` - The cache to which to add items.
+    /// *  `filter` - The filter selecting which items to add.
+    /// *  `args` - Command line arguments.
     fn construct<'a>(
         cache: files::Cache,
+        filter: data::SharedFilter,
         args: &clap::ArgMatches<'a>,
     ) -> Result<Self, String>;
 }
@@ -43,32 +104,46 @@ pub trait WithSources<'a>: Sized {
     /// Applies all source to this instance.
     ///
     /// This is where to add new sources.
+    ///
+    /// The tags source is applied only if the `tags` feature is enabled.
     fn with_sources(self) -> Self {
-        self.with_source::<DirectorySource>()
-            .with_source::<TagsSource>()
+        let result = self.with_source::<DirectorySource>();
+        #[cfg(feature = "tags")]
+        let result = result.with_source::<TagsSource>();
+        result
     }
 
     /// Applies a single source to this.
     fn with_source<S: ConfigurableSource>(self) -> Self;
 }
 
-impl<'a> From<(files::Cache, clap::ArgMatches<'a>)> for Box<Source> {
-    /// Converts a cache and arguments to a boxed source.
+impl<'a> From<(files::Cache, data::SharedFilter, clap::ArgMatches<'a>)>
+    for Box<dyn Source>
+{
+    /// Converts a cache, a filter and arguments to a boxed source.
     ///
     /// This is where to add new sources.
     ///
     /// # Arguments
     /// *  `cache` - The cache to which to add items.
+    /// *  `filter` - The filter selecting which items to add.
     /// *  `matches` - Command line arguments.
-    fn from((cache, args): (files::Cache, clap::ArgMatches<'a>)) -> Self {
+    fn from(
+        (cache, filter, args): (
+            files::Cache,
+            data::SharedFilter,
+            clap::ArgMatches<'a>,
+        ),
+    ) -> Self {
         match args.subcommand() {
             (DirectorySource::SUBCOMMAND_NAME, Some(ref app)) => {
-                DirectorySource::construct(cache, app)
+                DirectorySource::construct(cache, filter, app)
                     .map(|s| Box::new(s))
                     .expect("failed to construct directory source")
             }
+            #[cfg(feature = "tags")]
             (TagsSource::SUBCOMMAND_NAME, Some(ref app)) => {
-                TagsSource::construct(cache, app)
+                TagsSource::construct(cache, filter, app)
                     .map(|s| Box::new(s))
                     .expect("failed to construct tags source")
             }
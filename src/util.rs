@@ -1,6 +1,19 @@
+use std::cmp;
+use std::ffi;
+use std::fs;
 use std::io;
+use std::io::Read;
+use std::iter;
+use std::os::unix::fs::FileExt;
+use std::path;
+use std::str;
 
 use libc;
+use sha2;
+use sha2::Digest;
+
+/// The size of the buffer used when reading files to hash.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
 
 /// Maps an error kind to an `errno` number.
 ///
@@ -26,3 +39,212 @@ pub fn map_error(error: io::Error) -> i32 {
         _ => libc::EIO,
     }
 }
+
+/// Reads up to `size` bytes from a reader.
+///
+/// Unlike a single call to [`io::Read::read`], this function keeps reading
+/// until `size` bytes have been read or the end of the stream is reached, and
+/// reads interrupted by a signal are retried. A short result thus always
+/// means that the end of the stream was reached.
+///
+/// # Arguments
+/// *  `reader` - The reader from which to read.
+/// *  `size` - The maximum number of bytes to read.
+///
+/// [`io::Read::read`]: https://doc.rust-lang.org/std/io/trait.Read.html#tymethod.read
+pub fn read_fully<R: io::Read>(
+    reader: &mut R,
+    size: usize,
+) -> io::Result<Vec<u8>> {
+    let mut buffer = vec![0u8; size];
+    let mut offset = 0;
+    while offset < size {
+        match reader.read(&mut buffer[offset..]) {
+            Ok(0) => break,
+            Ok(count) => offset += count,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    buffer.truncate(offset);
+    Ok(buffer)
+}
+
+/// A reader reading a file from an offset using positional reads.
+///
+/// Positional reads neither use nor move the offset of the file, so several
+/// readers may read from the same file descriptor concurrently.
+pub struct PositionalReader<'a> {
+    /// The file from which to read.
+    file: &'a fs::File,
+
+    /// The offset of the next read.
+    offset: u64,
+}
+
+impl<'a> PositionalReader<'a> {
+    /// Creates a reader starting at an offset.
+    ///
+    /// # Arguments
+    /// *  `file` - The file from which to read.
+    /// *  `offset` - The offset of the first read.
+    pub fn new(file: &'a fs::File, offset: u64) -> Self {
+        Self { file, offset }
+    }
+}
+
+impl<'a> io::Read for PositionalReader<'a> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let count = self.file.read_at(buffer, self.offset)?;
+        self.offset += count as u64;
+        Ok(count)
+    }
+}
+
+/// Calculates the SHA-256 checksum of a file as a lower case hexadecimal
+/// string.
+///
+/// # Arguments
+/// *  `path` - The path of the file.
+pub fn sha256(path: &path::Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = sha2::Sha256::default();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            count => hasher.input(&buffer[..count]),
+        }
+    }
+
+    Ok(hasher
+        .result()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// Compares two names so that embedded numbers are ordered by value.
+///
+/// Names are compared character by character, except that runs of decimal
+/// digits are compared as numbers, so `page-2` is ordered before `page-10`.
+/// Numbers with the same value but different numbers of leading zeros, and
+/// names that are not valid UTF-8, are finally ordered by their plain
+/// representation, so that only equal names compare as equal.
+///
+/// # Arguments
+/// *  `a` - The first name.
+/// *  `b` - The second name.
+pub fn natural_cmp(a: &ffi::OsStr, b: &ffi::OsStr) -> cmp::Ordering {
+    let (left, right) = (a.to_string_lossy(), b.to_string_lossy());
+    let mut left = left.chars().peekable();
+    let mut right = right.chars().peekable();
+    loop {
+        let ordering = match (left.peek().cloned(), right.peek().cloned()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return cmp::Ordering::Less,
+            (Some(_), None) => return cmp::Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let (l, r) = (digits(&mut left), digits(&mut right));
+                l.len().cmp(&r.len()).then_with(|| l.cmp(&r))
+            }
+            (Some(l), Some(r)) => {
+                left.next();
+                right.next();
+                l.cmp(&r)
+            }
+        };
+        if ordering != cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Consumes a run of decimal digits and returns them without leading zeros.
+///
+/// # Arguments
+/// *  `chars` - The characters from which to read.
+fn digits(chars: &mut iter::Peekable<str::Chars>) -> String {
+    let mut result = String::new();
+    while let Some(c) = chars.peek().cloned().filter(|c| c.is_ascii_digit()) {
+        chars.next();
+        if !(result.is_empty() && c == '0') {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader returning at most a few bytes at a time, and failing every
+    /// other read as if interrupted by a signal.
+    struct SlowReader {
+        /// The remaining data.
+        data: Vec<u8>,
+
+        /// Whether the next read is interrupted.
+        interrupt: bool,
+    }
+
+    impl io::Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if !self.interrupt {
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            let count = buf.len().min(self.data.len()).min(3);
+            buf[..count].copy_from_slice(&self.data[..count]);
+            self.data.drain(..count);
+            Ok(count)
+        }
+    }
+
+    /// Tests that a slow reader is read in full by a single large request.
+    #[test]
+    fn read_fully_slow() {
+        let data = (0..100u8).collect::<Vec<_>>();
+        let mut reader = SlowReader {
+            data: data.clone(),
+            interrupt: false,
+        };
+
+        assert_eq!(data[..50].to_vec(), read_fully(&mut reader, 50).unwrap());
+        assert_eq!(data[50..].to_vec(), read_fully(&mut reader, 1000).unwrap());
+        assert!(read_fully(&mut reader, 1000).unwrap().is_empty());
+    }
+
+    /// Tests that errors other than interruptions are reported.
+    #[test]
+    fn read_fully_error() {
+        struct FailingReader;
+        impl io::Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+
+        assert_eq!(
+            io::ErrorKind::BrokenPipe,
+            read_fully(&mut FailingReader, 10).unwrap_err().kind(),
+        );
+    }
+
+    /// Tests that embedded numbers are ordered by value.
+    #[test]
+    fn natural_cmp_numbers() {
+        let cmp = |a, b| natural_cmp(ffi::OsStr::new(a), ffi::OsStr::new(b));
+
+        assert_eq!(cmp::Ordering::Less, cmp("page-2", "page-10"));
+        assert_eq!(cmp::Ordering::Greater, cmp("page-10", "page-2"));
+        assert_eq!(cmp::Ordering::Less, cmp("999", "1000"));
+        assert_eq!(cmp::Ordering::Less, cmp("02", "10"));
+        assert_eq!(cmp::Ordering::Less, cmp("page", "page-1"));
+        assert_eq!(cmp::Ordering::Less, cmp("Å 2", "Å 10"));
+        assert_eq!(cmp::Ordering::Equal, cmp("page-2", "page-2"));
+        assert_ne!(cmp::Ordering::Equal, cmp("page-02", "page-2"));
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::io;
+use std::path;
+use std::process;
+use std::thread;
+use std::time;
+
+/// The default interval between checks of the mount point.
+pub const DEFAULT_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
+/// Checks whether a mount point is available.
+///
+/// A mount point is available as long as it is a directory that can be
+/// inspected. A mount point that has been removed, or whose file system is
+/// no longer connected, is not.
+///
+/// # Arguments
+/// *  `mount_point` - The mount point.
+pub fn check(mount_point: &path::Path) -> io::Result<()> {
+    if fs::symlink_metadata(mount_point)?.is_dir() {
+        Ok(())
+    } else {
+        Err(io::Error::other("not a directory"))
+    }
+}
+
+/// Calls a function once a mount point becomes unavailable.
+///
+/// This function spawns a thread checking the mount point periodically. The
+/// thread exits after calling the function.
+///
+/// # Arguments
+/// *  `mount_point` - The mount point.
+/// *  `interval` - The interval between checks.
+/// *  `callback` - The function to call with the reason the mount point is
+///    unavailable.
+pub fn on_unavailable<F>(
+    mount_point: &path::Path,
+    interval: time::Duration,
+    callback: F,
+) -> thread::JoinHandle<()>
+where
+    F: FnOnce(io::Error) + Send + 'static,
+{
+    let mount_point = mount_point.to_path_buf();
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if let Err(e) = check(&mount_point) {
+            callback(e);
+            return;
+        }
+    })
+}
+
+/// Detaches the file system mounted on a mount point.
+///
+/// The file system is unmounted lazily, so that it is detached even if it is
+/// busy.
+///
+/// # Arguments
+/// *  `mount_point` - The mount point.
+pub fn unmount(mount_point: &path::Path) -> io::Result<()> {
+    let status = process::Command::new("fusermount")
+        .arg("-u")
+        .arg("-z")
+        .arg(mount_point)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(
+            format!("fusermount failed: {}", status),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use tempdir;
+
+    use super::*;
+
+    /// The interval used by tests.
+    const INTERVAL: time::Duration = time::Duration::from_millis(10);
+
+    /// Tests that only an existing directory is an available mount point.
+    #[test]
+    fn check_mount_point() {
+        let dir = tempdir::TempDir::new("medifs-watchdog").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, b"data").unwrap();
+
+        assert!(check(dir.path()).is_ok());
+        assert!(check(&file).is_err());
+        assert_eq!(
+            io::ErrorKind::NotFound,
+            check(&dir.path().join("missing")).unwrap_err().kind(),
+        );
+    }
+
+    /// Tests that the callback is called once the mount point is removed,
+    /// and not before.
+    #[test]
+    fn removed_mount_point() {
+        let dir = tempdir::TempDir::new("medifs-watchdog").unwrap();
+        let mount_point = dir.path().join("mount");
+        fs::create_dir(&mount_point).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let watchdog = on_unavailable(&mount_point, INTERVAL, move |e| {
+            tx.send(e.kind()).unwrap();
+        });
+        assert!(rx.recv_timeout(10 * INTERVAL).is_err());
+
+        fs::remove_dir(&mount_point).unwrap();
+        assert_eq!(
+            Ok(io::ErrorKind::NotFound),
+            rx.recv_timeout(time::Duration::from_secs(5)),
+        );
+        watchdog.join().unwrap();
+    }
+}